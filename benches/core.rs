@@ -0,0 +1,91 @@
+// Instruction throughput of a headless machine, stepped without any pacing. Run
+// with `cargo bench`, adding `--features jit` to compare against compiled blocks.
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use chip_eight::{
+    config::{Args, Config},
+    ChipEight,
+    ChipEightBuilder,
+};
+
+const STEPS: u64 = 100_000;
+
+// Arithmetic and logic in a tight loop
+const ALU_ROM: [u16; 8] = [
+    0x6001, // v0 = 1
+    0x8014, // v0 += v1
+    0x8125, // v1 -= v2
+    0x8236, // v2 = v3 >> 1
+    0x831E, // v3 = v1 << 1
+    0x8403, // v4 ^= v0
+    0x7501, // v5 += 1
+    0x1202, // loop
+];
+
+// A full-height sprite drawn at a moving position, over and over
+const DRAW_ROM: [u16; 5] = [
+    0xA050, // I = font
+    0xD01F, // draw 15 rows at v0, v1
+    0x7003, // v0 += 3
+    0x7101, // v1 += 1
+    0x1202, // loop
+];
+
+fn machine(program: &[u16], jit: bool) -> ChipEight {
+    let config = Config::from(Args::parse_from([
+        "chip-eight",
+        "--display-engine", "none",
+        "--audio-engine", "none",
+        "--input-engine", "none",
+        // Draws would otherwise stall until the next vblank
+        "--vblank-model", "none",
+    ]));
+
+    let builder = ChipEightBuilder::new(config).with_seed(0);
+
+    #[cfg(feature = "jit")]
+    let builder = if jit { builder.with_jit() } else { builder };
+    #[cfg(not(feature = "jit"))]
+    let _ = jit;
+
+    let mut chip_eight = builder.build();
+
+    let rom: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    chip_eight.load(&rom);
+
+    chip_eight
+}
+
+fn bench_rom(c: &mut Criterion, name: &str, program: &[u16]) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(STEPS));
+
+    group.bench_function("step", |b| b.iter_batched_ref(
+        || machine(program, false),
+        |chip_eight| {
+            for _ in 0..STEPS {
+                chip_eight.step();
+            }
+        },
+        BatchSize::LargeInput,
+    ));
+
+    // One machine throughout, so blocks are only compiled during the warm up
+    #[cfg(feature = "jit")]
+    group.bench_function("jit", |b| {
+        let mut chip_eight = machine(program, true);
+        b.iter(|| chip_eight.run_cycles(STEPS));
+    });
+
+    group.finish();
+}
+
+fn core(c: &mut Criterion) {
+    bench_rom(c, "alu", &ALU_ROM);
+    bench_rom(c, "draw", &DRAW_ROM);
+}
+
+criterion_group!(benches, core);
+criterion_main!(benches);
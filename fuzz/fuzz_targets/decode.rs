@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip_eight::{config::Profile, instructions::Instruction};
+
+// Decoding must reject unknown opcodes with an error, never panic.
+fuzz_target!(|opcode: u16| {
+    let _ = Instruction::try_from(opcode);
+
+    for profile in [Profile::Chip8, Profile::MegaChip, Profile::Chip8X] {
+        let _ = Instruction::decode(opcode, profile);
+    }
+});
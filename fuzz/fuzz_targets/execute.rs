@@ -0,0 +1,51 @@
+#![no_main]
+
+use clap::Parser;
+use libfuzzer_sys::fuzz_target;
+
+use chip_eight::{
+    config::{Args, Config},
+    devices::headless::KeyInput,
+    ChipEightBuilder,
+};
+
+const MAX_CYCLES: usize = 10_000;
+const CYCLES_PER_FRAME: usize = 10;
+
+// Run arbitrary bytes as a ROM, under the profile picked by the first byte. Faults
+// in the program are expected and end the run, but the interpreter itself must
+// never panic.
+fuzz_target!(|data: &[u8]| {
+    let Some((&profile, rom)) = data.split_first() else {
+        return;
+    };
+
+    let profile = ["chip8", "megachip", "chip8x"][profile as usize % 3];
+    let config = Config::from(Args::parse_from([
+        "chip-eight",
+        "--profile", profile,
+        "--display-engine", "none",
+        "--audio-engine", "none",
+        "--input-engine", "none",
+    ]));
+
+    // Anything past the end of memory can't be loaded
+    let rom = &rom[..rom.len().min(config.memory.length - config.memory.program_start)];
+
+    let mut chip_eight = ChipEightBuilder::new(config)
+        .with_input(Box::new(KeyInput::new()))
+        .with_seed(0)
+        .build();
+
+    chip_eight.load(rom);
+
+    for cycle in 1..=MAX_CYCLES {
+        if chip_eight.try_step().is_err() {
+            break;
+        }
+
+        if cycle % CYCLES_PER_FRAME == 0 {
+            chip_eight.vblank();
+        }
+    }
+});
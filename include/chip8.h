@@ -0,0 +1,79 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct Chip8 Chip8;
+
+// Create an emulator with the default configuration and no attached devices.
+// Returns null if it can't be created.
+Chip8 *chip8_new(void);
+
+// Reset the emulator and load a ROM into memory. Returns false if the ROM doesn't fit.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`, and `data` must point to `len` readable bytes.
+bool chip8_load_rom(Chip8 *chip8, const uint8_t *data, uintptr_t len);
+
+// Execute a single instruction.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+void chip8_step(Chip8 *chip8);
+
+// Signal the 60Hz vertical blank, counting the timers down and releasing a
+// pending draw wait.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+void chip8_vblank(Chip8 *chip8);
+
+// Borrow the last frame completed by `chip8_vblank` as `width * height` row-major
+// pixels, writing its dimensions through the out pointers when they aren't null.
+// The pointer is valid until the next call that takes `chip8` mutably.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+const bool *chip8_framebuffer(const Chip8 *chip8, uintptr_t *width, uintptr_t *height);
+
+// Borrow the last completed frame as `width * height * 4` bytes of row-major
+// RGBA8888 pixels in the display's colours, ready to upload as a texture.
+// Dimensions and lifetime are as for `chip8_framebuffer`.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+const uint8_t *chip8_framebuffer_rgba(const Chip8 *chip8, uintptr_t *width, uintptr_t *height);
+
+// Press or release keypad key 0x0 to 0xF. Other keys are ignored.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+void chip8_set_key(Chip8 *chip8, uint8_t key, bool pressed);
+
+// Press keypad key 0x0 to 0xF and hold it for a number of `chip8_vblank` calls,
+// at least one. Other keys are ignored.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new`.
+void chip8_tap_key(Chip8 *chip8, uint8_t key, uint64_t frames);
+
+// Free an emulator. Passing null does nothing.
+//
+// # Safety
+//
+// `chip8` must come from `chip8_new` and not be used afterwards.
+void chip8_destroy(Chip8 *chip8);
+
+#endif  /* CHIP8_H */
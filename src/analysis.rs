@@ -0,0 +1,177 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::instructions::{Instruction, Profile};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Platform {
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl Platform {
+    // Classify opcodes that only exist in extensions of the original instruction set.
+    pub fn of(opcode: u16) -> Option<Self> {
+        match opcode {
+            0x00FB..=0x00FF => Some(Platform::SuperChip),
+            0xF000 | 0xF002 => Some(Platform::XoChip),
+            0x0010 | 0x0011 | 0x0100..=0x09FF => Some(Platform::MegaChip),
+            _ if opcode & 0xFFF0 == 0x00C0 => Some(Platform::SuperChip),
+            _ if opcode & 0xFFF0 == 0x00D0 => Some(Platform::XoChip),
+            _ if opcode & 0xF00F == 0xD000 => Some(Platform::SuperChip),
+            _ if opcode & 0xF00F == 0x5002 || opcode & 0xF00F == 0x5003 => Some(Platform::XoChip),
+            _ => match opcode & 0xF0FF {
+                0xF030 | 0xF075 | 0xF085 => Some(Platform::SuperChip),
+                0xF001 | 0xF03A => Some(Platform::XoChip),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Result of statically walking the code reachable from the entry point.
+pub struct Analysis {
+    pub entry_point: usize,
+    pub reachable: BTreeSet<usize>,
+    pub jump_targets: BTreeSet<usize>,
+    pub call_targets: BTreeSet<usize>,
+    pub index_targets: BTreeSet<usize>,
+    pub dynamic_jumps: BTreeSet<usize>,
+    pub undecodable: BTreeSet<usize>,
+    pub platforms: BTreeSet<Platform>,
+    pub self_modifying: bool,
+}
+
+impl Analysis {
+    // Walk the code of a ROM loaded at program_start, decoding it as the profile
+    // given does.
+    pub fn scan(rom: &[u8], program_start: usize, profile: Profile) -> Self {
+        let mut analysis = Self {
+            entry_point: program_start,
+            reachable: BTreeSet::new(),
+            jump_targets: BTreeSet::new(),
+            call_targets: BTreeSet::new(),
+            index_targets: BTreeSet::new(),
+            dynamic_jumps: BTreeSet::new(),
+            undecodable: BTreeSet::new(),
+            platforms: BTreeSet::new(),
+            self_modifying: false,
+        };
+
+        let mut writes_memory = false;
+        let mut queue = VecDeque::from([program_start]);
+
+        while let Some(addr) = queue.pop_front() {
+            if analysis.reachable.contains(&addr) {
+                continue;
+            }
+
+            let Some(opcode) = fetch(rom, program_start, addr) else {
+                continue;
+            };
+
+            analysis.reachable.insert(addr);
+
+            if let Some(platform) = Platform::of(opcode) {
+                analysis.platforms.insert(platform);
+            }
+
+            let instruction = match Instruction::decode(opcode, profile) {
+                Ok(instruction) => instruction,
+                Err(_) => {
+                    match Platform::of(opcode) {
+                        Some(_) => queue.push_back(addr + 2),
+                        None => {
+                            analysis.reachable.remove(&addr);
+                            analysis.undecodable.insert(addr);
+                        },
+                    }
+
+                    continue;
+                },
+            };
+
+            match instruction {
+                Instruction::SetI(target) => {
+                    analysis.index_targets.insert(target);
+                },
+                Instruction::StoreVxBCDAtI(_) | Instruction::VDump(_) => writes_memory = true,
+                _ => (),
+            }
+
+            match instruction {
+                Instruction::Jump(target) => {
+                    analysis.jump_targets.insert(target);
+                },
+                Instruction::Call(target) => {
+                    analysis.call_targets.insert(target);
+                },
+                Instruction::JumpWithOffset(_) => {
+                    analysis.dynamic_jumps.insert(addr);
+                },
+                _ => (),
+            }
+
+            // Skipping F000 NNNN steps over both of its words
+            let wide_next = fetch(rom, program_start, addr + 2) == Some(0xF000);
+
+            queue.extend(successors(&instruction, addr).into_iter().map(|successor| {
+                if wide_next && successor == addr + 4 && is_skip(&instruction) {
+                    successor + 2
+                } else {
+                    successor
+                }
+            }));
+        }
+
+        // Code that points the index register at itself and then stores to memory is
+        // very likely rewriting its own instructions.
+        analysis.self_modifying = writes_memory && analysis.index_targets
+            .iter()
+            .any(|target| analysis.reachable.contains(target) || analysis.reachable.contains(&(target.wrapping_sub(1))));
+
+        analysis
+    }
+
+    // Number of ROM bytes covered by reachable instructions.
+    pub fn code_bytes(&self) -> usize {
+        self.reachable.len() * 2
+    }
+}
+
+// Read the opcode at addr, if both of its bytes lie within the ROM.
+pub fn fetch(rom: &[u8], program_start: usize, addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(program_start)?;
+    let parts = rom.get(offset..offset + 2)?;
+
+    Some(((parts[0] as u16) << 8) | parts[1] as u16)
+}
+
+// Addresses execution may continue at after the instruction at addr. Dynamic jumps
+// (BNNN) have no statically known successors.
+pub fn successors(instruction: &Instruction, addr: usize) -> Vec<usize> {
+    let next = addr + 2;
+
+    match *instruction {
+        Instruction::Return | Instruction::JumpWithOffset(_) => vec![],
+        Instruction::Jump(target) => vec![target],
+        Instruction::Call(target) => vec![target, next],
+        // 01NN NNNN and F000 NNNN take up two words
+        Instruction::SetILong(_) | Instruction::SetIWide => vec![next + 2],
+        _ if is_skip(instruction) => vec![next, next + 2],
+        _ => vec![next],
+    }
+}
+
+// Whether the instruction may skip the one after it.
+pub fn is_skip(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::IfVxEq(..)
+            | Instruction::IfVxNotEq(..)
+            | Instruction::IfVxEqVy(..)
+            | Instruction::IfVxNotEqVy(..)
+            | Instruction::IfKeyPressed(_)
+            | Instruction::IfKeyNotPressed(_)
+    )
+}
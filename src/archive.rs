@@ -0,0 +1,277 @@
+// The public CHIP-8 program archive: a catalogue of programs, mostly from the
+// Octojam game jams, each with the platform, quirks, speed and colours it was
+// written for. Its catalogue and ROMs are downloaded on first use and kept in the
+// data directory:
+//
+// <data dir>/archive/programs.json     the catalogue
+// <data dir>/archive/roms/<name>.ch8   ROMs run so far
+//
+// Downloads go through the system's curl, which also reads file:// URLs for
+// mirrors on disk, so the emulator needs no TLS of its own.
+
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{info, warn};
+
+use crate::json::{self, Value};
+
+pub const DEFAULT_URL: &str = "https://raw.githubusercontent.com/JohnEarnest/chip8Archive/master";
+
+// Octo's quirk options and the flags for the behaviour each asks for, when set
+// and when unset. The archive names the load/store quirk both ways.
+const QUIRK_FLAGS: [(&str, Option<&str>, Option<&str>); 7] = [
+    ("shiftQuirks", Some("--skip-shift-set"), None),
+    ("loadStoreQuirks", Some("--preserve-index"), None),
+    ("loadStoreQuirk", Some("--preserve-index"), None),
+    ("jumpQuirks", Some("--jump-with-vx"), None),
+    ("logicQuirks", None, Some("--skip-reset-vf")),
+    ("clipQuirks", None, Some("--wrap-sprites")),
+    ("vBlankQuirks", None, Some("--vblank-model=none")),
+];
+
+// Octo's quirk options for SUPER-CHIP, for schip programs that leave them unset.
+const SCHIP_QUIRKS: [(&str, bool); 6] = [
+    ("shiftQuirks", true),
+    ("loadStoreQuirks", true),
+    ("jumpQuirks", true),
+    ("logicQuirks", false),
+    ("clipQuirks", true),
+    ("vBlankQuirks", false),
+];
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    // URL that couldn't be fetched, and why
+    Download(String, String),
+    Json(json::ParseError),
+    // Catalogue that isn't an object of programs
+    Catalogue,
+    UnknownProgram(String),
+    // Program name that would put its ROM outside the cache
+    InvalidName(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(error) => write!(f, "{}", error),
+            ArchiveError::Download(url, reason) => write!(f, "failed to download {}: {}", url, reason),
+            ArchiveError::Json(error) => write!(f, "{}", error),
+            ArchiveError::Catalogue => write!(f, "catalogue is not an object of programs"),
+            ArchiveError::UnknownProgram(name) => write!(f, "no program named {} in the archive", name),
+            ArchiveError::InvalidName(name) => write!(f, "program name {} isn't a plain file name", name),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(error: io::Error) -> Self {
+        ArchiveError::Io(error)
+    }
+}
+
+// A program in the catalogue, with what it needs to run as intended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    // Key in the catalogue, which also names its ROM
+    pub name: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub event: String,
+    pub release: String,
+    pub description: String,
+    // chip8, schip or xochip
+    pub platform: String,
+    // Instructions per 60Hz frame
+    pub tickrate: Option<u64>,
+    // Background and fill colours, as #RRGGBB
+    pub colors: Option<(String, String)>,
+    pub rotation: Option<u64>,
+    // Octo quirk options set for the program
+    pub quirks: Vec<(String, bool)>,
+}
+
+impl Program {
+    fn parse(name: &str, entry: &Value) -> Self {
+        let text = |value: Option<&Value>| value.and_then(Value::as_str).unwrap_or_default().to_string();
+        let options = entry.get("options");
+        let option = |key: &str| options.and_then(|options| options.get(key));
+
+        let colors = option("backgroundColor").and_then(Value::as_str)
+            .zip(option("fillColor").and_then(Value::as_str))
+            .map(|(off, on)| (off.to_string(), on.to_string()));
+
+        let quirks = QUIRK_FLAGS.iter()
+            .filter_map(|(quirk, _, _)| Some((quirk.to_string(), option(quirk)?.as_bool()?)))
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            title: text(entry.get("title")),
+            authors: entry.get("authors").map_or(&[][..], Value::as_array).iter()
+                .filter_map(|author| Some(author.as_str()?.to_string()))
+                .collect(),
+            event: text(entry.get("event")),
+            release: text(entry.get("release")),
+            description: text(entry.get("description")),
+            platform: text(entry.get("platform")),
+            tickrate: option("tickrate").and_then(Value::as_f64).map(|rate| rate as u64),
+            colors,
+            rotation: option("screenRotation").and_then(Value::as_f64).map(|degrees| degrees as u64),
+            quirks,
+        }
+    }
+
+    // Whether the query appears in the program's name, title, authors, event or
+    // description, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+
+        [&self.name, &self.title, &self.event, &self.description].into_iter()
+            .chain(&self.authors)
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+
+    // Flags to run the program with as it was written to be run.
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = vec![];
+
+        let mut quirks = self.quirks.clone();
+
+        match self.platform.as_str() {
+            // XO-CHIP programs have the whole 64K
+            "xochip" => flags.push("--memory-length=65536".to_string()),
+            "schip" => {
+                // Either spelling of the load/store quirk counts as given
+                let unset = SCHIP_QUIRKS.iter()
+                    .filter(|(quirk, _)| !self.quirks.iter().any(|(given, _)| given.trim_end_matches('s') == quirk.trim_end_matches('s')))
+                    .map(|&(quirk, set)| (quirk.to_string(), set))
+                    .collect::<Vec<_>>();
+
+                quirks.extend(unset);
+            },
+            "" | "chip8" => {},
+            platform => warn!("{} is for the {} platform, which isn't applied", self.name, platform),
+        }
+
+        if let Some(tickrate) = self.tickrate.filter(|&rate| rate > 0) {
+            flags.push(format!("--clock-speed={}", tickrate * 60));
+        }
+
+        if let Some((off, on)) = &self.colors {
+            flags.push(format!("--colors={},{}", off, on));
+        }
+
+        if let Some(rotation @ (90 | 180 | 270)) = self.rotation {
+            flags.push(format!("--rotate={}", rotation));
+        }
+
+        for (quirk, set) in &quirks {
+            let flag = QUIRK_FLAGS.iter()
+                .find(|(name, _, _)| name == quirk)
+                .and_then(|(_, when_set, when_unset)| if *set { *when_set } else { *when_unset });
+
+            if let Some(flag) = flag.filter(|flag| !flags.iter().any(|given| given == flag)) {
+                flags.push(flag.to_string());
+            }
+        }
+
+        flags
+    }
+}
+
+// The archive at a URL, cached in a directory.
+pub struct Archive {
+    url: String,
+    dir: PathBuf,
+}
+
+impl Archive {
+    pub fn new(url: &str, dir: &Path) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    // Every program in the catalogue, sorted by name, downloading the catalogue if
+    // it isn't cached or refresh is set.
+    pub fn programs(&self, refresh: bool) -> Result<Vec<Program>, ArchiveError> {
+        let path = self.dir.join("programs.json");
+
+        if refresh || !path.exists() {
+            self.download("programs.json", &path)?;
+        }
+
+        let catalogue = json::parse(&fs::read_to_string(&path)?).map_err(ArchiveError::Json)?;
+
+        match catalogue {
+            Value::Object(entries) => Ok(entries.iter().map(|(name, entry)| Program::parse(name, entry)).collect()),
+            _ => Err(ArchiveError::Catalogue),
+        }
+    }
+
+    // The program with this name, ignoring case.
+    pub fn program(&self, name: &str, refresh: bool) -> Result<Program, ArchiveError> {
+        self.programs(refresh)?
+            .into_iter()
+            .find(|program| program.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ArchiveError::UnknownProgram(name.to_string()))
+    }
+
+    // Path to the program's ROM, downloading it if it isn't cached.
+    pub fn rom(&self, program: &Program) -> Result<PathBuf, ArchiveError> {
+        // The name comes from the catalogue, so it mustn't reach outside roms/
+        if program.name.is_empty() || program.name.contains(['/', '\\']) || program.name.contains("..") {
+            return Err(ArchiveError::InvalidName(program.name.clone()));
+        }
+
+        let file = format!("{}.ch8", program.name);
+        let path = self.dir.join("roms").join(&file);
+
+        if !path.exists() {
+            self.download(&format!("roms/{}", file), &path)?;
+        }
+
+        Ok(path)
+    }
+
+    // Fetch a file of the archive to path, by way of a temporary file so an
+    // interrupted download isn't mistaken for a cached one.
+    fn download(&self, file: &str, path: &Path) -> Result<(), ArchiveError> {
+        let url = format!("{}/{}", self.url, file);
+        let partial = path.with_extension("part");
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        info!("Downloading {}", url);
+
+        let output = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+            .arg(&partial)
+            .arg(&url)
+            .output()
+            .map_err(|error| ArchiveError::Download(url.clone(), format!("couldn't run curl: {}", error)))?;
+
+        if !output.status.success() {
+            let _ = fs::remove_file(&partial);
+            let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(ArchiveError::Download(url, reason));
+        }
+
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
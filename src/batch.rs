@@ -0,0 +1,230 @@
+// Runs ROMs headless for a number of cycles, many at once, reporting how each run
+// ended. For curating ROM collections and checking the interpreter against them.
+
+use std::{
+    fmt::{self, Write as _},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use clap::Parser;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    config::{Args, Config},
+    devices::headless::KeyInput,
+    json,
+    loader,
+    ChipEightBuilder,
+};
+
+// Flags every run starts from, ahead of those given, so nothing opens a window or
+// plays sound.
+pub const HEADLESS: [&str; 7] = [
+    "chip-eight", "--display-engine", "none", "--audio-engine", "none", "--input-engine", "none",
+];
+
+// How a run ended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    // Ran every cycle without stopping
+    Ran { cycles: u64 },
+    // Reached the exit opcode
+    Exited { cycles: u64 },
+    // The frame stayed the same for as many frames as asked
+    Settled { cycles: u64 },
+    // Hit an instruction it couldn't execute
+    Faulted { addr: usize, cycles: u64, error: String },
+    // Couldn't be read or assembled
+    Unreadable(String),
+    // The emulator panicked
+    Crashed(String),
+}
+
+impl Outcome {
+    // Whether the run points to a problem with the ROM or the emulator.
+    pub fn failed(&self) -> bool {
+        matches!(self, Outcome::Faulted { .. } | Outcome::Unreadable(_) | Outcome::Crashed(_))
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Outcome::Ran { .. } => "ran",
+            Outcome::Exited { .. } => "exited",
+            Outcome::Settled { .. } => "settled",
+            Outcome::Faulted { .. } => "faulted",
+            Outcome::Unreadable(_) => "unreadable",
+            Outcome::Crashed(_) => "crashed",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ran { cycles } => write!(f, "ran {} cycles", cycles),
+            Outcome::Exited { cycles } => write!(f, "exited after {} cycles", cycles),
+            Outcome::Settled { cycles } => write!(f, "settled after {} cycles", cycles),
+            Outcome::Faulted { addr, cycles, error } => {
+                write!(f, "faulted at 0x{:03X} after {} cycles: {}", addr, cycles, error)
+            },
+            Outcome::Unreadable(error) => write!(f, "unreadable: {}", error),
+            Outcome::Crashed(message) => write!(f, "crashed: {}", message),
+        }
+    }
+}
+
+// A ROM's run, and the frame it finished on, if it got as far as running.
+#[derive(Clone, Debug)]
+pub struct RomRun {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    pub frame: Vec<bool>,
+    pub width: usize,
+}
+
+impl RomRun {
+    pub fn frame_hash(&self) -> Option<String> {
+        (!self.frame.is_empty()).then(|| frame_hash(&self.frame))
+    }
+}
+
+// SHA-1 of a frame, with one byte per pixel in row order, in hex.
+pub fn frame_hash(frame: &[bool]) -> String {
+    let pixels: Vec<u8> = frame.iter().map(|&pixel| pixel.into()).collect();
+
+    format!("{:x}", Sha1::digest(&pixels))
+}
+
+// Run the ROM at path for a number of cycles with the flags given, stopping early
+// if it exits, faults or its frame settles for the frames in --exit-when-settled.
+// Flags that don't parse are returned as an error.
+pub fn run_rom(path: &Path, cycles: u64, flags: &[String]) -> Result<RomRun, clap::Error> {
+    let args = Args::try_parse_from(HEADLESS.iter().map(|arg| arg.to_string()).chain(flags.iter().cloned()))?;
+    let config = Config::from(args);
+
+    let mut run = RomRun { path: path.to_path_buf(), outcome: Outcome::Ran { cycles }, frame: vec![], width: 0 };
+
+    let rom = match loader::read(path, &config.memory, None) {
+        Ok((rom, _)) => rom,
+        Err(error) => {
+            run.outcome = Outcome::Unreadable(error.to_string());
+            return Ok(run);
+        },
+    };
+
+    // The machine can't be inspected after a panic, so only the outcome is kept
+    let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+        // Nothing presses keys, so programs waiting on one wait out the cycles
+        let mut chip_eight = ChipEightBuilder::new(config)
+            .with_input(Box::new(KeyInput::new()))
+            .with_seed(0)
+            .build();
+        chip_eight.load(&rom);
+
+        let cycles_per_frame = chip_eight.cycles_per_frame();
+        let mut outcome = Outcome::Ran { cycles };
+
+        for cycle in 1..=cycles {
+            if let Err(error) = chip_eight.try_cycle() {
+                outcome = Outcome::Faulted { addr: chip_eight.pc(), cycles: cycle, error: error.to_string() };
+                break;
+            }
+
+            if chip_eight.exited() {
+                outcome = Outcome::Exited { cycles: cycle };
+                break;
+            }
+
+            if cycle % cycles_per_frame == 0 {
+                chip_eight.vblank();
+
+                if chip_eight.settled() {
+                    outcome = Outcome::Settled { cycles: cycle };
+                    break;
+                }
+            }
+        }
+
+        (outcome, chip_eight.frame_buffer().to_vec(), chip_eight.resolution().0)
+    }));
+
+    match ran {
+        Ok((outcome, frame, width)) => {
+            run.outcome = outcome;
+            run.frame = frame;
+            run.width = width;
+        },
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            run.outcome = Outcome::Crashed(message);
+        },
+    }
+
+    Ok(run)
+}
+
+// Run every ROM in paths as run_rom does, on a number of threads, returning the
+// runs in the order of paths.
+pub fn run_all(paths: &[PathBuf], cycles: u64, flags: &[String], threads: usize) -> Result<Vec<RomRun>, clap::Error> {
+    // Machines share their config within a thread, so each thread builds its own
+    let next = AtomicUsize::new(0);
+    let runs = Mutex::new(vec![None; paths.len()]);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, paths.len().max(1)))
+            .map(|_| scope.spawn(|| -> Result<(), clap::Error> {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        return Ok(());
+                    };
+
+                    let run = run_rom(path, cycles, flags)?;
+                    runs.lock().unwrap()[index] = Some(run);
+                }
+            }))
+            .collect();
+
+        workers.into_iter().try_for_each(|worker| worker.join().unwrap())
+    })?;
+
+    Ok(runs.into_inner().unwrap().into_iter().flatten().collect())
+}
+
+// Runs as a JSON array, an object per ROM.
+pub fn to_json(runs: &[RomRun]) -> String {
+    let mut json = String::from("[\n");
+
+    for (index, run) in runs.iter().enumerate() {
+        let _ = write!(json, "  {{\"rom\": {}, \"outcome\": \"{}\"", json::quote(&run.path.to_string_lossy()), run.outcome.kind());
+
+        match &run.outcome {
+            Outcome::Ran { cycles } | Outcome::Exited { cycles } | Outcome::Settled { cycles } => {
+                let _ = write!(json, ", \"cycles\": {}", cycles);
+            },
+            Outcome::Faulted { addr, cycles, error } => {
+                let _ = write!(json, ", \"cycles\": {}, \"addr\": {}, \"error\": {}", cycles, addr, json::quote(error));
+            },
+            Outcome::Unreadable(error) | Outcome::Crashed(error) => {
+                let _ = write!(json, ", \"error\": {}", json::quote(error));
+            },
+        }
+
+        if let Some(hash) = run.frame_hash() {
+            let _ = write!(json, ", \"frame_hash\": \"{}\"", hash);
+        }
+
+        json.push_str(if index + 1 < runs.len() { "},\n" } else { "}\n" });
+    }
+
+    json.push(']');
+    json
+}
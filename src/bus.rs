@@ -0,0 +1,127 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        mpmc::{self, Receiver, Sender},
+        mpsc,
+    },
+};
+
+// Events the machine sends to its audio device.
+pub enum AudioEvent {
+    PlayTone,
+    StopTone,
+}
+
+// Events the machine sends to its display device.
+pub enum DisplayEvent {
+    // The program switched resolution, taking effect from the next frame
+    ModeChange(usize, usize),
+}
+
+// Events devices send to control the machine.
+pub enum ControlEvent {
+    LoadRom(PathBuf),
+    Shutdown,
+    // Multiple of the configured clock speed to run at
+    SetSpeed(f64),
+    // Save state slot for SaveState and LoadState to use
+    SelectSlot(u8),
+    SaveState,
+    LoadState,
+    ToggleMute,
+    // Percentage points to raise the volume by, or lower it by when negative
+    ChangeVolume(i8),
+    // The window lost or regained the keyboard focus
+    FocusLost,
+    FocusGained,
+    // Switch to the next colour theme
+    NextTheme,
+    // Device pixels per virtual pixel to add, or remove when negative
+    ChangeScale(i8),
+}
+
+// Events the machine sends to embedders subscribed with ChipEight::events.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CoreEvent {
+    SoundStarted,
+    SoundStopped,
+    // The XO-CHIP audio pattern or its pitch changed, with the pattern now played, if
+    // any, and the pitch
+    PatternChanged(Option<[u8; 16]>, u8),
+    // A frame was completed and presented, with how many have been so far
+    FrameCompleted(u64),
+    // The program switched resolution
+    ModeChanged(usize, usize),
+    // The program stopped at the exit opcode or a trapped machine call, or faulted
+    Halted,
+}
+
+// Carries events between the machine and its devices, with a queue per kind of
+// event so that a burst of one kind never holds up another. The machine drains
+// every queue fully once a frame.
+pub struct EventBus {
+    audio: (Sender<AudioEvent>, Receiver<AudioEvent>),
+    display: (Sender<DisplayEvent>, Receiver<DisplayEvent>),
+    control: (Sender<ControlEvent>, Receiver<ControlEvent>),
+
+    // A queue to each subscriber to the machine's events, on stable channels so
+    // embedders can receive from them without nightly features
+    subscribers: Vec<mpsc::Sender<CoreEvent>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            audio: mpmc::channel(),
+            display: mpmc::channel(),
+            control: mpmc::channel(),
+            subscribers: vec![],
+        }
+    }
+
+    pub fn audio_sender(&self) -> Sender<AudioEvent> {
+        self.audio.0.clone()
+    }
+
+    pub fn control_sender(&self) -> Sender<ControlEvent> {
+        self.control.0.clone()
+    }
+
+    pub fn send_display(&self, event: DisplayEvent) {
+        // The bus holds the receiver, so the queue is always open
+        let _ = self.display.0.send(event);
+    }
+
+    // Open a queue the machine's events will be sent to from now on.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<CoreEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+
+        receiver
+    }
+
+    // Send an event to every subscriber, forgetting those that have dropped their end.
+    pub fn publish(&mut self, event: CoreEvent) {
+        self.subscribers.retain(|subscriber| subscriber.send(event).is_ok());
+    }
+
+    // Next pending event of each kind, if any.
+
+    pub fn next_audio(&self) -> Option<AudioEvent> {
+        self.audio.1.try_recv().ok()
+    }
+
+    pub fn next_display(&self) -> Option<DisplayEvent> {
+        self.display.1.try_recv().ok()
+    }
+
+    pub fn next_control(&self) -> Option<ControlEvent> {
+        self.control.1.try_recv().ok()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,40 @@
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use crate::instructions::Instruction;
+
+// Decoded instructions by address, so that loops skip decoding the same opcodes
+// every cycle. Memory writes invalidate the entries they touch, which keeps
+// self-modifying programs correct. Entries only reach as far as the code cached,
+// so the cache stays small in MegaChip's 16MB. Clones share the same entries.
+#[derive(Clone, Default)]
+pub struct InstructionCache(Rc<RefCell<Vec<Option<Instruction>>>>);
+
+impl InstructionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, addr: usize) -> Option<Instruction> {
+        self.0.borrow().get(addr).copied().flatten()
+    }
+
+    pub fn insert(&self, addr: usize, instruction: Instruction) {
+        let mut entries = self.0.borrow_mut();
+
+        if addr >= entries.len() {
+            entries.resize(addr + 1, None);
+        }
+
+        entries[addr] = Some(instruction);
+    }
+
+    pub fn invalidate(&self, range: Range<usize>) {
+        let mut entries = self.0.borrow_mut();
+
+        // The instruction starting a byte earlier overlaps the range too
+        let end = range.end.min(entries.len());
+        let start = range.start.saturating_sub(1).min(end);
+
+        entries[start..end].fill(None);
+    }
+}
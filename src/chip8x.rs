@@ -0,0 +1,94 @@
+// State for CHIP-8X's colour board, which tints the monochrome display with a
+// background colour and a grid of foreground colour attributes. Each attribute
+// covers 8 pixels of a single row, and BXY0 sets whole 8x4 pixel zones of them.
+
+// Colour board palette, indexed by 3-bit colour codes
+const COLORS: [u32; 8] = [
+    0xFF000000, // Black
+    0xFFFF0000, // Red
+    0xFF0000FF, // Blue
+    0xFFFF00FF, // Violet
+    0xFF00FF00, // Green
+    0xFFFFFF00, // Yellow
+    0xFF00FFFF, // Aqua
+    0xFFFFFFFF, // White
+];
+
+// Background colours, in the order 02A0 steps through them
+const BACKGROUNDS: [u32; 4] = [0xFF000080, 0xFF000000, 0xFF008000, 0xFF800000];
+
+const ZONE_WIDTH: usize = 8;
+const ZONE_HEIGHT: usize = 4;
+
+const DEFAULT_FOREGROUND: u8 = 1;
+
+pub struct Chip8X {
+    width: usize,
+    height: usize,
+    background: usize,
+
+    // Colour code of each 8x1 pixel cell, in row-major order
+    attributes: Vec<u8>,
+}
+
+impl Chip8X {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            background: 0,
+            attributes: vec![DEFAULT_FOREGROUND; width.div_ceil(ZONE_WIDTH) * height],
+        }
+    }
+
+    pub fn step_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    // Colour the 8x4 zones from the low nibble to the high nibble of each coordinate,
+    // inclusive.
+    pub fn set_zones(&mut self, horizontal: u8, vertical: u8, color: u8) {
+        let columns = (horizontal & 0xF) as usize..=(horizontal >> 4) as usize;
+        let rows = ((vertical & 0xF) as usize * ZONE_HEIGHT)..((vertical >> 4) as usize + 1) * ZONE_HEIGHT;
+
+        for row in rows {
+            for column in columns.clone() {
+                self.set(column, row, color);
+            }
+        }
+    }
+
+    // Colour the cells under an 8 pixel wide sprite of the given height, drawn at x, y.
+    pub fn set_rows(&mut self, x: usize, y: usize, height: usize, color: u8) {
+        for row in y..y + height {
+            self.set(x / ZONE_WIDTH, row, color);
+        }
+    }
+
+    fn set(&mut self, column: usize, row: usize, color: u8) {
+        let columns = self.width.div_ceil(ZONE_WIDTH);
+
+        if column < columns && row < self.height {
+            self.attributes[row * columns + column] = color & 0x7;
+        }
+    }
+
+    // Tint a monochrome frame, producing ARGB pixels.
+    pub fn colorize(&self, frame: &[bool]) -> Vec<u32> {
+        let columns = self.width.div_ceil(ZONE_WIDTH);
+
+        frame
+            .iter()
+            .enumerate()
+            .map(|(index, &on)| {
+                let (x, y) = (index % self.width, index / self.width);
+
+                if on {
+                    COLORS[self.attributes[y * columns + x / ZONE_WIDTH] as usize]
+                } else {
+                    BACKGROUNDS[self.background]
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,140 @@
+// Two machines run side by side in one window, for comparing two builds of a ROM or
+// the same ROM under different quirks. Both are given the same key presses, and in
+// lockstep the same random numbers too, with the pixels where their frames differ
+// highlighted from the first frame they diverge on.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use crate::{devices::Display, system::ChipEight};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Columns between the two frames
+pub const GAP: usize = 2;
+
+// Colour of the pixels that differ, and of the gap once the frames have diverged
+const HIGHLIGHT: u32 = 0xFFFF3030;
+
+pub struct Comparison {
+    machines: [ChipEight; 2],
+    display: Box<dyn Display>,
+    lockstep: bool,
+
+    // Colours of unlit and lit pixels, as ARGB
+    palette: [u32; 2],
+
+    // Frames run so far, and the first the two differed on
+    frames: u64,
+    diverged_at: Option<u64>,
+}
+
+impl Comparison {
+    // Compare two machines with their ROMs loaded, drawing on the display given. The
+    // first machine's input device and audio are used for both. In lockstep, the two
+    // are seeded alike and watched for diverging.
+    pub fn new(machines: [ChipEight; 2], display: Box<dyn Display>, colors: [(u8, u8, u8); 2], lockstep: bool) -> Self {
+        let mut comparison = Self {
+            machines,
+            display,
+            lockstep,
+            palette: colors.map(|(r, g, b)| u32::from_be_bytes([0xFF, r, g, b])),
+            frames: 0,
+            diverged_at: None,
+        };
+
+        if lockstep {
+            let seed = rand::random();
+
+            for machine in &mut comparison.machines {
+                machine.set_seed(seed);
+            }
+        }
+
+        comparison
+    }
+
+    // Run both machines a frame at a time, in real time, until stopped or both
+    // programs exit. Returns the first frame they diverged on, in lockstep.
+    pub fn run(&mut self) -> Option<u64> {
+        let mut input = self.machines[0].take_input();
+        let stop = self.machines[0].stop_handle();
+        let mut deadline = Instant::now();
+
+        while !stop.is_stopped() && !self.machines.iter().all(ChipEight::exited) {
+            let events = input.poll_events();
+
+            for machine in &mut self.machines {
+                for &event in &events {
+                    machine.key_event(event);
+                }
+
+                machine.run_frames(1);
+            }
+
+            self.frames += 1;
+
+            if self.lockstep && self.diverged_at.is_none() && self.differ() {
+                info!("Diverged at frame {}", self.frames);
+                self.diverged_at = Some(self.frames);
+                self.display.set_title(&format!("Diverged at frame {}", self.frames));
+            }
+
+            let (frame, width, height) = self.compose();
+            self.display.draw_color(&frame, width, height);
+
+            deadline += FRAME_DURATION;
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+
+        self.diverged_at
+    }
+
+    // Frames run so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn differ(&self) -> bool {
+        let [a, b] = &self.machines;
+        a.resolution() != b.resolution() || a.frame_buffer() != b.frame_buffer()
+    }
+
+    // Both frames side by side, with the pixels that differ highlighted once they
+    // have diverged, and the resolution of the whole.
+    fn compose(&self) -> (Vec<u32>, usize, usize) {
+        let [a, b] = &self.machines;
+        let ((a_width, a_height), (b_width, b_height)) = (a.resolution(), b.resolution());
+
+        let width = a_width + GAP + b_width;
+        let height = a_height.max(b_height);
+        let diverged = self.diverged_at.is_some();
+
+        let mut frame = vec![self.palette[0]; width * height];
+
+        if diverged {
+            for row in frame.chunks_mut(width) {
+                row[a_width..a_width + GAP].fill(HIGHLIGHT);
+            }
+        }
+
+        let comparable = (a_width, a_height) == (b_width, b_height);
+
+        for (machine, left) in [(a, 0), (b, a_width + GAP)] {
+            let (machine_width, _) = machine.resolution();
+
+            for (index, &lit) in machine.frame_buffer().iter().enumerate() {
+                let (x, y) = (index % machine_width, index / machine_width);
+
+                let differs = diverged && comparable && a.frame_buffer()[index] != b.frame_buffer()[index];
+                frame[y * width + left + x] = if differs { HIGHLIGHT } else { self.palette[lit as usize] };
+            }
+        }
+
+        (frame, width, height)
+    }
+}
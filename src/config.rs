@@ -1,20 +1,99 @@
-use std::rc::Rc;
+use std::{error::Error, fmt, rc::Rc, str::FromStr};
 
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 
-use crate::devices::Key;
+use crate::{archive, devices::Key, postprocess::Stage, watches::Watch};
 
+// Settings of the interpreter core, which live alongside it so it builds without std
+pub use crate::{
+    cpu::{MachineCalls, QuirksConfig, RandomModel, VblankModel},
+    instructions::Profile,
+    memory::MemoryConfig,
+};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    // Flags given again override, such as those from a ROM's settings file
+    args_override_self = true,
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Without a subcommand, e.g. `chip-eight rom.ch8`, the arguments are for `run`
+    #[command(flatten)]
+    pub run: Args,
+}
+
+// Arguments to run a ROM with, which also make up a Config.
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(args_override_self = true)]
 pub struct Args {
-    /// Path to a ROM file.
-    pub rom_path: String,
+    /// Path to a ROM file, which may be zipped or gzipped. When omitted, a menu of the ROMs in the ROM directory is shown.
+    pub rom_path: Option<String>,
+
+    /// File in a zipped ROM path to run, in place of the first ROM in it.
+    #[arg(long, value_name = "NAME")]
+    pub member: Option<String>,
+
+    /// Directory listed by the ROM menu. Navigate with keys 2 and 8, and select with 5.
+    #[arg(short, long, default_value = "roms")]
+    pub rom_dir: String,
+
+    /// Reset and reload the ROM whenever its file changes, such as when an assembler rebuilds it.
+    #[arg(long)]
+    pub watch: bool,
 
+    /// Start paused in a debugger taking commands on stdin. Enter `help` at its prompt for the commands.
+    #[arg(long)]
+    pub debug: bool,
 
-    /// Number of instruction to process per second.
+    /// Symbol file labelling addresses in the ROM, used when logging addresses. Octo `.8o` sources provide their own.
+    #[arg(long)]
+    pub symbols: Option<String>,
+
+    /// Patch written over the ROM once loaded: an IPS file, or text with one "2A4:FF" address and byte per line.
+    #[arg(long, value_name = "PATH")]
+    pub patch: Option<String>,
+
+    /// Directory for settings and saved data, in place of ~/.config/chip-eight and ~/.local/share/chip-eight. Per-ROM flags are read from roms/<sha1>.args within it.
+    #[arg(long, value_name = "PATH")]
+    pub data_dir: Option<String>,
+
+    /// Carry on from where the ROM was left last time, rather than booting it afresh, and save where it is left on shutdown.
+    #[arg(long, conflicts_with_all = ["host", "join"])]
+    pub resume: bool,
+
+    /// Record the key presses of the run to this file, to play back with `chip-eight replay`.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["host", "join", "debug", "resume"])]
+    pub record: Option<String>,
+
+    /// Report on exit which of the ROM's instructions the run executed, out of those reachable from the entry point: as a summary with the blocks never executed, or as that and a disassembly marking executed instructions + and the rest - (listing).
+    #[arg(long, value_enum, value_name = "REPORT", num_args = 0..=1, require_equals = true, default_missing_value = "summary")]
+    pub coverage: Option<CoverageOutput>,
+
+
+    /// Number of instruction to process per second, or 0 to run as fast as possible.
     #[arg(short, long, default_value_t = 600)]
     pub clock_speed: u64,
 
+    /// Run as fast as possible, without pacing. Shorthand for `--clock-speed 0`.
+    #[arg(long)]
+    pub unlimited: bool,
+
+    /// Run every cycle of loops that wait on the delay timer (FX07, then a skip on vX, then a jump back), rather than skipping to the display interrupt that changes it. Skipping leaves the program running exactly as it would have, but for hooks and the debugger, which see every cycle anyway.
+    #[arg(long = "no-skip-delay-waits", action = clap::ArgAction::SetFalse)]
+    pub skip_delay_waits: bool,
+
+    /// Machine to emulate. MegaChip adds a 256x192 colour mode, digitised sound and 24-bit addressing. CHIP-8X adds colour zones and starts programs at 0x300 by default.
+    #[arg(long, value_enum, default_value_t = Profile::Chip8)]
+    pub profile: Profile,
+
     /// Skip setting vF to zero after executing opcodes 8XY1, 8XY2 and 8XY3.
     #[arg(short = 'v', long)]
     pub skip_reset_vf: bool,
@@ -23,14 +102,26 @@ pub struct Args {
     #[arg(short = 'n', long)]
     pub preserve_index: bool,
 
-    /// Opcode DXYN draws sprite immediately, instead of waiting for the display interrupt.
+    /// Opcode DXYN draws sprite immediately, instead of waiting for the display interrupt. Shorthand for `--vblank-model none`.
     #[arg(short = 'w', long)]
     pub skip_draw_wait: bool,
 
-    /// Wrap sprites drawn at the edge of the display.
+    /// Which sprite draws wait for the display interrupt: all of them (vip), only those in low resolution (schip), or none.
+    #[arg(long, value_enum, default_value_t = VblankModel::Vip)]
+    pub vblank_model: VblankModel,
+
+    /// Wrap sprites drawn at the edge of the display. Shorthand for `--wrap-x --wrap-y`.
     #[arg(short = 'l', long)]
     pub wrap_sprites: bool,
 
+    /// Wrap sprites drawn over the left or right edge of the display, instead of clipping them.
+    #[arg(long)]
+    pub wrap_x: bool,
+
+    /// Wrap sprites drawn over the top or bottom edge of the display, instead of clipping them.
+    #[arg(long)]
+    pub wrap_y: bool,
+
     /// Skip setting vX to vY before shifting vX for opcodes 8XY6 and 8XYE.
     #[arg(short, long)]
     pub skip_shift_set: bool,
@@ -39,11 +130,35 @@ pub struct Args {
     #[arg(short, long)]
     pub jump_with_vx: bool,
 
+    /// Set vF to 1 when opcode FX1E moves the index register past the end of memory (0xFFF by default), and to 0 otherwise.
+    #[arg(long)]
+    pub index_overflow_flag: bool,
+
+    /// In hi-res, set vF to the number of sprite rows that collided or were clipped off the bottom of the display, as SUPER-CHIP 1.1 does, rather than to 1 on any collision.
+    #[arg(long)]
+    pub collision_count: bool,
+
+    /// How to handle 0NNN calls to machine code routines, which can't be emulated: skip them (ignore), break into the debugger on them, or stop when not debugging (trap), or fail (error).
+    #[arg(long, value_enum, default_value_t = MachineCalls::Ignore)]
+    pub machine_calls: MachineCalls,
+
+    /// Where CXNN's random numbers come from: the machine's random number generator (modern), or the COSMAC VIP interpreter's routine (vip), which works them out from a seed counted up at each display interrupt and the bytes of memory at 0x100 to 0x1FF, as a few ROMs rely on.
+    #[arg(long, value_enum, default_value_t = RandomModel::Modern)]
+    pub rng: RandomModel,
 
-    /// Size of memory in bytes.
-    #[arg(short, long, default_value_t = 0x1000)]
+    /// Stop cleanly when the program reaches this opcode, in hex, as test ROMs do to signal they have finished. Given without a value (e.g. `--exit-opcode` rather than `--exit-opcode=00FD`), 0000 is the exit opcode.
+    #[arg(long, value_name = "OPCODE", num_args = 0..=1, require_equals = true, default_missing_value = "0000", value_parser = parse_opcode)]
+    pub exit_opcode: Option<u16>,
+
+
+    /// Size of memory in bytes, up to the 64K (65536) XO-CHIP programs address. MegaChip always has 16MB, allocated as programs use it.
+    #[arg(short, long, default_value_t = 0x1000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=0x10000))]
     pub memory_length: usize,
 
+    /// Fail writes below the program start address, where the interpreter and fonts live, instead of letting the program overwrite them.
+    #[arg(long)]
+    pub protect_low_memory: bool,
+
     /// Memory address of the first intruction of the loaded program.
     #[arg(short, long, default_value_t = 0x200)]
     pub program_start: usize,
@@ -52,8 +167,12 @@ pub struct Args {
     #[arg(short = 'o', long, default_value_t = 0x50)]
     pub font_start: usize,
 
+    /// Memory address of the first byte of the large (8x10) font.
+    #[arg(short, long, default_value_t = 0xA0)]
+    pub big_font_start: usize,
+
 
-    /// Display engine.
+    /// Display engine. When it can't be opened, such as without a display server, SDL3 falls back to the terminal and the terminal to none.
     #[arg(short, long, value_enum, default_value_t = DisplayEngine::SDL3)]
     pub display_engine: DisplayEngine,
 
@@ -69,19 +188,677 @@ pub struct Args {
     #[arg(short = 'f', long, default_value_t = 20)]
     pub scale_factor: usize,
 
+    /// Rotate the display clockwise by this many degrees, for screens mounted on their side or upside down.
+    #[arg(long, value_enum, default_value_t = Rotation::None)]
+    pub rotate: Rotation,
+
+    /// Mirror the display left to right, after rotating it.
+    #[arg(long)]
+    pub flip_x: bool,
+
+    /// Mirror the display top to bottom, after rotating it.
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Colours of unlit and lit pixels, as hex RGB separated by a comma, or a theme: classic, paper, amber, green, lcd or octo.
+    #[arg(long, value_name = "OFF,ON", default_value = "000000,FFFFFF")]
+    pub colors: Colors,
+
+    /// Effects to pass frames through before they're shown, in order, separated by commas: phosphor[:DECAY] fades pixels out over the following frames, scanlines[:STRENGTH] darkens every other line, and gamma:GAMMA brightens or darkens midtones. Needs a display that draws colour, such as the SDL3 window.
+    #[arg(long, value_name = "STAGES", value_delimiter = ',')]
+    pub post_process: Vec<Stage>,
 
-    /// Audio engine.
+
+    /// Audio engine. When it can't be opened, such as without an audio device, SDL3 falls back to none.
     #[arg(short, long, value_enum, default_value_t = AudioEngine::SDL3)]
     pub audio_engine: AudioEngine,
 
+    /// Master volume, as a percentage.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: u8,
+
+    /// Start with sound muted. Toggle it while running with M.
+    #[arg(long)]
+    pub mute: bool,
 
-    /// Input engine.
+
+    /// Input engine. When it can't be opened, SDL3 falls back to none.
     #[arg(short, long, value_enum, default_value_t = InputEngine::SDL3)]
     pub input_engine: InputEngine,
+
+    /// List the display, audio and input engines, and whether each can be opened here, then exit.
+    #[arg(long)]
+    pub list_engines: bool,
+
+    /// Keyboard keys to bind the keypad to: default puts it on 1234, QWER, ASDF and ZXCV, and p1p2 splits it down the middle for two players on one keyboard, the left two columns on 12, QW, AS and ZX, and the right two on 90, OP, L; and ./, with the up and down arrows also pressing C and D.
+    #[arg(long, value_enum, default_value_t = InputProfile::Default)]
+    pub input_profile: InputProfile,
+
+    /// Keyboard keys for the keypad's left two columns, 1 2 4 5 7 8 A 0 in that order, separated by commas, in place of the profile's.
+    #[arg(long, value_name = "KEYS")]
+    pub player1_keys: Option<PlayerKeys>,
+
+    /// Keyboard keys for the keypad's right two columns, 3 C 6 D 9 E B F in that order, separated by commas, in place of the profile's.
+    #[arg(long, value_name = "KEYS")]
+    pub player2_keys: Option<PlayerKeys>,
+
+    /// Pause the machine, timers included, while the window is out of focus, such as when switching to another window.
+    #[arg(long)]
+    pub pause_on_focus_loss: bool,
+
+
+    /// Stream frames to, and accept key events from, WebSocket clients on this address (e.g. ":8080"), instead of using the display and input engines.
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<String>,
+
+    /// Wait for another player to join on this address (e.g. ":7800"), then play in lockstep, sharing the keypad.
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["join", "debug"])]
+    pub host: Option<String>,
+
+    /// Join a player hosting on this address, sharing the keypad. Both must run the same ROM at the same clock speed.
+    #[arg(long, value_name = "ADDR", conflicts_with = "debug")]
+    pub join: Option<String>,
+
+    /// Lua script to run alongside the program, with callbacks on each frame or instruction that can read and change the machine.
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["host", "join", "debug", "record"])]
+    pub script: Option<String>,
+
+
+    /// Stop after a number of instruction cycles, or of seconds with an "s" suffix (e.g. "5000" or "2.5s"), and exit successfully.
+    #[arg(long, value_name = "CYCLES|SECONDS")]
+    pub exit_after: Option<ExitAfter>,
+
+    /// Stop once the frame has stayed the same for this many frames in a row, after something has been drawn, and print it.
+    #[arg(long, value_name = "FRAMES")]
+    pub exit_when_settled: Option<u64>,
+
+    /// Show the instructions per second achieved over the display, and report how closely the run kept to real time on exit.
+    #[arg(long)]
+    pub timing_stats: bool,
+
+    /// Show a register or byte of memory over the display, updated every frame: v0 to vF, i, pc, dt, st, or mem[ADDR] such as mem[0x3A0]. Add :plot to also plot its recent values. Separate several with commas.
+    #[arg(long, value_name = "EXPR[:plot]", value_delimiter = ',')]
+    pub watch_expr: Vec<Watch>,
+
+    /// Show a speaker in the corner of the display while the tone plays, with the XO-CHIP audio pattern beside it.
+    #[arg(long)]
+    pub sound_overlay: bool,
+
+    /// Show a map of memory beside the display, updated every frame, lighting each byte blue when the program reads it, red when it writes it and green when it executes it, brighter the more often.
+    #[arg(long)]
+    pub heatmap: bool,
+
+    /// SHA-1 of the final frame, in hex, to check on exit. Exits with a failure status when it doesn't match.
+    #[arg(long, value_name = "HEX")]
+    pub expect_framebuffer_hash: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM. `chip-eight rom.ch8` is shorthand for `chip-eight run rom.ch8`.
+    Run(Args),
+
+    /// List a ROM's instructions, with the bytes code never reaches shown as data.
+    Disasm {
+        /// Path to a ROM file.
+        rom_path: String,
+
+        /// Memory address the ROM is loaded at.
+        #[arg(short, long, default_value_t = 0x200)]
+        program_start: usize,
+
+        /// Machine whose instruction set to decode with.
+        #[arg(long, value_enum, default_value_t = Profile::Chip8)]
+        profile: Profile,
+
+        /// Symbol file labelling addresses in the ROM. Octo `.8o` sources provide their own.
+        #[arg(long)]
+        symbols: Option<String>,
+    },
+
+    /// Assemble an Octo source file into a ROM.
+    Asm {
+        /// Path to an Octo `.8o` source file.
+        source_path: String,
+
+        /// Path to write the ROM to, in place of the source path with a `.ch8` extension.
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<String>,
+
+        /// Memory address the ROM is loaded at.
+        #[arg(short, long, default_value_t = 0x200)]
+        program_start: usize,
+
+        /// Also write the labels to this symbol file, for `--symbols`.
+        #[arg(long, value_name = "PATH")]
+        symbols: Option<String>,
+    },
+
+    /// Print metadata about a ROM, gathered by statically scanning its code.
+    Info {
+        /// Path to a ROM file.
+        rom_path: String,
+
+        /// Memory address the ROM is loaded at.
+        #[arg(short, long, default_value_t = 0x200)]
+        program_start: usize,
+
+        /// Machine whose instruction set to decode with.
+        #[arg(long, value_enum, default_value_t = Profile::Chip8)]
+        profile: Profile,
+    },
+
+    /// Walk the code reachable from the entry point and report suspicious patterns.
+    Lint {
+        /// Path to a ROM file.
+        rom_path: String,
+
+        /// Memory address the ROM is loaded at.
+        #[arg(short, long, default_value_t = 0x200)]
+        program_start: usize,
+
+        /// Size of memory in bytes, from 512 (0x200) up to the 64K the profile addresses, or 16MB for MegaChip.
+        #[arg(short, long, default_value_t = 0x1000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(0x200..=0x1000000))]
+        memory_length: usize,
+
+        /// Machine whose instruction set to decode with.
+        #[arg(long, value_enum, default_value_t = Profile::Chip8)]
+        profile: Profile,
+
+        /// Symbol file labelling addresses in the ROM, used in place of addresses in findings.
+        #[arg(long)]
+        symbols: Option<String>,
+    },
+
+    /// Run each ROM in a directory of test ROMs headless, printing its final frame.
+    TestSuite {
+        /// Directory of test ROMs.
+        #[arg(default_value = "roms/tests")]
+        dir: String,
+
+        /// Number of cycles to run each ROM for, unless it reaches the exit opcode first.
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+
+        /// Stop each ROM early once its frame has stayed the same for this many frames in a row.
+        #[arg(long, value_name = "FRAMES")]
+        settle: Option<u64>,
+
+        /// Flags to run the ROMs with, such as quirks, given after `--`.
+        #[arg(last = true)]
+        flags: Vec<String>,
+    },
+
+    /// Run every ROM in a directory headless, several at once, reporting how each run ended.
+    Batch {
+        /// Directory of ROMs.
+        dir: String,
+
+        /// Number of cycles to run each ROM for, unless it exits, faults or settles first.
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+
+        /// Stop each ROM early once its frame has stayed the same for this many frames in a row.
+        #[arg(long, value_name = "FRAMES", default_value_t = 60)]
+        settle: u64,
+
+        /// Number of ROMs to run at once, one per processor by default.
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Path to write the results to, as JSON.
+        #[arg(short, long, value_name = "PATH")]
+        out: Option<String>,
+
+        /// Flags to run the ROMs with, such as quirks, given after `--`.
+        #[arg(last = true)]
+        flags: Vec<String>,
+    },
+
+    /// Browse and run programs from the public CHIP-8 program archive, downloading them on first use.
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveCommand,
+
+        /// Base URL of the archive, for a mirror. file:// URLs read one on disk.
+        #[arg(long, global = true, default_value = archive::DEFAULT_URL)]
+        url: String,
+
+        /// Directory for settings and saved data, in place of the XDG directories. Downloads are kept in its archive directory.
+        #[arg(long, global = true, value_name = "PATH")]
+        data_dir: Option<String>,
+
+        /// Download the archive's catalogue again, rather than using the copy kept from last time.
+        #[arg(long, global = true)]
+        refresh: bool,
+    },
+
+    /// Play back the key presses recorded with `run --record`, on the same ROM.
+    Replay {
+        /// Path to a replay file.
+        replay_path: String,
+
+        #[command(flatten)]
+        args: Args,
+    },
+
+    /// Run two ROMs side by side in one window, both given the same key presses, such as two versions of a homebrew ROM.
+    Compare {
+        /// Path to the ROM shown on the left.
+        a_path: String,
+
+        /// Path to the ROM shown on the right.
+        b_path: String,
+
+        /// Give both the same random numbers too, and highlight the pixels that differ from the first frame the two diverge on.
+        #[arg(long)]
+        lockstep: bool,
+
+        /// Flags to run both ROMs with, such as quirks, given after `--`.
+        #[arg(last = true)]
+        flags: Vec<String>,
+    },
+
+    /// Run a ROM headless under two sets of flags, or against a trace, printing the first cycle the runs diverge on.
+    Diverge {
+        /// Path to a ROM file.
+        rom_path: String,
+
+        /// Flags of the first run, such as quirks, quoted as one argument.
+        #[arg(long, value_name = "FLAGS", allow_hyphen_values = true, default_value = "")]
+        flags_a: String,
+
+        /// Flags of the second run, quoted as one argument (e.g. "--skip-shift-set").
+        #[arg(long, value_name = "FLAGS", allow_hyphen_values = true, default_value = "", conflicts_with = "trace")]
+        flags_b: String,
+
+        /// Trace to compare the first run against, in place of a second run, as written by --write-trace.
+        #[arg(long, value_name = "PATH")]
+        trace: Option<String>,
+
+        /// Write a trace of the first run to this path instead of comparing, one line per instruction executed.
+        #[arg(long, value_name = "PATH", conflicts_with = "trace")]
+        write_trace: Option<String>,
+
+        /// Number of cycles to run for, unless the runs diverge or exit first.
+        #[arg(long, default_value_t = 100_000)]
+        cycles: u64,
+    },
+
+    /// Stream a ROM to WebSocket clients, which send its key events. Shorthand for `run --serve`.
+    Serve {
+        /// Address to listen on (e.g. ":8080").
+        addr: String,
+
+        #[command(flatten)]
+        args: Args,
+    },
+
+    /// List the save states kept for a ROM, or delete one.
+    States {
+        /// Path to a ROM file.
+        rom_path: String,
+
+        /// Memory address the ROM is loaded at.
+        #[arg(short, long, default_value_t = 0x200)]
+        program_start: usize,
+
+        /// Directory for settings and saved data, in place of the XDG directories.
+        #[arg(long, value_name = "PATH")]
+        data_dir: Option<String>,
+
+        /// Delete the state saved in this slot.
+        #[arg(long, value_name = "SLOT")]
+        delete: Option<u8>,
+    },
+
+    /// Inspect save state files.
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+
+    /// Print what differs between two save states, such as of two builds run to the same cycle count.
+    DiffState {
+        /// Path to the first save state.
+        a_path: String,
+
+        /// Path to the second save state.
+        b_path: String,
+    },
+
+    /// Print a completion script for a shell, to source from its startup file.
+    Completions {
+        shell: Shell,
+    },
+
+    /// Write a config file of every setting commented out at its default, to edit.
+    InitConfig {
+        /// Directory for settings and saved data, in place of the XDG directories.
+        #[arg(long, value_name = "PATH")]
+        data_dir: Option<String>,
+
+        /// Replace the config file if there already is one.
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StateCommand {
+    /// Print a save state's format version, chunks, registers and quirks.
+    Info {
+        /// Path to a save state.
+        path: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ArchiveCommand {
+    /// List every program in the archive.
+    List,
+
+    /// List the programs whose name, title, authors, event or description contain the text given.
+    Search {
+        query: String,
+    },
+
+    /// Run a program with the platform, quirks, speed and colours the archive gives for it.
+    Run {
+        /// Name of the program, as listed.
+        name: String,
+
+        /// Flags to run the program with, overriding the archive's, given after `--`.
+        #[arg(last = true)]
+        flags: Vec<String>,
+    },
+}
+
+// Point at which a run stops by itself
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExitAfter {
+    Cycles(u64),
+    Seconds(f64),
+}
+
+// Colours of unlit and lit pixels, in that order
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Colors(pub [(u8, u8, u8); 2]);
+
+// Named colours, which the theme hotkey cycles through in this order
+pub const THEMES: [(&str, Colors); 6] = [
+    ("classic", Colors([(0x00, 0x00, 0x00), (0xFF, 0xFF, 0xFF)])),
+    ("paper", Colors([(0xFF, 0xFF, 0xFF), (0x00, 0x00, 0x00)])),
+    ("amber", Colors([(0x1A, 0x0F, 0x00), (0xFF, 0xB0, 0x00)])),
+    ("green", Colors([(0x00, 0x1A, 0x00), (0x33, 0xFF, 0x33)])),
+    ("lcd", Colors([(0x9B, 0xBC, 0x0F), (0x0F, 0x38, 0x0F)])),
+    ("octo", Colors([(0x99, 0x66, 0x00), (0xFF, 0xCC, 0x00)])),
+];
+
+impl Colors {
+    // Name of the theme with these colours, if there is one.
+    pub fn theme(self) -> Option<&'static str> {
+        THEMES.iter().find(|(_, colors)| *colors == self).map(|(name, _)| *name)
+    }
+
+    // Colours of the theme after this one, or the first theme for colours of no theme.
+    pub fn next_theme(self) -> (&'static str, Colors) {
+        let next = THEMES.iter().position(|(_, colors)| *colors == self).map_or(0, |index| (index + 1) % THEMES.len());
+        THEMES[next]
+    }
+}
+
+impl FromStr for Colors {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some((_, colors)) = THEMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(value.trim())) {
+            return Ok(*colors);
+        }
+
+        let color = |hex: &str| {
+            let digits = hex.trim().trim_start_matches('#');
+            let rgb = u32::from_str_radix(digits, 16).ok().filter(|_| digits.len() == 6);
+
+            rgb.map(|rgb| ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+                .ok_or_else(|| format!("invalid colour: {}", hex))
+        };
+
+        match value.split_once(',') {
+            Some((off, on)) => Ok(Self([color(off)?, color(on)?])),
+            None => Err(format!("expected two colours separated by a comma, or a theme: {}", value)),
+        }
+    }
+}
+
+// Opcode in hex, with or without a 0x prefix
+fn parse_opcode(value: &str) -> Result<u16, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid opcode: {}", value))
+}
+
+impl FromStr for ExitAfter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_suffix('s') {
+            Some(seconds) => seconds.parse()
+                .ok()
+                .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+                .map(Self::Seconds)
+                .ok_or_else(|| format!("invalid number of seconds: {}", seconds)),
+            None => value.parse()
+                .map(Self::Cycles)
+                .map_err(|_| format!("invalid number of cycles: {}", value)),
+        }
+    }
+}
+
+impl QuirksConfig {
+    // Names the quirks are changed by at runtime, as the fields name them. Words can
+    // also be separated with dashes, as the command line flags do.
+    pub const NAMES: [&str; 11] = [
+        "skip_reset_vf", "preserve_index", "vblank_model", "wrap_x", "wrap_y", "skip_shift_set",
+        "jump_with_vx", "index_overflow_flag", "collision_count", "machine_calls", "rng",
+    ];
+
+    // Value of a quirk by name: on or off, or the name of the model in use.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let flag = |set: bool| if set { "on" } else { "off" }.to_string();
+
+        Some(match name.replace('-', "_").as_str() {
+            "skip_reset_vf" => flag(self.skip_reset_vf),
+            "preserve_index" => flag(self.preserve_index),
+            "vblank_model" => value_name(&self.vblank_model),
+            "wrap_x" => flag(self.wrap_x),
+            "wrap_y" => flag(self.wrap_y),
+            "skip_shift_set" => flag(self.skip_shift_set),
+            "jump_with_vx" => flag(self.jump_with_vx),
+            "index_overflow_flag" => flag(self.index_overflow_flag),
+            "collision_count" => flag(self.collision_count),
+            "machine_calls" => value_name(&self.machine_calls),
+            "rng" => value_name(&self.rng),
+            _ => return None,
+        })
+    }
+
+    // Set a quirk by name: on/off, true/false or 1/0, or the name of a model as the
+    // command line takes it.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), QuirkError> {
+        let name = name.replace('-', "_");
+
+        let flag = || match value.to_ascii_lowercase().as_str() {
+            "on" | "true" | "1" => Ok(true),
+            "off" | "false" | "0" => Ok(false),
+            _ => Err(QuirkError::Value(name.clone(), value.to_string(), "on or off".to_string())),
+        };
+
+        match name.as_str() {
+            "skip_reset_vf" => self.skip_reset_vf = flag()?,
+            "preserve_index" => self.preserve_index = flag()?,
+            "vblank_model" => self.vblank_model = model(&name, value)?,
+            "wrap_x" => self.wrap_x = flag()?,
+            "wrap_y" => self.wrap_y = flag()?,
+            "skip_shift_set" => self.skip_shift_set = flag()?,
+            "jump_with_vx" => self.jump_with_vx = flag()?,
+            "index_overflow_flag" => self.index_overflow_flag = flag()?,
+            "collision_count" => self.collision_count = flag()?,
+            "machine_calls" => self.machine_calls = model(&name, value)?,
+            "rng" => self.rng = model(&name, value)?,
+            _ => return Err(QuirkError::Unknown(name)),
+        }
+
+        Ok(())
+    }
+}
+
+// Name a model is given on the command line.
+fn value_name(value: &impl ValueEnum) -> String {
+    value.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
+
+fn model<T: ValueEnum>(name: &str, value: &str) -> Result<T, QuirkError> {
+    T::from_str(value, true).map_err(|_| {
+        let names: Vec<String> = T::value_variants().iter().map(value_name).collect();
+        QuirkError::Value(name.to_string(), value.to_string(), names.join(", "))
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QuirkError {
+    // Name that isn't a quirk's
+    Unknown(String),
+    // Quirk, the value it couldn't be set to, and the values it can be
+    Value(String, String, String),
+}
+
+impl fmt::Display for QuirkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuirkError::Unknown(name) => write!(f, "no quirk named {}, expected one of {}", name, QuirksConfig::NAMES.join(", ")),
+            QuirkError::Value(name, value, expected) => write!(f, "{} can't be {}, expected {}", name, value, expected),
+        }
+    }
+}
+
+impl Error for QuirkError {}
+
+// Flags for a single run, which the config file leaves out.
+const PER_RUN_FLAGS: [&str; 16] = [
+    "member", "watch", "debug", "symbols", "patch", "data_dir", "record", "coverage", "serve",
+    "host", "join", "script", "exit_after", "exit_when_settled", "expect_framebuffer_hash",
+    "list_engines",
+];
+
+// Width the config file's comments wrap at.
+const CONFIG_FILE_WIDTH: usize = 80;
+
+#[derive(Debug)]
+pub enum ConfigFileError {
+    Toml(toml::de::Error),
+    // Key whose value isn't a string, number or boolean
+    Value(String),
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFileError::Toml(error) => write!(f, "{}", error),
+            ConfigFileError::Value(key) => write!(f, "{}: expected a string, number or boolean", key),
+        }
+    }
+}
+
+impl Error for ConfigFileError {}
+
+// Command line flags from a TOML config file, whose keys are the long names of
+// flags, such as `clock-speed = 900` for `--clock-speed=900`. True booleans give
+// the flag alone, and false ones leave it out.
+pub fn config_file_flags(source: &str) -> Result<Vec<String>, ConfigFileError> {
+    let table: toml::Table = source.parse().map_err(ConfigFileError::Toml)?;
+
+    table.into_iter()
+        .filter(|(_, value)| value.as_bool() != Some(false))
+        .map(|(key, value)| match value {
+            toml::Value::Boolean(_) => Ok(format!("--{}", key)),
+            toml::Value::String(value) => Ok(format!("--{}={}", key, value)),
+            toml::Value::Integer(value) => Ok(format!("--{}={}", key, value)),
+            toml::Value::Float(value) => Ok(format!("--{}={}", key, value)),
+            _ => Err(ConfigFileError::Value(key)),
+        })
+        .collect()
+}
+
+// A config file with every setting commented out at its default, and its help
+// as a comment above it.
+pub fn default_config_file() -> String {
+    let mut text = String::from(
+        "# Settings for every run, which per-ROM settings and command line flags override.\n\
+         # Uncomment a setting to change it from the default shown.\n",
+    );
+
+    let command = Args::command();
+    let settings = command.get_arguments()
+        .filter(|arg| arg.get_long().is_some() && !PER_RUN_FLAGS.contains(&arg.get_id().as_str()));
+
+    for arg in settings {
+        text.push('\n');
+
+        let help = arg.get_help().map(|help| help.to_string()).unwrap_or_default();
+        let possible: Vec<String> = arg.get_possible_values().iter().map(|value| value.get_name().to_string()).collect();
+        let help = if possible.is_empty() || !arg.get_action().takes_values() {
+            help
+        } else {
+            format!("{}. One of: {}.", help.trim_end_matches('.'), possible.join(", "))
+        };
+
+        let mut line = String::from("#");
+        for word in help.split_whitespace() {
+            if line.len() + word.len() + 1 > CONFIG_FILE_WIDTH {
+                text.push_str(&line);
+                text.push('\n');
+                line = String::from("#");
+            }
+
+            line.push(' ');
+            line.push_str(word);
+        }
+        text.push_str(&line);
+        text.push('\n');
+
+        let value = if !arg.get_action().takes_values() {
+            "false".to_string()
+        } else {
+            // Settings without a default show what they take instead
+            let value = arg.get_default_values().first()
+                .map(|value| value.to_string_lossy().into_owned())
+                .or_else(|| arg.get_value_names().and_then(|names| names.first()).map(|name| format!("<{}>", name)))
+                .unwrap_or_default();
+
+            match value.parse::<i64>() {
+                Ok(_) => value,
+                Err(_) => format!("{:?}", value),
+            }
+        };
+
+        text.push_str(&format!("# {} = {}\n", arg.get_long().unwrap(), value));
+    }
+
+    text
 }
 
 pub struct Config {
     pub clock_speed: u64,
+    pub skip_delay_waits: bool,
+    pub exit_after: Option<ExitAfter>,
+    pub exit_when_settled: Option<u64>,
+    pub exit_opcode: Option<u16>,
+    pub timing_stats: bool,
+    pub watches: Vec<Watch>,
+    pub sound_overlay: bool,
+    pub profile: Profile,
     pub quirks: QuirksConfig,
     pub memory: Rc<MemoryConfig>,
     pub display: Rc<DisplayConfig>,
@@ -89,26 +866,44 @@ pub struct Config {
     pub input: Rc<InputConfig>,
 }
 
-pub struct QuirksConfig {
-    pub skip_reset_vf: bool,
-    pub preserve_index: bool,
-    pub skip_draw_wait: bool,
-    pub wrap_sprites: bool,
-    pub skip_shift_set: bool,
-    pub jump_with_vx: bool,
-}
-
-pub struct MemoryConfig {
-    pub length: usize,
-    pub program_start: usize,
-    pub font_start: usize,
-    pub default_font: [u8; 80],
+// How --coverage reports the instructions executed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum CoverageOutput {
+    Summary,
+    // The summary followed by an annotated disassembly
+    Listing,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum DisplayEngine {
     SDL3,
+    // Frames drawn as coloured text in the terminal
+    Terminal,
+    None,
+}
+
+impl DisplayEngine {
+    // Engine tried next when this one fails to open.
+    pub fn fallback(self) -> Option<Self> {
+        match self {
+            DisplayEngine::SDL3 => Some(DisplayEngine::Terminal),
+            DisplayEngine::Terminal => Some(DisplayEngine::None),
+            DisplayEngine::None => None,
+        }
+    }
+}
+
+// Clockwise rotation of the display, in degrees.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum Rotation {
+    #[value(name = "0")]
     None,
+    #[value(name = "90")]
+    Quarter,
+    #[value(name = "180")]
+    Half,
+    #[value(name = "270")]
+    ThreeQuarters,
 }
 
 pub struct DisplayConfig {
@@ -117,17 +912,52 @@ pub struct DisplayConfig {
     pub height: usize,
     pub scale_factor: usize,
     pub colors: [(u8, u8, u8); 2],
+    pub post_process: Vec<Stage>,
+    pub rotation: Rotation,
+    pub flip_x: bool,
+    pub flip_y: bool,
 }
 
 impl DisplayConfig {
-    // Width in device pixels
+    // Width of the screen in device pixels
     pub fn scaled_width(&self) -> usize {
-        self.width * self.scale_factor
+        self.oriented_size(self.width, self.height).0 * self.scale_factor
     }
 
-    // Height in device pixels
+    // Height of the screen in device pixels
     pub fn scaled_height(&self) -> usize {
-        self.height * self.scale_factor
+        self.oriented_size(self.width, self.height).1 * self.scale_factor
+    }
+
+    // Whether frames are shown as the program draws them, without rotating or
+    // flipping them
+    pub fn upright(&self) -> bool {
+        self.rotation == Rotation::None && !self.flip_x && !self.flip_y
+    }
+
+    // Resolution on screen of frames of the given resolution
+    pub fn oriented_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.rotation {
+            Rotation::Quarter | Rotation::ThreeQuarters => (height, width),
+            Rotation::None | Rotation::Half => (width, height),
+        }
+    }
+
+    // Where a pixel of a frame of the given resolution ends up on screen, after
+    // rotating and then flipping it.
+    pub fn orient(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (height - 1 - y, x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (y, width - 1 - x),
+        };
+
+        let (screen_width, screen_height) = self.oriented_size(width, height);
+        let x = if self.flip_x { screen_width - 1 - x } else { x };
+        let y = if self.flip_y { screen_height - 1 - y } else { y };
+
+        (x, y)
     }
 }
 
@@ -137,8 +967,21 @@ pub enum AudioEngine {
     None,
 }
 
+impl AudioEngine {
+    // Engine tried next when this one fails to open.
+    pub fn fallback(self) -> Option<Self> {
+        match self {
+            AudioEngine::SDL3 => Some(AudioEngine::None),
+            AudioEngine::None => None,
+        }
+    }
+}
+
 pub struct AudioConfig {
     pub engine: AudioEngine,
+    // Master volume as a percentage, from 0 to 100
+    pub volume: u8,
+    pub muted: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -147,26 +990,173 @@ pub enum InputEngine {
     None,
 }
 
+impl InputEngine {
+    // Engine tried next when this one fails to open.
+    pub fn fallback(self) -> Option<Self> {
+        match self {
+            InputEngine::SDL3 => Some(InputEngine::None),
+            InputEngine::None => None,
+        }
+    }
+}
+
+// Keyboard keys the keypad is bound to, by SDL scancode name.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum InputProfile {
+    // The keypad's shape on the left of the keyboard
+    Default,
+    // The keypad split between two players, so neither reaches across the other
+    // and fewer keys held at once share a row, which cheap keyboards ghost on
+    #[value(name = "p1p2")]
+    P1p2,
+}
+
+// Keypad keys of each player, the left two columns and the right two, row by row
+pub const PLAYER1_PAD: [Key; 8] = [Key::_1, Key::_2, Key::_4, Key::_5, Key::_7, Key::_8, Key::A, Key::_0];
+pub const PLAYER2_PAD: [Key; 8] = [Key::_3, Key::C, Key::_6, Key::D, Key::_9, Key::E, Key::B, Key::F];
+
+const DEFAULT_KEYS: [(Key, &str); 16] = [
+    (Key::_0, "X"),
+    (Key::_1, "1"),
+    (Key::_2, "2"),
+    (Key::_3, "3"),
+    (Key::_4, "Q"),
+    (Key::_5, "W"),
+    (Key::_6, "E"),
+    (Key::_7, "A"),
+    (Key::_8, "S"),
+    (Key::_9, "D"),
+    (Key::A, "Z"),
+    (Key::B, "C"),
+    (Key::C, "4"),
+    (Key::D, "R"),
+    (Key::E, "F"),
+    (Key::F, "V"),
+];
+
+// Keys of each player with the p1p2 profile, in the order of their keypad keys
+const PLAYER1_KEYS: [&str; 8] = ["1", "2", "Q", "W", "A", "S", "Z", "X"];
+const PLAYER2_KEYS: [&str; 8] = ["9", "0", "O", "P", "L", ";", ".", "/"];
+
+// Player 2's paddle keys in two-player games such as PONG, on the arrow keys too
+const PLAYER2_ARROWS: [(Key, &str); 2] = [(Key::C, "Up"), (Key::D, "Down")];
+
+impl InputProfile {
+    // Key map of the profile, with either player's keys replaced when given.
+    pub fn key_map(self, player1: Option<&PlayerKeys>, player2: Option<&PlayerKeys>) -> Vec<(Key, String)> {
+        let mut key_map: Vec<(Key, String)> = match self {
+            InputProfile::Default => DEFAULT_KEYS.iter().map(|&(key, name)| (key, name.to_string())).collect(),
+            InputProfile::P1p2 => PLAYER1_PAD.iter().zip(PLAYER1_KEYS)
+                .chain(PLAYER2_PAD.iter().zip(PLAYER2_KEYS))
+                .chain(PLAYER2_ARROWS.iter().map(|(key, name)| (key, *name)))
+                .map(|(&key, name)| (key, name.to_string()))
+                .collect(),
+        };
+
+        for (pad, keys) in [(PLAYER1_PAD, player1), (PLAYER2_PAD, player2)] {
+            let Some(PlayerKeys(keys)) = keys else {
+                continue;
+            };
+
+            key_map.retain(|(key, _)| !pad.contains(key));
+            key_map.extend(pad.into_iter().zip(keys.iter().cloned()));
+        }
+
+        key_map
+    }
+}
+
+// Keyboard keys for one player's half of the keypad, by SDL scancode name
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlayerKeys(pub [String; 8]);
+
+impl FromStr for PlayerKeys {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let keys: Vec<String> = value.split(',').map(|key| key.trim().to_string()).collect();
+
+        keys.try_into()
+            .map(Self)
+            .map_err(|keys: Vec<String>| format!("expected 8 keys separated by commas, got {}", keys.len()))
+    }
+}
+
 pub struct InputConfig {
     pub engine: InputEngine,
+    pub pause_on_focus_loss: bool,
     pub key_map: Vec<(Key, String)>,
 }
 
+// Frame length of unthrottled machines, the same as at the default clock speed
+const UNTHROTTLED_CYCLES_PER_FRAME: u64 = 10;
+
+impl Config {
+    // Whether instructions run as fast as possible, rather than at the clock speed
+    pub fn unthrottled(&self) -> bool {
+        self.clock_speed == 0
+    }
+
+    // Number of cycles between 60Hz display interrupts
+    pub fn cycles_per_frame(&self) -> u64 {
+        if self.unthrottled() {
+            UNTHROTTLED_CYCLES_PER_FRAME
+        } else {
+            (self.clock_speed / 60).max(1)
+        }
+    }
+}
+
+impl Default for Config {
+    // Same configuration as running the CLI with no arguments.
+    fn default() -> Self {
+        Self::from(Args::parse_from(["chip-eight"]))
+    }
+}
+
 impl From<Args> for Config {
     fn from(args: Args) -> Self {
         Self {
-            clock_speed: args.clock_speed,
+            clock_speed: if args.unlimited { 0 } else { args.clock_speed },
+            skip_delay_waits: args.skip_delay_waits,
+            exit_after: args.exit_after,
+            exit_when_settled: args.exit_when_settled,
+            exit_opcode: args.exit_opcode,
+            timing_stats: args.timing_stats,
+            watches: args.watch_expr,
+            sound_overlay: args.sound_overlay,
+            profile: args.profile,
             quirks: QuirksConfig {
                 skip_reset_vf: args.skip_reset_vf,
                 preserve_index: args.preserve_index,
-                skip_draw_wait: args.skip_draw_wait,
-                wrap_sprites: args.wrap_sprites,
+                vblank_model: if args.skip_draw_wait {
+                    VblankModel::None
+                } else {
+                    args.vblank_model
+                },
+                wrap_x: args.wrap_sprites || args.wrap_x,
+                wrap_y: args.wrap_sprites || args.wrap_y,
                 skip_shift_set: args.skip_shift_set,
                 jump_with_vx: args.jump_with_vx,
+                index_overflow_flag: args.index_overflow_flag,
+                collision_count: args.collision_count,
+                machine_calls: args.machine_calls,
+                rng: args.rng,
             },
             memory: Rc::new(MemoryConfig {
-                length: args.memory_length,
-                program_start: args.program_start,
+                // MegaChip programs address up to 16MB through the 24-bit index register
+                length: if args.profile == Profile::MegaChip {
+                    args.memory_length.max(0x1000000)
+                } else {
+                    args.memory_length
+                },
+                // CHIP-8X programs start after the interpreter's larger footprint
+                program_start: if args.profile == Profile::Chip8X && args.program_start == 0x200 {
+                    0x300
+                } else {
+                    args.program_start
+                },
+                protect_low_memory: args.protect_low_memory,
                 font_start: args.font_start,
                 default_font: [
                     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -186,42 +1176,46 @@ impl From<Args> for Config {
                     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
                     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
                 ],
+                big_font_start: args.big_font_start,
+                default_big_font: [
+                    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+                    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+                    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+                    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+                    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+                    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+                    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+                    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+                    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+                    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+                    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+                    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+                    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+                    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+                    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+                    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+                ],
             }),
             display: Rc::new(DisplayConfig {
                 engine: args.display_engine,
                 width: args.width,
                 height: args.height,
                 scale_factor: args.scale_factor,
-                colors: [
-                    // Off
-                    (0, 0, 0),
-                    // On
-                    (255, 255, 255),
-                ],
+                colors: args.colors.0,
+                post_process: args.post_process,
+                rotation: args.rotate,
+                flip_x: args.flip_x,
+                flip_y: args.flip_y,
             }),
             audio: Rc::new(AudioConfig {
                 engine: args.audio_engine,
+                volume: args.volume,
+                muted: args.mute,
             }),
             input: Rc::new(InputConfig {
                 engine: args.input_engine,
-                key_map: vec![
-                    (Key::_0, "X".to_string()),
-                    (Key::_1, "1".to_string()),
-                    (Key::_2, "2".to_string()),
-                    (Key::_3, "3".to_string()),
-                    (Key::_4, "Q".to_string()),
-                    (Key::_5, "W".to_string()),
-                    (Key::_6, "E".to_string()),
-                    (Key::_7, "A".to_string()),
-                    (Key::_8, "S".to_string()),
-                    (Key::_9, "D".to_string()),
-                    (Key::A, "Z".to_string()),
-                    (Key::B, "C".to_string()),
-                    (Key::C, "4".to_string()),
-                    (Key::D, "R".to_string()),
-                    (Key::E, "F".to_string()),
-                    (Key::F, "V".to_string()),
-                ],
+                pause_on_focus_loss: args.pause_on_focus_loss,
+                key_map: args.input_profile.key_map(args.player1_keys.as_ref(), args.player2_keys.as_ref()),
             }),
         }
     }
@@ -0,0 +1,144 @@
+// Which of a ROM's instructions a run executed, for finding dead code and checking
+// how much of a game a set of inputs exercises.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    rc::Rc,
+};
+
+use crate::{
+    analysis::{self, Analysis},
+    config::Profile,
+    disasm,
+    instructions::Instruction,
+    symbols::Symbols,
+    ChipEight,
+};
+
+// Instructions executed so far, by address, shared with the hook recording them.
+#[derive(Clone, Default)]
+pub struct Coverage {
+    executed: Rc<RefCell<BTreeMap<usize, Instruction>>>,
+}
+
+impl Coverage {
+    // Record every instruction the machine executes from now on.
+    pub fn track(chip_eight: &mut ChipEight) -> Self {
+        let coverage = Self::default();
+
+        chip_eight.on_instruction({
+            let executed = coverage.executed.clone();
+            move |pc, instruction| {
+                executed.borrow_mut().insert(pc, *instruction);
+            }
+        });
+
+        coverage
+    }
+
+    pub fn executed(&self) -> BTreeSet<usize> {
+        self.executed.borrow().keys().copied().collect()
+    }
+
+    // How much of the ROM loaded at program_start was executed. Code counts as
+    // reachable when a path from the entry point leads to it, or when it was
+    // executed, as code behind dynamic jumps is.
+    pub fn report(&self, rom: &[u8], program_start: usize, profile: Profile) -> CoverageReport {
+        let executed = self.executed.borrow();
+        let rom_range = program_start..program_start + rom.len();
+
+        let mut reachable = Analysis::scan(rom, program_start, profile).reachable;
+        reachable.extend(executed.keys().filter(|addr| rom_range.contains(addr)));
+
+        // Runs of reachable instructions, one after the other, that never executed
+        let mut unexecuted: Vec<(usize, usize)> = vec![];
+
+        for &addr in reachable.iter().filter(|addr| !executed.contains_key(addr)) {
+            match unexecuted.last_mut() {
+                Some((start, count)) if *start + *count * 2 == addr => *count += 1,
+                _ => unexecuted.push((addr, 1)),
+            }
+        }
+
+        let kind = |instruction: &Instruction| format!("{:?}", instruction).split('(').next().unwrap_or_default().to_string();
+
+        let executed_kinds: BTreeSet<String> = executed.values().map(kind).collect();
+        let unexecuted_kinds = reachable.iter()
+            .filter_map(|&addr| analysis::fetch(rom, program_start, addr))
+            .filter_map(|opcode| Instruction::decode(opcode, profile).ok())
+            .map(|instruction| kind(&instruction))
+            .filter(|kind| !executed_kinds.contains(kind))
+            .collect();
+
+        CoverageReport {
+            reachable: reachable.len(),
+            executed: reachable.iter().filter(|addr| executed.contains_key(addr)).count(),
+            unexecuted,
+            unexecuted_kinds,
+        }
+    }
+
+    // Disassembly of the ROM loaded at program_start, with executed instructions
+    // marked + and reachable ones that never executed marked -.
+    pub fn listing(&self, rom: &[u8], program_start: usize, profile: Profile, symbols: &Symbols) -> String {
+        let executed = self.executed();
+
+        let mut code = Analysis::scan(rom, program_start, profile).reachable;
+        code.extend(&executed);
+
+        disasm::annotated(rom, program_start, profile, symbols, &code, |addr, is_code| {
+            match (is_code, executed.contains(&addr)) {
+                (true, true) => '+',
+                (true, false) => '-',
+                (false, _) => ' ',
+            }
+        })
+    }
+}
+
+pub struct CoverageReport {
+    // Reachable instructions, and how many of them executed
+    pub reachable: usize,
+    pub executed: usize,
+    // Start and number of instructions of each run of reachable instructions that
+    // never executed
+    pub unexecuted: Vec<(usize, usize)>,
+    // Kinds of instruction reachable but never executed, such as Draw
+    pub unexecuted_kinds: BTreeSet<String>,
+}
+
+impl CoverageReport {
+    pub fn percent(&self) -> f64 {
+        self.executed as f64 / self.reachable.max(1) as f64 * 100.0
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Coverage: {} of {} reachable instructions executed ({:.1}%)",
+            self.executed,
+            self.reachable,
+            self.percent(),
+        )?;
+
+        if !self.unexecuted.is_empty() {
+            write!(f, "\nUnexecuted blocks:")?;
+
+            for &(start, count) in &self.unexecuted {
+                let plural = if count == 1 { "" } else { "s" };
+                write!(f, "\n  0x{:03X}..0x{:03X}  {} instruction{}", start, start + count * 2, count, plural)?;
+            }
+        }
+
+        if !self.unexecuted_kinds.is_empty() {
+            let kinds: Vec<&str> = self.unexecuted_kinds.iter().map(String::as_str).collect();
+            write!(f, "\nNever executed: {}", kinds.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,680 @@
+use alloc::{rc::Rc, vec, vec::Vec};
+use core::{cell::Cell, error::Error, fmt};
+
+use log::warn;
+
+use crate::{
+    frame::FrameBuffer,
+    instructions::{Instruction, InvalidOpcodeError, Profile},
+    memory::{Memory, MemoryConfig, MemoryError},
+};
+
+// Nanoseconds between ticks of the timers, at 60Hz.
+const TICK_NANOS: u64 = 1_000_000_000 / 60;
+
+// Nesting at which calls are assumed to be missing their returns, as it is past the
+// 16 levels most interpreters have room for.
+const DEEP_STACK: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum VblankModel {
+    Vip,
+    Schip,
+    None,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum MachineCalls {
+    Ignore,
+    Trap,
+    Error,
+}
+
+// Where CXNN's random numbers come from.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum RandomModel {
+    // The machine's source of random numbers
+    Modern,
+    // The COSMAC VIP interpreter's routine, which steps a seed through its own page
+    // of memory, so the numbers follow from the machine's state
+    Vip,
+}
+
+// Page of memory the VIP's routine reads, where its interpreter lived
+const VIP_RANDOM_PAGE: usize = 0x100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuirksConfig {
+    pub skip_reset_vf: bool,
+    pub preserve_index: bool,
+    pub vblank_model: VblankModel,
+    pub wrap_x: bool,
+    pub wrap_y: bool,
+    pub skip_shift_set: bool,
+    pub jump_with_vx: bool,
+    pub index_overflow_flag: bool,
+    pub collision_count: bool,
+    pub machine_calls: MachineCalls,
+    pub rng: RandomModel,
+}
+
+// Quirks in effect, shared between the CPU and whatever changes them while it runs,
+// such as the debugger. The CPU reads them afresh for every instruction, so changes
+// take effect from the next one.
+#[derive(Clone, Debug)]
+pub struct Quirks(Rc<Cell<QuirksConfig>>);
+
+impl Quirks {
+    pub fn new(quirks: QuirksConfig) -> Self {
+        Self(Rc::new(Cell::new(quirks)))
+    }
+
+    pub fn get(&self) -> QuirksConfig {
+        self.0.get()
+    }
+
+    pub fn set(&self, quirks: QuirksConfig) {
+        self.0.set(quirks);
+    }
+
+    // Change some of the quirks, leaving the rest as they are.
+    pub fn update(&self, change: impl FnOnce(&mut QuirksConfig)) {
+        let mut quirks = self.get();
+        change(&mut quirks);
+        self.set(quirks);
+    }
+}
+
+// Reasons a program can fail to execute.
+#[derive(Debug, PartialEq)]
+pub enum ExecutionError {
+    // Memory access that failed, and what it was for
+    Memory(&'static str, MemoryError),
+    InvalidOpcode(InvalidOpcodeError),
+    MachineCall(usize),
+    EmptyStack,
+    // Extension instruction executed without its profile selected
+    Profile(&'static str, &'static str),
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::Memory(action, error) => write!(f, "Failed to {}: {}", action, error),
+            ExecutionError::InvalidOpcode(error) => write!(f, "Failed to parse instruction: {}", error),
+            ExecutionError::MachineCall(addr) => write!(f, "Failed to call machine code routine at 0x{:03X}: not supported", addr),
+            ExecutionError::EmptyStack => write!(f, "Failed to return from subroutine: stack is empty"),
+            ExecutionError::Profile(extension, profile) => write!(f, "Failed to execute {} instruction: the {} profile is not selected", extension, profile),
+        }
+    }
+}
+
+impl Error for ExecutionError {}
+
+// Subroutine call that has yet to return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    // Address of the call instruction
+    pub call_site: usize,
+    // Address of the subroutine called
+    pub routine: usize,
+}
+
+// What the CPU needs from the machine around it: the timers, the keypad and a source
+// of random numbers. Hosts without threads or clocks can count the timers down in
+// virtual time with VirtualTimers.
+pub trait Peripherals {
+    fn delay(&self) -> u8;
+    fn set_delay(&mut self, value: u8);
+    fn set_sound(&mut self, value: u8);
+
+    // Whether the key, from 0x0 to 0xF, is held down
+    fn key_down(&self, key: u8) -> bool;
+
+    fn random(&mut self) -> u8;
+}
+
+// Delay and sound timers counted down in virtual time. Advance them by the time the
+// instructions executed stand for, such as a batch of cycles over the clock speed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VirtualTimers {
+    pub delay: u8,
+    pub sound: u8,
+
+    // Time since the last tick, in nanoseconds
+    elapsed: u64,
+}
+
+impl VirtualTimers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Count down once for every 60th of a second passed, returning how many times.
+    pub fn advance(&mut self, nanos: u64) -> u64 {
+        self.elapsed += nanos;
+
+        let ticks = self.elapsed / TICK_NANOS;
+        self.elapsed %= TICK_NANOS;
+
+        // Both timers are at 0 after 255 ticks
+        for _ in 0..ticks.min(u8::MAX.into()) {
+            self.tick();
+        }
+
+        ticks
+    }
+
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+}
+
+// Outcomes of an instruction the machine around the CPU has to act on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    // The frame buffer was cleared
+    Cleared,
+    // Execution holds until a key is pressed and released, to store in the register
+    WaitForKey(usize),
+    // A machine call halted the machine, leaving the program counter on it
+    Trapped,
+    // Instruction of an extension the CPU has no state for, left for the machine
+    Extension(Instruction),
+}
+
+// Registers, stack, memory and frame buffer of a CHIP-8 and SUPER-CHIP, and the
+// executor for their instructions. Needs nothing more than alloc, so the core can
+// run on microcontrollers, with the machine around it providing the peripherals.
+pub struct Cpu {
+    quirks: Quirks,
+    // Quirks for the next instruction only, in place of the shared ones
+    quirks_override: Option<QuirksConfig>,
+    memory_config: Rc<MemoryConfig>,
+    profile: Profile,
+
+    // Resolution the machine starts out at, which the SUPER-CHIP vblank model treats
+    // as low resolution.
+    base_resolution: (usize, usize),
+
+    // Stack containing addressess used to call/return from functions and subroutines.
+    pub stack: Vec<usize>,
+
+    // Calls on the stack, kept alongside their return addresses for debugging.
+    pub calls: Vec<CallFrame>,
+
+    // Whether the stack has been reported as growing without returns.
+    deep_stack_reported: bool,
+
+    // Program counter which points to the current instruction in memory.
+    pub pc: usize,
+
+    // 16 8-bit general purpose variable registers.
+    pub v: [u8; 16],
+
+    // Index register to point at locations in memory.
+    pub i: usize,
+
+    // Memory model
+    pub memory: Memory,
+
+    // Frame data used to determine what to draw to each pixel, as
+    // well as whether drawing a pixel resulted in a collision.
+    pub frame_buffer: FrameBuffer,
+
+    // Current resolution of the frame buffer, which hi-res programs can change.
+    pub width: usize,
+    pub height: usize,
+
+    // Set by DXYN until the next display interrupt, as the vblank model dictates.
+    pub waiting_for_vblank: bool,
+
+    // Seed of the VIP's random number routine, counted up at each display
+    // interrupt and stepped by each CXNN.
+    pub random_seed: u16,
+}
+
+impl Cpu {
+    pub fn new(quirks: QuirksConfig, memory_config: Rc<MemoryConfig>, profile: Profile, width: usize, height: usize) -> Self {
+        Self {
+            quirks: Quirks::new(quirks),
+            quirks_override: None,
+            profile,
+            base_resolution: (width, height),
+            stack: vec![],
+            calls: vec![],
+            deep_stack_reported: false,
+            pc: memory_config.program_start,
+            v: [0; 16],
+            i: 0,
+            memory: Memory::new(memory_config.clone()),
+            frame_buffer: FrameBuffer::new(width, height),
+            width,
+            height,
+            waiting_for_vblank: false,
+            random_seed: 0,
+            memory_config,
+        }
+    }
+
+    // Quirks the next instruction executes with.
+    pub fn quirks(&self) -> QuirksConfig {
+        self.quirks_override.unwrap_or_else(|| self.quirks.get())
+    }
+
+    // Handle to the quirks, for changing them between instructions.
+    pub fn quirks_handle(&self) -> Quirks {
+        self.quirks.clone()
+    }
+
+    // Execute the next instruction with different quirks, going back to the shared
+    // ones after it.
+    pub fn override_quirks(&mut self, quirks: QuirksConfig) {
+        self.quirks_override = Some(quirks);
+    }
+
+    // Rows of a sprite DXYN draws and bytes to a row. SUPER-CHIP draws 16x16 sprites
+    // with DXY0 in hi-res.
+    pub(crate) fn sprite_size(&self, sprite_height: u8) -> (usize, usize) {
+        let hires = (self.width, self.height) != self.base_resolution;
+
+        match (hires, sprite_height) {
+            (true, 0) => (16, 2),
+            _ => (sprite_height as usize, 1),
+        }
+    }
+
+    // Return to the power-on state, with memory cleared.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.calls.clear();
+        self.deep_stack_reported = false;
+        self.pc = self.memory_config.program_start;
+        self.v = [0; 16];
+        self.i = 0;
+        self.memory.clear();
+        self.frame_buffer.fill(false);
+        self.waiting_for_vblank = false;
+        self.random_seed = 0;
+    }
+
+    // Count the random seed up at a display interrupt, so the VIP's numbers depend on
+    // how long the program ran before asking for them.
+    pub fn tick_random_seed(&mut self) {
+        self.random_seed = self.random_seed.wrapping_add(1);
+    }
+
+    // Next number from the VIP's routine: step the seed, then add the byte of its
+    // page the seed's low byte points at to the seed's high byte, which is the
+    // number. Programs writing over that page steer it, as on the VIP.
+    fn vip_random(&mut self) -> u8 {
+        self.random_seed = self.random_seed.wrapping_add(1);
+
+        let [high, low] = self.random_seed.to_be_bytes();
+        let high = high.wrapping_add(self.memory.read_byte(VIP_RANDOM_PAGE | low as usize).unwrap_or(0));
+        self.random_seed = u16::from_be_bytes([high, low]);
+
+        high
+    }
+
+    // Store the fonts and the given ROM in memory.
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), MemoryError> {
+        self.memory.load_buf(self.memory_config.font_start, &self.memory_config.default_font)?;
+        self.memory.load_buf(self.memory_config.big_font_start, &self.memory_config.default_big_font)?;
+        self.memory.load_buf(self.memory_config.program_start, rom)
+    }
+
+    // Change the resolution of the frame buffer, clearing it.
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.frame_buffer.resize(width, height);
+    }
+
+    // Display interrupt, releasing a sprite draw waiting for it.
+    pub fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
+    }
+
+    // Opcode at the program counter.
+    pub fn fetch(&self) -> Result<u16, ExecutionError> {
+        let parts = self.memory.read_buf(self.pc, 2)
+            .map_err(|error| ExecutionError::Memory("fetch instruction", error))?;
+
+        Ok(((parts[0] as u16) << 8) | parts[1] as u16)
+    }
+
+    // Move past the next instruction, all four bytes of it if it is F000 NNNN.
+    fn skip(&mut self) {
+        let wide = self.memory.read_buf(self.pc, 2).is_ok_and(|parts| parts[..] == [0xF0, 0x00]);
+        self.pc += if wide { 4 } else { 2 };
+    }
+
+    // Fetch, decode and execute a single instruction. Does nothing while a sprite
+    // draw is waiting for the display interrupt.
+    pub fn step(&mut self, peripherals: &mut impl Peripherals) -> Result<Option<Effect>, ExecutionError> {
+        if self.waiting_for_vblank {
+            return Ok(None);
+        }
+
+        let instruction = Instruction::decode(self.fetch()?, self.profile)
+            .map_err(ExecutionError::InvalidOpcode)?;
+
+        self.pc += 2;
+        self.execute(instruction, peripherals)
+    }
+
+    // Execute a decoded instruction, with the program counter already moved past it.
+    pub fn execute(&mut self, instruction: Instruction, peripherals: &mut impl Peripherals) -> Result<Option<Effect>, ExecutionError> {
+        // Sized before the override is used up
+        let sprite_size = match instruction {
+            Instruction::Draw(_, _, sprite_height) => self.sprite_size(sprite_height),
+            _ => (0, 0),
+        };
+
+        let quirks = self.quirks();
+        self.quirks_override = None;
+
+        match instruction {
+            Instruction::MachineCall(addr) => match quirks.machine_calls {
+                MachineCalls::Ignore => (),
+                // Halt on the call, leaving the machine as it was for inspection
+                MachineCalls::Trap => {
+                    self.pc -= 2;
+                    return Ok(Some(Effect::Trapped));
+                },
+                MachineCalls::Error => return Err(ExecutionError::MachineCall(addr)),
+            },
+            Instruction::Clear => {
+                self.frame_buffer.fill(false);
+                return Ok(Some(Effect::Cleared));
+            },
+            Instruction::Return => {
+                self.pc = self.stack.pop().ok_or(ExecutionError::EmptyStack)?;
+                self.calls.pop();
+            },
+            Instruction::Jump(addr) => self.pc = addr,
+            Instruction::Call(addr) => {
+                let call_site = self.pc - 2;
+
+                self.stack.push(self.pc);
+                self.calls.push(CallFrame {
+                    call_site,
+                    routine: addr,
+                });
+                self.pc = addr;
+
+                // Usually a subroutine jumping back to its caller rather than returning
+                if self.stack.len() > DEEP_STACK && !self.deep_stack_reported {
+                    self.deep_stack_reported = true;
+                    warn!("Calls are nested {} deep at 0x{:03X}, some may be missing their returns", self.stack.len(), call_site);
+                }
+            }
+            Instruction::IfVxEq(reg, val) => {
+                if self.v[reg] == val {
+                    self.skip();
+                }
+            },
+            Instruction::IfVxNotEq(reg, val) => {
+                if self.v[reg] != val {
+                    self.skip();
+                }
+            },
+            Instruction::IfVxEqVy(reg_x, reg_y) => {
+                if self.v[reg_x] == self.v[reg_y] {
+                    self.skip();
+                }
+            },
+            Instruction::SetVx(reg, val) => self.v[reg] = val,
+            Instruction::AddToVx(reg, val) => self.v[reg] = self.v[reg].wrapping_add(val),
+            Instruction::SetVxToVy(reg_x, reg_y) => self.v[reg_x] = self.v[reg_y],
+            Instruction::SetVxOrVy(reg_x, reg_y) => {
+                self.v[reg_x] |= self.v[reg_y];
+
+                if !quirks.skip_reset_vf {
+                    self.v[0xF] = 0;
+                }
+            },
+            Instruction::SetVxAndVy(reg_x, reg_y) => {
+                self.v[reg_x] &= self.v[reg_y];
+
+                if !quirks.skip_reset_vf {
+                    self.v[0xF] = 0;
+                }
+            },
+            Instruction::SetVxXorVy(reg_x, reg_y) => {
+                self.v[reg_x] ^= self.v[reg_y];
+
+                if !quirks.skip_reset_vf {
+                    self.v[0xF] = 0;
+                }
+            },
+            Instruction::AddVyToVx(reg_x, reg_y) => {
+                let (result, overflowed) = self.v[reg_x].overflowing_add(self.v[reg_y]);
+                self.v[reg_x] = result;
+                self.v[0xF] = overflowed.into();
+            },
+            Instruction::SubVyFromVx(reg_x, reg_y) => {
+                let (result, overflowed) = self.v[reg_x].overflowing_sub(self.v[reg_y]);
+                self.v[reg_x] = result;
+                self.v[0xF] = (!overflowed).into();
+            },
+            Instruction::RightShiftVx(reg_x, reg_y) => {
+                let reg = if quirks.skip_shift_set {
+                    reg_x
+                } else {
+                    reg_y
+                };
+
+                let bit = self.v[reg] & 1;
+                self.v[reg_x] = self.v[reg] >> 1;
+                self.v[0xF] = bit;
+            },
+            Instruction::SubVxFromVy(reg_x, reg_y) => {
+                let (result, overflowed) = self.v[reg_y].overflowing_sub(self.v[reg_x]);
+                self.v[reg_x] = result;
+                self.v[0xF] = (!overflowed).into();
+            },
+            Instruction::LeftShiftVx(reg_x, reg_y) => {
+                let reg = if quirks.skip_shift_set {
+                    reg_x
+                } else {
+                    reg_y
+                };
+
+                let bit = (self.v[reg] >> 7) & 1;
+                self.v[reg_x] = self.v[reg] << 1;
+                self.v[0xF] = bit;
+            },
+            Instruction::IfVxNotEqVy(reg_x, reg_y) => {
+                if self.v[reg_x] != self.v[reg_y] {
+                    self.skip();
+                }
+            },
+            Instruction::SetI(addr) => self.i = addr,
+            Instruction::JumpWithOffset(addr) => {
+                let offset = if quirks.jump_with_vx {
+                    self.v[(addr >> 8) & 0xF]
+                } else {
+                    self.v[0]
+                };
+
+                self.pc = addr + offset as usize;
+            },
+            Instruction::SetVxRand(reg, val) => {
+                let random = match quirks.rng {
+                    RandomModel::Modern => peripherals.random(),
+                    RandomModel::Vip => self.vip_random(),
+                };
+
+                self.v[reg] = random & val;
+            },
+            Instruction::Draw(reg_x, reg_y, _) => {
+                let x = self.v[reg_x] as usize % self.width;
+                let y = self.v[reg_y] as usize % self.height;
+
+                // SUPER-CHIP 1.1 counts colliding and clipped rows in hi-res
+                let count = quirks.collision_count && (self.width, self.height) != self.base_resolution;
+                let (rows, row_bytes) = sprite_size;
+
+                let sprite = self.memory
+                    .read_buf(self.i, rows * row_bytes)
+                    .map_err(|error| ExecutionError::Memory("fetch sprite", error))?;
+
+                let mut collisions = 0;
+
+                for (layer, bytes) in sprite.chunks(row_bytes).enumerate() {
+                    let mut current_y = y + layer;
+
+                    if !quirks.wrap_y {
+                        if current_y >= self.height {
+                            if count {
+                                collisions += rows - layer;
+                            }
+                            break;
+                        }
+                    } else {
+                        current_y %= self.height;
+                    }
+
+                    let row = bytes.iter().fold(0u16, |row, &byte| row << 8 | byte as u16);
+                    let row_width = row_bytes * 8;
+                    let mut collided = false;
+
+                    for position in 0..row_width {
+                        let mut current_x = x + position;
+
+                        if !quirks.wrap_x {
+                            if current_x >= self.width {
+                                break;
+                            }
+                        } else {
+                            current_x %= self.width;
+                        }
+
+                        let bit = (row >> (row_width - 1 - position)) & 1;
+
+                        if bit != 0 && self.frame_buffer.toggle(current_y * self.width + current_x) == Some(true) {
+                            collided = true;
+                        }
+                    }
+
+                    collisions += collided as usize;
+                }
+
+                self.v[0xF] = match count {
+                    true => collisions.min(0xFF) as u8,
+                    false => (collisions > 0) as u8,
+                };
+
+                self.waiting_for_vblank = match quirks.vblank_model {
+                    VblankModel::Vip => true,
+                    // SUPER-CHIP only waits in low resolution
+                    VblankModel::Schip => (self.width, self.height) == self.base_resolution,
+                    VblankModel::None => false,
+                };
+            },
+            Instruction::IfKeyPressed(reg) => {
+                if peripherals.key_down(self.v[reg] & 0xF) {
+                    self.skip();
+                }
+            },
+            Instruction::IfKeyNotPressed(reg) => {
+                if !peripherals.key_down(self.v[reg] & 0xF) {
+                    self.skip();
+                }
+            },
+            Instruction::SetVxToDelay(reg) => self.v[reg] = peripherals.delay(),
+            Instruction::SetVxToKey(reg) => return Ok(Some(Effect::WaitForKey(reg))),
+            Instruction::SetDelayToVx(reg) => peripherals.set_delay(self.v[reg]),
+            Instruction::SetSoundToVx(reg) => peripherals.set_sound(self.v[reg]),
+            Instruction::AddVxToI(reg) => {
+                let index = self.i + self.v[reg] as usize;
+                self.i = index & self.profile.index_mask();
+
+                // Past the end of memory, which is 0xFFF by default
+                if quirks.index_overflow_flag {
+                    self.v[0xF] = (index >= self.memory.len()).into();
+                }
+            },
+            Instruction::SetIToCharInVx(reg) => self.i = self.memory_config.font_start + ((self.v[reg] & 0xF) * 5) as usize,
+            Instruction::SetIToBigCharInVx(reg) => self.i = self.memory_config.big_font_start + ((self.v[reg] & 0xF) * 10) as usize,
+            Instruction::StoreVxBCDAtI(reg) => {
+                let mut value = self.v[reg];
+                for index in (0..3).rev() {
+                    self.memory.write_byte(self.i + index, value % 10)
+                        .map_err(|error| ExecutionError::Memory("store BCD digit to memory", error))?;
+
+                    value /= 10;
+                }
+            },
+            Instruction::VDump(reg) => {
+                for index in 0..=reg {
+                    self.memory.write_byte(self.i + index, self.v[index])
+                        .map_err(|error| ExecutionError::Memory("store value in register to memory", error))?;
+                }
+
+                if !quirks.preserve_index {
+                    self.i = (self.i + reg + 1) & self.profile.index_mask();
+                }
+            },
+            Instruction::VLoad(reg) => {
+                for index in 0..=reg {
+                    let byte = self.memory.read_byte(self.i + index)
+                        .map_err(|error| ExecutionError::Memory("load value from memory to register", error))?;
+                    self.v[index] = byte;
+                }
+
+                if !quirks.preserve_index {
+                    self.i = (self.i + reg + 1) & self.profile.index_mask();
+                }
+            },
+            Instruction::SetIWide => {
+                let word = self.memory.read_buf(self.pc, 2)
+                    .map_err(|error| ExecutionError::Memory("fetch address", error))?;
+
+                self.i = ((word[0] as usize) << 8) | word[1] as usize;
+                self.pc += 2;
+            },
+            Instruction::SetILong(high) => {
+                let low = self.memory.read_buf(self.pc, 2)
+                    .map_err(|error| ExecutionError::Memory("fetch address", error))?;
+
+                self.i = ((high as usize) << 16) | ((low[0] as usize) << 8) | low[1] as usize;
+                self.pc += 2;
+            },
+            Instruction::AddVyToVxNibbles(reg_x, reg_y) => {
+                let (x, y) = (self.v[reg_x], self.v[reg_y]);
+
+                // Each nibble is added separately, modulo 8
+                self.v[reg_x] = ((((x >> 4) + (y >> 4)) & 0x7) << 4) | (((x & 0xF) + (y & 0xF)) & 0x7);
+            },
+            // There is no second keypad, so its keys are never pressed
+            Instruction::IfKey2Pressed(_) => (),
+            Instruction::IfKey2NotPressed(_) => self.skip(),
+            // Sets the tone of the sound board, which the audio devices can't change yet
+            Instruction::OutputVx(_) => (),
+            Instruction::LoadAudioPattern
+            | Instruction::SetPitch(_)
+            | Instruction::DisableMegaChip
+            | Instruction::EnableMegaChip
+            | Instruction::LoadPalette(_)
+            | Instruction::SetSpriteWidth(_)
+            | Instruction::SetSpriteHeight(_)
+            | Instruction::SetScreenAlpha(_)
+            | Instruction::PlaySample(_)
+            | Instruction::StopSample
+            | Instruction::SetBlendMode(_)
+            | Instruction::SetCollisionColor(_)
+            | Instruction::StepBackgroundColor
+            | Instruction::SetZoneColor(..)
+            | Instruction::SetRowColor(..) => return Ok(Some(Effect::Extension(instruction))),
+        }
+
+        Ok(None)
+    }
+}
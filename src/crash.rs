@@ -0,0 +1,159 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{instructions::Instruction, system::CallFrame};
+
+// Number of executed instructions kept for crash reports.
+pub const TRACE_LEN: usize = 1000;
+
+// What the machine was doing when it hit a fault it can't carry on from, written
+// out so the fault can be looked into after the machine is gone.
+pub struct CrashReport {
+    pub error: String,
+    // SHA-1 of the loaded ROM, if one was loaded through storage
+    pub rom_id: Option<String>,
+    // Settings the machine was built with, one per line
+    pub config: Vec<String>,
+    // Latest instructions executed and their addresses, oldest first
+    pub trace: Vec<(usize, Instruction)>,
+    pub pc: usize,
+    pub v: [u8; 16],
+    pub i: usize,
+    pub timers: (u8, u8),
+    pub calls: Vec<CallFrame>,
+    pub resolution: (usize, usize),
+    pub frame_buffer: Vec<bool>,
+    // Colours of unlit and lit pixels
+    pub colors: [(u8, u8, u8); 2],
+}
+
+impl CrashReport {
+    // Write the report as a new directory in dir, holding report.txt and a
+    // frame.png of the display, returning the directory's path.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        // Faults can come in quick succession, such as from tests
+        let path = (0..)
+            .map(|attempt| match attempt {
+                0 => dir.join(format!("crash-{}", now)),
+                _ => dir.join(format!("crash-{}-{}", now, attempt)),
+            })
+            .find(|path| !path.exists())
+            .unwrap();
+
+        fs::create_dir_all(&path)?;
+        fs::write(path.join("report.txt"), self.text())?;
+
+        let (width, height) = self.resolution;
+        fs::write(path.join("frame.png"), png(&self.frame_buffer, width, height, self.colors))?;
+
+        Ok(path)
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+
+        let _ = writeln!(text, "Error: {}", self.error);
+        let _ = writeln!(text, "ROM SHA-1: {}", self.rom_id.as_deref().unwrap_or("unknown"));
+
+        let _ = writeln!(text, "\nConfig:");
+        for line in &self.config {
+            let _ = writeln!(text, "  {}", line);
+        }
+
+        let _ = writeln!(text, "\nRegisters:");
+        let _ = writeln!(text, "  PC: 0x{:03X}  I: 0x{:03X}  DT: {}  ST: {}", self.pc, self.i, self.timers.0, self.timers.1);
+        for (row, regs) in self.v.chunks(8).enumerate() {
+            let regs: Vec<String> = regs.iter()
+                .enumerate()
+                .map(|(index, value)| format!("V{:X}: {:02X}", row * 8 + index, value))
+                .collect();
+
+            let _ = writeln!(text, "  {}", regs.join("  "));
+        }
+
+        let _ = writeln!(text, "\nCalls, innermost first:");
+        for call in self.calls.iter().rev() {
+            let _ = writeln!(text, "  0x{:03X} from 0x{:03X}", call.routine, call.call_site);
+        }
+
+        let _ = writeln!(text, "\nLast {} instructions, oldest first:", self.trace.len());
+        for (pc, instruction) in &self.trace {
+            let _ = writeln!(text, "  0x{:03X}: {:?}", pc, instruction);
+        }
+
+        text
+    }
+}
+
+// Pixels in row order as an RGB PNG, with zlib's uncompressed blocks in place of
+// compression as frames are small.
+fn png(pixels: &[bool], width: usize, height: usize, colors: [(u8, u8, u8); 2]) -> Vec<u8> {
+    // Each row starts with a filter type byte, none here
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width.max(1)).take(height) {
+        raw.push(0);
+
+        for &lit in row {
+            let (r, g, b) = colors[lit as usize];
+            raw.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(u16::MAX as usize).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+
+        zlib.push((index + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = (width as u32).to_be_bytes().to_vec();
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, then default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", header), (b"IDAT", zlib), (b"IEND", vec![])] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(&data);
+
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB88320,
+            _ => crc >> 1,
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+
+    (b << 16) | a
+}
@@ -0,0 +1,906 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    io::{self, IsTerminal, Write},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Colors, QuirksConfig, THEMES},
+    devices::Key,
+    heatmap::Heatmap,
+    instructions::Instruction,
+    raster,
+    storage::{self, SLOTS},
+    symbols::Symbols,
+    system::{ChipEight, KeyWait, StopToken, MAX_SCALE, MIN_SCALE},
+    watches::{Watch, WatchExpr, WatchExprError},
+};
+
+// Bytes shown per row of a memory dump, and rows shown when no length is given.
+const ROW_LEN: usize = 16;
+const DUMP_ROWS: usize = 8;
+
+// Rows of a sprite shown when neither a length nor a draw gives one
+const SPRITE_ROWS: usize = 8;
+
+// Layout of the COSMAC VIP's hex keypad
+const KEYPAD: [[Key; 4]; 4] = [
+    [Key::_1, Key::_2, Key::_3, Key::C],
+    [Key::_4, Key::_5, Key::_6, Key::D],
+    [Key::_7, Key::_8, Key::_9, Key::E],
+    [Key::A, Key::_0, Key::B, Key::F],
+];
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const HELP: &str = "\
+step [n]              execute n instructions (s)
+rstep [n]             undo the last n instructions (rs)
+continue              run until a breakpoint, a fault or an interrupt (c)
+break [addr] [action=a...]
+                      set a breakpoint, or list them without an address (b). With
+                      actions, it runs them and carries on instead of stopping:
+                      action=log \"text\" prints the text with %v0-%vF, %i, %pc, %dt,
+                      %st and %mem[addr] replaced by their values, action=dump-frame
+                      draws the display, and action=script \"cmd; cmd\" runs commands
+delete addr           remove a breakpoint (d)
+regs                  show the registers and timers (r)
+keys                  show the keypad, its bindings, and the keys held as of the last cycle (k)
+bt                    show the calls execution is nested in
+mem [addr] [len]      dump memory, carrying on from the last dump without an address (m)
+sprite [addr] [rows]  show memory as an 8 pixel wide sprite, starting at I and the size
+                      of the draw about to execute, then carrying on through memory (sp)
+set addr byte...      write bytes to memory, ignoring protection
+set vX byte           set a register
+set quirk name value  change a quirk from the next instruction on, to on or off or
+                      one of its models
+quirks                show the quirks in effect
+freeze [addr byte]    hold a byte at a value, or list the frozen bytes without one (f)
+unfreeze addr         let the program write a frozen byte again (uf)
+watch [expr[:plot]]   show v0-vF, i, pc, dt, st or mem[addr] over the display, plotting
+                      its recent values with :plot, or list the watches without one (w)
+unwatch expr          stop showing a watch (uw)
+heatmap [addr] [len]  show how often each byte was read, written and executed, all of
+                      memory without an address (hm)
+heatmap clear         forget the accesses counted so far
+save [slot]           save the machine's state to a slot, the hotkeys' slot by default
+load [slot]           load the state saved in a slot
+states [rm slot]      list the slots holding states, or delete the state in one
+theme [colours]       list the colour themes, or switch to one or to OFF,ON hex colours
+scale [n]             show the display's scale factor, or resize it
+quit                  stop the machine (q)
+
+Addresses are hex with a 0x prefix, decimal, or labels from the symbols. Memory
+dumps mark the program counter, the byte at I, the fonts, and bytes written since
+execution last stopped.";
+
+// Debugger commands that move execution, which breakpoint actions can't run
+const EXECUTION_COMMANDS: [&str; 7] = ["step", "s", "rstep", "rs", "continue", "c", "load"];
+
+// What a breakpoint does in place of stopping when execution reaches it
+#[derive(Clone)]
+enum Action {
+    Log(Vec<LogPart>),
+    DumpFrame,
+    // Commands run in turn, as if entered at the prompt
+    Script(Vec<String>),
+}
+
+#[derive(Clone)]
+enum LogPart {
+    Text(String),
+    Value(WatchExpr),
+}
+
+impl Action {
+    // An action from the word after action=, taking what it needs from the words
+    // that follow.
+    fn parse<'a>(name: &str, words: &mut impl Iterator<Item = &'a str>) -> Result<Self, String> {
+        match name {
+            "log" => {
+                let text = words.next().ok_or("log needs the text to print")?;
+                Ok(Action::Log(parse_log(text)?))
+            },
+            "dump-frame" => Ok(Action::DumpFrame),
+            "script" => {
+                let script = words.next().ok_or("script needs the commands to run")?;
+                let commands: Vec<String> = script.split(';')
+                    .map(str::trim)
+                    .filter(|command| !command.is_empty())
+                    .map(str::to_string)
+                    .collect();
+
+                let moves = commands.iter().find(|command| {
+                    EXECUTION_COMMANDS.contains(&command.split_whitespace().next().unwrap_or_default())
+                });
+
+                match moves {
+                    Some(command) => Err(format!("Breakpoint scripts can't move execution, as '{}' does", command)),
+                    None => Ok(Action::Script(commands)),
+                }
+            },
+            _ => Err(format!("Unknown action '{}', expected log, dump-frame or script", name)),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Log(parts) => {
+                let text: String = parts.iter()
+                    .map(|part| match part {
+                        LogPart::Text(text) => text.replace('%', "%%"),
+                        LogPart::Value(expr) => format!("%{}", expr),
+                    })
+                    .collect();
+
+                write!(f, "log \"{}\"", text)
+            },
+            Action::DumpFrame => write!(f, "dump-frame"),
+            Action::Script(commands) => write!(f, "script \"{}\"", commands.join("; ")),
+        }
+    }
+}
+
+// How a byte is marked in a memory dump
+#[derive(Clone, Copy)]
+enum Mark {
+    Pc,
+    Index,
+    Written,
+    Font,
+}
+
+impl Mark {
+    fn ansi(self) -> &'static str {
+        match self {
+            Mark::Pc => "\x1b[7m",
+            Mark::Index => "\x1b[4m",
+            Mark::Written => "\x1b[33m",
+            Mark::Font => "\x1b[2m",
+        }
+    }
+
+    // Shown in front of the byte when the output isn't a terminal
+    fn symbol(self) -> char {
+        match self {
+            Mark::Pc => '>',
+            Mark::Index => '^',
+            Mark::Written => '*',
+            Mark::Font => '.',
+        }
+    }
+}
+
+// Line based debugger driving a machine from commands read on stdin, for stepping
+// through a program in either direction and inspecting and patching its memory.
+// Stepping backwards relies on the machine having been built with a history.
+pub struct Debugger {
+    symbols: Symbols,
+    // Breakpoints and their actions, stopping at those without any
+    breakpoints: BTreeMap<usize, Vec<Action>>,
+    // Whether each address was written since execution last stopped
+    written: Rc<RefCell<Vec<bool>>>,
+    // Accesses of each address since the program was loaded
+    heatmap: Heatmap,
+    // Where a memory dump without an address carries on from
+    next_dump: Option<usize>,
+    // Where and how many rows a sprite view without an address carries on with,
+    // until execution resumes
+    next_sprite: Option<(usize, usize)>,
+    // Cycles run since the last vblank
+    cycles: u64,
+    // Returns from continue to the prompt when stopped
+    interrupt: StopToken,
+    color: bool,
+}
+
+impl Debugger {
+    pub fn new(symbols: Symbols) -> Self {
+        Self {
+            symbols,
+            breakpoints: BTreeMap::new(),
+            written: Rc::new(RefCell::new(Vec::new())),
+            heatmap: Heatmap::new(0),
+            next_dump: None,
+            next_sprite: None,
+            cycles: 0,
+            interrupt: StopToken::new(),
+            color: io::stdout().is_terminal(),
+        }
+    }
+
+    // Token breaking out of continue, such as from a Ctrl-C handler.
+    pub fn interrupt_handle(&self) -> StopToken {
+        self.interrupt.clone()
+    }
+
+    // Load the ROM and take commands until quit, the end of input, or the machine
+    // being stopped.
+    pub fn run(&mut self, chip_eight: &mut ChipEight, rom: &[u8]) {
+        let written = self.written.clone();
+        written.replace(vec![false; chip_eight.memory().len()]);
+
+        chip_eight.memory_mut().on_write(move |range| {
+            let mut written = written.borrow_mut();
+            let end = range.end.min(written.len());
+            let start = range.start.min(end);
+            written[start..end].fill(true);
+        });
+
+        self.heatmap = Heatmap::new(chip_eight.memory().len());
+        self.heatmap.track(chip_eight);
+
+        chip_eight.load(rom);
+        self.before_execution();
+        self.show_location(chip_eight);
+
+        let mut lines = io::stdin().lines();
+
+        while !chip_eight.stop_handle().is_stopped() {
+            print!("(chip-eight) ");
+            let _ = io::stdout().flush();
+
+            let Some(Ok(line)) = lines.next() else {
+                break;
+            };
+
+            let words = split_words(&line);
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+            if let Err(message) = self.command(chip_eight, &words) {
+                println!("{}", message);
+            }
+        }
+
+        chip_eight.shutdown();
+    }
+
+    fn command(&mut self, chip_eight: &mut ChipEight, words: &[&str]) -> Result<(), String> {
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(());
+        };
+
+        match (command, args) {
+            ("help" | "h", _) => println!("{}", HELP),
+            ("step" | "s", _) => {
+                let count = self.count(args)?;
+                self.before_execution();
+
+                for _ in 0..count {
+                    if !self.step(chip_eight) {
+                        break;
+                    }
+                }
+
+                self.show_location(chip_eight);
+            },
+            ("rstep" | "rs", _) => {
+                let count = self.count(args)?;
+                self.before_execution();
+
+                for _ in 0..count {
+                    if !chip_eight.step_back() {
+                        println!("No more history to step back through");
+                        break;
+                    }
+                }
+
+                self.show_location(chip_eight);
+            },
+            ("continue" | "c", []) => {
+                self.before_execution();
+                self.resume(chip_eight);
+                self.show_location(chip_eight);
+            },
+            ("break" | "b", []) => {
+                for (&addr, actions) in &self.breakpoints {
+                    let actions: Vec<String> = actions.iter().map(Action::to_string).collect();
+
+                    match actions.is_empty() {
+                        true => println!("{} (0x{:03X})", self.symbols.describe(addr), addr),
+                        false => println!("{} (0x{:03X}): {}", self.symbols.describe(addr), addr, actions.join(", ")),
+                    }
+                }
+            },
+            ("break" | "b", [addr, rest @ ..]) => {
+                let addr = self.value(addr)?;
+                let mut words = rest.iter().copied();
+                let mut actions = vec![];
+
+                while let Some(word) = words.next() {
+                    let name = word.strip_prefix("action=")
+                        .ok_or_else(|| format!("Expected action=log, action=dump-frame or action=script, not '{}'", word))?;
+
+                    actions.push(Action::parse(name, &mut words)?);
+                }
+
+                self.breakpoints.insert(addr, actions);
+            },
+            ("delete" | "d", [addr]) => {
+                let addr = self.value(addr)?;
+
+                if self.breakpoints.remove(&addr).is_none() {
+                    return Err(format!("No breakpoint at 0x{:03X}", addr));
+                }
+            },
+            ("regs" | "r", []) => self.show_registers(chip_eight),
+            ("keys" | "k", []) => self.show_keys(chip_eight),
+            ("bt", []) => println!("{}", self.symbols.backtrace(chip_eight.pc(), chip_eight.call_stack())),
+            ("mem" | "m", _) => {
+                let addr = match args.first() {
+                    Some(addr) => self.value(addr)?,
+                    None => self.next_dump.unwrap_or(chip_eight.pc() - chip_eight.pc() % ROW_LEN),
+                };
+
+                let len = match args.get(1) {
+                    Some(len) => self.value(len)?,
+                    None => ROW_LEN * DUMP_ROWS,
+                };
+
+                self.dump(chip_eight, addr, len);
+            },
+            ("sprite" | "sp", _) => {
+                let (next_addr, next_rows) = self.next_sprite.unwrap_or_else(|| {
+                    (chip_eight.index(), pending_draw_rows(chip_eight).unwrap_or(SPRITE_ROWS))
+                });
+
+                let addr = match args.first() {
+                    Some(addr) => self.value(addr)?,
+                    None => next_addr,
+                };
+
+                let rows = match args.get(1) {
+                    Some(rows) => self.value(rows)?,
+                    None => next_rows,
+                };
+
+                self.sprite(chip_eight, addr, rows)?;
+            },
+            ("set", ["quirk", name, value]) => {
+                let mut quirks = chip_eight.quirks();
+                quirks.set(name, value).map_err(|error| error.to_string())?;
+
+                chip_eight.set_quirks(quirks);
+            },
+            ("quirks", []) => {
+                let quirks = chip_eight.quirks();
+
+                for name in QuirksConfig::NAMES {
+                    println!("{:<20}{}", name, quirks.get(name).unwrap_or_default());
+                }
+            },
+            ("set", [target, values @ ..]) if !values.is_empty() => {
+                let bytes = values.iter()
+                    .map(|value| self.byte(value))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if let Some(reg) = parse_register(target) {
+                    match bytes[..] {
+                        [value] => chip_eight.set_register(reg as u8, value),
+                        _ => return Err("Registers take a single byte".to_string()),
+                    }
+                } else {
+                    let addr = self.value(target)?;
+
+                    chip_eight.memory_mut()
+                        .load_buf(addr, &bytes)
+                        .map_err(|error| error.to_string())?;
+                }
+            },
+            ("freeze" | "f", []) => {
+                for (&addr, &value) in chip_eight.freezes() {
+                    println!("{} (0x{:03X}) = 0x{:02X}", self.symbols.describe(addr), addr, value);
+                }
+            },
+            ("freeze" | "f", [addr, value]) => {
+                let addr = self.value(addr)?;
+                let value = self.byte(value)?;
+
+                chip_eight.freeze(addr, value).map_err(|error| error.to_string())?;
+            },
+            ("unfreeze" | "uf", [addr]) => {
+                let addr = self.value(addr)?;
+
+                if !chip_eight.unfreeze(addr) {
+                    return Err(format!("0x{:03X} isn't frozen", addr));
+                }
+            },
+            ("watch" | "w", []) => {
+                for watch in chip_eight.watches().iter() {
+                    let value = watch.expr.evaluate(chip_eight).map_or("-".to_string(), |value| format!("0x{:02X}", value));
+                    let plot = if watch.plot { " (plotted)" } else { "" };
+
+                    println!("{} = {}{}", watch.expr, value, plot);
+                }
+            },
+            ("watch" | "w", [watch]) => {
+                let watch: Watch = watch.parse().map_err(|error: WatchExprError| error.to_string())?;
+                chip_eight.add_watch(watch);
+            },
+            ("unwatch" | "uw", [expr]) => {
+                let expr: WatchExpr = expr.parse().map_err(|error: WatchExprError| error.to_string())?;
+
+                if !chip_eight.remove_watch(&expr) {
+                    return Err(format!("{} isn't watched", expr));
+                }
+            },
+            ("heatmap" | "hm", ["clear"]) => self.heatmap.clear(),
+            ("heatmap" | "hm", _) => {
+                let addr = match args.first() {
+                    Some(addr) => self.value(addr)?,
+                    None => 0,
+                };
+
+                let len = match args.get(1) {
+                    Some(len) => self.value(len)?,
+                    None => self.heatmap.len(),
+                };
+
+                if addr >= self.heatmap.len() {
+                    return Err(format!("0x{:03X} is outside of memory", addr));
+                }
+
+                print!("{}", self.heatmap.render(addr, len, self.color));
+            },
+            ("save", _) => {
+                let slot = self.slot(chip_eight, args)?;
+                let path = chip_eight.save_state(slot).map_err(|error| error.to_string())?;
+
+                println!("Saved to {}", path.display());
+            },
+            ("load", _) => {
+                let slot = self.slot(chip_eight, args)?;
+                chip_eight.load_state(slot).map_err(|error| error.to_string())?;
+
+                self.before_execution();
+                self.show_location(chip_eight);
+            },
+            ("states", []) => {
+                let rom_storage = chip_eight.rom_storage().ok_or("No data directory to keep states in")?;
+
+                for (slot, saved_at) in rom_storage.states() {
+                    println!("{}: saved {}", slot, storage::age(saved_at));
+                }
+            },
+            ("states", ["rm", slot]) => {
+                let slot = self.slot(chip_eight, &[slot])?;
+                let rom_storage = chip_eight.rom_storage().ok_or("No data directory to keep states in")?;
+
+                rom_storage.delete_state(slot).map_err(|error| error.to_string())?;
+            },
+            ("theme", []) => {
+                for (name, colors) in THEMES {
+                    let current = if colors.0 == chip_eight.colors() { " (current)" } else { "" };
+                    println!("{}{}", name, current);
+                }
+            },
+            ("theme", [colors]) => {
+                let colors: Colors = colors.parse()?;
+                chip_eight.set_colors(colors.0);
+            },
+            ("scale", []) => println!("{}", chip_eight.scale_factor()),
+            ("scale", [scale_factor]) => {
+                let scale_factor = self.value(scale_factor)?;
+
+                if !(MIN_SCALE..=MAX_SCALE).contains(&scale_factor) {
+                    return Err(format!("Scale factors go from {} to {}", MIN_SCALE, MAX_SCALE));
+                }
+
+                chip_eight.set_scale_factor(scale_factor);
+            },
+            ("quit" | "q", []) => chip_eight.stop_handle().stop(),
+            _ => return Err(format!("Unknown command '{}', try help", words.join(" "))),
+        }
+
+        Ok(())
+    }
+
+    // Execute the next instruction, waiting out the rest of the frame first if a
+    // draw is holding execution. False if the program faulted or exited.
+    fn step(&mut self, chip_eight: &mut ChipEight) -> bool {
+        if chip_eight.waiting_for_vblank() {
+            chip_eight.vblank();
+            self.cycles = 0;
+        }
+
+        self.cycle(chip_eight)
+    }
+
+    // Run a cycle, counting towards the next vblank. False if the program faulted,
+    // exited or trapped on a machine call. A trapped call is stepped past first.
+    fn cycle(&mut self, chip_eight: &mut ChipEight) -> bool {
+        if chip_eight.trapped().is_some() {
+            chip_eight.resume_trap();
+            return true;
+        }
+
+        if let Err(error) = chip_eight.try_cycle() {
+            println!("{}", error);
+            return false;
+        }
+
+        if let Some(addr) = chip_eight.trapped() {
+            println!("Trapped on machine call at {}", self.symbols.describe(addr));
+            return false;
+        }
+
+        self.cycles += 1;
+
+        if self.cycles >= chip_eight.cycles_per_frame() {
+            chip_eight.vblank();
+            self.cycles = 0;
+        }
+
+        if chip_eight.exited() {
+            println!("Program exited");
+            return false;
+        }
+
+        true
+    }
+
+    // Run at the configured speed until a breakpoint, a fault, or an interrupt.
+    fn resume(&mut self, chip_eight: &mut ChipEight) {
+        self.interrupt.clear();
+
+        let mut frame_started = Instant::now();
+        let mut first = true;
+        // Where the last actions ran, so an instruction that holds execution where it
+        // is, such as waiting for a key, runs them once
+        let mut acted_at = None;
+
+        loop {
+            let pc = chip_eight.pc();
+
+            match self.breakpoints.get(&pc) {
+                _ if chip_eight.waiting_for_vblank() => (),
+                // The breakpoint execution is resuming from doesn't count
+                Some(actions) if actions.is_empty() && !first => {
+                    println!("Breakpoint at {}", self.symbols.describe(pc));
+                    return;
+                },
+                Some(actions) if !actions.is_empty() && acted_at != Some(pc) => {
+                    acted_at = Some(pc);
+
+                    for action in actions.clone() {
+                        self.act(chip_eight, &action);
+                    }
+
+                    if chip_eight.stop_handle().is_stopped() {
+                        return;
+                    }
+                },
+                _ => (),
+            }
+
+            if acted_at != Some(pc) {
+                acted_at = None;
+            }
+
+            first = false;
+
+            if !self.cycle(chip_eight) {
+                return;
+            }
+
+            if self.cycles == 0 {
+                if self.interrupt.is_stopped() || chip_eight.stop_handle().is_stopped() {
+                    println!("Interrupted");
+                    return;
+                }
+
+                if !chip_eight.config().unthrottled() {
+                    thread::sleep(FRAME_DURATION.saturating_sub(frame_started.elapsed()));
+                }
+
+                frame_started = Instant::now();
+            }
+        }
+    }
+
+    // Run a breakpoint's action, printing any error a script's command gives.
+    fn act(&mut self, chip_eight: &mut ChipEight, action: &Action) {
+        match action {
+            Action::Log(parts) => {
+                let text: String = parts.iter()
+                    .map(|part| match part {
+                        LogPart::Text(text) => text.clone(),
+                        LogPart::Value(expr) => expr.evaluate(chip_eight).map_or("-".to_string(), |value| format!("0x{:02X}", value)),
+                    })
+                    .collect();
+
+                println!("{}", text);
+            },
+            Action::DumpFrame => {
+                let (width, _) = chip_eight.resolution();
+
+                println!("Frame at {}:", self.symbols.describe(chip_eight.pc()));
+                println!("{}", raster::render(chip_eight.frame_buffer(), width));
+            },
+            Action::Script(commands) => {
+                for command in commands {
+                    let words = split_words(command);
+                    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+                    if let Err(message) = self.command(chip_eight, &words) {
+                        println!("{}", message);
+                    }
+                }
+            },
+        }
+    }
+
+    fn show_location(&self, chip_eight: &ChipEight) {
+        let pc = chip_eight.pc();
+
+        let instruction = match chip_eight.memory().read_buf(pc, 2) {
+            Ok(bytes) => {
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+
+                match Instruction::decode(opcode, chip_eight.config().profile) {
+                    Ok(instruction) => format!("{:04X} {:?}", opcode, instruction),
+                    Err(error) => format!("{:04X} ({})", opcode, error),
+                }
+            },
+            Err(error) => error.to_string(),
+        };
+
+        println!("{} (0x{:03X}): {}", self.symbols.describe(pc), pc, instruction);
+    }
+
+    fn show_registers(&self, chip_eight: &ChipEight) {
+        let (delay, sound) = chip_eight.timers();
+
+        println!(
+            "pc {} (0x{:03X})  i 0x{:03X}  delay {}  sound {}  stack {}",
+            self.symbols.describe(chip_eight.pc()),
+            chip_eight.pc(),
+            chip_eight.index(),
+            delay,
+            sound,
+            chip_eight.stack().len(),
+        );
+
+        let registers: Vec<String> = chip_eight.registers().iter()
+            .enumerate()
+            .map(|(reg, value)| format!("v{:X} {:02X}", reg, value))
+            .collect();
+
+        println!("{}", registers[..8].join("  "));
+        println!("{}", registers[8..].join("  "));
+    }
+
+    fn show_keys(&self, chip_eight: &ChipEight) {
+        let key_map = &chip_eight.config().input.key_map;
+
+        for row in KEYPAD {
+            let cells: Vec<String> = row.iter()
+                .map(|&key| {
+                    let binding = key_map.iter()
+                        .find(|(mapped, _)| *mapped == key)
+                        .map_or("-", |(_, name)| name.as_str());
+                    let text = format!("{:X} {:<8}", key as u8, format!("({})", binding));
+
+                    match (chip_eight.keys_down().contains(&key), self.color) {
+                        (true, true) => format!(" {}{}\x1b[0m", Mark::Pc.ansi(), text),
+                        (true, false) => format!("*{}", text),
+                        (false, _) => format!(" {}", text),
+                    }
+                })
+                .collect();
+
+            println!("{}", cells.join(" "));
+        }
+
+        match chip_eight.key_wait() {
+            Some(KeyWait::Press(_)) => println!("FX0A is waiting for a key to be pressed"),
+            Some(KeyWait::Release(_, key)) => println!("FX0A is waiting for key {:X} to be released", key as u8),
+            None => (),
+        }
+    }
+
+    fn dump(&mut self, chip_eight: &ChipEight, addr: usize, len: usize) {
+        let memory = chip_eight.memory();
+        let end = (addr + len).min(memory.len());
+
+        if addr >= end {
+            println!("0x{:03X} is outside of memory", addr);
+            return;
+        }
+
+        // Reads through mapped regions may have side effects, so only plain memory is shown
+        for row in (addr..end).step_by(ROW_LEN) {
+            let mut line = format!("0x{:03X}:", row);
+
+            for byte_addr in row..(row + ROW_LEN).min(end) {
+                let text = if memory.is_mapped(byte_addr..byte_addr + 1) {
+                    "--".to_string()
+                } else {
+                    match memory.read_byte(byte_addr) {
+                        Ok(byte) => format!("{:02X}", byte),
+                        Err(_) => "??".to_string(),
+                    }
+                };
+
+                match (self.mark(chip_eight, byte_addr), self.color) {
+                    (Some(mark), true) => line.push_str(&format!(" {}{}\x1b[0m", mark.ansi(), text)),
+                    (Some(mark), false) => line.push_str(&format!("{}{}", mark.symbol(), text)),
+                    (None, _) => line.push_str(&format!(" {}", text)),
+                }
+            }
+
+            if let Some(label) = self.symbols.label(row) {
+                line.push_str(&format!("  {}", label));
+            }
+
+            println!("{}", line);
+        }
+
+        self.next_dump = Some(end);
+    }
+
+    fn sprite(&mut self, chip_eight: &ChipEight, addr: usize, rows: usize) -> Result<(), String> {
+        let memory = chip_eight.memory();
+
+        if memory.is_mapped(addr..addr + rows) {
+            return Err("Sprites can't be shown from mapped regions".to_string());
+        }
+
+        let bytes = memory.read_buf(addr, rows).map_err(|error| error.to_string())?;
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+        println!("{} (0x{:03X}), 8x{}: {}", self.symbols.describe(addr), addr, rows, hex.join(" "));
+        println!("+--------+");
+
+        for line in raster::render(&raster::sprite_pixels(&bytes), 8).lines() {
+            println!("|{}|", line);
+        }
+
+        println!("+--------+");
+
+        self.next_sprite = Some((addr + rows, rows));
+        Ok(())
+    }
+
+    fn mark(&self, chip_eight: &ChipEight, addr: usize) -> Option<Mark> {
+        let memory_config = &chip_eight.config().memory;
+        let font = memory_config.font_start..memory_config.font_start + memory_config.default_font.len();
+        let big_font = memory_config.big_font_start..memory_config.big_font_start + memory_config.default_big_font.len();
+
+        if (chip_eight.pc()..chip_eight.pc() + 2).contains(&addr) {
+            Some(Mark::Pc)
+        } else if addr == chip_eight.index() {
+            Some(Mark::Index)
+        } else if self.written.borrow().get(addr).is_some_and(|&written| written) {
+            Some(Mark::Written)
+        } else if font.contains(&addr) || big_font.contains(&addr) {
+            Some(Mark::Font)
+        } else {
+            None
+        }
+    }
+
+    // Forget what was written and viewed while stopped, before execution carries on.
+    fn before_execution(&mut self) {
+        self.written.borrow_mut().fill(false);
+        self.next_sprite = None;
+    }
+
+    fn count(&self, args: &[&str]) -> Result<usize, String> {
+        match args {
+            [] => Ok(1),
+            [count] => self.value(count),
+            _ => Err("Expected at most one count".to_string()),
+        }
+    }
+
+    // A number, or the address of a label.
+    fn value(&self, text: &str) -> Result<usize, String> {
+        let parsed = match text.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => text.parse().ok(),
+        };
+
+        parsed
+            .or_else(|| self.symbols.addr(text))
+            .ok_or_else(|| format!("'{}' is not a number or a known label", text))
+    }
+
+    // Save state slot given, or the one the hotkeys use.
+    fn slot(&self, chip_eight: &ChipEight, args: &[&str]) -> Result<u8, String> {
+        match args {
+            [] => Ok(chip_eight.slot()),
+            [slot] => match self.byte(slot)? {
+                slot @ 1..=SLOTS => Ok(slot),
+                slot => Err(format!("No slot {}, slots go from 1 to {}", slot, SLOTS)),
+            },
+            _ => Err("Expected at most one slot".to_string()),
+        }
+    }
+
+    fn byte(&self, text: &str) -> Result<u8, String> {
+        let value = self.value(text)?;
+        u8::try_from(value).map_err(|_| format!("{} does not fit in a byte", value))
+    }
+}
+
+// Rows the instruction at the program counter is about to draw, if it is a draw.
+fn pending_draw_rows(chip_eight: &ChipEight) -> Option<usize> {
+    let bytes = chip_eight.memory().read_buf(chip_eight.pc(), 2).ok()?;
+
+    match Instruction::decode(u16::from_be_bytes([bytes[0], bytes[1]]), chip_eight.config().profile) {
+        Ok(Instruction::Draw(_, _, rows)) => Some(rows as usize),
+        _ => None,
+    }
+}
+
+// Words of a command, with text in double quotes kept as one word without them.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = None;
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            },
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
+
+// Text to log, with each % followed by a watch expression replaced by its value
+// and %% by a %.
+fn parse_log(text: &str) -> Result<Vec<LogPart>, String> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('%') {
+        literal.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if let Some(after) = rest.strip_prefix('%') {
+            literal.push('%');
+            rest = after;
+            continue;
+        }
+
+        // Memory runs to its closing bracket, anything else to the end of the word
+        let len = match rest.get(..4) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("mem[") => rest.find(']').map_or(rest.len(), |end| end + 1),
+            _ => rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len()),
+        };
+
+        let expr = rest[..len].parse().map_err(|error: WatchExprError| error.to_string())?;
+        parts.extend((!literal.is_empty()).then(|| LogPart::Text(std::mem::take(&mut literal))));
+        parts.push(LogPart::Value(expr));
+        rest = &rest[len..];
+    }
+
+    literal.push_str(rest);
+    parts.extend((!literal.is_empty()).then_some(LogPart::Text(literal)));
+
+    Ok(parts)
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+
+    match digit.len() {
+        1 => usize::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
@@ -1,32 +1,175 @@
 mod sdl3;
+mod terminal;
+pub mod headless;
+pub mod null;
+pub mod websocket;
+#[cfg(feature = "embedded")]
+pub mod embedded;
 
 use std::{
+    collections::BTreeSet,
     rc::Rc,
     fmt,
-    error::Error
+    error::Error,
+    sync::mpmc::Sender,
+    time::Instant,
 };
 
+use clap::ValueEnum;
+use log::warn;
+use null::{NullAudio, NullDisplay, NullInput};
 use sdl3::{SDL3Audio, SDL3Display, SDL3Input};
+use terminal::TerminalDisplay;
 
-use crate::config;
+use crate::{bus::ControlEvent, config};
 
-pub enum DeviceEvent {
-    PlayTone,
-    StopTone,
-    Draw,
+pub trait Display {
+    // Draw a completed frame, numbered from 1 in the order frames are completed.
+    // Frames only arrive once finished, at the display interrupt, so they never show
+    // a sprite half drawn.
+    fn draw(&mut self, frame: &[bool], frame_number: u64);
+
+    // Draw a completed frame, given which of its rows changed since the previous one
+    // (top first), so backends that are slow to write to can update just those.
+    // Redraws the whole frame unless overridden.
+    fn draw_changes(&mut self, frame: &[bool], frame_number: u64, _changed_rows: &[usize]) {
+        self.draw(frame, frame_number);
+    }
+
+    // Called when the program switches resolution, before the first frame at the new
+    // size is drawn. Frames start out at the configured resolution.
+    fn set_resolution(&mut self, width: usize, height: usize);
+
+    // Draw a frame of ARGB pixels at its own resolution, for extensions with colour.
+    // Displays without colour support ignore these frames.
+    fn draw_color(&mut self, _frame: &[u32], _width: usize, _height: usize) {}
+
+    // Caption the display, such as in its window's title bar, with the ROM and what
+    // the machine is doing. Displays without a title ignore it.
+    fn set_title(&mut self, _title: &str) {}
+
+    // Change the colours of unlit and lit pixels from the next frame drawn.
+    // Displays with colours of their own ignore it.
+    fn set_colors(&mut self, _colors: [(u8, u8, u8); 2]) {}
+
+    // Change how many device pixels a virtual pixel takes up, resizing the window
+    // to match. Displays of a fixed size ignore it.
+    fn set_scale(&mut self, _scale_factor: usize) {}
 }
 
+// Open the configured display, or if it fails the first of its fallbacks that
+// opens, warning of each that didn't. The last resort is the null display.
+pub fn create_display_device(config: Rc<config::DisplayConfig>) -> Box<dyn Display> {
+    let mut engine = config.engine;
 
-pub trait Display {
-    fn draw(&mut self, frame: &[bool]);
+    loop {
+        let opened: Result<Box<dyn Display>, String> = match engine {
+            config::DisplayEngine::SDL3 => SDL3Display::new(config.clone()).map(|display| Box::new(display) as _),
+            config::DisplayEngine::Terminal => {
+                TerminalDisplay::new(config.width, config.height, config.colors).map(|display| Box::new(display) as _)
+            },
+            config::DisplayEngine::None => Ok(Box::new(NullDisplay)),
+        };
+
+        match (opened, engine.fallback()) {
+            (Ok(display), _) => return display,
+            (Err(error), Some(fallback)) => {
+                warn!("Failed to open the {} display ({}), falling back to {}", name(engine), error, name(fallback));
+                engine = fallback;
+            },
+            (Err(error), None) => {
+                warn!("Failed to open the {} display ({})", name(engine), error);
+                return Box::new(NullDisplay);
+            },
+        }
+    }
 }
 
-pub fn create_display_device(config: Rc<config::DisplayConfig>) -> Option<Box<dyn Display>> {
-    match config.engine {
-        config::DisplayEngine::SDL3 => {
-            Some(Box::new(SDL3Display::new(config)))
-        },
-        _ => None,
+// Wrap a display so it's drawn frames rotated and flipped as configured, for screens
+// that aren't mounted upright. Displays configured upright are left as they are.
+pub fn orient_display(display: Box<dyn Display>, config: Rc<config::DisplayConfig>) -> Box<dyn Display> {
+    if config.upright() {
+        return display;
+    }
+
+    let mut oriented = Oriented {
+        display,
+        width: config.width,
+        height: config.height,
+        config,
+    };
+    oriented.set_resolution(oriented.width, oriented.height);
+
+    Box::new(oriented)
+}
+
+// Display rotating and flipping each frame before passing it on, so that every
+// backend sees frames the way round they are to be shown.
+struct Oriented {
+    display: Box<dyn Display>,
+    config: Rc<config::DisplayConfig>,
+
+    // Resolution of the frames being drawn, before orienting them
+    width: usize,
+    height: usize,
+}
+
+impl Oriented {
+    fn orient<T: Copy + Default>(&self, frame: &[T], width: usize, height: usize) -> Vec<T> {
+        let (screen_width, _) = self.config.oriented_size(width, height);
+        let mut oriented = vec![T::default(); width * height];
+
+        for (index, &pixel) in frame.iter().enumerate().take(width * height) {
+            let (x, y) = self.config.orient(index % width, index / width, width, height);
+            oriented[y * screen_width + x] = pixel;
+        }
+
+        oriented
+    }
+}
+
+impl Display for Oriented {
+    fn draw(&mut self, frame: &[bool], frame_number: u64) {
+        let frame = self.orient(frame, self.width, self.height);
+        self.display.draw(&frame, frame_number);
+    }
+
+    fn draw_changes(&mut self, frame: &[bool], frame_number: u64, changed_rows: &[usize]) {
+        // Rows of the screen the changed rows end up on, which a quarter turn makes
+        // every row
+        let changed_rows: BTreeSet<usize> = changed_rows.iter()
+            .flat_map(|&y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.config.orient(x, y, self.width, self.height).1)
+            .collect();
+
+        let frame = self.orient(frame, self.width, self.height);
+        self.display.draw_changes(&frame, frame_number, &changed_rows.into_iter().collect::<Vec<_>>());
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+
+        let (width, height) = self.config.oriented_size(width, height);
+        self.display.set_resolution(width, height);
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        let frame = self.orient(frame, width, height);
+        let (width, height) = self.config.oriented_size(width, height);
+        self.display.draw_color(&frame, width, height);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.display.set_title(title);
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.display.set_colors(colors);
+    }
+
+    fn set_scale(&mut self, scale_factor: usize) {
+        self.display.set_scale(scale_factor);
     }
 }
 
@@ -34,14 +177,43 @@ pub fn create_display_device(config: Rc<config::DisplayConfig>) -> Option<Box<dy
 pub trait Audio {
     fn play_tone(&self);
     fn stop_tone(&self);
+
+    // Play 8-bit unsigned samples at the given rate, for extensions with digitised
+    // sound. Audio devices without sample playback stay silent.
+    fn play_samples(&self, _samples: &[u8], _rate: u32, _looping: bool) {}
+    fn stop_samples(&self) {}
+
+    // Replace the tone with a pattern of 128 1-bit samples, most significant bit
+    // first, looped at the given rate in samples per second. None restores the
+    // default tone. Audio devices without pattern playback keep the default tone.
+    fn set_pattern(&self, _pattern: Option<&[u8; 16]>, _rate: f32) {}
+
+    // Scale everything played from now on by volume, from 0 (silent) to 1 (full
+    // volume). Audio devices without volume control play at full volume.
+    fn set_volume(&self, _volume: f32) {}
 }
 
-pub fn create_audio_device(config: Rc<config::AudioConfig>) -> Option<Box<dyn Audio>> {
-    match config.engine {
-        config::AudioEngine::SDL3 => {
-            Some(Box::new(SDL3Audio::new(config)))
-        },
-        _ => None,
+// Open the configured audio device, falling back as displays do.
+pub fn create_audio_device(config: Rc<config::AudioConfig>) -> Box<dyn Audio> {
+    let mut engine = config.engine;
+
+    loop {
+        let opened: Result<Box<dyn Audio>, String> = match engine {
+            config::AudioEngine::SDL3 => SDL3Audio::new(config.clone()).map(|audio| Box::new(audio) as _),
+            config::AudioEngine::None => Ok(Box::new(NullAudio)),
+        };
+
+        match (opened, engine.fallback()) {
+            (Ok(audio), _) => return audio,
+            (Err(error), Some(fallback)) => {
+                warn!("Failed to open the {} audio ({}), falling back to {}", name(engine), error, name(fallback));
+                engine = fallback;
+            },
+            (Err(error), None) => {
+                warn!("Failed to open the {} audio ({})", name(engine), error);
+                return Box::new(NullAudio);
+            },
+        }
     }
 }
 
@@ -58,7 +230,7 @@ impl fmt::Display for InvalidKeyError {
 impl Error for InvalidKeyError {}
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Key {
     _0, _1, _2, _3,
     _4, _5, _6, _7,
@@ -92,15 +264,192 @@ impl TryFrom<u8> for Key {
     }
 }
 
+// A key being pressed or released, and when.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+    pub time: Instant,
+}
+
+impl KeyEvent {
+    // An event happening now.
+    pub fn new(key: Key, pressed: bool) -> Self {
+        Self { key, pressed, time: Instant::now() }
+    }
+}
+
 pub trait Input {
+    // Presses and releases since the last poll, oldest first. Every key reported
+    // pressed must eventually be reported released, even if the device loses focus
+    // or goes away.
+    fn poll_events(&mut self) -> Vec<KeyEvent>;
+}
+
+// Input devices that can only tell which keys are held down at the moment. Wrap one
+// in a PolledInput to use it as an Input.
+pub trait KeyPoller {
     fn get_keys_down(&mut self) -> Vec<Key>;
 }
 
-pub fn create_input_device(config: Rc<config::InputConfig>) -> Option<Box<dyn Input>> {
-    match config.engine {
-        config::InputEngine::SDL3 => {
-            Some(Box::new(SDL3Input::new(config)))
-        },
-        _ => None,
+// Works out events by comparing one poll of the keys held down to the next. A tap
+// shorter than the time between polls is missed.
+pub struct PolledInput<P> {
+    poller: P,
+    keys_down: Vec<Key>,
+}
+
+impl<P: KeyPoller> PolledInput<P> {
+    pub fn new(poller: P) -> Self {
+        Self { poller, keys_down: vec![] }
+    }
+}
+
+impl<P: KeyPoller> Input for PolledInput<P> {
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        let keys_down = self.poller.get_keys_down();
+
+        let released = self.keys_down.iter()
+            .filter(|key| !keys_down.contains(key))
+            .map(|&key| KeyEvent::new(key, false));
+        let pressed = keys_down.iter()
+            .filter(|key| !self.keys_down.contains(key))
+            .map(|&key| KeyEvent::new(key, true));
+        let events = released.chain(pressed).collect();
+
+        self.keys_down = keys_down;
+        events
+    }
+}
+
+// Several input devices used as one, such as a keyboard and a gamepad, or a
+// keyboard alongside network input. A key is held down while any device holds it,
+// and the devices' events are interleaved in the order they happened.
+pub struct MergedInput {
+    inputs: Vec<Box<dyn Input>>,
+    // Keys held down on each device
+    held: Vec<Vec<Key>>,
+}
+
+impl MergedInput {
+    pub fn new(inputs: Vec<Box<dyn Input>>) -> Self {
+        Self {
+            held: vec![vec![]; inputs.len()],
+            inputs,
+        }
+    }
+}
+
+impl Input for MergedInput {
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        let mut events: Vec<(usize, KeyEvent)> = self.inputs.iter_mut()
+            .enumerate()
+            .flat_map(|(source, input)| input.poll_events().into_iter().map(move |event| (source, event)))
+            .collect();
+        events.sort_by_key(|(_, event)| event.time);
+
+        // Only the first press of a key and its last release get through
+        events.into_iter()
+            .filter(|&(source, event)| {
+                let held_elsewhere = self.held.iter()
+                    .enumerate()
+                    .any(|(other, keys)| other != source && keys.contains(&event.key));
+                let keys = &mut self.held[source];
+
+                match (event.pressed, keys.contains(&event.key)) {
+                    (true, false) => keys.push(event.key),
+                    (false, true) => keys.retain(|&key| key != event.key),
+                    _ => return false,
+                }
+
+                !held_elsewhere
+            })
+            .map(|(_, event)| event)
+            .collect()
+    }
+}
+
+// Open the configured input device, falling back as displays do.
+pub fn create_input_device(
+    config: Rc<config::InputConfig>,
+    event_channel: Sender<ControlEvent>,
+) -> Box<dyn Input> {
+    let mut engine = config.engine;
+
+    loop {
+        let opened: Result<Box<dyn Input>, String> = match engine {
+            config::InputEngine::SDL3 => {
+                SDL3Input::new(config.clone(), event_channel.clone()).map(|input| Box::new(input) as _)
+            },
+            config::InputEngine::None => Ok(Box::new(NullInput)),
+        };
+
+        match (opened, engine.fallback()) {
+            (Ok(input), _) => return input,
+            (Err(error), Some(fallback)) => {
+                warn!("Failed to open the {} input ({}), falling back to {}", name(engine), error, name(fallback));
+                engine = fallback;
+            },
+            (Err(error), None) => {
+                warn!("Failed to open the {} input ({})", name(engine), error);
+                return Box::new(NullInput);
+            },
+        }
     }
 }
+
+// Every display, audio and input engine, with whether it can be opened here and why
+// not if it can't, checked without opening any windows or devices. Each engine's
+// fallbacks are noted after it.
+pub fn list_engines() -> String {
+    let mut text = String::new();
+
+    let mut list = |kind: &str, engines: Vec<(String, Result<(), String>, Option<String>)>| {
+        text.push_str(&format!("{}:\n", kind));
+
+        for (engine, available, fallback) in engines {
+            let status = match available {
+                Ok(()) => "available".to_string(),
+                Err(error) => format!("unavailable: {}", error),
+            };
+            let fallback = fallback.map(|fallback| format!(", falls back to {}", fallback)).unwrap_or_default();
+
+            text.push_str(&format!("  {:<10}{}{}\n", engine, status, fallback));
+        }
+    };
+
+    list("Display", config::DisplayEngine::value_variants().iter().map(|&engine| {
+        let available = match engine {
+            config::DisplayEngine::SDL3 => sdl3::video().map(|_| ()),
+            config::DisplayEngine::Terminal => terminal::probe(),
+            config::DisplayEngine::None => Ok(()),
+        };
+
+        (name(engine), available, engine.fallback().map(name))
+    }).collect());
+
+    list("Audio", config::AudioEngine::value_variants().iter().map(|&engine| {
+        let available = match engine {
+            config::AudioEngine::SDL3 => sdl3::audio().map(|_| ()),
+            config::AudioEngine::None => Ok(()),
+        };
+
+        (name(engine), available, engine.fallback().map(name))
+    }).collect());
+
+    list("Input", config::InputEngine::value_variants().iter().map(|&engine| {
+        let available = match engine {
+            config::InputEngine::SDL3 => sdl3::event_pump().map(|_| ()),
+            config::InputEngine::None => Ok(()),
+        };
+
+        (name(engine), available, engine.fallback().map(name))
+    }).collect());
+
+    text
+}
+
+// Name an engine is given on the command line.
+fn name(engine: impl ValueEnum) -> String {
+    engine.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+}
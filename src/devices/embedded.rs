@@ -0,0 +1,188 @@
+use std::collections::BTreeSet;
+
+use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+use log::warn;
+
+use super::{Display, Key, KeyPoller, PolledInput};
+
+
+/* Display */
+
+pub const SSD1306_WIDTH: usize = 128;
+pub const SSD1306_HEIGHT: usize = 64;
+
+// Sets up a 128x64 panel with its charge pump on, addressed a row of 8 pixels
+// high (a page) at a time from the top left.
+const SSD1306_INIT: [u8; 25] = [
+    0xAE,       // Display off
+    0xD5, 0x80, // Clock divide ratio
+    0xA8, 0x3F, // Multiplex ratio, 64 rows
+    0xD3, 0x00, // No display offset
+    0x40,       // Start at line 0
+    0x8D, 0x14, // Charge pump on
+    0x20, 0x00, // Horizontal addressing
+    0xA1,       // Column 127 is segment 0
+    0xC8,       // Scan rows from the bottom up
+    0xDA, 0x12, // Alternative row pin configuration
+    0x81, 0xCF, // Contrast
+    0xD9, 0xF1, // Pre-charge period
+    0xDB, 0x40, // VCOMH deselect level
+    0xA4,       // Show the contents of RAM
+    0xA6,       // Lit pixels are on
+    0xAF,       // Display on
+];
+
+// SSD1306 OLED panel on a 4-wire SPI bus, with a data/command pin telling the
+// panel whether bytes written are pixels or commands. Frames are scaled up by a
+// whole number to fit the panel, so the usual 64x32 frames fill it at double size,
+// and centred. Frames too large for the panel are cropped.
+//
+// Works with any embedded-hal implementation, such as linux-embedded-hal's
+// spidev and GPIO character device wrappers on a Raspberry Pi.
+pub struct Ssd1306Display<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+
+    // Resolution of the frames being drawn, in virtual pixels
+    width: usize,
+    height: usize,
+
+    // Only the first of a run of failed writes is logged, so a loose wire doesn't
+    // flood the log every frame
+    failing: bool,
+}
+
+impl<SPI: SpiDevice, DC: OutputPin> Ssd1306Display<SPI, DC> {
+    // Set up the panel, drawing frames of the given resolution until told otherwise.
+    pub fn new(spi: SPI, dc: DC, width: usize, height: usize) -> Result<Self, SPI::Error> {
+        let mut display = Self {
+            spi,
+            dc,
+            width,
+            height,
+            failing: false,
+        };
+
+        display.command(&SSD1306_INIT)?;
+        display.draw(&vec![false; width * height], 0);
+
+        Ok(display)
+    }
+
+    fn command(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_low();
+        self.spi.write(bytes)
+    }
+
+    fn data(&mut self, bytes: &[u8]) -> Result<(), SPI::Error> {
+        let _ = self.dc.set_high();
+        self.spi.write(bytes)
+    }
+
+    // Scale of the frame, and where its top left corner is on the panel.
+    fn layout(&self) -> (usize, usize, usize) {
+        let scale = (SSD1306_WIDTH / self.width.max(1))
+            .min(SSD1306_HEIGHT / self.height.max(1))
+            .max(1);
+        let left = SSD1306_WIDTH.saturating_sub(self.width * scale) / 2;
+        let top = SSD1306_HEIGHT.saturating_sub(self.height * scale) / 2;
+
+        (scale, left, top)
+    }
+
+    // Frame in the panel's layout: a byte per column of each page, with the top
+    // row of the page in the least significant bit.
+    fn pages(&self, frame: &[bool]) -> Vec<u8> {
+        let (scale, left, top) = self.layout();
+        let mut pages = vec![0; SSD1306_WIDTH * SSD1306_HEIGHT / 8];
+
+        for (index, _) in frame.iter().enumerate().filter(|(_, &lit)| lit) {
+            let (x, y) = (index % self.width, index / self.width);
+
+            for row in (top + y * scale..).take(scale).filter(|&row| row < SSD1306_HEIGHT) {
+                for column in (left + x * scale..).take(scale).filter(|&column| column < SSD1306_WIDTH) {
+                    pages[row / 8 * SSD1306_WIDTH + column] |= 1 << (row % 8);
+                }
+            }
+        }
+
+        pages
+    }
+
+    // Write the given pages of a frame, from the first column to the last.
+    fn write_pages(&mut self, frame: &[bool], first: usize, last: usize) {
+        let pages = self.pages(frame);
+
+        let written = self.command(&[0x21, 0, SSD1306_WIDTH as u8 - 1, 0x22, first as u8, last as u8])
+            .and_then(|()| self.data(&pages[first * SSD1306_WIDTH..(last + 1) * SSD1306_WIDTH]));
+
+        match written {
+            Ok(()) => self.failing = false,
+            Err(error) if !self.failing => {
+                warn!("Failed to draw to the SSD1306: {:?}", error);
+                self.failing = true;
+            },
+            Err(_) => (),
+        }
+    }
+}
+
+impl<SPI: SpiDevice, DC: OutputPin> Display for Ssd1306Display<SPI, DC> {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        self.write_pages(frame, 0, SSD1306_HEIGHT / 8 - 1);
+    }
+
+    fn draw_changes(&mut self, frame: &[bool], _frame_number: u64, changed_rows: &[usize]) {
+        let (scale, _, top) = self.layout();
+
+        // Pages the changed rows are drawn across once scaled up
+        let pages: BTreeSet<usize> = changed_rows.iter()
+            .flat_map(|&y| top + y * scale..top + (y + 1) * scale)
+            .filter(|&row| row < SSD1306_HEIGHT)
+            .map(|row| row / 8)
+            .collect();
+
+        // Rewrite from the first changed page to the last, which costs less than a
+        // transaction per page when the changes are spread out
+        if let (Some(&first), Some(&last)) = (pages.first(), pages.last()) {
+            self.write_pages(frame, first, last);
+        }
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+}
+
+
+/* Input */
+
+// Buttons wired to GPIO pins, each pressing a key while its pin reads low, as with
+// a button to ground and a pull-up resistor. Wrap in a PolledInput to use as the
+// machine's input, or use GpioButtons::polled.
+pub struct GpioButtons<P> {
+    buttons: Vec<(Key, P)>,
+}
+
+impl<P: InputPin> GpioButtons<P> {
+    pub fn new(buttons: Vec<(Key, P)>) -> Self {
+        Self { buttons }
+    }
+
+    pub fn polled(self) -> PolledInput<Self> {
+        PolledInput::new(self)
+    }
+}
+
+impl<P: InputPin> KeyPoller for GpioButtons<P> {
+    fn get_keys_down(&mut self) -> Vec<Key> {
+        // Pins that can't be read count as released
+        self.buttons.iter_mut()
+            .filter_map(|(key, pin)| pin.is_low().unwrap_or(false).then_some(*key))
+            .collect()
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    cell::{Cell, Ref, RefCell},
+    rc::Rc,
+};
+
+use super::{Audio, Display, Input, Key, KeyEvent};
+use crate::raster;
+
+// Devices backed by plain shared state rather than real peripherals, for hosts that
+// drive the emulator themselves. Keep a clone of each device before handing it to
+// the builder to read and write that state from outside.
+
+
+/* Display */
+
+#[derive(Clone)]
+pub struct FrameDisplay(Rc<RefCell<Vec<bool>>>);
+
+impl FrameDisplay {
+    pub fn new(len: usize) -> Self {
+        Self(Rc::new(RefCell::new(vec![false; len])))
+    }
+
+    // Most recently presented frame
+    pub fn frame(&self) -> Ref<'_, Vec<bool>> {
+        self.0.borrow()
+    }
+
+    // Most recently presented frame as text, given its width, for printing in logs
+    // and test failures.
+    pub fn render(&self, width: usize) -> String {
+        raster::render(&self.frame(), width)
+    }
+}
+
+impl Display for FrameDisplay {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        self.0.borrow_mut().copy_from_slice(frame);
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.0.borrow_mut().resize(width * height, false);
+    }
+}
+
+
+/* Audio */
+
+// Audio pattern bits and their rate in samples per second
+type Pattern = ([u8; 16], f32);
+
+#[derive(Clone, Default)]
+pub struct ToneAudio {
+    playing: Rc<Cell<bool>>,
+    pattern: Rc<Cell<Option<Pattern>>>,
+}
+
+impl ToneAudio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.get()
+    }
+
+    // Audio pattern to play in place of the tone
+    pub fn pattern(&self) -> Option<Pattern> {
+        self.pattern.get()
+    }
+}
+
+impl Audio for ToneAudio {
+    fn play_tone(&self) {
+        self.playing.set(true);
+    }
+
+    fn stop_tone(&self) {
+        self.playing.set(false);
+    }
+
+    fn set_pattern(&self, pattern: Option<&[u8; 16]>, rate: f32) {
+        self.pattern.set(pattern.map(|&bits| (bits, rate)));
+    }
+}
+
+
+/* Input */
+
+#[derive(Clone, Default)]
+pub struct KeyInput {
+    keys: Rc<Cell<[bool; 16]>>,
+    // Changes to the keys since the machine last polled them
+    events: Rc<RefCell<Vec<KeyEvent>>>,
+}
+
+impl KeyInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_key(&self, key: Key, down: bool) {
+        let mut keys = self.keys.get();
+        keys[key as usize] = down;
+        self.set_keys(keys);
+    }
+
+    pub fn set_keys(&self, keys: [bool; 16]) {
+        let previous = self.keys.replace(keys);
+        let mut events = self.events.borrow_mut();
+
+        for (index, (&was_down, &down)) in previous.iter().zip(&keys).enumerate() {
+            if let (true, Ok(key)) = (was_down != down, Key::try_from(index as u8)) {
+                events.push(KeyEvent::new(key, down));
+            }
+        }
+    }
+}
+
+impl Input for KeyInput {
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        self.events.take()
+    }
+}
@@ -0,0 +1,39 @@
+use super::{Audio, Display, Input, KeyEvent};
+
+// Devices that do nothing, for headless runs and tests: frames go nowhere, sound
+// is silent, and no key is ever pressed but those passed to ChipEight::key_event.
+// The `none` engines select them, and the machine falls back to them when no other
+// engine opens.
+
+
+/* Display */
+
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn draw(&mut self, _frame: &[bool], _frame_number: u64) {}
+
+    fn set_resolution(&mut self, _width: usize, _height: usize) {}
+}
+
+
+/* Audio */
+
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn play_tone(&self) {}
+
+    fn stop_tone(&self) {}
+}
+
+
+/* Input */
+
+pub struct NullInput;
+
+impl Input for NullInput {
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        vec![]
+    }
+}
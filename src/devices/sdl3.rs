@@ -1,25 +1,57 @@
-use std::rc::Rc;
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpmc::Sender,
+        Arc,
+        Mutex,
+    },
+};
 
+use log::{debug, warn};
 use sdl3::{
     pixels::Color,
     render,
     audio,
+    event::{Event, WindowEvent},
+    keyboard::{Mod, Scancode},
     EventPump,
 };
 
-use crate::config::{AudioConfig, DisplayConfig, InputConfig};
+use crate::{
+    bus::ControlEvent,
+    config::{AudioConfig, DisplayConfig, InputConfig},
+    storage::SLOTS,
+    system::{MAX_SPEED, MIN_SPEED},
+};
+
+use super::{Audio, Display, Input, Key, KeyEvent};
 
-use super::{Audio, Display, Input, Key};
+
+// SDL3's subsystems, which fail to initialise without a display server or audio
+// device to open. Initialising one without opening anything checks for those.
+pub fn video() -> Result<sdl3::VideoSubsystem, String> {
+    sdl3::init().and_then(|context| context.video()).map_err(|error| error.to_string())
+}
+
+pub fn audio() -> Result<sdl3::AudioSubsystem, String> {
+    sdl3::init().and_then(|context| context.audio()).map_err(|error| error.to_string())
+}
+
+pub fn event_pump() -> Result<EventPump, String> {
+    sdl3::init().and_then(|context| context.event_pump()).map_err(|error| error.to_string())
+}
 
 
 /* Display */
 
 macro_rules! color {
-    ($config:expr, $index:tt) => {
+    ($colors:expr, $index:tt) => {
         Color::RGB(
-            $config.colors[$index].0,
-            $config.colors[$index].1,
-            $config.colors[$index].2,
+            $colors[$index].0,
+            $colors[$index].1,
+            $colors[$index].2,
         )
     }
 }
@@ -27,44 +59,78 @@ macro_rules! color {
 pub struct SDL3Display {
     config: Rc<DisplayConfig>,
     canvas: render::WindowCanvas,
+
+    // Resolution of the frames being drawn, in virtual pixels
+    width: usize,
+    height: usize,
+
+    // Colours and scale factor in use, which start out as configured
+    colors: [(u8, u8, u8); 2],
+    scale_factor: usize,
 }
 
 impl SDL3Display {
-    pub fn new(config: Rc<DisplayConfig>) -> Self {
-        let context = sdl3::init().unwrap();
-        let video_subsystem = context.video().unwrap();
+    pub fn new(config: Rc<DisplayConfig>) -> Result<Self, String> {
+        debug!("Opening {}x{} SDL3 window", config.scaled_width(), config.scaled_height());
+
+        let video_subsystem = video()?;
 
-        let scaled_width: u32 = config.scaled_width().try_into().unwrap();
-        let scaled_height: u32 = config.scaled_height().try_into().unwrap();
+        let scaled_width: u32 = config.scaled_width().try_into().map_err(|_| "window too wide".to_string())?;
+        let scaled_height: u32 = config.scaled_height().try_into().map_err(|_| "window too tall".to_string())?;
 
-        let window = video_subsystem.window("Chip Eight", scaled_width, scaled_height)
+        let window = video_subsystem.window("chip-eight", scaled_width, scaled_height)
             .position_centered()
             .build()
-            .unwrap();
+            .map_err(|error| error.to_string())?;
 
         let mut canvas = window.into_canvas();
-        canvas.set_draw_color(color!(config, 0));
+        canvas.set_draw_color(color!(config.colors, 0));
         canvas.clear();
         canvas.present();
 
-        Self {
+        Ok(Self {
+            width: config.width,
+            height: config.height,
+            colors: config.colors,
+            scale_factor: config.scale_factor,
             config,
             canvas,
-        }
+        })
+    }
+
+    // Size of the window in device pixels
+    fn window_size(&self) -> (usize, usize) {
+        let (width, height) = self.config.oriented_size(self.config.width, self.config.height);
+
+        (width * self.scale_factor, height * self.scale_factor)
+    }
+
+    // Size of a virtual pixel, and the offset that centres a frame of the given
+    // resolution in the window.
+    fn layout(&self, width: usize, height: usize) -> (f32, f32, f32) {
+        let (window_width, window_height) = self.window_size();
+
+        let scale = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+        let left = (window_width as f32 - width as f32 * scale) / 2.0;
+        let top = (window_height as f32 - height as f32 * scale) / 2.0;
+
+        (scale, left, top)
     }
 }
 
 impl Display for SDL3Display {
-    fn draw(&mut self, frame: &[bool]) {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
         let mut on: Vec<render::FRect> = Vec::new();
         let mut off: Vec<render::FRect> = Vec::new();
 
+        let (scale, left, top) = self.layout(self.width, self.height);
+
         for (index, &value) in frame.iter().enumerate() {
             let rect = render::FRect::new(
-                ((index % self.config.width) * self.config.scale_factor) as f32,
-                ((index / self.config.width) * self.config.scale_factor) as f32,
-                self.config.scale_factor as f32,
-                self.config.scale_factor as f32,
+                left + (index % self.width) as f32 * scale,
+                top + (index / self.width) as f32 * scale,
+                scale,
+                scale,
             );
 
             if value {
@@ -74,39 +140,168 @@ impl Display for SDL3Display {
             }
         }
 
-        self.canvas.set_draw_color(color!(self.config, 1));
+        self.canvas.set_draw_color(color!(self.colors, 1));
         self.canvas.fill_rects(&on)
             .expect("Failed to draw");
 
-        self.canvas.set_draw_color(color!(self.config, 0));
+        self.canvas.set_draw_color(color!(self.colors, 0));
         self.canvas.fill_rects(&off)
             .expect("Failed to draw");
 
 
         self.canvas.present();
     }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+
+        // Clear the letterbox left around a smaller frame
+        self.canvas.set_draw_color(color!(self.colors, 0));
+        self.canvas.clear();
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        let (scale, left, top) = self.layout(width, height);
+
+        let mut rects: HashMap<u32, Vec<render::FRect>> = HashMap::new();
+
+        for (index, &color) in frame.iter().enumerate() {
+            rects.entry(color).or_default().push(render::FRect::new(
+                left + (index % width) as f32 * scale,
+                top + (index / width) as f32 * scale,
+                scale,
+                scale,
+            ));
+        }
+
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+
+        for (color, rects) in rects {
+            self.canvas.set_draw_color(Color::RGB((color >> 16) as u8, (color >> 8) as u8, color as u8));
+            self.canvas.fill_rects(&rects)
+                .expect("Failed to draw");
+        }
+
+        self.canvas.present();
+    }
+
+    fn set_title(&mut self, title: &str) {
+        // Titles with a NUL in them can't be shown, so keep the last one
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.colors = colors;
+
+        // Repaint the letterbox in the new unlit colour
+        self.canvas.set_draw_color(color!(self.colors, 0));
+        self.canvas.clear();
+    }
+
+    fn set_scale(&mut self, scale_factor: usize) {
+        self.scale_factor = scale_factor;
+
+        let (width, height) = self.window_size();
+        debug!("Resizing SDL3 window to {}x{}", width, height);
+
+        if let Err(error) = self.canvas.window_mut().set_size(width as u32, height as u32) {
+            warn!("Failed to resize window: {}", error);
+        }
+
+        self.canvas.set_draw_color(color!(self.colors, 0));
+        self.canvas.clear();
+    }
 }
 
 
 /* Audio */
 
-struct SquareWave {
+struct Pattern {
+    bits: [u8; 16],
+    position: f32,
+    phase_inc: f32,
+}
+
+// Plays a square wave, or the audio pattern in its place once one is set.
+struct Tone {
     phase_inc: f32,
     phase: f32,
-    volume: f32
+    volume: f32,
+    master_volume: Arc<AtomicU32>,
+    pattern: Arc<Mutex<Option<Pattern>>>,
 }
 
-impl audio::AudioCallback<f32> for SquareWave {
+impl audio::AudioCallback<f32> for Tone {
     fn callback(&mut self, stream: &mut audio::AudioStream, len: i32) {
         let mut out = vec![0.0; len as usize];
+        let mut pattern = self.pattern.lock().unwrap();
+        let volume = self.volume * f32::from_bits(self.master_volume.load(Ordering::Relaxed));
 
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
+            let high = match pattern.as_mut() {
+                Some(pattern) => {
+                    let bit = pattern.position as usize;
+                    pattern.position = (pattern.position + pattern.phase_inc) % 128.0;
+
+                    (pattern.bits[bit / 8] >> (7 - bit % 8)) & 1 == 1
+                },
+                None => {
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+
+                    self.phase <= 0.5
+                },
+            };
+
+            *x = if high {
+                volume
             } else {
-                -self.volume
+                -volume
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+
+        stream.put_data_f32(&out)
+            .expect("Failed to push samples to audio stream");
+    }
+}
+
+struct Sample {
+    data: Vec<u8>,
+    position: f32,
+    phase_inc: f32,
+    looping: bool,
+}
+
+// Plays back digitised sound, resampled to the output rate.
+struct SamplePlayer {
+    sample: Arc<Mutex<Option<Sample>>>,
+    volume: f32,
+    master_volume: Arc<AtomicU32>,
+}
+
+impl audio::AudioCallback<f32> for SamplePlayer {
+    fn callback(&mut self, stream: &mut audio::AudioStream, len: i32) {
+        let mut out = vec![0.0; len as usize];
+        let mut sample = self.sample.lock().unwrap();
+        let volume = self.volume * f32::from_bits(self.master_volume.load(Ordering::Relaxed));
+
+        for x in out.iter_mut() {
+            let Some(current) = sample.as_mut() else {
+                break;
+            };
+
+            if current.position as usize >= current.data.len() {
+                if !current.looping || current.data.is_empty() {
+                    *sample = None;
+                    break;
+                }
+
+                current.position = 0.0;
+            }
+
+            *x = (current.data[current.position as usize] as f32 - 128.0) / 128.0 * volume;
+            current.position += current.phase_inc;
         }
 
         stream.put_data_f32(&out)
@@ -115,13 +310,22 @@ impl audio::AudioCallback<f32> for SquareWave {
 }
 
 pub struct SDL3Audio {
-    stream: audio::AudioStreamWithCallback<SquareWave>,
+    stream: audio::AudioStreamWithCallback<Tone>,
+    pattern: Arc<Mutex<Option<Pattern>>>,
+    sample_stream: audio::AudioStreamWithCallback<SamplePlayer>,
+    sample: Arc<Mutex<Option<Sample>>>,
+    source_freq: i32,
+
+    // Volume both streams are scaled by, as the bits of an f32, so it can change
+    // while they play
+    master_volume: Arc<AtomicU32>,
 }
 
 impl SDL3Audio {
-    pub fn new(_config: Rc<AudioConfig>) -> Self {
-        let context = sdl3::init().unwrap();
-        let audio_subsystem = context.audio().unwrap();
+    pub fn new(_config: Rc<AudioConfig>) -> Result<Self, String> {
+        debug!("Opening SDL3 audio stream");
+
+        let audio_subsystem = audio()?;
 
         let source_freq = 44100;
         let source_spec = audio::AudioSpec {
@@ -130,15 +334,32 @@ impl SDL3Audio {
             format: Some(audio::AudioFormat::f32_sys())    // floating 32 bit samples
         };
 
-        let stream = audio_subsystem.open_playback_stream(&source_spec, SquareWave {
+        let master_volume = Arc::new(AtomicU32::new(f32::to_bits(1.0)));
+
+        let pattern = Arc::new(Mutex::new(None));
+        let stream = audio_subsystem.open_playback_stream(&source_spec, Tone {
             phase_inc: 440.0 / source_freq as f32,
             phase: 0.0,
             volume: 0.03,
-        }).unwrap();
-
-        Self {
+            master_volume: master_volume.clone(),
+            pattern: pattern.clone(),
+        }).map_err(|error| error.to_string())?;
+
+        let sample = Arc::new(Mutex::new(None));
+        let sample_stream = audio_subsystem.open_playback_stream(&source_spec, SamplePlayer {
+            sample: sample.clone(),
+            volume: 0.1,
+            master_volume: master_volume.clone(),
+        }).map_err(|error| error.to_string())?;
+
+        Ok(Self {
             stream,
-        }
+            pattern,
+            sample_stream,
+            sample,
+            source_freq,
+            master_volume,
+        })
     }
 }
 
@@ -152,41 +373,169 @@ impl Audio for SDL3Audio {
         self.stream.pause()
             .expect("Failed to stop audio");
     }
+
+    fn play_samples(&self, samples: &[u8], rate: u32, looping: bool) {
+        *self.sample.lock().unwrap() = Some(Sample {
+            data: samples.to_vec(),
+            position: 0.0,
+            phase_inc: rate as f32 / self.source_freq as f32,
+            looping,
+        });
+
+        self.sample_stream.resume()
+            .expect("Failed to play audio");
+    }
+
+    fn stop_samples(&self) {
+        *self.sample.lock().unwrap() = None;
+
+        self.sample_stream.pause()
+            .expect("Failed to stop audio");
+    }
+
+    fn set_pattern(&self, pattern: Option<&[u8; 16]>, rate: f32) {
+        let mut current = self.pattern.lock().unwrap();
+
+        *current = pattern.map(|&bits| Pattern {
+            bits,
+            // Keep the position when only the pitch changes, to avoid clicks
+            position: current.as_ref().map_or(0.0, |pattern| pattern.position),
+            phase_inc: rate / self.source_freq as f32,
+        });
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.master_volume.store(volume.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
 }
 
 
 /* Input */
 
+// Percentage points the [ and ] hotkeys lower and raise the volume by
+const VOLUME_STEP: i8 = 10;
+
+// Device pixels per virtual pixel the F7 and F8 hotkeys shrink and grow the window by
+const SCALE_STEP: i8 = 1;
+
 pub struct SDL3Input {
     config: Rc<InputConfig>,
     event_pump: EventPump,
+    event_channel: Sender<ControlEvent>,
+
+    // Speed last requested with the + and - hotkeys
+    speed: f64,
+
+    // Key presses and releases seen since events were last polled
+    events: Vec<KeyEvent>,
 }
 
 impl SDL3Input {
-    pub fn new(config: Rc<InputConfig>) -> Self {
-        let context = sdl3::init().unwrap();
-        let event_pump = context.event_pump().unwrap();
+    pub fn new(config: Rc<InputConfig>, event_channel: Sender<ControlEvent>) -> Result<Self, String> {
+        debug!("Opening SDL3 event pump");
 
-        Self {
+        Ok(Self {
             config,
-            event_pump,
-        }
+            event_pump: event_pump()?,
+            event_channel,
+            speed: 1.0,
+            events: Vec::new(),
+        })
+    }
+
+    fn mapped_key(&self, scancode: Scancode) -> Option<Key> {
+        self.config.key_map.iter()
+            .find(|mapping| mapping.1 == scancode.name())
+            .map(|mapping| mapping.0)
     }
 }
 
 impl Input for SDL3Input {
-    fn get_keys_down(&mut self) -> Vec<Key> {
-        self.event_pump.pump_events();
-
-        self.event_pump.keyboard_state()
-            .pressed_scancodes()
-            .filter_map(|scancode| {
-                if let Some(index) = self.config.key_map.iter().position(|mapping| mapping.1 == scancode.name()) {
-                    return Some(self.config.key_map[index].0.clone());
-                }
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+
+        for event in events {
+            match event {
+                Event::DropFile { filename, .. } => {
+                    self.event_channel.send(ControlEvent::LoadRom(filename.into()))
+                        .expect("Failed to send load ROM event");
+                },
+                Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                    self.event_channel.send(ControlEvent::FocusLost)
+                        .expect("Failed to send focus event");
+                },
+                Event::Window { win_event: WindowEvent::FocusGained, .. } => {
+                    self.event_channel.send(ControlEvent::FocusGained)
+                        .expect("Failed to send focus event");
+                },
+                Event::Quit { .. } | Event::KeyDown { scancode: Some(Scancode::Escape), .. } => {
+                    self.event_channel.send(ControlEvent::Shutdown)
+                        .expect("Failed to send shutdown event");
+                },
+                Event::KeyDown { scancode: Some(scancode @ (Scancode::Equals | Scancode::KpPlus | Scancode::Minus | Scancode::KpMinus)), .. } => {
+                    self.speed = if matches!(scancode, Scancode::Equals | Scancode::KpPlus) {
+                        (self.speed * 2.0).min(MAX_SPEED)
+                    } else {
+                        (self.speed / 2.0).max(MIN_SPEED)
+                    };
+
+                    self.event_channel.send(ControlEvent::SetSpeed(self.speed))
+                        .expect("Failed to send set speed event");
+                },
+                Event::KeyDown { scancode: Some(Scancode::M), repeat: false, .. } if self.mapped_key(Scancode::M).is_none() => {
+                    self.event_channel.send(ControlEvent::ToggleMute)
+                        .expect("Failed to send mute event");
+                },
+                Event::KeyDown { scancode: Some(scancode @ (Scancode::LeftBracket | Scancode::RightBracket)), .. } => {
+                    let change = if matches!(scancode, Scancode::RightBracket) { VOLUME_STEP } else { -VOLUME_STEP };
+
+                    self.event_channel.send(ControlEvent::ChangeVolume(change))
+                        .expect("Failed to send change volume event");
+                },
+                Event::KeyDown { scancode: Some(Scancode::F6), repeat: false, .. } => {
+                    self.event_channel.send(ControlEvent::NextTheme)
+                        .expect("Failed to send next theme event");
+                },
+                Event::KeyDown { scancode: Some(scancode @ (Scancode::F7 | Scancode::F8)), .. } => {
+                    let change = if matches!(scancode, Scancode::F8) { SCALE_STEP } else { -SCALE_STEP };
+
+                    self.event_channel.send(ControlEvent::ChangeScale(change))
+                        .expect("Failed to send change scale event");
+                },
+                Event::KeyDown { scancode: Some(scancode @ (Scancode::F5 | Scancode::F9)), repeat: false, .. } => {
+                    let event = if matches!(scancode, Scancode::F5) {
+                        ControlEvent::SaveState
+                    } else {
+                        ControlEvent::LoadState
+                    };
+
+                    self.event_channel.send(event).expect("Failed to send save state event");
+                },
+                Event::KeyDown { scancode: Some(scancode), keymod, repeat: false, .. } => {
+                    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+
+                    // Ctrl and a number key selects a save state slot rather than pressing a key
+                    if let (true, Some(slot)) = (ctrl, slot_number(scancode)) {
+                        self.event_channel.send(ControlEvent::SelectSlot(slot))
+                            .expect("Failed to send select slot event");
+                    } else if let Some(key) = self.mapped_key(scancode) {
+                        self.events.push(KeyEvent::new(key, true));
+                    }
+                },
+                Event::KeyUp { scancode: Some(scancode), .. } => {
+                    if let Some(key) = self.mapped_key(scancode) {
+                        self.events.push(KeyEvent::new(key, false));
+                    }
+                },
+                _ => (),
+            }
+        }
 
-                None
-            })
-            .collect()
+        std::mem::take(&mut self.events)
     }
 }
+
+// Save state slot a number key selects, from 1 to SLOTS.
+fn slot_number(scancode: Scancode) -> Option<u8> {
+    scancode.name().parse().ok().filter(|slot| (1..=SLOTS).contains(slot))
+}
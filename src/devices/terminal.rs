@@ -0,0 +1,108 @@
+use std::io::{self, IsTerminal, Stdout, Write};
+
+use log::debug;
+
+use super::Display;
+
+// Frames drawn in the terminal the emulator was started from, for when there's no
+// window to open, such as over SSH. Each line of text covers two rows of pixels
+// with half blocks coloured in 24-bit colour, so the terminal needs to support
+// that, and be at least half as tall as the frame and as wide.
+pub struct TerminalDisplay {
+    stdout: Stdout,
+
+    // Resolution of the frames being drawn
+    width: usize,
+    height: usize,
+
+    // Colours of unlit and lit pixels, as ARGB
+    palette: [u32; 2],
+}
+
+// Whether frames can be drawn in the terminal, which they can't when output is
+// redirected.
+pub fn probe() -> Result<(), String> {
+    if !io::stdout().is_terminal() {
+        return Err("stdout isn't a terminal".to_string());
+    }
+
+    Ok(())
+}
+
+impl TerminalDisplay {
+    pub fn new(width: usize, height: usize, colors: [(u8, u8, u8); 2]) -> Result<Self, String> {
+        probe()?;
+
+        debug!("Drawing {}x{} frames in the terminal", width, height);
+
+        let mut display = Self {
+            stdout: io::stdout(),
+            width,
+            height,
+            palette: [0; 2],
+        };
+        display.set_colors(colors);
+        display.clear();
+
+        Ok(display)
+    }
+
+    fn clear(&mut self) {
+        // Failing to write to the terminal leaves it as it was, which is all that can be done
+        let _ = write!(self.stdout, "\x1b[2J");
+    }
+}
+
+impl Display for TerminalDisplay {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        let pixels: Vec<u32> = frame.iter().map(|&lit| self.palette[lit as usize]).collect();
+        self.draw_color(&pixels, self.width, self.height);
+    }
+
+    fn draw_changes(&mut self, frame: &[bool], frame_number: u64, changed_rows: &[usize]) {
+        if !changed_rows.is_empty() {
+            self.draw(frame, frame_number);
+        }
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.clear();
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        let rgb = |pixel: u32| {
+            let [_, r, g, b] = pixel.to_be_bytes();
+            format!("{};{};{}", r, g, b)
+        };
+
+        // From the top left, with the upper pixel of each pair in the foreground
+        let mut text = String::from("\x1b[H");
+
+        for y in (0..height).step_by(2) {
+            // Colours are only given where they change along the line
+            let mut current = None;
+
+            for x in 0..width {
+                let top = frame.get(y * width + x).copied().unwrap_or(self.palette[0]);
+                let bottom = frame.get((y + 1) * width + x).copied().filter(|_| y + 1 < height).unwrap_or(self.palette[0]);
+
+                if current != Some((top, bottom)) {
+                    text.push_str(&format!("\x1b[38;2;{}m\x1b[48;2;{}m", rgb(top), rgb(bottom)));
+                    current = Some((top, bottom));
+                }
+
+                text.push('▀');
+            }
+
+            text.push_str("\x1b[0m\r\n");
+        }
+
+        let _ = self.stdout.write_all(text.as_bytes()).and_then(|_| self.stdout.flush());
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.palette = colors.map(|(r, g, b)| u32::from_be_bytes([0xFF, r, g, b]));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    rc::Rc,
+    sync::{mpmc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info};
+use tungstenite::{self, Message as WebSocketMessage};
+
+use crate::{config::DisplayConfig, protocol::Message};
+
+use super::{Display, Input, Key, KeyEvent};
+
+// How long a client connection blocks waiting for key events before checking for new frames.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// State shared between the devices and the per-client connection threads.
+#[derive(Default)]
+struct Shared {
+    clients: Mutex<Vec<mpmc::Sender<Vec<u8>>>>,
+    last_frame: Mutex<Option<Vec<u8>>>,
+    keys: Mutex<[bool; 16]>,
+    events: Mutex<Vec<KeyEvent>>,
+}
+
+// Listen for WebSocket connections on addr, returning a display that streams frames
+// to every connected client and an input fed by the key events they send back. An
+// address without a host, such as ":8080", listens on all interfaces.
+pub fn serve(addr: &str, config: Rc<DisplayConfig>) -> io::Result<(WebSocketDisplay, WebSocketInput)> {
+    let addr = if addr.starts_with(':') {
+        format!("0.0.0.0{}", addr)
+    } else {
+        addr.to_string()
+    };
+
+    let listener = TcpListener::bind(addr)?;
+    let shared = Arc::new(Shared::default());
+
+    let shared_clone = shared.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let shared = shared_clone.clone();
+            thread::spawn(move || handle_client(stream, shared));
+        }
+    });
+
+    Ok((
+        WebSocketDisplay {
+            width: config.width,
+            height: config.height,
+            shared: shared.clone(),
+        },
+        WebSocketInput {
+            shared,
+        },
+    ))
+}
+
+fn handle_client(stream: TcpStream, shared: Arc<Shared>) {
+    let peer = stream.peer_addr().map_or_else(|_| "unknown address".to_string(), |addr| addr.to_string());
+
+    let Ok(mut socket) = tungstenite::accept(stream) else {
+        debug!("WebSocket handshake with {} failed", peer);
+        return;
+    };
+
+    info!("Client connected from {}", peer);
+
+    if socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+
+    let (frame_tx, frame_rx) = mpmc::channel();
+
+    // Bring the new client up to date straight away, holding the last frame until
+    // the client is added so it can't miss rows changed since
+    let last_frame = shared.last_frame.lock().unwrap();
+
+    if let Some(frame) = last_frame.clone() {
+        let _ = frame_tx.send(frame);
+    }
+
+    shared.clients.lock().unwrap().push(frame_tx);
+    drop(last_frame);
+
+    'connection: loop {
+        while let Ok(frame) = frame_rx.try_recv() {
+            if socket.send(WebSocketMessage::Binary(frame.into())).is_err() {
+                break 'connection;
+            }
+        }
+
+        match socket.read() {
+            Ok(WebSocketMessage::Binary(bytes)) => {
+                if let Ok(Message::Key { key, pressed }) = Message::decode(&bytes) {
+                    shared.keys.lock().unwrap()[key as usize] = pressed;
+                    shared.events.lock().unwrap().push(KeyEvent::new(key, pressed));
+                }
+            },
+            Ok(WebSocketMessage::Close(_)) => break 'connection,
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(error)) if matches!(
+                error.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut,
+            ) => (),
+            Err(_) => break 'connection,
+        }
+    }
+
+    // Don't leave keys held by a client that has gone away
+    let mut keys = shared.keys.lock().unwrap();
+    let held = (0..16).filter(|&key| keys[key as usize]).filter_map(|key| Key::try_from(key).ok());
+    shared.events.lock().unwrap().extend(held.map(|key| KeyEvent::new(key, false)));
+    keys.fill(false);
+    drop(keys);
+
+    info!("Client {} disconnected", peer);
+}
+
+
+/* Display */
+
+pub struct WebSocketDisplay {
+    width: usize,
+    height: usize,
+    shared: Arc<Shared>,
+}
+
+impl WebSocketDisplay {
+    // Send every client the frame, or just its changed rows when given and smaller.
+    fn stream(&mut self, frame: &[bool], changed_rows: Option<&[usize]>) {
+        let encoded = Message::Frame {
+            width: self.width as u16,
+            height: self.height as u16,
+            pixels: frame.to_vec(),
+        }.encode();
+
+        let mut last_frame = self.shared.last_frame.lock().unwrap();
+
+        // Only stream frames that actually changed
+        if last_frame.as_ref() == Some(&encoded) {
+            return;
+        }
+
+        // Clients can only apply rows to a frame they already have
+        let rows = changed_rows.filter(|_| last_frame.is_some()).map(|changed_rows| Message::Rows {
+            width: self.width as u16,
+            rows: changed_rows.iter()
+                .map(|&y| (y as u16, frame[y * self.width..(y + 1) * self.width].to_vec()))
+                .collect(),
+        }.encode());
+
+        let message = match rows {
+            Some(rows) if rows.len() < encoded.len() => rows,
+            _ => encoded.clone(),
+        };
+
+        self.shared.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(message.clone()).is_ok());
+
+        *last_frame = Some(encoded);
+    }
+}
+
+impl Display for WebSocketDisplay {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        self.stream(frame, None);
+    }
+
+    fn draw_changes(&mut self, frame: &[bool], _frame_number: u64, changed_rows: &[usize]) {
+        self.stream(frame, Some(changed_rows));
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+}
+
+
+/* Input */
+
+pub struct WebSocketInput {
+    shared: Arc<Shared>,
+}
+
+impl Input for WebSocketInput {
+    fn poll_events(&mut self) -> Vec<KeyEvent> {
+        std::mem::take(&mut *self.shared.events.lock().unwrap())
+    }
+}
@@ -0,0 +1,162 @@
+// Differences between two snapshots of the machine, for finding where two runs part
+// ways, such as the same ROM run to the same cycle count by two builds.
+
+use std::{fmt, ops::Range};
+
+use crate::{raster, snapshot::Snapshot};
+
+// Bytes shown per row of differing memory
+const ROW_LEN: usize = 16;
+
+// Equal bytes between two differing ones for them to be shown as one range
+const MEMORY_GAP: usize = 8;
+
+const V_NAMES: [&str; 16] = [
+    "v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7", "v8", "v9", "vA", "vB", "vC", "vD", "vE", "vF",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    // Name of a register or other piece of state, and its value in each snapshot
+    Register(&'static str, String, String),
+    MemorySize(usize, usize),
+    // Start of a range of memory, and its bytes in each snapshot
+    Memory(usize, Vec<u8>, Vec<u8>),
+    Stack(Vec<usize>, Vec<usize>),
+    Resolution((usize, usize), (usize, usize)),
+    // Region of the frame as x, y, width and height, and its pixels in each snapshot
+    Frame((usize, usize, usize, usize), Vec<bool>, Vec<bool>),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Register(name, a, b) => write!(f, "{}: {} vs {}", name, a, b),
+            Difference::MemorySize(a, b) => write!(f, "memory size: {} bytes vs {}", a, b),
+            Difference::Memory(start, a, b) => {
+                write!(f, "memory 0x{:03X}..0x{:03X}:", start, start + a.len())?;
+
+                for (row, (a, b)) in a.chunks(ROW_LEN).zip(b.chunks(ROW_LEN)).enumerate() {
+                    let addr = start + row * ROW_LEN;
+                    let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+                    let marks = a.iter().zip(b).map(|(a, b)| if a == b { "  " } else { "^^" }).collect::<Vec<_>>().join(" ");
+
+                    write!(f, "\n  a 0x{:03X}  {}", addr, hex(a))?;
+                    write!(f, "\n  b 0x{:03X}  {}", addr, hex(b))?;
+                    write!(f, "\n  {:width$}{}", "", marks.trim_end(), width = format!("a 0x{:03X}  ", addr).len())?;
+                }
+
+                Ok(())
+            },
+            Difference::Stack(a, b) => write!(f, "stack: {:03X?} vs {:03X?}", a, b),
+            Difference::Resolution(a, b) => write!(f, "resolution: {}x{} vs {}x{}", a.0, a.1, b.0, b.1),
+            Difference::Frame((x, y, width, height), a, b) => {
+                let differing = a.iter().zip(b).filter(|(a, b)| a != b).count();
+
+                writeln!(f, "frame {}x{} at {},{}, {} pixels differ:", width, height, x, y, differing)?;
+                writeln!(f, "a:\n{}", raster::render(a, *width))?;
+                write!(f, "b:\n{}", raster::render(b, *width))
+            },
+        }
+    }
+}
+
+// What differs between two snapshots, registers first, then memory, the stack and
+// the frame. When the time they were taken is all that differs, there's nothing.
+pub fn diff(a: &Snapshot, b: &Snapshot) -> Vec<Difference> {
+    let hex = |addr: usize| format!("0x{:03X}", addr);
+    let byte = |value: u8| format!("0x{:02X}", value);
+
+    let mut registers = vec![
+        ("pc", hex(a.pc), hex(b.pc)),
+        ("i", hex(a.i), hex(b.i)),
+    ];
+    registers.extend(V_NAMES.iter().enumerate().map(|(reg, &name)| (name, byte(a.v[reg]), byte(b.v[reg]))));
+    registers.extend([
+        ("delay timer", a.timers.0.to_string(), b.timers.0.to_string()),
+        ("sound timer", a.timers.1.to_string(), b.timers.1.to_string()),
+        ("key wait", format!("{:?}", a.key_wait), format!("{:?}", b.key_wait)),
+        ("waiting for vblank", a.waiting_for_vblank.to_string(), b.waiting_for_vblank.to_string()),
+        ("exited", a.exited.to_string(), b.exited.to_string()),
+        ("audio pattern", format!("{:02X?}", a.audio_pattern), format!("{:02X?}", b.audio_pattern)),
+        ("pitch", a.pitch.to_string(), b.pitch.to_string()),
+        ("random seed", a.rng_seed.to_string(), b.rng_seed.to_string()),
+        ("VIP random seed", hex(a.random_seed as usize), hex(b.random_seed as usize)),
+    ]);
+
+    let mut differences: Vec<Difference> = registers.into_iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(name, a, b)| Difference::Register(name, a, b))
+        .collect();
+
+    // Memory of different sizes is compared as far as the smaller goes
+    if a.memory.len() != b.memory.len() {
+        differences.push(Difference::MemorySize(a.memory.len(), b.memory.len()));
+    }
+
+    differences.extend(differing_ranges(&a.memory, &b.memory).into_iter().map(|range| {
+        Difference::Memory(range.start, a.memory[range.clone()].to_vec(), b.memory[range].to_vec())
+    }));
+
+    if a.stack != b.stack {
+        differences.push(Difference::Stack(a.stack.clone(), b.stack.clone()));
+    }
+
+    if a.resolution != b.resolution {
+        differences.push(Difference::Resolution(a.resolution, b.resolution));
+    } else {
+        let width = a.resolution.0;
+
+        differences.extend(differing_regions(&a.frame_buffer, &b.frame_buffer, width).into_iter().map(|(x, y, w, h)| {
+            let crop = |frame: &[bool]| (y..y + h).flat_map(|row| frame[row * width + x..row * width + x + w].to_vec()).collect();
+            Difference::Frame((x, y, w, h), crop(&a.frame_buffer), crop(&b.frame_buffer))
+        }));
+    }
+
+    differences
+}
+
+// Ranges of the bytes that differ, merging those less than MEMORY_GAP bytes apart.
+fn differing_ranges(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+
+    for addr in (0..a.len().min(b.len())).filter(|&addr| a[addr] != b[addr]) {
+        match ranges.last_mut() {
+            Some(range) if addr - range.end < MEMORY_GAP => range.end = addr + 1,
+            _ => ranges.push(addr..addr + 1),
+        }
+    }
+
+    ranges
+}
+
+// Regions of the frame where pixels differ, as x, y, width and height: one for each
+// run of rows with differences, spanning the columns they differ in.
+fn differing_regions(a: &[bool], b: &[bool], width: usize) -> Vec<(usize, usize, usize, usize)> {
+    let mut regions: Vec<(usize, usize, usize, usize)> = vec![];
+
+    if width == 0 {
+        return regions;
+    }
+
+    for (y, (a, b)) in a.chunks(width).zip(b.chunks(width)).enumerate() {
+        let differs = |&x: &usize| a[x] != b[x];
+
+        let Some(left) = (0..width).find(differs) else {
+            continue;
+        };
+        let right = (0..width).rfind(differs).unwrap_or(left) + 1;
+
+        match regions.last_mut() {
+            Some((x, top, w, h)) if *top + *h == y => {
+                let end = (*x + *w).max(right);
+                *x = (*x).min(left);
+                *w = end - *x;
+                *h += 1;
+            },
+            _ => regions.push((left, y, right - left, 1)),
+        }
+    }
+
+    regions
+}
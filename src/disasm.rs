@@ -0,0 +1,98 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{
+    analysis::{self, Analysis},
+    config::Profile,
+    instructions::Instruction,
+    symbols::Symbols,
+};
+
+// Bytes of data shown to a line.
+const DATA_PER_LINE: usize = 8;
+
+// A listing of a ROM loaded at program_start, one instruction per line. Bytes that
+// no path from the entry point reaches as code are listed as data, since sprites
+// and tables often decode as instructions. Labelled addresses get a line of their
+// own.
+//
+// 0x200: 6A02  SetVx(10, 2)
+// 0x2A4: .byte 3C 42 81 81 42 3C 00 00
+pub fn disassemble(rom: &[u8], program_start: usize, profile: Profile, symbols: &Symbols) -> String {
+    let analysis = Analysis::scan(rom, program_start, profile);
+
+    listing(rom, program_start, profile, symbols, &analysis.reachable, None)
+}
+
+// A listing as disassemble gives, of the instructions at the addresses of code,
+// with each line after a mark from the address it starts at and whether it's code.
+pub fn annotated(
+    rom: &[u8],
+    program_start: usize,
+    profile: Profile,
+    symbols: &Symbols,
+    code: &BTreeSet<usize>,
+    mark: impl Fn(usize, bool) -> char,
+) -> String {
+    listing(rom, program_start, profile, symbols, code, Some(&mark))
+}
+
+fn listing(
+    rom: &[u8],
+    program_start: usize,
+    profile: Profile,
+    symbols: &Symbols,
+    code: &BTreeSet<usize>,
+    mark: Option<&dyn Fn(usize, bool) -> char>,
+) -> String {
+    let end = program_start + rom.len();
+
+    let mut listing = String::new();
+    let mut addr = program_start;
+
+    while addr < end {
+        if let Some(label) = symbols.label(addr) {
+            let _ = writeln!(listing, "{}{}:", mark.map_or("", |_| "  "), label);
+        }
+
+        let opcode = analysis::fetch(rom, program_start, addr).filter(|_| code.contains(&addr));
+
+        if let Some(mark) = mark {
+            let _ = write!(listing, "{} ", mark(addr, opcode.is_some()));
+        }
+
+        if let Some(opcode) = opcode {
+            // F000 NNNN is listed with its address word
+            if let (Ok(Instruction::SetIWide), Some(word)) = (Instruction::decode(opcode, profile), analysis::fetch(rom, program_start, addr + 2)) {
+                let _ = writeln!(listing, "0x{:03X}: {:04X} {:04X}  SetIWide(0x{:04X})", addr, opcode, word, word);
+                addr += 4;
+                continue;
+            }
+
+            let instruction = match Instruction::decode(opcode, profile) {
+                Ok(instruction) => format!("{:?}", instruction),
+                Err(error) => format!("({})", error),
+            };
+
+            let _ = writeln!(listing, "0x{:03X}: {:04X}  {}", addr, opcode, instruction);
+            addr += 2;
+            continue;
+        }
+
+        // Data runs until the next instruction or label
+        let len = (addr..end)
+            .take(DATA_PER_LINE)
+            .enumerate()
+            .take_while(|&(index, addr)| index == 0 || (!code.contains(&addr) && symbols.label(addr).is_none()))
+            .count();
+
+        let bytes: Vec<String> = rom[addr - program_start..addr - program_start + len]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+
+        let _ = writeln!(listing, "0x{:03X}: .byte {}", addr, bytes.join(" "));
+        addr += len;
+    }
+
+    listing
+}
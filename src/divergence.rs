@@ -0,0 +1,252 @@
+// Where two headless runs of a ROM first differ, such as under two sets of quirks or
+// against a trace recorded earlier, for working out which quirk a ROM needs or what
+// an interpreter change broke. Runs step a cycle at a time, with the display
+// interrupt every frame's worth of cycles, as the golden trace tests do.
+
+use std::{cell::Cell, collections::BTreeSet, fmt, rc::Rc};
+
+use crate::{
+    cpu::ExecutionError,
+    diff,
+    disasm,
+    symbols::Symbols,
+    system::ChipEight,
+};
+
+// Instructions listed either side of the one the runs diverged on
+const CONTEXT: usize = 4;
+
+pub struct Divergence {
+    // Cycle the runs differed after, counting from 1
+    pub cycle: u64,
+    // Address of the instruction executed last, which the difference followed
+    pub pc: usize,
+    // What differs between the runs, one difference to a line
+    pub differences: Vec<String>,
+    // Disassembly around the instruction, from the first run's memory
+    pub listing: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Diverged at cycle {}, after the instruction at 0x{:03X}:", self.cycle, self.pc)?;
+
+        for difference in &self.differences {
+            writeln!(f, "{}", difference)?;
+        }
+
+        write!(f, "\n{}", self.listing.trim_end())
+    }
+}
+
+// A machine stepped a cycle at a time, noting the instruction each cycle executed,
+// as cycles spent waiting for a key or the display interrupt execute none.
+struct Stepper<'a> {
+    chip_eight: &'a mut ChipEight,
+    executed: Rc<Cell<Option<usize>>>,
+    cycles_per_frame: u64,
+}
+
+impl<'a> Stepper<'a> {
+    fn new(chip_eight: &'a mut ChipEight) -> Self {
+        let executed = Rc::new(Cell::new(None));
+
+        chip_eight.on_instruction({
+            let executed = executed.clone();
+            move |pc, _| executed.set(Some(pc))
+        });
+
+        Self {
+            cycles_per_frame: chip_eight.cycles_per_frame(),
+            chip_eight,
+            executed,
+        }
+    }
+
+    // Run a cycle, returning the address and opcode of the instruction it executed.
+    fn step(&mut self, cycle: u64) -> Result<Option<(usize, u16)>, ExecutionError> {
+        let pc = self.chip_eight.pc();
+        let opcode = self.chip_eight.memory().read_buf(pc, 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .unwrap_or_default();
+
+        self.executed.set(None);
+        let result = self.chip_eight.try_cycle();
+
+        if cycle.is_multiple_of(self.cycles_per_frame) {
+            self.chip_eight.vblank();
+        }
+
+        result.map(|_| self.executed.get().map(|pc| (pc, opcode)))
+    }
+}
+
+// Line of a trace for an instruction just executed: its address and opcode, then
+// the registers and I as they were left, as tests/golden holds.
+pub fn trace_line(chip_eight: &ChipEight, pc: usize, opcode: u16) -> String {
+    let registers: String = chip_eight.registers().iter().map(|v| format!("{:02X}", v)).collect();
+
+    format!("{:03X} {:04X} {} {:03X}", pc, opcode, registers, chip_eight.index())
+}
+
+// Trace of a run of up to a number of cycles, ending early if the program faults,
+// exits or settles into a jump to itself.
+pub fn trace(chip_eight: &mut ChipEight, cycles: u64) -> String {
+    let mut trace = String::new();
+    let mut stepper = Stepper::new(chip_eight);
+
+    for cycle in 1..=cycles {
+        let Ok(executed) = stepper.step(cycle) else {
+            break;
+        };
+
+        if let Some((pc, opcode)) = executed {
+            trace.push_str(&trace_line(stepper.chip_eight, pc, opcode));
+            trace.push('\n');
+
+            if stepper.chip_eight.pc() == pc {
+                break;
+            }
+        }
+
+        if stepper.chip_eight.exited() {
+            break;
+        }
+    }
+
+    trace
+}
+
+// Run two machines with the same ROM loaded side by side for up to a number of
+// cycles, returning the first cycle after which their program counters, registers,
+// timers or frames differ, or one faults and the other doesn't.
+pub fn compare(a: &mut ChipEight, b: &mut ChipEight, cycles: u64, symbols: &Symbols) -> Option<Divergence> {
+    let mut a = Stepper::new(a);
+    let mut b = Stepper::new(b);
+    let mut last_pc = a.chip_eight.pc();
+
+    for cycle in 1..=cycles {
+        let results = (a.step(cycle), b.step(cycle));
+
+        let differences = match &results {
+            (Ok(_), Ok(_)) => state_differences(a.chip_eight, b.chip_eight),
+            // Runs that fault alike haven't diverged
+            (Err(a), Err(b)) if a == b => return None,
+            (a, b) => vec![format!("  a: {}", outcome(a)), format!("  b: {}", outcome(b))],
+        };
+
+        if let Ok(Some((pc, _))) = results.0 {
+            last_pc = pc;
+        }
+
+        if !differences.is_empty() {
+            return Some(Divergence {
+                cycle,
+                pc: last_pc,
+                differences,
+                listing: listing(a.chip_eight, last_pc, symbols),
+            });
+        }
+
+        if a.chip_eight.exited() && b.chip_eight.exited() {
+            break;
+        }
+    }
+
+    None
+}
+
+// Run a machine against a trace recorded earlier, returning the first instruction
+// whose trace line differs. Runs that go on past the end of the trace match it.
+pub fn compare_trace(chip_eight: &mut ChipEight, expected: &str, cycles: u64, symbols: &Symbols) -> Option<Divergence> {
+    let mut expected = expected.lines();
+    let mut stepper = Stepper::new(chip_eight);
+    let mut instructions = 0;
+
+    for cycle in 1..=cycles {
+        let executed = match stepper.step(cycle) {
+            Ok(executed) => executed,
+            Err(error) => {
+                let line = expected.next()?;
+
+                return Some(Divergence {
+                    cycle,
+                    pc: stepper.chip_eight.pc(),
+                    differences: vec![
+                        format!("  instruction {} of the trace", instructions + 1),
+                        format!("  expected {}", line),
+                        format!("   faulted: {}", error),
+                    ],
+                    listing: listing(stepper.chip_eight, stepper.chip_eight.pc(), symbols),
+                });
+            },
+        };
+
+        let Some((pc, opcode)) = executed else {
+            continue;
+        };
+
+        instructions += 1;
+
+        let line = expected.next()?;
+        let actual = trace_line(stepper.chip_eight, pc, opcode);
+
+        if line != actual {
+            return Some(Divergence {
+                cycle,
+                pc,
+                differences: vec![
+                    format!("  instruction {} of the trace", instructions),
+                    format!("  expected {}", line),
+                    format!("    actual {}", actual),
+                ],
+                listing: listing(stepper.chip_eight, pc, symbols),
+            });
+        }
+    }
+
+    None
+}
+
+fn outcome(result: &Result<Option<(usize, u16)>, ExecutionError>) -> String {
+    match result {
+        Ok(_) => "ran".to_string(),
+        Err(error) => format!("faulted: {}", error),
+    }
+}
+
+// Differences in the state the runs are compared on, with the details diff gives.
+fn state_differences(a: &mut ChipEight, b: &mut ChipEight) -> Vec<String> {
+    let same = a.pc() == b.pc()
+        && a.registers() == b.registers()
+        && a.index() == b.index()
+        && a.timers() == b.timers()
+        && a.resolution() == b.resolution()
+        && a.frame_buffer() == b.frame_buffer();
+
+    if same {
+        return vec![];
+    }
+
+    match (a.snapshot(), b.snapshot()) {
+        (Ok(a), Ok(b)) => diff::diff(&a, &b).iter().map(|difference| format!("  {}", difference)).collect(),
+        _ => vec![format!("  pc: 0x{:03X} vs 0x{:03X}", a.pc(), b.pc())],
+    }
+}
+
+// Disassembly of the instructions around addr, marking the one at addr.
+fn listing(chip_eight: &ChipEight, addr: usize, symbols: &Symbols) -> String {
+    let memory = chip_eight.memory();
+    let start = addr.saturating_sub(CONTEXT * 2);
+    let end = (addr + (CONTEXT + 1) * 2).min(memory.len());
+
+    let Ok(bytes) = memory.read_buf(start, end - start) else {
+        return String::new();
+    };
+
+    let code: BTreeSet<usize> = (start..end).step_by(2).collect();
+
+    disasm::annotated(&bytes, start, chip_eight.config().profile, symbols, &code, |line, _| {
+        if line == addr { '>' } else { ' ' }
+    })
+}
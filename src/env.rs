@@ -0,0 +1,106 @@
+use crate::{
+    config::Config,
+    devices::{
+        headless::{FrameDisplay, KeyInput, ToneAudio},
+        Key,
+    },
+    ChipEight,
+    ChipEightBuilder,
+};
+
+type RewardFn = Box<dyn FnMut(&ChipEight) -> f64>;
+type DoneFn = Box<dyn FnMut(&ChipEight) -> bool>;
+
+// Gym-style environment for training agents against a ROM. Each step holds the
+// chosen keys down for one frame, then reports the frame buffer as the observation
+// alongside a reward and whether the episode is over, both computed by
+// user-supplied closures over the machine state.
+//
+// Runs are deterministic: the random number generator is seeded, the timers count
+// down per frame rather than in real time, and no real devices are attached, so
+// frames can be stepped as fast as the host allows.
+pub struct ChipEightEnv {
+    chip_eight: ChipEight,
+    rom: Vec<u8>,
+    input: KeyInput,
+    cycles_per_frame: u64,
+    reward: RewardFn,
+    done: DoneFn,
+}
+
+impl ChipEightEnv {
+    pub fn new(config: Config, rom: &[u8], seed: u64) -> Self {
+        let cycles_per_frame = config.cycles_per_frame();
+        let display = FrameDisplay::new(config.display.width * config.display.height);
+        let input = KeyInput::new();
+
+        let builder = ChipEightBuilder::new(config)
+            .with_display(Box::new(display))
+            .with_audio(Box::new(ToneAudio::new()))
+            .with_input(Box::new(input.clone()))
+            .with_seed(seed);
+
+        #[cfg(feature = "jit")]
+        let builder = builder.with_jit();
+
+        let mut chip_eight = builder.build();
+
+        chip_eight.load(rom);
+
+        Self {
+            chip_eight,
+            rom: rom.to_vec(),
+            input,
+            cycles_per_frame,
+            reward: Box::new(|_| 0.0),
+            done: Box::new(|_| false),
+        }
+    }
+
+    // Reward for the state reached after each step. Defaults to 0.
+    pub fn with_reward(mut self, reward: impl FnMut(&ChipEight) -> f64 + 'static) -> Self {
+        self.reward = Box::new(reward);
+        self
+    }
+
+    // Whether the state reached after each step ends the episode. Defaults to never.
+    pub fn with_done(mut self, done: impl FnMut(&ChipEight) -> bool + 'static) -> Self {
+        self.done = Box::new(done);
+        self
+    }
+
+    pub fn chip_eight(&self) -> &ChipEight {
+        &self.chip_eight
+    }
+
+    // Start a new episode from power-on, returning the first observation.
+    pub fn reset(&mut self) -> &[bool] {
+        self.input.set_keys([false; 16]);
+        self.chip_eight.reset();
+        self.chip_eight.load(&self.rom);
+
+        self.chip_eight.frame_buffer()
+    }
+
+    // Hold the given keys for one frame, returning the observation, reward and
+    // whether the episode is done.
+    pub fn step(&mut self, action: &[Key]) -> (&[bool], f64, bool) {
+        let mut keys = [false; 16];
+
+        for &key in action {
+            keys[key as usize] = true;
+        }
+
+        self.input.set_keys(keys);
+
+        self.chip_eight.run_cycles(self.cycles_per_frame);
+
+        self.chip_eight.vblank();
+
+        let reward = (self.reward)(&self.chip_eight);
+        // Reaching the exit opcode always ends the episode
+        let done = (self.done)(&self.chip_eight) || self.chip_eight.exited();
+
+        (self.chip_eight.frame_buffer(), reward, done)
+    }
+}
@@ -0,0 +1,172 @@
+// C API for driving the interpreter from other languages. The header is generated
+// with cbindgen (see cbindgen.toml) and checked in as include/chip8.h.
+//
+// The host owns the clock: call chip8_step once per instruction and chip8_vblank 60
+// times a second, reading chip8_framebuffer whenever it wants to draw.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    slice,
+};
+
+use clap::Parser;
+
+use crate::{
+    config::{Args, Config},
+    devices::{headless::KeyInput, Key},
+    ChipEight,
+    ChipEightBuilder,
+};
+
+// Opaque handle to an emulator instance.
+pub struct Chip8 {
+    chip_eight: ChipEight,
+    input: KeyInput,
+}
+
+/// Create an emulator with the default configuration and no attached devices.
+/// Returns null if it can't be created.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    panic::catch_unwind(|| {
+        let config = Config::from(Args::parse_from([
+            "chip-eight",
+            "--display-engine", "none",
+            "--audio-engine", "none",
+            "--input-engine", "none",
+        ]));
+        let input = KeyInput::new();
+
+        let chip_eight = ChipEightBuilder::new(config)
+            .with_input(Box::new(input.clone()))
+            .build();
+
+        Box::into_raw(Box::new(Chip8 { chip_eight, input }))
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// Reset the emulator and load a ROM into memory. Returns false if the ROM doesn't fit.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`, and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, data: *const u8, len: usize) -> bool {
+    let (Some(chip8), false) = (chip8.as_mut(), data.is_null()) else {
+        return false;
+    };
+
+    let rom = slice::from_raw_parts(data, len);
+
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        chip8.chip_eight.reset();
+        chip8.chip_eight.try_load(rom)
+    })).is_ok_and(|loaded| loaded.is_ok())
+}
+
+/// Execute a single instruction.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        // Unwinding into the host is undefined behaviour, so a faulting program just stops advancing
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| chip8.chip_eight.cycle()));
+    }
+}
+
+/// Signal the 60Hz vertical blank, counting the timers down and releasing a
+/// pending draw wait.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_vblank(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.chip_eight.vblank();
+    }
+}
+
+/// Borrow the last frame completed by `chip8_vblank` as `width * height` row-major
+/// pixels, writing its dimensions through the out pointers when they aren't null.
+/// The pointer is valid until the next call that takes `chip8` mutably.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip8: *const Chip8, width: *mut usize, height: *mut usize) -> *const bool {
+    let Some(chip8) = chip8.as_ref() else {
+        return ptr::null();
+    };
+
+    let frame = chip8.chip_eight.frame();
+    let (frame_width, frame_height) = (frame.width(), frame.height());
+
+    if let Some(width) = width.as_mut() {
+        *width = frame_width;
+    }
+
+    if let Some(height) = height.as_mut() {
+        *height = frame_height;
+    }
+
+    frame.as_ptr()
+}
+
+/// Borrow the last completed frame as `width * height * 4` bytes of row-major
+/// RGBA8888 pixels in the display's colours, ready to upload as a texture.
+/// Dimensions and lifetime are as for `chip8_framebuffer`.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer_rgba(chip8: *const Chip8, width: *mut usize, height: *mut usize) -> *const u8 {
+    if chip8_framebuffer(chip8, width, height).is_null() {
+        return ptr::null();
+    }
+
+    (*chip8).chip_eight.frame_rgba().as_ptr()
+}
+
+/// Press or release keypad key 0x0 to 0xF. Other keys are ignored.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) {
+    if let (Some(chip8), Ok(key)) = (chip8.as_mut(), Key::try_from(key)) {
+        chip8.input.set_key(key, pressed);
+    }
+}
+
+/// Press keypad key 0x0 to 0xF and hold it for a number of `chip8_vblank` calls,
+/// at least one. Other keys are ignored.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tap_key(chip8: *mut Chip8, key: u8, frames: u64) {
+    if let (Some(chip8), Ok(key)) = (chip8.as_mut(), Key::try_from(key)) {
+        chip8.chip_eight.tap_key_for(key, frames);
+    }
+}
+
+/// Free an emulator. Passing null does nothing.
+///
+/// # Safety
+///
+/// `chip8` must come from `chip8_new` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_destroy(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Deref;
+
+// Colours of unlit and lit pixels, in that order, when none are given.
+const DEFAULT_PALETTE: [(u8, u8, u8); 2] = [(0, 0, 0), (255, 255, 255)];
+
+// Monochrome frame buffer, kept alongside two ready-made renderings of itself so
+// frontends can hand a frame straight to a canvas, texture or encoder without
+// converting it pixel by pixel:
+//
+// - A packed bitfield, a row at a time from the top, with each row padded to a
+//   whole number of bytes and its leftmost pixel in the most significant bit (the
+//   layout of sprites, and of PBM images).
+// - RGBA8888 bytes, a row at a time from the top, in the palette's colours.
+//
+// Both are updated pixel by pixel as the frame changes, rather than rebuilt, and the
+// rows changed are noted until taken. Derefs to the frame as a pixel per bool.
+#[derive(Clone, Debug)]
+pub struct FrameBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+    bits: Vec<u8>,
+    rgba: Vec<u8>,
+    palette: [[u8; 4]; 2],
+
+    // Whether each row has changed since the changes were last taken
+    changed: Vec<bool>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut frame = Self {
+            width: 0,
+            height: 0,
+            pixels: vec![],
+            bits: vec![],
+            rgba: vec![],
+            changed: vec![],
+            palette: DEFAULT_PALETTE.map(|(r, g, b)| [r, g, b, 0xFF]),
+        };
+
+        frame.resize(width, height);
+        frame
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+
+    // Bytes per row of the packed bitfield.
+    pub fn bits_stride(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    // Change the colours of unlit and lit pixels, in that order, re-rendering the
+    // RGBA bytes in them.
+    pub fn set_palette(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.palette = colors.map(|(r, g, b)| [r, g, b, 0xFF]);
+
+        for (color, &lit) in self.rgba.chunks_exact_mut(4).zip(&self.pixels) {
+            color.copy_from_slice(&self.palette[lit as usize]);
+        }
+    }
+
+    // Rows changed since this was last called, top first.
+    pub fn take_changed_rows(&mut self) -> Vec<usize> {
+        let rows = self.changed.iter().enumerate().filter(|(_, &changed)| changed).map(|(row, _)| row).collect();
+        self.changed.fill(false);
+        rows
+    }
+
+    // Count every row as changed, for when whatever the frame was last shown on has
+    // been drawn over with something else.
+    pub fn mark_all_changed(&mut self) {
+        self.changed.fill(true);
+    }
+
+    // Change the resolution, clearing the frame.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![false; width * height];
+        self.bits = vec![0; width.div_ceil(8) * height];
+        self.rgba = self.palette[0].repeat(width * height);
+        self.changed = vec![true; height];
+    }
+
+    pub fn set(&mut self, index: usize, lit: bool) {
+        let Some(pixel) = self.pixels.get_mut(index) else {
+            return;
+        };
+
+        if *pixel == lit {
+            return;
+        }
+
+        *pixel = lit;
+
+        let (x, y) = (index % self.width, index / self.width);
+        self.changed[y] = true;
+        let byte = &mut self.bits[y * self.width.div_ceil(8) + x / 8];
+
+        if lit {
+            *byte |= 0x80 >> (x % 8);
+        } else {
+            *byte &= !(0x80 >> (x % 8));
+        }
+
+        self.rgba[index * 4..index * 4 + 4].copy_from_slice(&self.palette[lit as usize]);
+    }
+
+    // Flip a pixel, returning whether it was lit before, or None when it is out of
+    // the frame.
+    pub fn toggle(&mut self, index: usize) -> Option<bool> {
+        let lit = *self.pixels.get(index)?;
+        self.set(index, !lit);
+        Some(lit)
+    }
+
+    pub fn fill(&mut self, lit: bool) {
+        self.pixels.fill(lit);
+        self.changed.fill(true);
+
+        for color in self.rgba.chunks_exact_mut(4) {
+            color.copy_from_slice(&self.palette[lit as usize]);
+        }
+
+        // Padding at the end of each row stays unlit
+        let stride = self.bits_stride();
+
+        for row in self.bits.chunks_exact_mut(stride.max(1)) {
+            for (column, byte) in row.iter_mut().enumerate() {
+                let pixels = (self.width - column * 8).min(8);
+                *byte = if lit { (0xFF00u16 >> pixels) as u8 } else { 0 };
+            }
+        }
+    }
+
+    // Replace the frame with one of the same resolution, updating only the pixels
+    // that differ.
+    pub fn copy_from(&mut self, frame: &[bool]) {
+        for (index, &lit) in frame.iter().enumerate() {
+            self.set(index, lit);
+        }
+    }
+}
+
+impl Deref for FrameBuffer {
+    type Target = [bool];
+
+    fn deref(&self) -> &[bool] {
+        &self.pixels
+    }
+}
@@ -0,0 +1,204 @@
+// How often the program has read, written and executed each byte of memory, for
+// seeing how a ROM uses its 4K: where its code, sprites and variables live, and
+// which of them are busiest. Shown beside the display as it runs, or printed in the
+// debugger.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{devices::Display, ChipEight};
+
+// Columns between the frame and the heatmap beside it
+pub const GAP: usize = 2;
+
+// Bytes to a row when printed
+const ROW_LEN: usize = 64;
+
+// Letters of reads, writes and executes, in the order of Access
+const LETTERS: [char; 3] = ['r', 'w', 'x'];
+
+// Brightness of an access of each kind the first time, rising towards full with
+// the logarithm of how often it happened
+const MIN_BRIGHTNESS: f32 = 64.0;
+
+// Counts of reads, writes and executes of each byte, shared with the hook updating
+// them.
+#[derive(Clone)]
+pub struct Heatmap {
+    counts: Rc<RefCell<Vec<[u32; 3]>>>,
+}
+
+impl Heatmap {
+    // A heatmap of memory the length given, counting nothing until tracking a machine.
+    pub fn new(len: usize) -> Self {
+        Self { counts: Rc::new(RefCell::new(vec![[0; 3]; len])) }
+    }
+
+    // Count every access the machine's instructions make from now on.
+    pub fn track(&self, chip_eight: &mut ChipEight) {
+        let counts = self.counts.clone();
+
+        chip_eight.on_memory_access(move |range, access| {
+            let mut counts = counts.borrow_mut();
+            let end = range.end.min(counts.len());
+            let start = range.start.min(end);
+
+            for count in &mut counts[start..end] {
+                count[access as usize] = count[access as usize].saturating_add(1);
+            }
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Reads, writes and executes of a byte so far.
+    pub fn counts(&self, addr: usize) -> [u32; 3] {
+        self.counts.borrow().get(addr).copied().unwrap_or_default()
+    }
+
+    // Forget the accesses counted so far.
+    pub fn clear(&self) {
+        self.counts.borrow_mut().fill([0; 3]);
+    }
+
+    // Colour of each of a run of bytes as ARGB, a pixel to each span of bytes given:
+    // blue for reads, red for writes and green for executes, mixed where a span had
+    // more than one, and brighter the more often. Spans never accessed are black.
+    pub fn colors(&self, span: usize) -> Vec<u32> {
+        let counts = self.counts.borrow();
+        let span = span.max(1);
+
+        let spans: Vec<[u32; 3]> = counts.chunks(span)
+            .map(|bytes| bytes.iter().fold([0u32; 3], |total, count| {
+                [0, 1, 2].map(|access| total[access].saturating_add(count[access]))
+            }))
+            .collect();
+
+        // Each kind is scaled to its own busiest span, so rare writes still show
+        let max = [0, 1, 2].map(|access| spans.iter().map(|count| count[access]).max().unwrap_or(0));
+
+        spans.iter()
+            .map(|count| {
+                let [read, write, execute] = [0, 1, 2].map(|access| brightness(count[access], max[access]));
+                u32::from_be_bytes([0xFF, write, execute, read])
+            })
+            .collect()
+    }
+
+    // Memory from addr on as rows of 64 bytes, each a coloured cell in a terminal,
+    // or otherwise the letter of its most frequent access: r, w or x, or . for none.
+    pub fn render(&self, addr: usize, len: usize, color: bool) -> String {
+        let counts = self.counts.borrow();
+        let end = (addr + len).min(counts.len());
+        let colors = if color { self.colors(1) } else { vec![] };
+
+        let mut text = String::new();
+
+        for row in (addr..end).step_by(ROW_LEN) {
+            text.push_str(&format!("0x{:03X}: ", row));
+
+            for byte_addr in row..(row + ROW_LEN).min(end) {
+                let count = counts[byte_addr];
+
+                if color {
+                    let [_, r, g, b] = colors[byte_addr].to_be_bytes();
+                    text.push_str(&format!("\x1b[48;2;{};{};{}m \x1b[0m", r, g, b));
+                    continue;
+                }
+
+                let busiest = (0..3).filter(|&access| count[access] > 0).max_by_key(|&access| count[access]);
+                text.push(busiest.map_or('.', |access| LETTERS[access]));
+            }
+
+            text.push('\n');
+        }
+
+        text
+    }
+
+    // Wrap a display so it shows the heatmap to the right of each frame, in a window
+    // the width and height given: the frame's configured size, plus the gap and
+    // twice the frame's width. The heatmap fills that twice the width at any
+    // resolution, each pixel a byte of memory for the usual 4K and 64x32 frames.
+    pub fn overlay(&self, display: Box<dyn Display>, width: usize, height: usize, colors: [(u8, u8, u8); 2]) -> Box<dyn Display> {
+        Box::new(Overlay {
+            display,
+            heatmap: self.clone(),
+            pixels: 2 * width * height,
+            resolution: (width, height),
+            palette: colors.map(|(r, g, b)| u32::from_be_bytes([0xFF, r, g, b])),
+        })
+    }
+}
+
+// Brightness of a channel for a count, out of the highest count of its kind.
+fn brightness(count: u32, max: u32) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+
+    let scale = (count as f32).ln_1p() / (max as f32).ln_1p();
+    (MIN_BRIGHTNESS + (255.0 - MIN_BRIGHTNESS) * scale) as u8
+}
+
+// Display drawing the heatmap beside each frame, in colour.
+struct Overlay {
+    display: Box<dyn Display>,
+    heatmap: Heatmap,
+    // Pixels the heatmap takes up, whatever the resolution
+    pixels: usize,
+    resolution: (usize, usize),
+    palette: [u32; 2],
+}
+
+impl Display for Overlay {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        let (frame_width, height) = self.resolution;
+        let map_width = self.pixels / height.max(1);
+        let width = frame_width + GAP + map_width;
+
+        let span = self.heatmap.len().div_ceil(self.pixels);
+        let heat = self.heatmap.colors(span);
+
+        let mut pixels = vec![0xFF000000; width * height];
+
+        for (y, row) in pixels.chunks_mut(width).enumerate() {
+            for (x, &lit) in frame[y * frame_width..(y + 1) * frame_width].iter().enumerate() {
+                row[x] = self.palette[lit as usize];
+            }
+
+            for (x, &color) in heat.iter().skip(y * map_width).take(map_width).enumerate() {
+                row[frame_width + GAP + x] = color;
+            }
+        }
+
+        self.display.draw_color(&pixels, width, height);
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.resolution = (width, height);
+        self.display.set_resolution(width, height);
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        self.display.draw_color(frame, width, height);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.display.set_title(title);
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.palette = colors.map(|(r, g, b)| u32::from_be_bytes([0xFF, r, g, b]));
+        self.display.set_colors(colors);
+    }
+
+    fn set_scale(&mut self, scale_factor: usize) {
+        self.display.set_scale(scale_factor);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+
+use crate::system::{CallFrame, KeyWait};
+
+// Machine state from just before an instruction executed, holding only what that
+// instruction could change beyond the registers.
+pub struct Delta {
+    pub pc: usize,
+    pub v: [u8; 16],
+    pub i: usize,
+    pub timers: (u8, u8),
+    pub key_wait: Option<KeyWait>,
+    pub waiting_for_vblank: bool,
+    // Depth of the stack, and the call a return popped off it
+    pub stack_len: usize,
+    pub returned_from: Option<(usize, CallFrame)>,
+    // Bytes a store overwrote, and where
+    pub memory: Option<(usize, Vec<u8>)>,
+    pub frame: Option<Vec<bool>>,
+    // Random number generator, and the VIP routine's seed
+    pub rng: Option<(StdRng, u16)>,
+    pub audio: Option<(Option<[u8; 16]>, u8)>,
+}
+
+// The most recent deltas, oldest first, dropping the oldest once full.
+pub struct History {
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, delta: Delta) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.deltas.len() == self.capacity {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(delta);
+    }
+
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+}
@@ -0,0 +1,262 @@
+// Decoder for DEFLATE (RFC 1951), the compression inside zip and gzip files. Built
+// for ROM-sized data rather than speed, decoding a bit at a time through canonical
+// Huffman code tables.
+
+use std::{error::Error, fmt};
+
+// Base lengths and extra bits of the length codes 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+// Base distances and extra bits of the distance codes 0 to 29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order the code length code lengths of a dynamic block come in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const MAX_BITS: usize = 15;
+
+#[derive(Debug, PartialEq)]
+pub enum InflateError {
+    // The data ended before the last block did
+    Truncated,
+    // A block type, code or distance the format doesn't allow
+    Invalid(&'static str),
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::Truncated => write!(f, "compressed data ends early"),
+            InflateError::Invalid(what) => write!(f, "invalid compressed data: {}", what),
+        }
+    }
+}
+
+impl Error for InflateError {}
+
+// Decompress raw DEFLATE data, returning it along with the number of bytes of the
+// input it took up.
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut bits = Bits { data, at: 0, bit: 0 };
+    let mut out = vec![];
+
+    loop {
+        let last = bits.read(1)? == 1;
+
+        match bits.read(2)? {
+            0 => stored(&mut bits, &mut out)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                codes(&mut bits, &mut out, &literals, &distances)?;
+            },
+            _ => return Err(InflateError::Invalid("block type")),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    Ok((out, bits.at + (bits.bit > 0) as usize))
+}
+
+// Input read a bit at a time, least significant bit of each byte first.
+struct Bits<'a> {
+    data: &'a [u8],
+    at: usize,
+    bit: u8,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u8) -> Result<u32, InflateError> {
+        let mut value = 0;
+
+        for i in 0..count {
+            let byte = *self.data.get(self.at).ok_or(InflateError::Truncated)?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+
+            self.bit += 1;
+
+            if self.bit == 8 {
+                self.bit = 0;
+                self.at += 1;
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.at += 1;
+        }
+    }
+}
+
+// Canonical Huffman code: how many codes there are of each length, and the
+// symbols in code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; MAX_BITS + 1];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0; MAX_BITS + 2];
+
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, InflateError> {
+        // First code of the current length, and index of its symbol
+        let (mut code, mut first, mut index) = (0, 0, 0);
+
+        for length in 1..=MAX_BITS {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(InflateError::Invalid("code"))
+    }
+}
+
+fn stored(bits: &mut Bits, out: &mut Vec<u8>) -> Result<(), InflateError> {
+    bits.align();
+
+    let header = bits.data.get(bits.at..bits.at + 4).ok_or(InflateError::Truncated)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+
+    if length != !complement {
+        return Err(InflateError::Invalid("stored block length"));
+    }
+
+    bits.at += 4;
+    let block = bits.data.get(bits.at..bits.at + length as usize).ok_or(InflateError::Truncated)?;
+    out.extend_from_slice(block);
+    bits.at += length as usize;
+
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.read(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&code_lengths);
+    let mut lengths = vec![];
+
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(InflateError::Invalid("repeat with no previous length"))?;
+                (previous, 3 + bits.read(2)?)
+            },
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+
+        lengths.extend((0..repeat).map(|_| length));
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err(InflateError::Invalid("code lengths"));
+    }
+
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let code = symbol - 257;
+                let length = LENGTH_BASE[code] as usize + bits.read(LENGTH_EXTRA[code])? as usize;
+
+                let code = distances.decode(bits)? as usize;
+
+                if code >= DISTANCE_BASE.len() {
+                    return Err(InflateError::Invalid("distance code"));
+                }
+
+                let distance = DISTANCE_BASE[code] as usize + bits.read(DISTANCE_EXTRA[code])? as usize;
+
+                if distance > out.len() {
+                    return Err(InflateError::Invalid("distance before the start of the data"));
+                }
+
+                // Copies can overlap what they copy, repeating it
+                let start = out.len() - distance;
+
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            },
+            _ => return Err(InflateError::Invalid("length code")),
+        }
+    }
+}
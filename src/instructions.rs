@@ -1,4 +1,26 @@
-use std::{error::Error, fmt};
+use core::{error::Error, fmt};
+
+// Machine to emulate, which decides the instruction set.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "std", derive(clap::ValueEnum))]
+pub enum Profile {
+    Chip8,
+    #[cfg_attr(feature = "std", value(name = "megachip"))]
+    MegaChip,
+    #[cfg_attr(feature = "std", value(name = "chip8x"))]
+    Chip8X,
+}
+
+impl Profile {
+    // Bits of the index register the instruction set can address: 24 with
+    // MegaChip's 01NN NNNN, and 16 with XO-CHIP's F000 NNNN otherwise.
+    pub fn index_mask(self) -> usize {
+        match self {
+            Profile::MegaChip => 0xFF_FFFF,
+            _ => 0xFFFF,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct InvalidOpcodeError(u16);
@@ -11,7 +33,9 @@ impl fmt::Display for InvalidOpcodeError {
 
 impl Error for InvalidOpcodeError {}
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
+    MachineCall(usize),
     Clear,
     Return,
     Jump(usize),
@@ -43,9 +67,67 @@ pub enum Instruction {
     SetSoundToVx(usize),
     AddVxToI(usize),
     SetIToCharInVx(usize),
+    SetIToBigCharInVx(usize),
     StoreVxBCDAtI(usize),
     VDump(usize),
     VLoad(usize),
+
+    // XO-CHIP
+    // F000 NNNN, taking its 16-bit address from the word after it
+    SetIWide,
+    LoadAudioPattern,
+    SetPitch(usize),
+
+    // MegaChip
+    DisableMegaChip,
+    EnableMegaChip,
+    SetILong(u8),
+    LoadPalette(u8),
+    SetSpriteWidth(u8),
+    SetSpriteHeight(u8),
+    SetScreenAlpha(u8),
+    PlaySample(bool),
+    StopSample,
+    SetBlendMode(u8),
+    SetCollisionColor(u8),
+
+    // CHIP-8X
+    StepBackgroundColor,
+    AddVyToVxNibbles(usize, usize),
+    SetZoneColor(usize, usize),
+    SetRowColor(usize, usize, u8),
+    IfKey2Pressed(usize),
+    IfKey2NotPressed(usize),
+    OutputVx(usize),
+}
+
+impl Instruction {
+    // Decode an opcode as the given profile understands it, since some extensions
+    // give opcodes of the original instruction set a different meaning.
+    pub fn decode(opcode: u16, profile: Profile) -> Result<Self, InvalidOpcodeError> {
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+
+        match profile {
+            Profile::MegaChip => Self::try_from(opcode),
+            Profile::Chip8X if opcode == 0x02A0 => Ok(Self::StepBackgroundColor),
+            // Outside of MegaChip, 0NNN opcodes other than 00E0 and 00EE call machine code
+            _ if opcode & 0xF000 == 0 && !matches!(opcode, 0x00E0 | 0x00EE) => {
+                Ok(Self::MachineCall((opcode & 0xFFF).into()))
+            },
+            Profile::Chip8X => match opcode & 0xF000 {
+                0x5000 if n == 0x1 => Ok(Self::AddVyToVxNibbles(x, y)),
+                0xB000 if n == 0x0 => Ok(Self::SetZoneColor(x, y)),
+                0xB000 => Ok(Self::SetRowColor(x, y, n)),
+                0xE000 if opcode & 0xFF == 0xF2 => Ok(Self::IfKey2Pressed(x)),
+                0xE000 if opcode & 0xFF == 0xF5 => Ok(Self::IfKey2NotPressed(x)),
+                0xF000 if opcode & 0xFF == 0xF8 => Ok(Self::OutputVx(x)),
+                _ => Self::try_from(opcode),
+            },
+            _ => Self::try_from(opcode),
+        }
+    }
 }
 
 impl TryFrom<u16> for Instruction {
@@ -61,9 +143,20 @@ impl TryFrom<u16> for Instruction {
 
         match op_type {
             0x0 => {
-                match nn {
-                    0xE0 => Ok(Self::Clear),
-                    0xEE => Ok(Self::Return),
+                match (x, nn) {
+                    (0x0, 0xE0) => Ok(Self::Clear),
+                    (0x0, 0xEE) => Ok(Self::Return),
+                    (0x0, 0x10) => Ok(Self::DisableMegaChip),
+                    (0x0, 0x11) => Ok(Self::EnableMegaChip),
+                    (0x1, _) => Ok(Self::SetILong(nn)),
+                    (0x2, _) => Ok(Self::LoadPalette(nn)),
+                    (0x3, _) => Ok(Self::SetSpriteWidth(nn)),
+                    (0x4, _) => Ok(Self::SetSpriteHeight(nn)),
+                    (0x5, _) => Ok(Self::SetScreenAlpha(nn)),
+                    (0x6, 0x00..=0x0F) => Ok(Self::PlaySample(n == 0)),
+                    (0x7, 0x00) => Ok(Self::StopSample),
+                    (0x8, 0x00..=0x0F) => Ok(Self::SetBlendMode(n)),
+                    (0x9, _) => Ok(Self::SetCollisionColor(nn)),
                     _ => Err(InvalidOpcodeError(opcode)),
                 }
             },
@@ -108,9 +201,13 @@ impl TryFrom<u16> for Instruction {
                     0x18 => Ok(Self::SetSoundToVx(x.into())),
                     0x1E => Ok(Self::AddVxToI(x.into())),
                     0x29 => Ok(Self::SetIToCharInVx(x.into())),
+                    0x30 => Ok(Self::SetIToBigCharInVx(x.into())),
                     0x33 => Ok(Self::StoreVxBCDAtI(x.into())),
                     0x55 => Ok(Self::VDump(x.into())),
                     0x65 => Ok(Self::VLoad(x.into())),
+                    0x00 if x == 0x0 => Ok(Self::SetIWide),
+                    0x02 if x == 0x0 => Ok(Self::LoadAudioPattern),
+                    0x3A => Ok(Self::SetPitch(x.into())),
                     _ => Err(InvalidOpcodeError(opcode)),
                 }
             },
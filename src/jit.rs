@@ -0,0 +1,331 @@
+use std::{cell::RefCell, mem, ops::Range, rc::Rc};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::{config::Config, cpu::QuirksConfig, instructions::Instruction, memory::Memory};
+
+// Longest run of instructions compiled into one block, so that a block never
+// overshoots the end of a frame by much.
+const MAX_BLOCK_LEN: usize = 32;
+
+// Runs the block's instructions against the registers and index register.
+type BlockFn = unsafe extern "C" fn(*mut u8, *mut usize);
+
+#[derive(Clone, Copy)]
+pub struct Block {
+    pub func: BlockFn,
+    // Number of instructions the block executes
+    pub len: u64,
+    // Address execution continues from after the block
+    pub next: usize,
+}
+
+// Translation attempted at some address
+#[derive(Clone, Copy)]
+struct Entry {
+    // Address following the last byte it was read from
+    end: usize,
+    // None when the instruction there can't be compiled
+    block: Option<Block>,
+}
+
+// Compiles straight runs of register instructions, up to and including a jump, into
+// native code with cranelift. Everything else, including any instruction that
+// touches memory, the display, timers or input, is left to the interpreter. Blocks
+// are dropped when memory they were read from is written, which keeps self-modifying
+// programs correct, and all of them when the quirks they were compiled under change.
+// The code of dropped blocks is not freed until the machine is.
+pub struct Jit {
+    module: JITModule,
+    builder_context: FunctionBuilderContext,
+    // Indexed by the address translation started at, as far as blocks have been
+    entries: Rc<RefCell<Vec<Option<Entry>>>>,
+    // Quirks the blocks were compiled under
+    quirks: Option<QuirksConfig>,
+}
+
+impl Jit {
+    // None if cranelift doesn't support the host.
+    pub fn new() -> Option<Self> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+
+        let isa = cranelift_native::builder().ok()?
+            .finish(settings::Flags::new(flags))
+            .ok()?;
+
+        Some(Self {
+            module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+            builder_context: FunctionBuilderContext::new(),
+            entries: Rc::new(RefCell::new(Vec::new())),
+            quirks: None,
+        })
+    }
+
+    // Callback dropping the blocks read from a range of memory, for Memory::on_write.
+    pub fn invalidator(&self) -> impl FnMut(Range<usize>) + 'static {
+        let entries = self.entries.clone();
+
+        move |range| {
+            let mut entries = entries.borrow_mut();
+
+            // Only blocks starting at most a block's length before the range reach it
+            let end = range.end.min(entries.len());
+            let start = range.start.saturating_sub(MAX_BLOCK_LEN * 2).min(end);
+
+            for entry in &mut entries[start..end] {
+                if entry.is_some_and(|entry| entry.end > range.start) {
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    // Block starting at addr under the quirks given, compiling it on first use.
+    pub fn block(&mut self, addr: usize, memory: &Memory, config: &Config, quirks: QuirksConfig) -> Option<Block> {
+        if self.quirks != Some(quirks) {
+            self.entries.borrow_mut().fill(None);
+            self.quirks = Some(quirks);
+        }
+
+        if addr >= memory.len() {
+            return None;
+        }
+
+        if let Some(Some(entry)) = self.entries.borrow().get(addr) {
+            return entry.block;
+        }
+
+        let instructions = Self::fetch(addr, memory, config);
+
+        // Execution carries on after the last instruction, unless it jumps elsewhere
+        let next = match instructions.last() {
+            Some(&Instruction::Jump(target)) => target,
+            _ => addr + instructions.len() * 2,
+        };
+
+        let block = if instructions.is_empty() {
+            None
+        } else {
+            self.compile(&instructions, config, &quirks).map(|func| Block {
+                func,
+                len: instructions.len() as u64,
+                next,
+            })
+        };
+
+        let mut entries = self.entries.borrow_mut();
+
+        if addr >= entries.len() {
+            entries.resize(addr + 1, None);
+        }
+
+        entries[addr] = Some(Entry {
+            end: addr + (instructions.len() * 2).max(2),
+            block,
+        });
+
+        block
+    }
+
+    // The run of compilable instructions starting at addr.
+    fn fetch(addr: usize, memory: &Memory, config: &Config) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+
+        while instructions.len() < MAX_BLOCK_LEN {
+            let pc = addr + instructions.len() * 2;
+
+            // Mapped addresses may read differently every time
+            if memory.is_mapped(pc..pc + 2) {
+                break;
+            }
+
+            let Ok(parts) = memory.read_buf(pc, 2) else {
+                break;
+            };
+
+            let opcode = u16::from_be_bytes([parts[0], parts[1]]);
+
+            // The interpreter halts on the exit opcode
+            if Some(opcode) == config.exit_opcode {
+                break;
+            }
+
+            match Instruction::decode(opcode, config.profile) {
+                // A jump ends the block, which hands its target back as the next address
+                Ok(instruction @ Instruction::Jump(_)) if !instructions.is_empty() => {
+                    instructions.push(instruction);
+                    break;
+                },
+                Ok(instruction) if Self::compilable(&instruction) => instructions.push(instruction),
+                _ => break,
+            }
+        }
+
+        instructions
+    }
+
+    fn compilable(instruction: &Instruction) -> bool {
+        matches!(
+            instruction,
+            Instruction::SetVx(..)
+                | Instruction::AddToVx(..)
+                | Instruction::SetVxToVy(..)
+                | Instruction::SetVxOrVy(..)
+                | Instruction::SetVxAndVy(..)
+                | Instruction::SetVxXorVy(..)
+                | Instruction::AddVyToVx(..)
+                | Instruction::SubVyFromVx(..)
+                | Instruction::RightShiftVx(..)
+                | Instruction::SubVxFromVy(..)
+                | Instruction::LeftShiftVx(..)
+                | Instruction::SetI(..)
+                | Instruction::AddVxToI(..)
+                | Instruction::SetIToCharInVx(..)
+                | Instruction::SetIToBigCharInVx(..)
+        )
+    }
+
+    fn compile(&mut self, instructions: &[Instruction], config: &Config, quirks: &QuirksConfig) -> Option<BlockFn> {
+        let pointer = self.module.target_config().pointer_type();
+        let mut context = self.module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.params.push(AbiParam::new(pointer));
+
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let v = builder.block_params(entry)[0];
+        let i = builder.block_params(entry)[1];
+        let flags = MemFlags::trusted();
+
+        let load = |builder: &mut FunctionBuilder, reg: usize| builder.ins().load(types::I8, flags, v, reg as i32);
+        let store = |builder: &mut FunctionBuilder, reg: usize, value: Value| {
+            builder.ins().store(flags, value, v, reg as i32);
+        };
+        let store_const = |builder: &mut FunctionBuilder, reg: usize, value: u8| {
+            let value = builder.ins().iconst(types::I8, value as i64);
+            store(builder, reg, value);
+        };
+
+        // Same semantics, quirks included, as the interpreter in system.rs
+        for &instruction in instructions {
+            match instruction {
+                Instruction::SetVx(reg, val) => store_const(&mut builder, reg, val),
+                Instruction::AddToVx(reg, val) => {
+                    let value = load(&mut builder, reg);
+                    let result = builder.ins().iadd_imm(value, val as i64);
+                    store(&mut builder, reg, result);
+                },
+                Instruction::SetVxToVy(reg_x, reg_y) => {
+                    let value = load(&mut builder, reg_y);
+                    store(&mut builder, reg_x, value);
+                },
+                Instruction::SetVxOrVy(reg_x, reg_y)
+                | Instruction::SetVxAndVy(reg_x, reg_y)
+                | Instruction::SetVxXorVy(reg_x, reg_y) => {
+                    let x = load(&mut builder, reg_x);
+                    let y = load(&mut builder, reg_y);
+                    let result = match instruction {
+                        Instruction::SetVxOrVy(..) => builder.ins().bor(x, y),
+                        Instruction::SetVxAndVy(..) => builder.ins().band(x, y),
+                        _ => builder.ins().bxor(x, y),
+                    };
+                    store(&mut builder, reg_x, result);
+
+                    if !quirks.skip_reset_vf {
+                        store_const(&mut builder, 0xF, 0);
+                    }
+                },
+                Instruction::AddVyToVx(reg_x, reg_y) => {
+                    let x = load(&mut builder, reg_x);
+                    let y = load(&mut builder, reg_y);
+                    let result = builder.ins().iadd(x, y);
+                    let overflowed = builder.ins().icmp(IntCC::UnsignedLessThan, result, x);
+                    store(&mut builder, reg_x, result);
+                    store(&mut builder, 0xF, overflowed);
+                },
+                Instruction::SubVyFromVx(reg_x, reg_y) | Instruction::SubVxFromVy(reg_x, reg_y) => {
+                    let x = load(&mut builder, reg_x);
+                    let y = load(&mut builder, reg_y);
+                    let (minuend, subtrahend) = match instruction {
+                        Instruction::SubVyFromVx(..) => (x, y),
+                        _ => (y, x),
+                    };
+                    let result = builder.ins().isub(minuend, subtrahend);
+                    let not_borrowed = builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, minuend, subtrahend);
+                    store(&mut builder, reg_x, result);
+                    store(&mut builder, 0xF, not_borrowed);
+                },
+                Instruction::RightShiftVx(reg_x, reg_y) | Instruction::LeftShiftVx(reg_x, reg_y) => {
+                    let reg = if quirks.skip_shift_set {
+                        reg_x
+                    } else {
+                        reg_y
+                    };
+
+                    let value = load(&mut builder, reg);
+                    let (result, bit) = match instruction {
+                        Instruction::RightShiftVx(..) => (builder.ins().ushr_imm(value, 1), builder.ins().band_imm(value, 1)),
+                        _ => (builder.ins().ishl_imm(value, 1), builder.ins().ushr_imm(value, 7)),
+                    };
+                    store(&mut builder, reg_x, result);
+                    store(&mut builder, 0xF, bit);
+                },
+                Instruction::SetI(addr) => {
+                    let value = builder.ins().iconst(pointer, addr as i64);
+                    builder.ins().store(flags, value, i, 0);
+                },
+                Instruction::AddVxToI(reg) => {
+                    let value = load(&mut builder, reg);
+                    let value = builder.ins().uextend(pointer, value);
+                    let index = builder.ins().load(pointer, flags, i, 0);
+                    let index = builder.ins().iadd(index, value);
+                    let wrapped = builder.ins().band_imm(index, config.profile.index_mask() as i64);
+                    builder.ins().store(flags, wrapped, i, 0);
+
+                    if quirks.index_overflow_flag {
+                        let overflowed = builder.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, index, config.memory.length as i64);
+                        store(&mut builder, 0xF, overflowed);
+                    }
+                },
+                Instruction::SetIToCharInVx(reg) | Instruction::SetIToBigCharInVx(reg) => {
+                    let (start, size) = match instruction {
+                        Instruction::SetIToCharInVx(..) => (config.memory.font_start, 5),
+                        _ => (config.memory.big_font_start, 10),
+                    };
+
+                    let value = load(&mut builder, reg);
+                    let digit = builder.ins().band_imm(value, 0xF);
+                    let digit = builder.ins().uextend(pointer, digit);
+                    let offset = builder.ins().imul_imm(digit, size);
+                    let index = builder.ins().iadd_imm(offset, start as i64);
+                    builder.ins().store(flags, index, i, 0);
+                },
+                // Taken care of by the block's next address
+                Instruction::Jump(_) => (),
+                _ => unreachable!("uncompilable instruction in block"),
+            }
+        }
+
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        let id = self.module.declare_anonymous_function(&context.func.signature).ok()?;
+        self.module.define_function(id, &mut context).ok()?;
+        self.module.clear_context(&mut context);
+        self.module.finalize_definitions().ok()?;
+
+        // The signature declared above matches BlockFn
+        Some(unsafe { mem::transmute::<*const u8, BlockFn>(self.module.get_finalized_function(id)) })
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "std", feature(mpmc_channel))]
+
+extern crate alloc;
+
+// The interpreter core, which only needs alloc
+pub mod cpu;
+pub mod frame;
+pub mod instructions;
+pub mod memory;
+
+// Everything else needs the standard library
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod system;
+#[cfg(feature = "std")]
+pub mod devices;
+#[cfg(feature = "std")]
+pub mod env;
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod archive;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod compare;
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod crash;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod divergence;
+#[cfg(feature = "std")]
+pub mod heatmap;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod menu;
+#[cfg(feature = "std")]
+pub mod netplay;
+#[cfg(feature = "std")]
+pub mod octo;
+#[cfg(feature = "std")]
+pub mod patch;
+#[cfg(feature = "std")]
+pub mod postprocess;
+#[cfg(feature = "std")]
+pub mod protocol;
+#[cfg(feature = "std")]
+pub mod raster;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod scope;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod timing;
+#[cfg(feature = "std")]
+pub mod unpack;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod watches;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod chip8x;
+#[cfg(feature = "std")]
+mod history;
+#[cfg(feature = "std")]
+mod inflate;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "jit")]
+mod jit;
+#[cfg(feature = "std")]
+mod megachip;
+#[cfg(feature = "std")]
+mod timer;
+
+#[cfg(feature = "std")]
+pub use system::{ChipEight, ChipEightBuilder, StopToken};
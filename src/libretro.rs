@@ -0,0 +1,509 @@
+// libretro core entry points, so the interpreter can run inside RetroArch and other
+// libretro frontends. Build with:
+//
+//     cargo rustc --release --lib --features libretro --crate-type cdylib
+//
+// The frontend drives everything: retro_run executes one frame's worth of
+// instructions, and video, audio and input all go through its callbacks.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_uint, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    slice,
+    sync::Mutex,
+};
+
+use crate::{
+    config::Config,
+    devices::{
+        headless::{FrameDisplay, KeyInput, ToneAudio},
+        Key,
+    },
+    ChipEight,
+    ChipEightBuilder,
+};
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const TONE_FREQUENCY: f32 = 440.0;
+const TONE_VOLUME: i16 = 1000;
+
+// RetroPad buttons and the keypad keys they press. The directions follow the
+// common 2/4/6/8 convention, with A on 5.
+const JOYPAD_MAP: [(c_uint, Key); 12] = [
+    (4, Key::_2), // Up
+    (5, Key::_8), // Down
+    (6, Key::_4), // Left
+    (7, Key::_6), // Right
+    (8, Key::_5), // A
+    (0, Key::_0), // B
+    (9, Key::_1), // X
+    (1, Key::_3), // Y
+    (10, Key::_7), // L
+    (11, Key::_9), // R
+    (2, Key::A), // Select
+    (3, Key::B), // Start
+];
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+thread_local! {
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+
+/* Core */
+
+struct Core {
+    chip_eight: ChipEight,
+    rom: Vec<u8>,
+    display: FrameDisplay,
+    audio: ToneAudio,
+    input: KeyInput,
+    // Keyboard keys (as libretro keycodes) for each keypad key
+    key_map: Vec<(Key, c_uint)>,
+    colors: [u32; 2],
+    cycles_per_frame: u64,
+    phase: f32,
+    // Memory as of the last frame, once the frontend has a pointer to it, for
+    // finding what it wrote there in between, such as cheats
+    exposed: Option<Vec<u8>>,
+}
+
+impl Core {
+    fn new(rom: &[u8]) -> Self {
+        let config = Config::default();
+
+        let display = FrameDisplay::new(config.display.width * config.display.height);
+        let audio = ToneAudio::new();
+        let input = KeyInput::new();
+
+        // Key names are single characters, which libretro represents by their lowercase ASCII code
+        let key_map = config.input.key_map
+            .iter()
+            .filter_map(|(key, name)| {
+                let [byte] = name.as_bytes() else {
+                    return None;
+                };
+
+                Some((*key, byte.to_ascii_lowercase() as c_uint))
+            })
+            .collect();
+
+        let colors = config.display.colors.map(|(r, g, b)| ((r as u32) << 16) | ((g as u32) << 8) | b as u32);
+        let cycles_per_frame = config.cycles_per_frame();
+
+        let mut chip_eight = ChipEightBuilder::new(config)
+            .with_display(Box::new(display.clone()))
+            .with_audio(Box::new(audio.clone()))
+            .with_input(Box::new(input.clone()))
+            .build();
+
+        chip_eight.load(rom);
+
+        Self {
+            chip_eight,
+            rom: rom.to_vec(),
+            display,
+            audio,
+            input,
+            key_map,
+            colors,
+            cycles_per_frame,
+            phase: 0.0,
+            exposed: None,
+        }
+    }
+
+    // Memory as the program sees it.
+    fn memory(&self) -> Vec<u8> {
+        let memory = self.chip_eight.memory();
+        memory.read_buf(0, memory.len()).map(|bytes| bytes.into_owned()).unwrap_or_default()
+    }
+
+    // Invalidate what the frontend changed through the memory pointer since the last
+    // frame, which the instruction cache and JIT would otherwise never see.
+    fn sync_exposed_memory(&mut self) {
+        let Some(exposed) = self.exposed.take() else {
+            return;
+        };
+
+        let current = self.memory();
+        let mut addr = 0;
+
+        while addr < current.len().min(exposed.len()) {
+            if current[addr] == exposed[addr] {
+                addr += 1;
+                continue;
+            }
+
+            let start = addr;
+
+            while addr < current.len().min(exposed.len()) && current[addr] != exposed[addr] {
+                addr += 1;
+            }
+
+            self.chip_eight.memory_mut().mark_written(start..addr);
+        }
+
+        self.exposed = Some(current);
+    }
+
+    fn run_frame(&mut self, callbacks: &Callbacks) {
+        if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state) {
+            let mut keys = [false; 16];
+
+            unsafe {
+                input_poll();
+
+                for (id, key) in JOYPAD_MAP {
+                    keys[key as usize] |= input_state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0;
+                }
+
+                for &(key, code) in &self.key_map {
+                    keys[key as usize] |= input_state(0, RETRO_DEVICE_KEYBOARD, 0, code) != 0;
+                }
+            }
+
+            self.input.set_keys(keys);
+        }
+
+        self.sync_exposed_memory();
+        self.chip_eight.run_cycles(self.cycles_per_frame);
+
+        self.chip_eight.vblank();
+
+        // What the program wrote is already known about
+        if self.exposed.is_some() {
+            self.exposed = Some(self.memory());
+        }
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            let (width, height) = self.chip_eight.resolution();
+            let pixels = self.display
+                .frame()
+                .iter()
+                .map(|&on| self.colors[on as usize])
+                .collect::<Vec<_>>();
+
+            unsafe {
+                video_refresh(
+                    pixels.as_ptr() as *const c_void,
+                    width as c_uint,
+                    height as c_uint,
+                    width * size_of::<u32>(),
+                );
+            }
+        }
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let frames = (SAMPLE_RATE / FPS) as usize;
+            let mut samples = Vec::with_capacity(frames * 2);
+
+            let pattern = self.audio.pattern();
+
+            // Phase runs over one period of the tone, or over the whole audio pattern
+            let phase_inc = match pattern {
+                Some((_, rate)) => rate / 128.0 / SAMPLE_RATE as f32,
+                None => TONE_FREQUENCY / SAMPLE_RATE as f32,
+            };
+
+            for _ in 0..frames {
+                let high = match pattern {
+                    Some((bits, _)) => {
+                        let bit = (self.phase * 128.0) as usize;
+                        (bits[bit / 8] >> (7 - bit % 8)) & 1 == 1
+                    },
+                    None => self.phase <= 0.5,
+                };
+
+                let sample = match (self.audio.is_playing(), high) {
+                    (true, true) => TONE_VOLUME,
+                    (true, false) => -TONE_VOLUME,
+                    (false, _) => 0,
+                };
+
+                samples.extend([sample, sample]);
+                self.phase = (self.phase + phase_inc) % 1.0;
+            }
+
+            unsafe {
+                audio_sample_batch(samples.as_ptr(), frames);
+            }
+        }
+    }
+}
+
+
+/* Entry points */
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with_borrow_mut(|core| *core = None);
+}
+
+/// # Safety
+///
+/// `info` must point to a valid `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"Chip Eight".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"ch8|c8|rom".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a valid `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let config = Config::default();
+    let width = config.display.width as c_uint;
+    let height = config.display.height as c_uint;
+
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: width,
+            base_height: height,
+            max_width: width,
+            // Room for programs that switch to the 64x64 hi-res mode
+            max_height: height * 2,
+            aspect_ratio: width as f32 / height as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    CORE.with_borrow_mut(|core| {
+        if let Some(core) = core {
+            core.chip_eight.reset();
+            core.chip_eight.load(&core.rom);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = CALLBACKS.lock().unwrap();
+
+    CORE.with_borrow_mut(|core| {
+        let Some(running) = core else {
+            return;
+        };
+
+        // A panic must not unwind into the frontend, so stop the core instead
+        if panic::catch_unwind(AssertUnwindSafe(|| running.run_frame(&callbacks))).is_err() {
+            *core = None;
+        }
+    });
+}
+
+/// # Safety
+///
+/// `game` must point to a valid `retro_game_info` whose data holds `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size);
+
+    if let Some(environment) = CALLBACKS.lock().unwrap().environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+
+        if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            return false;
+        }
+    }
+
+    match panic::catch_unwind(|| Core::new(rom)) {
+        Ok(loaded) => {
+            CORE.with_borrow_mut(|core| *core = Some(loaded));
+            true
+        },
+        Err(_) => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_kind: c_uint, _info: *const RetroGameInfo, _count: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with_borrow_mut(|core| *core = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return ptr::null_mut();
+    }
+
+    CORE.with_borrow_mut(|core| match core {
+        Some(core) => {
+            let data = core.chip_eight.memory_mut().as_mut_slice().as_mut_ptr() as *mut c_void;
+
+            // The frontend may write through the pointer whenever it likes, so each frame
+            // looks for what changed
+            core.exposed = Some(core.memory());
+            data
+        },
+        None => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return 0;
+    }
+
+    CORE.with_borrow(|core| core.as_ref().map_or(0, |core| core.chip_eight.memory().len()))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize(_data: *mut c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unserialize(_data: *const c_void, _size: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
@@ -0,0 +1,145 @@
+use std::{collections::{BTreeSet, HashSet}, fmt};
+
+use crate::{
+    analysis::{fetch, successors, Platform},
+    instructions::{Instruction, Profile},
+};
+
+// Nesting depth past which calls are reported as a likely stack overflow.
+const STACK_LIMIT: usize = 16;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Lint {
+    JumpOutOfBounds(usize),
+    OddJumpTarget(usize),
+    SpriteOutOfBounds(usize, usize),
+    WriteBelowProgramStart(usize),
+    ReturnWithEmptyStack,
+    StackOverflow,
+    Undecodable(u16),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::JumpOutOfBounds(target) => write!(f, "jump to 0x{:03X} is outside of memory", target),
+            Lint::OddJumpTarget(target) => write!(f, "jump to odd address 0x{:03X}", target),
+            Lint::SpriteOutOfBounds(addr, len) => write!(f, "sprite at 0x{:03X} reads {} bytes past the end of memory", addr, len),
+            Lint::WriteBelowProgramStart(addr) => write!(f, "write to 0x{:03X} is below the start of the program", addr),
+            Lint::ReturnWithEmptyStack => write!(f, "return with an empty stack"),
+            Lint::StackOverflow => write!(f, "subroutine calls nest deeper than {} levels", STACK_LIMIT),
+            Lint::Undecodable(opcode) => write!(f, "undecodable opcode 0x{:04X}", opcode),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Finding {
+    pub addr: usize,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:03X}: {}", self.addr, self.lint)
+    }
+}
+
+// Point in the walk: the address about to execute, how many calls deep we are, and
+// the value of the index register if it is statically known.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    addr: usize,
+    depth: usize,
+    i: Option<usize>,
+}
+
+pub fn lint(rom: &[u8], program_start: usize, memory_length: usize, profile: Profile) -> BTreeSet<Finding> {
+    let mut findings = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![State { addr: program_start, depth: 0, i: None }];
+
+    while let Some(state) = pending.pop() {
+        if !visited.insert(state) {
+            continue;
+        }
+
+        let mut report = |lint| {
+            findings.insert(Finding { addr: state.addr, lint });
+        };
+
+        let Some(opcode) = fetch(rom, program_start, state.addr) else {
+            continue;
+        };
+
+        let instruction = match Instruction::decode(opcode, profile) {
+            Ok(instruction) => instruction,
+            Err(_) => {
+                if Platform::of(opcode).is_none() {
+                    report(Lint::Undecodable(opcode));
+                }
+
+                continue;
+            },
+        };
+
+        match instruction {
+            Instruction::Jump(target) | Instruction::Call(target) => {
+                if target + 1 >= memory_length {
+                    report(Lint::JumpOutOfBounds(target));
+                } else if target % 2 != 0 {
+                    report(Lint::OddJumpTarget(target));
+                }
+
+                if matches!(instruction, Instruction::Call(_)) && state.depth >= STACK_LIMIT {
+                    report(Lint::StackOverflow);
+                }
+            },
+            Instruction::Return if state.depth == 0 => report(Lint::ReturnWithEmptyStack),
+            Instruction::Draw(_, _, height) => if let Some(i) = state.i {
+                let end = i + height as usize;
+
+                if end > memory_length {
+                    report(Lint::SpriteOutOfBounds(i, end - memory_length));
+                }
+            },
+            Instruction::StoreVxBCDAtI(_) | Instruction::VDump(_) => if let Some(i) = state.i {
+                if i < program_start {
+                    report(Lint::WriteBelowProgramStart(i));
+                }
+            },
+            _ => (),
+        }
+
+        let i = match instruction {
+            Instruction::SetI(addr) => Some(addr),
+            Instruction::AddVxToI(_)
+            | Instruction::SetIWide
+            | Instruction::SetIToCharInVx(_)
+            | Instruction::SetIToBigCharInVx(_)
+            | Instruction::VDump(_)
+            | Instruction::VLoad(_) => None,
+            _ => state.i,
+        };
+
+        match instruction {
+            Instruction::Call(target) => {
+                if state.depth < STACK_LIMIT {
+                    pending.push(State { addr: target, depth: state.depth + 1, i });
+                }
+
+                // Assume the subroutine returns, though it may have moved the index
+                // register while doing so.
+                pending.push(State { addr: state.addr + 2, depth: state.depth, i: None });
+            },
+            Instruction::Jump(target) if target + 1 >= memory_length => (),
+            _ => pending.extend(
+                successors(&instruction, state.addr)
+                    .into_iter()
+                    .map(|addr| State { addr, depth: state.depth, i }),
+            ),
+        }
+    }
+
+    findings
+}
@@ -0,0 +1,154 @@
+// Reads ROMs from disk: plain ROM files, Octo source to assemble, and either of
+// those zipped or gzipped. Files that are plainly something else, such as an image
+// saved in place of a ROM, are turned away by their magic numbers, and ROMs are
+// checked to fit in memory before they're loaded.
+
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use log::warn;
+
+use crate::{
+    memory::{MemoryConfig, MemoryError},
+    octo::{self, AssembleError},
+    symbols::Symbols,
+    unpack::{self, UnpackError},
+};
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+
+// Starts of files that can't be ROMs, and what they are
+const FOREIGN_MAGIC: [(&[u8], &str); 8] = [
+    (b"\x89PNG", "a PNG image"),
+    (b"GIF8", "a GIF image"),
+    (b"\xFF\xD8\xFF", "a JPEG image"),
+    (b"%PDF", "a PDF document"),
+    (b"\x7FELF", "an executable"),
+    (b"Rar!", "a RAR archive"),
+    (b"7z\xBC\xAF", "a 7-Zip archive"),
+    (b"<!DOCTYPE", "a web page"),
+];
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Assemble(AssembleError),
+    Unpack(UnpackError),
+    Empty,
+    // What the file looks to be instead
+    NotChip8(&'static str),
+    // Size of the ROM, and the bytes of memory from the program start to the end
+    TooLarge(usize, usize),
+    // The fonts don't fit where they're configured to go
+    Memory(MemoryError),
+}
+
+impl RomError {
+    // What to try instead, for errors with a likely fix.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            RomError::Io(error) if error.kind() == io::ErrorKind::NotFound => {
+                Some("check the path, or leave it out to pick a ROM from the menu")
+            },
+            RomError::Unpack(UnpackError::NoRom(_) | UnpackError::NoMember(..)) => {
+                Some("name the file in the zip to run with --member")
+            },
+            RomError::NotChip8("a web page") => Some("the download may have saved the page linking to the ROM, rather than the ROM itself"),
+            RomError::NotChip8(_) => Some("pass the ROM file itself, usually with a .ch8 extension"),
+            RomError::TooLarge(..) => {
+                Some("XO-CHIP programs need --memory-length 65536, and --program-start may be set too high")
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "{}", error),
+            RomError::Assemble(error) => write!(f, "failed to assemble: {}", error),
+            RomError::Unpack(error) => write!(f, "failed to unpack: {}", error),
+            RomError::Empty => write!(f, "file is empty"),
+            RomError::NotChip8(kind) => write!(f, "file is {}, not a CHIP-8 ROM", kind),
+            RomError::TooLarge(size, space) => {
+                write!(f, "ROM is {} bytes, but only {} fit after the program start", size, space)
+            },
+            RomError::Memory(error) => write!(f, "failed to load: {}", error),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+// Read a ROM file, assembling it first if it is Octo source.
+pub fn read_rom(path: &Path, program_start: usize) -> Result<Vec<u8>, RomError> {
+    read_program(path, program_start).map(|(rom, _)| rom)
+}
+
+// Same as read_rom, along with the labels of Octo source. Other ROMs have none.
+pub fn read_program(path: &Path, program_start: usize) -> Result<(Vec<u8>, Symbols), RomError> {
+    read_program_member(path, program_start, None)
+}
+
+// Same as read_program, also reading ROMs in zip and gzip files: the member of a
+// zip given, or else its first ROM, and the file a .gz was compressed from.
+pub fn read_program_member(path: &Path, program_start: usize, member: Option<&str>) -> Result<(Vec<u8>, Symbols), RomError> {
+    let data = fs::read(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    // Whatever is inside is read as its own name says
+    let (name, data) = if data.starts_with(ZIP_MAGIC) {
+        unpack::unzip(&data, member).map_err(RomError::Unpack)?
+    } else if data.starts_with(GZIP_MAGIC) {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        (name, unpack::gunzip(&data).map_err(RomError::Unpack)?)
+    } else {
+        (name, data)
+    };
+
+    if data.is_empty() {
+        return Err(RomError::Empty);
+    }
+
+    if let Some((_, kind)) = FOREIGN_MAGIC.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return Err(RomError::NotChip8(kind));
+    }
+
+    if name.to_ascii_lowercase().ends_with(".8o") {
+        let source = String::from_utf8(data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        return octo::assemble_with_symbols(&source, program_start).map_err(RomError::Assemble);
+    }
+
+    // Instructions are two bytes, though plenty of ROMs end on a byte of data
+    if data.len() % 2 != 0 {
+        warn!("{} is an odd number of bytes long ({}), which can mean it was cut short", name, data.len());
+    }
+
+    Ok((data, Symbols::new()))
+}
+
+// Read a ROM as read_program_member does, checking it fits in memory.
+pub fn read(path: &Path, memory: &MemoryConfig, member: Option<&str>) -> Result<(Vec<u8>, Symbols), RomError> {
+    let (rom, symbols) = read_program_member(path, memory.program_start, member)?;
+    check_fits(&rom, memory)?;
+
+    Ok((rom, symbols))
+}
+
+// Check the ROM fits in memory from the program start.
+pub fn check_fits(rom: &[u8], memory: &MemoryConfig) -> Result<(), RomError> {
+    let space = memory.length.saturating_sub(memory.program_start);
+
+    if rom.len() > space {
+        return Err(RomError::TooLarge(rom.len(), space));
+    }
+
+    Ok(())
+}
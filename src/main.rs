@@ -1,26 +1,964 @@
-#![feature(mpmc_channel)]
+use std::{collections::BTreeSet, env, ffi::OsString, fmt, fs, io, path::{Path, PathBuf}, process, rc::Rc, thread};
 
-mod config;
-mod system;
-mod timer;
-mod memory;
-mod instructions;
-mod devices;
+use clap::{CommandFactory, Parser, ValueEnum};
+use env_logger::Env;
+use log::{debug, error, info, log_enabled, trace, Level};
+use sha1::{Digest, Sha1};
 
-use std::{fs::File, io::Read};
+use chip_eight::{
+    analysis::{Analysis, Platform},
+    archive::Archive,
+    batch,
+    compare::{self, Comparison},
+    config::{
+        self, ArchiveCommand, Args, AudioEngine, Cli, Command, Config, CoverageOutput, DisplayConfig, DisplayEngine,
+        InputEngine, StateCommand,
+    },
+    coverage::Coverage,
+    debugger::Debugger,
+    devices::{self, create_display_device, headless::KeyInput, orient_display, websocket, Display},
+    diff,
+    disasm,
+    divergence,
+    heatmap::{self, Heatmap},
+    instructions::{Instruction, Profile},
+    lint,
+    loader::{self, RomError},
+    menu::Menu,
+    netplay::Session,
+    octo,
+    patch::Patch,
+    postprocess::post_process,
+    raster,
+    replay::Replay,
+    snapshot::{Snapshot, SnapshotError},
+    storage::{self, Storage},
+    symbols::Symbols,
+    system::SHUTDOWN_TIMEOUT,
+    ChipEight,
+    ChipEightBuilder,
+};
+#[cfg(feature = "scripting")]
+use chip_eight::scripting::Script;
 
-use clap::Parser;
+// Exit status when the program halts on the exit opcode, set apart from stopping
+// for any other reason
+const EXITED_STATUS: i32 = 3;
 
-use system::ChipEight;
-use config::{Args, Config};
+// Number of instructions the debugger can step back through.
+const DEBUG_HISTORY: usize = 100_000;
 
 fn main() {
-    let args = Args::parse();
+    // Info and above unless RUST_LOG says otherwise, e.g. RUST_LOG=chip_eight=debug
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let mut file = File::open(&args.rom_path).unwrap();
-    let mut rom = Vec::new();
-    file.read_to_end(&mut rom).unwrap();
+    let cli = Cli::parse();
 
-    ChipEight::from(Config::from(args))
-        .play(&rom);
+    match cli.command {
+        Some(Command::Disasm { rom_path, program_start, profile, symbols }) => {
+            let (rom, rom_symbols) = read_program(&rom_path, program_start);
+            let symbols = read_symbols(symbols.as_deref(), rom_symbols);
+
+            print!("{}", disasm::disassemble(&rom, program_start, profile, &symbols));
+        },
+        Some(Command::Asm { source_path, output, program_start, symbols }) => {
+            asm(&source_path, output.as_deref(), program_start, symbols.as_deref());
+        },
+        Some(Command::Info { rom_path, program_start, profile }) => info(&rom_path, program_start, profile),
+        Some(Command::Lint { rom_path, program_start, memory_length, profile, symbols }) => {
+            if memory_length > profile.index_mask() + 1 {
+                fail(format_args!(
+                    "Failed to lint {}: {} bytes of memory is more than the profile addresses, at most {}",
+                    rom_path, memory_length, profile.index_mask() + 1,
+                ));
+            }
+
+            let (rom, rom_symbols) = read_program(&rom_path, program_start);
+            let symbols = read_symbols(symbols.as_deref(), rom_symbols);
+            let findings = lint::lint(&rom, program_start, memory_length, profile);
+
+            for finding in &findings {
+                println!("{}: {}", symbols.describe(finding.addr), finding.lint);
+            }
+
+            if !findings.is_empty() {
+                process::exit(1);
+            }
+        },
+        Some(Command::TestSuite { dir, cycles, settle, flags }) => test_suite(&dir, cycles, settle, &flags),
+        Some(Command::Batch { dir, cycles, settle, jobs, out, flags }) => {
+            batch(&dir, cycles, settle, jobs, out.as_deref(), &flags);
+        },
+        Some(Command::States { rom_path, program_start, data_dir, delete }) => {
+            let (rom, _) = read_program(&rom_path, program_start);
+            let storage = storage(data_dir.as_deref()).unwrap_or_else(|| {
+                fail(format_args!("No data directory, set HOME or pass --data-dir"));
+            });
+            let rom_storage = storage.rom(&rom);
+
+            if let Some(slot) = delete {
+                if let Err(error) = rom_storage.delete_state(slot) {
+                    fail(format_args!("Failed to delete slot {}: {}", slot, error));
+                }
+
+                return;
+            }
+
+            for (slot, saved_at) in rom_storage.states() {
+                println!("{}: saved {} ({})", slot, storage::age(saved_at), rom_storage.save_state_path(slot).display());
+            }
+        },
+        Some(Command::State { action: StateCommand::Info { path } }) => state_info(&path),
+        Some(Command::DiffState { a_path, b_path }) => {
+            let differences = diff::diff(&read_snapshot(&a_path), &read_snapshot(&b_path));
+
+            for difference in &differences {
+                println!("{}", difference);
+            }
+
+            if !differences.is_empty() {
+                process::exit(1);
+            }
+        },
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "chip-eight", &mut io::stdout());
+        },
+        Some(Command::InitConfig { data_dir, force }) => {
+            let storage = storage(data_dir.as_deref()).unwrap_or_else(|| {
+                fail(format_args!("No config directory, set HOME or pass --data-dir"));
+            });
+            let path = storage.config_path();
+
+            if path.exists() && !force {
+                fail(format_args!("{} already exists, pass --force to replace it", path.display()));
+            }
+
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&path, config::default_config_file()));
+
+            match written {
+                Ok(()) => println!("{}", path.display()),
+                Err(error) => fail(format_args!("Failed to write {}: {}", path.display(), error)),
+            }
+        },
+        Some(Command::Compare { a_path, b_path, lockstep, flags }) => compare(&a_path, &b_path, lockstep, &flags),
+        Some(Command::Diverge { rom_path, flags_a, flags_b, trace, write_trace, cycles }) => {
+            diverge(&rom_path, [&flags_a, &flags_b], trace.as_deref(), write_trace.as_deref(), cycles);
+        },
+        Some(Command::Archive { action, url, data_dir, refresh }) => {
+            archive(action, &url, data_dir.as_deref(), refresh);
+        },
+        command => run(command, cli.run, env::args_os().collect(), vec![]),
+    }
+}
+
+// Run a ROM as the run, replay and serve subcommands do, given the arguments used
+// when there is no subcommand, the command line they were parsed from, and flags
+// the ROM's source gives for it.
+fn run(command: Option<Command>, args: Args, command_line: Vec<OsString>, source_flags: Vec<String>) {
+    let storage = storage(run_args(&command, &args).data_dir.as_deref());
+    let (command, args) = with_settings(command, args, storage.as_ref(), command_line, source_flags);
+
+    let (args, replay) = match command {
+        Some(Command::Run(args)) => (args, None),
+        Some(Command::Serve { addr, mut args }) => {
+            args.serve = Some(addr);
+            (args, None)
+        },
+        Some(Command::Replay { replay_path, args }) => {
+            let replay = Replay::read(Path::new(&replay_path)).unwrap_or_else(|error| {
+                fail(format_args!("Failed to read replay {}: {}", replay_path, error));
+            });
+
+            (args, Some(replay))
+        },
+        _ => (args, None),
+    };
+
+    if args.list_engines {
+        print!("{}", devices::list_engines());
+        return;
+    }
+
+    let rom_path = args.rom_path.clone();
+    let rom_dir = args.rom_dir.clone();
+    let member = args.member.clone();
+    let watch = args.watch;
+    let debug = args.debug;
+    let symbols_path = args.symbols.clone();
+    let patch_path = args.patch.clone();
+    let serve = args.serve.clone();
+    let host = args.host.clone();
+    let join = args.join.clone();
+    let resume = args.resume;
+    let record = args.record.clone();
+    let coverage = args.coverage;
+    let heatmap = args.heatmap;
+    let exit_after = args.exit_after;
+    let exit_when_settled = args.exit_when_settled;
+    let expected_hash = args.expect_framebuffer_hash.clone();
+    #[cfg(feature = "scripting")]
+    let script = args.script.as_ref().map(|path| {
+        Script::load(Path::new(path)).unwrap_or_else(|error| {
+            fail(format_args!("Failed to load script {}: {}", path, error));
+        })
+    });
+    let config = Config::from(args);
+
+    if replay.is_some() && (debug || resume || record.is_some() || host.is_some() || join.is_some()) {
+        fail(format_args!("Replays can't be played back in the debugger, with --resume, --record or netplay"));
+    }
+
+    let mut builder = ChipEightBuilder::new(config);
+
+    if let Some(storage) = storage {
+        builder = builder
+            .with_storage(storage)
+            .with_crash_reports();
+    } else if resume {
+        fail(format_args!("No data directory to keep auto-saves in, set HOME or pass --data-dir"));
+    }
+
+    if resume {
+        builder = builder.with_resume();
+    }
+
+    if let Some(path) = patch_path {
+        let patch = Patch::read(Path::new(&path), builder.config().memory.program_start).unwrap_or_else(|error| {
+            fail(format_args!("Failed to read patch {}: {}", path, error));
+        });
+
+        builder = builder.with_patch(patch);
+    }
+
+    // The heatmap is shown beside the frame, in a window widened to fit it
+    let heatmap = heatmap.then(|| Heatmap::new(builder.config().memory.length));
+    let display = builder.config().display.clone();
+
+    // The menu needs somewhere to be shown and keys to choose with
+    let headless = serve.is_none() && (display.engine == DisplayEngine::None || builder.config().input.engine == InputEngine::None);
+
+    let mut displays: Vec<Box<dyn Display>> = match &heatmap {
+        Some(heatmap) => {
+            let window = Rc::new(window_config(&display, display.width * 3 + heatmap::GAP));
+            vec![heatmap.overlay(create_display_device(window), display.width, display.height, display.colors)]
+        },
+        None => vec![create_display_device(display.clone())],
+    };
+    let mut served_input = None;
+
+    // Served frames go out alongside those in the window, if there is one, and
+    // clients' key presses count along with the keyboard's
+    if let Some(addr) = serve {
+        let (websocket_display, input) = websocket::serve(&addr, builder.config().display.clone())
+            .unwrap_or_else(|error| {
+                fail(format_args!("Failed to listen on {}: {}", addr, error));
+            });
+
+        info!("Serving on {}", addr);
+
+        displays.push(Box::new(websocket_display));
+        served_input = Some(input);
+    }
+
+    // Scripts draw their text over every display in use
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &script {
+        let width = builder.config().display.width;
+
+        displays = displays.into_iter().map(|display| script.overlay(display, width)).collect();
+    }
+
+    for display in displays {
+        builder = builder.with_display(display);
+    }
+
+    // Stepping backwards needs the history
+    if debug {
+        builder = builder.with_history(DEBUG_HISTORY);
+    }
+
+    // Without a ROM, pick one from the menu, which plays any demos in the demos
+    // directory alongside the ROMs while idle
+    let menu = rom_path.is_none().then(|| {
+        if headless {
+            fail(format_args!("A ROM path is required when no display or input device is available"));
+        }
+
+        let menu = Menu::from_dir(Path::new(&rom_dir)).unwrap_or_else(|error| {
+            fail(format_args!("Failed to list ROMs in {}: {}", rom_dir, error));
+        });
+
+        if menu.is_empty() {
+            fail(format_args!("No ROMs found in {}", rom_dir));
+        }
+
+        menu.with_demos(&Path::new(&rom_dir).join("demos"))
+    });
+
+    let mut chip_eight = builder.build();
+
+    if let Some(heatmap) = &heatmap {
+        heatmap.track(&mut chip_eight);
+    }
+
+    if let Some(input) = served_input {
+        chip_eight.add_input(Box::new(input));
+    }
+
+    let rom_path = match (rom_path, menu) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, menu) => {
+            let menu = menu.expect("ROMs are listed whenever there's no ROM path");
+
+            // Closed from the menu
+            chip_eight.pick_rom(menu).unwrap_or_else(|| process::exit(0))
+        },
+    };
+
+    // Kept until the run is over
+    let _watcher = watch.then(|| chip_eight.watch_rom(&rom_path).unwrap_or_else(|error| {
+        fail(format_args!("Failed to watch {}: {}", rom_path.display(), error));
+    }));
+
+    let (rom, rom_symbols) = loader::read(&rom_path, &chip_eight.config().memory, member.as_deref())
+        .unwrap_or_else(|error| fail_rom(&rom_path, error));
+    let symbols = read_symbols(symbols_path.as_deref(), rom_symbols);
+
+    if let Some(name) = rom_path.file_stem() {
+        chip_eight.set_rom_name(&name.to_string_lossy());
+    }
+
+    // Every instruction executed, with RUST_LOG=chip_eight::trace=trace
+    if log_enabled!(target: "chip_eight::trace", Level::Trace) {
+        let symbols = symbols.clone();
+
+        chip_eight.on_instruction(move |pc, instruction| {
+            let target = match instruction {
+                Instruction::Jump(addr) | Instruction::Call(addr) | Instruction::SetI(addr) => symbols.label(*addr),
+                _ => None,
+            };
+
+            match target {
+                Some(label) => trace!(target: "chip_eight::trace", "{}: {:?} ({})", symbols.describe(pc), instruction, label),
+                None => trace!(target: "chip_eight::trace", "{}: {:?}", symbols.describe(pc), instruction),
+            }
+        });
+    }
+
+    let tracked = coverage.map(|_| Coverage::track(&mut chip_eight));
+
+    // The debugger handles Ctrl-C itself
+    if !debug {
+        let stop = chip_eight.stop_handle();
+        ctrlc::set_handler(move || {
+            info!("Shutting down...");
+            stop.stop_or_exit(SHUTDOWN_TIMEOUT);
+        }).expect("Failed to set Ctrl-C handler");
+    }
+
+    if host.is_some() || join.is_some() {
+        play_netplay(&mut chip_eight, &rom, host.as_deref(), join.as_deref());
+    } else if debug {
+        let mut debugger = Debugger::new(symbols.clone());
+
+        // Ctrl-C breaks back into the debugger rather than quitting
+        let interrupt = debugger.interrupt_handle();
+        ctrlc::set_handler(move || interrupt.stop()).expect("Failed to set Ctrl-C handler");
+
+        debugger.run(&mut chip_eight, &rom);
+    } else if let Some(replay) = replay {
+        info!("Playing back {} frames", replay.frames());
+
+        if let Err(error) = replay.play(&mut chip_eight, &rom) {
+            fail(format_args!("Failed to play back replay: {}", error));
+        }
+    } else if let Some(path) = record {
+        let replay = Replay::record(&mut chip_eight, &rom);
+
+        match replay.write(Path::new(&path)) {
+            Ok(()) => info!("Recorded {} frames to {}", replay.frames(), path),
+            Err(error) => fail(format_args!("Failed to write replay {}: {}", path, error)),
+        }
+    } else {
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &script {
+            chip_eight.load(&rom);
+
+            if let Err(error) = script.play(&mut chip_eight) {
+                fail(format_args!("Script failed: {}", error));
+            }
+        } else {
+            chip_eight.play(&rom);
+        }
+
+        #[cfg(not(feature = "scripting"))]
+        chip_eight.play(&rom);
+    }
+
+    // Whichever way the run went, the replay is written before the auto-save and
+    // the window closes before the reports
+    chip_eight.shutdown();
+
+    // Scripted runs report the frame they finished on
+    if exit_after.is_some() || exit_when_settled.is_some() || expected_hash.is_some() {
+        let hash = batch::frame_hash(chip_eight.frame_buffer());
+        info!("Final frame hash: {}", hash);
+
+        if chip_eight.settled() {
+            info!("Frame settled by frame {}", chip_eight.frame_number());
+            println!("{}", raster::render(chip_eight.frame(), chip_eight.frame().width()));
+        }
+
+        if let Some(expected) = expected_hash {
+            if !hash.eq_ignore_ascii_case(expected.trim()) {
+                fail(format_args!("Final frame hash {} does not match the expected {}", hash, expected));
+            }
+        }
+    }
+
+    if chip_eight.config().timing_stats {
+        println!("{}", chip_eight.timing());
+    }
+
+    if let Some(tracked) = &tracked {
+        let program_start = chip_eight.config().memory.program_start;
+        println!("{}", tracked.report(&rom, program_start, chip_eight.config().profile));
+
+        if coverage == Some(CoverageOutput::Listing) {
+            print!("{}", tracked.listing(&rom, program_start, chip_eight.config().profile, &symbols));
+        }
+    }
+
+    let backtrace = symbols.backtrace(chip_eight.pc(), chip_eight.call_stack());
+
+    if chip_eight.exited() {
+        info!("Program exited at {}", backtrace);
+        process::exit(EXITED_STATUS);
+    }
+
+    debug!("Stopped at {}", backtrace);
+}
+
+// Storage in the directory given, or the XDG directories.
+fn storage(data_dir: Option<&str>) -> Option<Storage> {
+    match data_dir {
+        Some(dir) => Some(Storage::in_dir(Path::new(dir))),
+        None => Storage::xdg(),
+    }
+}
+
+// Arguments to run with, from whichever of the subcommand and the top level they
+// were given to.
+fn run_args<'a>(command: &'a Option<Command>, args: &'a Args) -> &'a Args {
+    match command {
+        Some(Command::Run(args) | Command::Replay { args, .. } | Command::Serve { args, .. }) => args,
+        _ => args,
+    }
+}
+
+// Command line with the flags from the config file, then those from the ROM's
+// source, then those from the settings file for the ROM given on it, inserted
+// ahead of those given. Later flags take precedence.
+fn with_settings(
+    command: Option<Command>,
+    args: Args,
+    storage: Option<&Storage>,
+    command_line: Vec<OsString>,
+    source_flags: Vec<String>,
+) -> (Option<Command>, Args) {
+    let mut settings = vec![];
+
+    if let Some(storage) = storage {
+        let config_path = storage.config_path();
+        settings = match fs::read_to_string(&config_path) {
+            Ok(source) => config::config_file_flags(&source).unwrap_or_else(|error| {
+                fail(format_args!("Invalid config in {}: {}", config_path.display(), error));
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => fail(format_args!("Failed to read config from {}: {}", config_path.display(), error)),
+        };
+
+        if !settings.is_empty() {
+            info!("Using config from {}: {}", config_path.display(), settings.join(" "));
+        }
+    }
+
+    settings.extend(source_flags);
+
+    // ROMs picked from the menu are only known once the machine is running. Settings
+    // are kept by the program loaded, as the machine saves them, not the file it's in.
+    let run = run_args(&command, &args);
+    let rom = run.rom_path.as_ref().and_then(|rom_path| {
+        loader::read_program_member(Path::new(rom_path), run.program_start, run.member.as_deref())
+            .ok()
+            .map(|(rom, _)| rom)
+    });
+
+    if let Some((storage, rom)) = storage.zip(rom) {
+        let rom_storage = storage.rom(&rom);
+        let settings_path = rom_storage.settings_path().display().to_string();
+
+        let rom_settings = rom_storage.settings().unwrap_or_else(|error| {
+            fail(format_args!("Failed to read settings from {}: {}", settings_path, error));
+        });
+
+        if rom_settings.is_empty() {
+            debug!("No settings for the ROM at {}", settings_path);
+        } else {
+            info!("Using settings from {}: {}", settings_path, rom_settings.join(" "));
+            settings.extend(rom_settings);
+        }
+    }
+
+    if settings.is_empty() {
+        return (command, args);
+    }
+
+    // The settings go after the subcommand, if there is one
+    let mut command_line = command_line.into_iter();
+    let start: Vec<OsString> = command_line.by_ref().take(1 + command.is_some() as usize).collect();
+    let settings = settings.into_iter().map(OsString::from);
+
+    let cli = Cli::try_parse_from(start.into_iter().chain(settings).chain(command_line)).unwrap_or_else(|error| {
+        fail(format_args!("Invalid settings: {}", error));
+    });
+
+    (cli.command, cli.run)
+}
+
+// Connect to the other player, then play the ROM in lockstep with them.
+fn play_netplay(chip_eight: &mut ChipEight, rom: &[u8], host: Option<&str>, join: Option<&str>) {
+    let cycles_per_frame = chip_eight.cycles_per_frame();
+
+    let session = match (host, join) {
+        (Some(addr), _) => {
+            info!("Waiting for a player to join on {}", addr);
+            Session::host(addr, rom, cycles_per_frame)
+        },
+        (None, Some(addr)) => Session::join(addr, rom, cycles_per_frame),
+        (None, None) => return,
+    };
+
+    let session = session.unwrap_or_else(|error| {
+        fail(format_args!("Failed to start netplay: {}", error));
+    });
+
+    chip_eight.set_seed(session.seed());
+    chip_eight.load(rom);
+
+    if let Err(error) = session.play(chip_eight) {
+        fail(format_args!("Netplay failed: {}", error));
+    }
+}
+
+// A ROM and its labels, assembling Octo sources.
+fn read_program(path: impl AsRef<Path>, program_start: usize) -> (Vec<u8>, Symbols) {
+    let path = path.as_ref();
+
+    loader::read_program(path, program_start).unwrap_or_else(|error| fail_rom(path, error))
+}
+
+// Save state in the file at path, exiting if it can't be read or decoded.
+fn read_snapshot(path: &str) -> Snapshot {
+    let snapshot = fs::read(path).map_err(SnapshotError::from).and_then(|bytes| Snapshot::decode(&bytes));
+
+    snapshot.unwrap_or_else(|error| {
+        fail(format_args!("Failed to read {}: {}", path, error));
+    })
+}
+
+// Labels from the symbol file at path if one was given, otherwise those that came
+// with the ROM.
+fn read_symbols(path: Option<&str>, rom_symbols: Symbols) -> Symbols {
+    match path {
+        Some(path) => Symbols::read(Path::new(path)).unwrap_or_else(|error| {
+            fail(format_args!("Failed to read symbols from {}: {}", path, error));
+        }),
+        None => rom_symbols,
+    }
+}
+
+// Log an error the user can act on and exit, rather than panicking.
+fn fail(message: fmt::Arguments) -> ! {
+    error!("{}", message);
+    process::exit(1);
+}
+
+// Fail to read a ROM, with a hint at what to do about it if there is one.
+fn fail_rom(path: &Path, error: RomError) -> ! {
+    match error.hint() {
+        Some(hint) => fail(format_args!("Failed to read {}: {} (hint: {})", path.display(), error, hint)),
+        None => fail(format_args!("Failed to read {}: {}", path.display(), error)),
+    }
+}
+
+// Assemble an Octo source file, writing the ROM and optionally its labels.
+fn asm(source_path: &str, output: Option<&str>, program_start: usize, symbols_path: Option<&str>) {
+    let source = fs::read_to_string(source_path).unwrap_or_else(|error| {
+        fail(format_args!("Failed to read {}: {}", source_path, error));
+    });
+
+    let (rom, symbols) = octo::assemble_with_symbols(&source, program_start).unwrap_or_else(|error| {
+        fail(format_args!("Failed to assemble {}: {}", source_path, error));
+    });
+
+    let output = output.map_or_else(|| Path::new(source_path).with_extension("ch8"), PathBuf::from);
+
+    fs::write(&output, &rom).unwrap_or_else(|error| {
+        fail(format_args!("Failed to write {}: {}", output.display(), error));
+    });
+
+    info!("Wrote {} bytes to {}", rom.len(), output.display());
+
+    if let Some(path) = symbols_path {
+        fs::write(path, symbols.to_string()).unwrap_or_else(|error| {
+            fail(format_args!("Failed to write symbols to {}: {}", path, error));
+        });
+    }
+}
+
+// ROM files in dir, sorted by name.
+fn rom_paths(dir: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|error| fail(format_args!("Failed to list ROMs in {}: {}", dir, error)))
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Flags to run with, followed by --exit-when-settled when settle is given, unless
+// the flags already have it.
+fn settle_flags(flags: &[String], settle: Option<u64>) -> Vec<String> {
+    let mut flags = flags.to_vec();
+
+    if let Some(frames) = settle.filter(|_| !flags.iter().any(|flag| flag.starts_with("--exit-when-settled"))) {
+        flags.extend(["--exit-when-settled".to_string(), frames.to_string()]);
+    }
+
+    flags
+}
+
+// Run every ROM in dir headless for a number of cycles, or until its frame settles
+// for the frames given, with the flags given, printing how each run ended and its
+// final frame. Fails if any ROM faulted.
+fn test_suite(dir: &str, cycles: u64, settle: Option<u64>, flags: &[String]) {
+    let paths = rom_paths(dir);
+    let flags = settle_flags(flags, settle);
+    let mut faulted = 0;
+
+    for path in &paths {
+        let run = batch::run_rom(path, cycles, &flags)
+            .unwrap_or_else(|error| fail(format_args!("Invalid flags: {}", error)));
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if run.outcome.failed() {
+            faulted += 1;
+        }
+
+        println!("{}: {}, frame {}", name, run.outcome, run.frame_hash().unwrap_or_default());
+        println!("{}", raster::render(&run.frame, run.width));
+    }
+
+    if faulted > 0 {
+        fail(format_args!("{} of {} ROMs faulted", faulted, paths.len()));
+    }
+}
+
+// Run two ROMs side by side until the window is closed, exiting with a failure
+// status if they diverged in lockstep.
+fn compare(a_path: &str, b_path: &str, lockstep: bool, flags: &[String]) {
+    let args = || {
+        Args::try_parse_from(["chip-eight".to_string()].into_iter().chain(flags.iter().cloned()))
+            .unwrap_or_else(|error| fail(format_args!("Invalid flags: {}", error)))
+    };
+
+    // The machines draw to the one window, and the second is given no devices
+    let machine = |path: &str, devices: bool| {
+        let mut args = args();
+        args.display_engine = DisplayEngine::None;
+
+        if !devices {
+            args.audio_engine = AudioEngine::None;
+            args.input_engine = InputEngine::None;
+        }
+
+        let config = Config::from(args);
+        let (rom, _) = loader::read(Path::new(path), &config.memory, None).unwrap_or_else(|error| fail_rom(Path::new(path), error));
+
+        let mut chip_eight = ChipEightBuilder::new(config).build();
+        chip_eight.load(&rom);
+        chip_eight
+    };
+
+    let display = Config::from(args()).display;
+    let window = Rc::new(window_config(&display, display.width * 2 + compare::GAP));
+    if window.engine == DisplayEngine::None {
+        fail(format_args!("Comparing needs a display engine"));
+    }
+
+    let device = create_display_device(window.clone());
+
+    let stages = window.post_process.iter().map(|stage| stage.build()).collect();
+    let mut device = orient_display(post_process(device, stages, &window), window.clone());
+    device.set_title(&format!("{} | {}", a_path, b_path));
+
+    let mut comparison = Comparison::new([machine(a_path, true), machine(b_path, false)], device, display.colors, lockstep);
+
+    match comparison.run() {
+        Some(frame) => fail(format_args!("Diverged at frame {}", frame)),
+        None if lockstep => info!("No divergence in {} frames", comparison.frames()),
+        None => (),
+    }
+}
+
+// Run a ROM headless under two sets of flags, or under one against a trace, and
+// print the first cycle the runs diverge on, failing if they do. With write_trace,
+// the run under the first flags is traced for comparing against later instead.
+fn diverge(rom_path: &str, flags: [&str; 2], trace: Option<&str>, write_trace: Option<&str>, cycles: u64) {
+    let path = Path::new(rom_path);
+
+    // Nothing presses keys and the random numbers are the same every run
+    let machine = |flags: &str| {
+        let args = batch::HEADLESS.iter().copied().chain(flags.split_whitespace());
+        let config = Config::from(Args::try_parse_from(args).unwrap_or_else(|error| fail(format_args!("Invalid flags: {}", error))));
+
+        let (rom, symbols) = loader::read(path, &config.memory, None).unwrap_or_else(|error| fail_rom(path, error));
+
+        let mut chip_eight = ChipEightBuilder::new(config)
+            .with_input(Box::new(KeyInput::new()))
+            .with_seed(0)
+            .build();
+        chip_eight.load(&rom);
+        (chip_eight, symbols)
+    };
+
+    let (mut a, symbols) = machine(flags[0]);
+
+    if let Some(out) = write_trace {
+        if let Err(error) = fs::write(out, divergence::trace(&mut a, cycles)) {
+            fail(format_args!("Failed to write trace to {}: {}", out, error));
+        }
+
+        return;
+    }
+
+    let divergence = match trace {
+        Some(trace) => {
+            let expected = fs::read_to_string(trace)
+                .unwrap_or_else(|error| fail(format_args!("Failed to read {}: {}", trace, error)));
+
+            divergence::compare_trace(&mut a, &expected, cycles, &symbols)
+        },
+        None => {
+            let (mut b, _) = machine(flags[1]);
+            divergence::compare(&mut a, &mut b, cycles, &symbols)
+        },
+    };
+
+    match divergence {
+        Some(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        },
+        None => println!("No divergence in {} cycles", cycles),
+    }
+}
+
+// Settings of a window showing more beside the frame, as wide as given in virtual
+// pixels.
+fn window_config(display: &DisplayConfig, width: usize) -> DisplayConfig {
+    DisplayConfig {
+        engine: display.engine,
+        width,
+        height: display.height,
+        scale_factor: display.scale_factor,
+        colors: display.colors,
+        post_process: display.post_process.clone(),
+        rotation: display.rotation,
+        flip_x: display.flip_x,
+        flip_y: display.flip_y,
+    }
+}
+
+// Run every ROM in dir headless on a number of threads, printing how each run
+// ended and writing the results to out as JSON. Fails if any ROM faulted.
+fn batch(dir: &str, cycles: u64, settle: u64, jobs: Option<usize>, out: Option<&str>, flags: &[String]) {
+    let paths = rom_paths(dir);
+    let flags = settle_flags(flags, Some(settle));
+    let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+
+    info!("Running {} ROMs on {} threads", paths.len(), jobs);
+
+    let runs = batch::run_all(&paths, cycles, &flags, jobs)
+        .unwrap_or_else(|error| fail(format_args!("Invalid flags: {}", error)));
+
+    for run in &runs {
+        let name = run.path.file_name().unwrap_or_default().to_string_lossy();
+
+        match run.frame_hash() {
+            Some(hash) => println!("{}: {}, frame {}", name, run.outcome, hash),
+            None => println!("{}: {}", name, run.outcome),
+        }
+    }
+
+    if let Some(path) = out {
+        if let Err(error) = fs::write(path, batch::to_json(&runs)) {
+            fail(format_args!("Failed to write results to {}: {}", path, error));
+        }
+    }
+
+    let failed = runs.iter().filter(|run| run.outcome.failed()).count();
+
+    if failed > 0 {
+        fail(format_args!("{} of {} ROMs faulted or crashed", failed, runs.len()));
+    }
+}
+
+// List, search or run the programs of the archive at url, cached in the data
+// directory.
+fn archive(action: ArchiveCommand, url: &str, data_dir: Option<&str>, refresh: bool) {
+    let storage = storage(data_dir).unwrap_or_else(|| {
+        fail(format_args!("No data directory to keep downloads in, set HOME or pass --data-dir"));
+    });
+    let archive = Archive::new(url, &storage.archive_dir());
+
+    let programs = |query: Option<&str>| {
+        let programs = archive.programs(refresh).unwrap_or_else(|error| {
+            fail(format_args!("Failed to read the archive's catalogue: {}", error));
+        });
+
+        for program in programs.iter().filter(|program| query.is_none_or(|query| program.matches(query))) {
+            if program.authors.is_empty() {
+                println!("{:<24} {:<7} {}", program.name, program.platform, program.title);
+            } else {
+                println!("{:<24} {:<7} {} by {}", program.name, program.platform, program.title, program.authors.join(", "));
+            }
+        }
+    };
+
+    match action {
+        ArchiveCommand::List => programs(None),
+        ArchiveCommand::Search { query } => programs(Some(&query)),
+        ArchiveCommand::Run { name, flags } => {
+            let program = archive.program(&name, refresh).unwrap_or_else(|error| fail(format_args!("{}", error)));
+            let rom_path = archive.rom(&program).unwrap_or_else(|error| {
+                fail(format_args!("Failed to download {}: {}", program.name, error));
+            });
+
+            let source_flags = program.flags();
+            info!("Running {}, with the archive's settings: {}", program.title, source_flags.join(" "));
+
+            // Downloads and saved data share a directory
+            let data_dir_flags = data_dir.map(|dir| format!("--data-dir={}", dir));
+            let command_line: Vec<OsString> = ["chip-eight".to_string(), "run".to_string()].into_iter()
+                .chain(data_dir_flags)
+                .chain(flags)
+                .chain([rom_path.display().to_string()])
+                .map(OsString::from)
+                .collect();
+
+            let cli = Cli::try_parse_from(&command_line).unwrap_or_else(|error| {
+                fail(format_args!("Invalid flags: {}", error));
+            });
+
+            run(cli.command, cli.run, command_line, source_flags);
+        },
+    }
+}
+
+fn info(rom_path: &str, program_start: usize, profile: Profile) {
+    let (rom, _) = read_program(rom_path, program_start);
+    let analysis = Analysis::scan(&rom, program_start, profile);
+
+    let platform = match analysis.platforms.last() {
+        Some(Platform::MegaChip) => "MEGACHIP",
+        Some(Platform::XoChip) => "XO-CHIP",
+        Some(Platform::SuperChip) => "SUPER-CHIP",
+        None => "CHIP-8",
+    };
+
+    println!("ROM:             {}", rom_path);
+    println!("Size:            {} bytes", rom.len());
+    println!("SHA-1:           {:x}", Sha1::digest(&rom));
+    println!("Entry point:     0x{:03X}", analysis.entry_point);
+    println!("Platform:        {}", platform);
+    println!("Self-modifying:  {}", if analysis.self_modifying { "likely" } else { "no" });
+    println!("Reachable code:  {} of {} bytes", analysis.code_bytes(), rom.len());
+    println!("Jump targets:    {}", addresses(&analysis.jump_targets));
+    println!("Call targets:    {}", addresses(&analysis.call_targets));
+    println!("Index targets:   {}", addresses(&analysis.index_targets));
+    println!("Dynamic jumps:   {}", addresses(&analysis.dynamic_jumps));
+    println!("Undecodable:     {}", addresses(&analysis.undecodable));
+}
+
+fn state_info(path: &str) {
+    let bytes = fs::read(path).unwrap_or_else(|error| fail(format_args!("Failed to read {}: {}", path, error)));
+    let (info, snapshot) = Snapshot::info(&bytes)
+        .and_then(|info| Ok((info, Snapshot::decode(&bytes)?)))
+        .unwrap_or_else(|error| fail(format_args!("Failed to read {}: {}", path, error)));
+
+    let chunks = match info.chunks.is_empty() {
+        true => "none, fields in order".to_string(),
+        false => info.chunks.iter()
+            .map(|(tag, len)| format!("{} ({} bytes)", String::from_utf8_lossy(tag).trim_end(), len))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+
+    let quirks = match &snapshot.quirks {
+        Some(quirks) => {
+            let mut flags = vec![
+                format!("vblank-model {}", value_name(quirks.vblank_model)),
+                format!("machine-calls {}", value_name(quirks.machine_calls)),
+                format!("rng {}", value_name(quirks.rng)),
+            ];
+            flags.extend([
+                (quirks.skip_reset_vf, "skip-reset-vf"),
+                (quirks.preserve_index, "preserve-index"),
+                (quirks.wrap_x, "wrap-x"),
+                (quirks.wrap_y, "wrap-y"),
+                (quirks.skip_shift_set, "skip-shift-set"),
+                (quirks.jump_with_vx, "jump-with-vx"),
+                (quirks.index_overflow_flag, "index-overflow-flag"),
+                (quirks.collision_count, "collision-count"),
+            ].into_iter().filter(|(on, _)| *on).map(|(_, flag)| flag.to_string()));
+
+            flags.join(", ")
+        },
+        None => "not recorded".to_string(),
+    };
+
+    let (width, height) = snapshot.resolution;
+
+    println!("State:        {}", path);
+    println!("Version:      {}", info.version);
+    println!("Saved:        {}", storage::age(snapshot.saved_at));
+    println!("Chunks:       {}", chunks);
+    println!("PC:           0x{:03X}", snapshot.pc);
+    println!("I:            0x{:03X}", snapshot.i);
+    println!("V:            {:02X?}", snapshot.v);
+    println!("Timers:       delay {}, sound {}", snapshot.timers.0, snapshot.timers.1);
+    println!("Stack:        {:03X?}", snapshot.stack);
+    println!("Memory:       {} bytes", snapshot.memory.len());
+    println!("Resolution:   {}x{}", width, height);
+    println!("Quirks:       {}", quirks);
+}
+
+// Name of an option's value as given on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+fn addresses(set: &BTreeSet<usize>) -> String {
+    if set.is_empty() {
+        return "none".to_string();
+    }
+
+    set.iter()
+        .map(|addr| format!("0x{:03X}", addr))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
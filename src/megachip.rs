@@ -0,0 +1,133 @@
+// State for MegaChip's colour mode, which replaces the monochrome display with a
+// 256x192 buffer of palette colours that programs draw into, and present with 00E0.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Blend {
+    Normal,
+    // Sprite opacity, out of 4
+    Opacity(u8),
+    Add,
+    Multiply,
+}
+
+impl From<u8> for Blend {
+    fn from(mode: u8) -> Self {
+        match mode {
+            1 => Blend::Opacity(3),
+            2 => Blend::Opacity(2),
+            3 => Blend::Opacity(1),
+            4 => Blend::Add,
+            5 => Blend::Multiply,
+            _ => Blend::Normal,
+        }
+    }
+}
+
+pub struct MegaChip {
+    pub enabled: bool,
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub alpha: u8,
+    pub blend: Blend,
+    pub collision_color: u8,
+
+    // ARGB colours, with index 0 being transparent
+    palette: [u32; 256],
+
+    // Colour and palette index of every pixel drawn since the last clear
+    frame: Vec<u32>,
+    indices: Vec<u8>,
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            sprite_width: WIDTH,
+            sprite_height: 1,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision_color: 0,
+            palette: [0; 256],
+            frame: vec![0; WIDTH * HEIGHT],
+            indices: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    // Replace palette entries from 1 onwards with the given 4 byte ARGB colours.
+    pub fn load_palette(&mut self, colors: &[u8]) {
+        for (entry, color) in self.palette[1..].iter_mut().zip(colors.chunks_exact(4)) {
+            *entry = u32::from_be_bytes([color[0], color[1], color[2], color[3]]);
+        }
+    }
+
+    // Draw a sprite of one palette index per byte, skipping index 0 and clipping at
+    // the edges. Returns whether a pixel of the collision colour was drawn over.
+    pub fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collided = false;
+
+        for (row, line) in sprite.chunks(self.sprite_width).enumerate() {
+            let current_y = y + row;
+
+            if current_y >= HEIGHT {
+                break;
+            }
+
+            for (column, &index) in line.iter().enumerate() {
+                let current_x = x + column;
+
+                if current_x >= WIDTH {
+                    break;
+                }
+
+                if index == 0 {
+                    continue;
+                }
+
+                let pixel = current_y * WIDTH + current_x;
+
+                if self.indices[pixel] == self.collision_color {
+                    collided = true;
+                }
+
+                self.indices[pixel] = index;
+                self.frame[pixel] = blend(self.blend, self.frame[pixel], self.palette[index as usize]);
+            }
+        }
+
+        collided
+    }
+
+    pub fn clear(&mut self) {
+        self.frame.fill(0);
+        self.indices.fill(0);
+    }
+
+    // Frame as ARGB pixels, with the screen alpha applied.
+    pub fn frame(&self) -> Vec<u32> {
+        self.frame
+            .iter()
+            .map(|&color| (color & 0x00FFFFFF) | ((self.alpha as u32) << 24))
+            .collect()
+    }
+}
+
+fn blend(mode: Blend, dst: u32, src: u32) -> u32 {
+    let channel = |color: u32, shift: u32| (color >> shift) & 0xFF;
+
+    [16, 8, 0].iter().fold(0xFF000000, |color, &shift| {
+        let (d, s) = (channel(dst, shift), channel(src, shift));
+
+        let mixed = match mode {
+            Blend::Normal => s,
+            Blend::Opacity(opacity) => (s * opacity as u32 + d * (4 - opacity as u32)) / 4,
+            Blend::Add => (s + d).min(0xFF),
+            Blend::Multiply => s * d / 0xFF,
+        };
+
+        color | (mixed << shift)
+    })
+}
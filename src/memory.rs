@@ -1,11 +1,21 @@
-use std::{error::Error, fmt, rc::Rc, usize};
+use alloc::{borrow::Cow, boxed::Box, rc::Rc, vec, vec::Vec};
+use core::{cell::RefCell, error::Error, fmt, mem, ops::Range};
 
-use crate::config::MemoryConfig;
+pub struct MemoryConfig {
+    pub length: usize,
+    pub program_start: usize,
+    pub protect_low_memory: bool,
+    pub font_start: usize,
+    pub default_font: [u8; 80],
+    pub big_font_start: usize,
+    pub default_big_font: [u8; 160],
+}
 
 #[derive(Debug, PartialEq)]
 pub enum MemoryError {
     AddrOutOfBounds(usize),
     RangeOutOfBounds(usize, usize),
+    Protected(usize),
 }
 
 impl fmt::Display for MemoryError {
@@ -13,21 +23,176 @@ impl fmt::Display for MemoryError {
         match self {
             MemoryError::AddrOutOfBounds(addr) => write!(f, "attempt to access byte at {} failed: address out of bounds", addr),
             MemoryError::RangeOutOfBounds(addr, len) => write!(f, "attempt to access range from {} to {} failed: range out of bounds", addr, addr + (len - 1)),
+            MemoryError::Protected(addr) => write!(f, "attempt to write byte at {} failed: address is protected", addr),
         }
     }
 }
 
 impl Error for MemoryError {}
 
-pub struct Memory(Vec<u8>);
+// Intercepts accesses to a region of memory mapped with Memory::map_region, for
+// memory-mapped devices and watchpoints. Both methods default to passing the access
+// through unchanged.
+pub trait MemoryHandler {
+    // Value read from addr, given the byte stored there.
+    fn read(&mut self, _addr: usize, stored: u8) -> u8 {
+        stored
+    }
+
+    // Value to store at addr, given the byte being written.
+    fn write(&mut self, _addr: usize, value: u8) -> u8 {
+        value
+    }
+}
+
+// How an instruction uses the memory it accesses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    // Fetched as the instruction itself
+    Execute,
+}
+
+type WriteHook = Box<dyn FnMut(Range<usize>)>;
+
+// Bytes allocated at a time as writes reach further into memory
+const PAGE_LEN: usize = 0x10000;
+
+struct Region {
+    range: Range<usize>,
+    // Reads go through a shared reference, so the handler needs interior mutability
+    handler: RefCell<Box<dyn MemoryHandler>>,
+}
+
+pub struct Memory {
+    // Bytes up to the furthest page written so far. Those past it read as zero, so
+    // MegaChip's 16MB is only allocated as far as the program uses it.
+    data: Vec<u8>,
+    len: usize,
+    regions: Vec<Region>,
+
+    // Writes below this address fail, leaving the interpreter area intact
+    protected_below: usize,
+
+    // Called with the addresses changed by every write
+    write_hooks: Vec<WriteHook>,
+}
 
 impl Memory {
     pub fn new(config: Rc<MemoryConfig>) -> Self {
-        Self(vec![0; config.length])
+        Self {
+            data: vec![0; config.length.min(PAGE_LEN)],
+            len: config.length,
+            regions: Vec::new(),
+            protected_below: if config.protect_low_memory {
+                config.program_start
+            } else {
+                0
+            },
+            write_hooks: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The whole address space, for frontends that expose memory directly. Accesses
+    // through it bypass mapped regions, and count as a write to every address.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.allocate(self.len);
+        self.notify_write(0..self.len);
+        &mut self.data
+    }
+
+    // Zero every byte, keeping mapped regions in place.
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+        self.notify_write(0..self.len);
+    }
+
+    // Allocate the pages up to end, if they aren't already.
+    fn allocate(&mut self, end: usize) {
+        if end > self.data.len() {
+            self.data.resize(end.next_multiple_of(PAGE_LEN).min(self.len), 0);
+        }
+    }
+
+    // Register a callback invoked with the range of addresses changed by every
+    // write, for caches of memory contents.
+    pub fn on_write(&mut self, callback: impl FnMut(Range<usize>) + 'static) {
+        self.write_hooks.push(Box::new(callback));
+    }
+
+    // Run the write hooks over a range changed other than through Memory, such as
+    // by a frontend through as_mut_slice's pointer.
+    pub fn mark_written(&mut self, range: Range<usize>) {
+        self.notify_write(range);
+    }
+
+    fn notify_write(&mut self, range: Range<usize>) {
+        for hook in &mut self.write_hooks {
+            hook(range.clone());
+        }
+    }
+
+    // Route reads and writes of the given addresses through a handler. Where regions
+    // overlap, the first one mapped wins.
+    pub fn map_region(&mut self, range: Range<usize>, handler: impl MemoryHandler + 'static) {
+        // What the program reads there may change, as if it had been written
+        self.notify_write(range.clone());
+        self.regions.push(Region {
+            range,
+            handler: RefCell::new(Box::new(handler)),
+        });
+    }
+
+    // Remove every region mapped over the given address.
+    pub fn unmap_region(&mut self, addr: usize) {
+        let (removed, kept): (Vec<_>, Vec<_>) = mem::take(&mut self.regions)
+            .into_iter()
+            .partition(|region| region.range.contains(&addr));
+
+        self.regions = kept;
+
+        for region in removed {
+            self.notify_write(region.range);
+        }
     }
 
     fn is_in_bounds(&self, addr: usize) -> bool {
-        addr < self.0.len()
+        addr < self.len
+    }
+
+    fn region(&self, addr: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.range.contains(&addr))
+    }
+
+    // Whether any address in the range is mapped to a handler.
+    pub fn is_mapped(&self, range: Range<usize>) -> bool {
+        self.regions.iter().any(|region| region.range.start < range.end && range.start < region.range.end)
+    }
+
+    fn intercept_read(&self, addr: usize) -> u8 {
+        let stored = self.data.get(addr).copied().unwrap_or(0);
+
+        match self.region(addr) {
+            Some(region) => region.handler.borrow_mut().read(addr, stored),
+            None => stored,
+        }
+    }
+
+    fn intercept_write(&mut self, addr: usize, data: u8) {
+        self.allocate(addr + 1);
+        self.data[addr] = match self.region(addr) {
+            Some(region) => region.handler.borrow_mut().write(addr, data),
+            None => data,
+        };
     }
 
     pub fn read_byte(&self, addr: usize) -> Result<u8, MemoryError> {
@@ -35,23 +200,29 @@ impl Memory {
             return Err(MemoryError::AddrOutOfBounds(addr));
         }
 
-        Ok(self.0[addr])
+        Ok(self.intercept_read(addr))
     }
 
+    // Borrows the bytes directly, unless the range touches a mapped region or memory
+    // not yet allocated.
     pub fn read_buf(
         &self,
         addr: usize,
         len: usize,
-    ) -> Result<&[u8], MemoryError> {
-        if len < 1 {
-            return Ok(&[]);
+    ) -> Result<Cow<'_, [u8]>, MemoryError> {
+        if len == 0 {
+            return Ok(Cow::Borrowed(&[]));
         }
 
         if !self.is_in_bounds(addr + (len - 1)) {
             return Err(MemoryError::RangeOutOfBounds(addr, len));
         }
 
-        Ok(&self.0[addr..addr + len])
+        if self.is_mapped(addr..addr + len) || addr + len > self.data.len() {
+            return Ok(Cow::Owned((addr..addr + len).map(|addr| self.intercept_read(addr)).collect()));
+        }
+
+        Ok(Cow::Borrowed(&self.data[addr..addr + len]))
     }
 
     pub fn write_byte(
@@ -63,7 +234,12 @@ impl Memory {
             return Err(MemoryError::AddrOutOfBounds(addr));
         }
 
-        self.0[addr] = data;
+        if addr < self.protected_below {
+            return Err(MemoryError::Protected(addr));
+        }
+
+        self.intercept_write(addr, data);
+        self.notify_write(addr..addr + 1);
         Ok(())
     }
 
@@ -72,7 +248,20 @@ impl Memory {
         addr: usize,
         data: &[u8],
     ) -> Result<(), MemoryError> {
-        if data.len() < 1 {
+        if !data.is_empty() && addr < self.protected_below {
+            return Err(MemoryError::Protected(addr));
+        }
+
+        self.load_buf(addr, data)
+    }
+
+    // Same as write_buf, but ignoring memory protection, for the loader.
+    pub fn load_buf(
+        &mut self,
+        addr: usize,
+        data: &[u8],
+    ) -> Result<(), MemoryError> {
+        if data.is_empty() {
             return Ok(());
         }
 
@@ -80,7 +269,16 @@ impl Memory {
             return Err(MemoryError::RangeOutOfBounds(addr, data.len()));
         }
 
-        self.0[addr..(addr + data.len())].copy_from_slice(data);
+        if self.is_mapped(addr..addr + data.len()) {
+            for (offset, &byte) in data.iter().enumerate() {
+                self.intercept_write(addr + offset, byte);
+            }
+        } else {
+            self.allocate(addr + data.len());
+            self.data[addr..(addr + data.len())].copy_from_slice(data);
+        }
+
+        self.notify_write(addr..addr + data.len());
         Ok(())
     }
 }
@@ -0,0 +1,200 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::replay::Replay;
+
+// Horizontal distance between glyphs, and vertical distance between entries, in virtual pixels.
+const GLYPH_ADVANCE: usize = 4;
+pub const LINE_HEIGHT: usize = 6;
+
+// Replay of one of the menu's ROMs, played back while the menu is left idle.
+pub struct Demo {
+    pub replay: Replay,
+    pub rom: Vec<u8>,
+}
+
+// Selectable list of ROMs, rendered straight into a frame buffer with a small 3x5 font.
+pub struct Menu {
+    entries: Vec<PathBuf>,
+    selected: usize,
+    demos: Vec<Demo>,
+}
+
+impl Menu {
+    pub fn from_dir(dir: &Path) -> io::Result<Self> {
+        let mut entries = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+
+        entries.sort();
+
+        Ok(Self {
+            entries,
+            selected: 0,
+            demos: vec![],
+        })
+    }
+
+    // Play the replays in dir as demos while the menu is idle, in name order. Replays
+    // that can't be read, or of ROMs that aren't in the menu, are left out. A missing
+    // dir means no demos.
+    pub fn with_demos(mut self, dir: &Path) -> Self {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return self;
+        };
+
+        let mut paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+
+        paths.sort();
+
+        let roms = self.entries.iter()
+            .filter_map(|path| fs::read(path).ok())
+            .collect::<Vec<_>>();
+
+        for path in paths {
+            let replay = match Replay::read(&path) {
+                Ok(replay) => replay,
+                Err(error) => {
+                    warn!("Skipping demo {}: {}", path.display(), error);
+                    continue;
+                },
+            };
+
+            match roms.iter().find(|rom| replay.matches(rom)) {
+                Some(rom) => self.demos.push(Demo { rom: rom.clone(), replay }),
+                None => warn!("Skipping demo {}: its ROM isn't in the menu", path.display()),
+            }
+        }
+
+        self
+    }
+
+    pub fn demos(&self) -> &[Demo] {
+        &self.demos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected(&self) -> &Path {
+        &self.entries[self.selected]
+    }
+
+    pub fn render(&self, frame: &mut [bool], width: usize, height: usize) {
+        frame.fill(false);
+
+        let columns = width.saturating_sub(1) / GLYPH_ADVANCE;
+        let rows = height / LINE_HEIGHT;
+
+        if rows == 0 {
+            return;
+        }
+
+        // Scroll just far enough to keep the selected entry on screen
+        let top = self.selected.saturating_sub(rows - 1);
+
+        for (index, entry) in self.entries.iter().enumerate().skip(top).take(rows) {
+            let y = (index - top) * LINE_HEIGHT;
+            let highlighted = index == self.selected;
+
+            if highlighted {
+                frame[y * width..(y + LINE_HEIGHT) * width].fill(true);
+            }
+
+            let label = entry.file_name()
+                .map(|name| name.to_string_lossy().to_uppercase())
+                .unwrap_or_default();
+
+            let label: String = label.chars().take(columns).collect();
+            draw_text(frame, width, 1, y, &label, !highlighted);
+        }
+    }
+}
+
+// Draw text into a frame buffer in the 3x5 font, with its top left corner at x, y,
+// setting the pixels of each glyph to lit. Whatever falls outside the frame is cut off.
+pub fn draw_text(frame: &mut [bool], width: usize, x: usize, y: usize, text: &str, lit: bool) {
+    let height = frame.len().checked_div(width).unwrap_or(0);
+
+    for (column, character) in text.chars().enumerate() {
+        let left = x + column * GLYPH_ADVANCE;
+
+        for (row, bits) in glyph(character.to_ascii_uppercase()).iter().enumerate() {
+            for offset in 0..3 {
+                let (px, py) = (left + offset, y + row);
+
+                if (bits >> (2 - offset)) & 1 == 1 && px < width && py < height {
+                    frame[py * width + px] = lit;
+                }
+            }
+        }
+    }
+}
+
+fn glyph(character: char) -> [u8; 5] {
+    match character {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
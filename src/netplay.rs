@@ -0,0 +1,301 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    devices::{Key, KeyEvent},
+    system::ChipEight,
+};
+
+const MAGIC: &[u8; 4] = b"C8NP";
+const VERSION: u8 = 1;
+
+// Frames between a key event and the frame it is applied on, giving it time to
+// reach the other machine.
+const INPUT_DELAY: u64 = 3;
+
+// How long to wait on the other machine before giving up on it.
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    // The other end isn't a chip-eight speaking the same protocol
+    Protocol(&'static str),
+    RomMismatch,
+    // Cycles per frame on each end
+    SpeedMismatch(u64, u64),
+    // Frame the machines were found to have diverged by
+    Desync(u64),
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetplayError::Io(error) => write!(f, "{}", error),
+            NetplayError::Protocol(message) => write!(f, "protocol error: {}", message),
+            NetplayError::RomMismatch => write!(f, "the other player is running a different ROM"),
+            NetplayError::SpeedMismatch(ours, theirs) => write!(
+                f,
+                "the other player runs {} cycles a frame, rather than {}",
+                theirs,
+                ours,
+            ),
+            NetplayError::Desync(frame) => write!(f, "machines diverged by frame {}", frame),
+        }
+    }
+}
+
+impl Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(error: io::Error) -> Self {
+        NetplayError::Io(error)
+    }
+}
+
+// Whether this end of a session listened for the connection or made it. The host
+// picks the random seed, and its key events apply before the guest's on each frame.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+    Host,
+    Guest,
+}
+
+// A connection to another chip-eight running the same ROM. Both machines run in
+// lockstep: each frame, every key event from either end is applied to both, in the
+// same order and on the same frame, so the two runs stay identical.
+//
+// Messages start with the big endian frame number they apply to, then a checksum
+// of the sender's machine INPUT_DELAY frames earlier, and a count of key events,
+// each a keycode with the top bit set for a press.
+pub struct Session {
+    stream: TcpStream,
+    role: Role,
+    seed: u64,
+}
+
+impl Session {
+    // Wait for another player to join on addr. An address without a host, such as
+    // ":7800", listens on all interfaces.
+    pub fn host(addr: &str, rom: &[u8], cycles_per_frame: u64) -> Result<Self, NetplayError> {
+        let addr = if addr.starts_with(':') {
+            format!("0.0.0.0{}", addr)
+        } else {
+            addr.to_string()
+        };
+
+        let (stream, peer) = TcpListener::bind(addr)?.accept()?;
+        info!("Player joined from {}", peer);
+
+        Self::handshake(stream, Role::Host, rand::random(), rom, cycles_per_frame)
+    }
+
+    // Join the player hosting on addr.
+    pub fn join(addr: &str, rom: &[u8], cycles_per_frame: u64) -> Result<Self, NetplayError> {
+        let stream = TcpStream::connect(addr)?;
+        info!("Joined {}", addr);
+
+        Self::handshake(stream, Role::Guest, 0, rom, cycles_per_frame)
+    }
+
+    // Swap the seed, ROM and speed, checking the two machines will run alike.
+    fn handshake(
+        mut stream: TcpStream,
+        role: Role,
+        seed: u64,
+        rom: &[u8],
+        cycles_per_frame: u64,
+    ) -> Result<Self, NetplayError> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+
+        let mut hello = MAGIC.to_vec();
+        hello.push(VERSION);
+        hello.extend_from_slice(&seed.to_be_bytes());
+        hello.extend_from_slice(&cycles_per_frame.to_be_bytes());
+        hello.extend_from_slice(&Sha1::digest(rom));
+        stream.write_all(&hello)?;
+
+        let mut theirs = [0; 41];
+        stream.read_exact(&mut theirs)?;
+
+        if &theirs[..4] != MAGIC {
+            return Err(NetplayError::Protocol("not a netplay connection"));
+        }
+
+        if theirs[4] != VERSION {
+            return Err(NetplayError::Protocol("unsupported version"));
+        }
+
+        let their_seed = u64::from_be_bytes(theirs[5..13].try_into().unwrap());
+        let their_cycles = u64::from_be_bytes(theirs[13..21].try_into().unwrap());
+
+        if their_cycles != cycles_per_frame {
+            return Err(NetplayError::SpeedMismatch(cycles_per_frame, their_cycles));
+        }
+
+        if theirs[21..] != hello[21..] {
+            return Err(NetplayError::RomMismatch);
+        }
+
+        Ok(Self {
+            stream,
+            role,
+            seed: match role {
+                Role::Host => seed,
+                Role::Guest => their_seed,
+            },
+        })
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    // Seed both machines share, to pass to ChipEight::set_seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Run the loaded program in lockstep with the other machine until either end
+    // stops or the program exits. The machine's own input device supplies the key
+    // events from this end. Expects the machine to count its timers down on vblank,
+    // so they run alike on both.
+    pub fn play(mut self, chip_eight: &mut ChipEight) -> Result<(), NetplayError> {
+        let mut input = chip_eight.take_input();
+        let cycles_per_frame = chip_eight.cycles_per_frame();
+        let stop = chip_eight.stop_handle();
+
+        // Key events and checksums from this end, for the frames still to come
+        let mut local: VecDeque<Vec<KeyEvent>> = (0..INPUT_DELAY).map(|_| vec![]).collect();
+        let mut checksums = VecDeque::new();
+
+        let mut deadline = Instant::now();
+
+        for frame in 0.. {
+            if stop.is_stopped() || chip_eight.exited() {
+                break;
+            }
+
+            let checksum = checksum(chip_eight);
+            let events = input.poll_events();
+            match self.send(frame + INPUT_DELAY, checksum, &events) {
+                Err(error) if hung_up(&error) => {
+                    info!("The other player left");
+                    break;
+                },
+                result => result?,
+            }
+
+            local.push_back(events);
+            checksums.push_back(checksum);
+
+            let remote = if frame < INPUT_DELAY {
+                vec![]
+            } else {
+                let Some((remote_frame, remote_checksum, events)) = self.receive()? else {
+                    info!("The other player left");
+                    break;
+                };
+
+                if remote_frame != frame {
+                    return Err(NetplayError::Protocol("frame out of sequence"));
+                }
+
+                if checksums.pop_front() != Some(remote_checksum) {
+                    return Err(NetplayError::Desync(frame - INPUT_DELAY));
+                }
+
+                events
+            };
+
+            let local = local.pop_front().unwrap_or_default();
+            let (first, second) = match self.role {
+                Role::Host => (local, remote),
+                Role::Guest => (remote, local),
+            };
+
+            for event in first.into_iter().chain(second) {
+                chip_eight.key_event(event);
+            }
+
+            chip_eight.run_cycles(cycles_per_frame);
+            chip_eight.vblank();
+
+            deadline += FRAME_DURATION;
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, frame: u64, checksum: u32, events: &[KeyEvent]) -> io::Result<()> {
+        // More than fit in a message can't happen with 16 keys at 60 frames a second
+        let events = &events[..events.len().min(u8::MAX as usize)];
+
+        let mut message = frame.to_be_bytes().to_vec();
+        message.extend_from_slice(&checksum.to_be_bytes());
+        message.push(events.len() as u8);
+        message.extend(events.iter().map(|event| event.key as u8 | (event.pressed as u8) << 7));
+
+        self.stream.write_all(&message)
+    }
+
+    // The next frame's message, or None once the other end has hung up.
+    fn receive(&mut self) -> Result<Option<(u64, u32, Vec<KeyEvent>)>, NetplayError> {
+        let mut header = [0; 13];
+
+        match self.stream.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(error) if hung_up(&error) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        }
+
+        let frame = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let checksum = u32::from_be_bytes(header[8..12].try_into().unwrap());
+
+        let mut bytes = vec![0; header[12] as usize];
+        self.stream.read_exact(&mut bytes)?;
+
+        let events = bytes.iter()
+            .map(|&byte| {
+                let key = Key::try_from(byte & 0x7F).map_err(|_| NetplayError::Protocol("invalid keycode"))?;
+                Ok(KeyEvent::new(key, byte & 0x80 != 0))
+            })
+            .collect::<Result<_, NetplayError>>()?;
+
+        Ok(Some((frame, checksum, events)))
+    }
+}
+
+fn hung_up(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted,
+    )
+}
+
+// FNV-1a over the state a divergence shows up in soonest.
+fn checksum(chip_eight: &ChipEight) -> u32 {
+    let (delay, sound) = chip_eight.timers();
+    let state = chip_eight.registers().iter()
+        .copied()
+        .chain((chip_eight.pc() as u16).to_be_bytes())
+        .chain((chip_eight.index() as u16).to_be_bytes())
+        .chain([delay, sound])
+        .chain(chip_eight.frame_buffer().iter().map(|&pixel| pixel as u8));
+
+    state.fold(0x811C9DC5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
@@ -0,0 +1,899 @@
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::symbols::Symbols;
+
+// Assembler for Octo (https://github.com/JohnEarnest/Octo) source, the `.8o` files
+// most modern CHIP-8 programs are written in. It covers labels, :alias, :const, the
+// control flow statements, and a subset of :calc expressions. Macros, :stringmode,
+// :next and :assert are not supported.
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+// Assemble a program to be loaded at origin, which begins with a jump to its `main`
+// label.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_symbols(source, origin).map(|(rom, _)| rom)
+}
+
+// Same as assemble, along with the address of every label.
+pub fn assemble_with_symbols(source: &str, origin: usize) -> Result<(Vec<u8>, Symbols), AssembleError> {
+    let mut assembler = Assembler::new(tokenize(source), origin);
+
+    // Reserve room for the jump to main
+    assembler.emit(0x0000);
+
+    while assembler.pos < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+
+    if let Some(flow) = assembler.flow.last() {
+        return Err(AssembleError {
+            line: flow.line,
+            message: "block is never closed".to_string(),
+        });
+    }
+
+    let main = *assembler.labels.get("main").ok_or(AssembleError {
+        line: 1,
+        message: "the program has no main label".to_string(),
+    })?;
+
+    assembler.patch(origin, 0x1000 | main as u16);
+    assembler.resolve()?;
+
+    let mut symbols = Symbols::new();
+
+    for (label, &addr) in &assembler.labels {
+        symbols.insert(addr, label);
+    }
+
+    Ok((assembler.rom, symbols))
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Split source into whitespace separated tokens, dropping comments. Brackets are
+// tokens of their own, so :calc expressions don't need spaces around them.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let spaced = code.replace('{', " { ").replace('}', " } ").replace('(', " ( ").replace(')', " ) ");
+
+        tokens.extend(spaced.split_whitespace().map(|text| Token {
+            text: text.to_string(),
+            line: index + 1,
+        }));
+    }
+
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+
+    match digit.len() {
+        1 => usize::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+// Operand that may name a label defined further on
+enum Address {
+    Known(usize),
+    Forward(String),
+}
+
+// How a label's address is written once it is known
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // Low 12 bits of an instruction
+    Nnn,
+    // A 16-bit word, for `i := long`
+    Word,
+    // High nibble of the address under a given nibble, for :unpack
+    High(u8),
+    // Low byte of the address, for :unpack
+    Low,
+}
+
+struct Fixup {
+    at: usize,
+    label: String,
+    kind: FixupKind,
+    line: usize,
+}
+
+// Comparison in a condition
+#[derive(Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,
+    NotKey,
+}
+
+impl Comparison {
+    fn negated(self) -> Self {
+        match self {
+            Comparison::Equal => Comparison::NotEqual,
+            Comparison::NotEqual => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterOrEqual,
+            Comparison::Greater => Comparison::LessOrEqual,
+            Comparison::LessOrEqual => Comparison::Greater,
+            Comparison::GreaterOrEqual => Comparison::Less,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        }
+    }
+}
+
+// Right hand side of a condition or assignment
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+struct Condition {
+    reg: usize,
+    comparison: Comparison,
+    operand: Option<Operand>,
+}
+
+enum FlowKind {
+    // Jump over the block when the condition fails, patched by else or end
+    If(usize),
+    // Jump over the else block, patched by end
+    Else(usize),
+    // Start of the loop, and the jumps out of it taken by while
+    Loop(usize, Vec<usize>),
+}
+
+struct Flow {
+    kind: FlowKind,
+    line: usize,
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    rom: Vec<u8>,
+    origin: usize,
+    here: usize,
+    labels: HashMap<String, usize>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    fixups: Vec<Fixup>,
+    flow: Vec<Flow>,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>, origin: usize) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            rom: Vec::new(),
+            origin,
+            here: origin,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos.saturating_sub(1)).map_or(1, |token| token.line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn next(&mut self) -> Result<String, AssembleError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.text.clone())
+            },
+            None => self.error("unexpected end of source"),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AssembleError> {
+        let token = self.next()?;
+
+        if token != expected {
+            return self.error(format!("expected '{}', found '{}'", expected, token));
+        }
+
+        Ok(())
+    }
+
+    /* Output */
+
+    fn patch_byte(&mut self, addr: usize, byte: u8) {
+        let index = addr - self.origin;
+
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+
+        self.rom[index] = byte;
+    }
+
+    fn patch(&mut self, addr: usize, opcode: u16) {
+        let [high, low] = opcode.to_be_bytes();
+        self.patch_byte(addr, high);
+        self.patch_byte(addr + 1, low);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.patch_byte(self.here, byte);
+        self.here += 1;
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.patch(self.here, opcode);
+        self.here += 2;
+    }
+
+    // Emit an instruction taking a 12-bit address, to be filled in later if the
+    // label isn't defined yet.
+    fn emit_address(&mut self, opcode: u16, addr: Address) -> Result<(), AssembleError> {
+        match addr {
+            Address::Known(addr) if addr > 0xFFF => self.error(format!("address 0x{:X} is out of range", addr)),
+            Address::Known(addr) => {
+                self.emit(opcode | addr as u16);
+                Ok(())
+            },
+            Address::Forward(label) => {
+                self.fixup(self.here, label, FixupKind::Nnn);
+                self.emit(opcode);
+                Ok(())
+            },
+        }
+    }
+
+    fn fixup(&mut self, at: usize, label: String, kind: FixupKind) {
+        self.fixups.push(Fixup {
+            at,
+            label,
+            kind,
+            line: self.line(),
+        });
+    }
+
+    // Fill in every reference to a label that was defined after it was used.
+    fn resolve(&mut self) -> Result<(), AssembleError> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&addr) = self.labels.get(&fixup.label) else {
+                return Err(AssembleError {
+                    line: fixup.line,
+                    message: format!("undefined name '{}'", fixup.label),
+                });
+            };
+
+            match fixup.kind {
+                FixupKind::Nnn if addr > 0xFFF => {
+                    return Err(AssembleError {
+                        line: fixup.line,
+                        message: format!("address of '{}' is out of range", fixup.label),
+                    });
+                },
+                FixupKind::Nnn => {
+                    let high = self.rom[fixup.at - self.origin];
+                    self.patch(fixup.at, u16::from_be_bytes([high, 0]) | addr as u16);
+                },
+                FixupKind::Word => self.patch(fixup.at, addr as u16),
+                FixupKind::High(nibble) => self.patch_byte(fixup.at, (nibble << 4) | ((addr >> 8) & 0xF) as u8),
+                FixupKind::Low => self.patch_byte(fixup.at, addr as u8),
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Operands */
+
+    fn register(&mut self) -> Result<usize, AssembleError> {
+        let token = self.next()?;
+
+        match self.lookup_register(&token) {
+            Some(reg) => Ok(reg),
+            None => self.error(format!("expected a register, found '{}'", token)),
+        }
+    }
+
+    fn lookup_register(&self, token: &str) -> Option<usize> {
+        parse_register(token).or_else(|| self.aliases.get(token).copied())
+    }
+
+    fn lookup_value(&self, token: &str) -> Option<f64> {
+        parse_number(token)
+            .map(|value| value as f64)
+            .or_else(|| self.consts.get(token).copied())
+    }
+
+    fn number(&mut self) -> Result<i64, AssembleError> {
+        let token = self.next()?;
+
+        match self.lookup_value(&token) {
+            Some(value) => Ok(value.floor() as i64),
+            None => self.error(format!("expected a number, found '{}'", token)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AssembleError> {
+        let value = self.number()?;
+        self.to_byte(value)
+    }
+
+    fn to_byte(&self, value: i64) -> Result<u8, AssembleError> {
+        match value {
+            -128..=255 => Ok(value as u8),
+            _ => self.error(format!("{} does not fit in a byte", value)),
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssembleError> {
+        let value = self.number()?;
+
+        match value {
+            0..=15 => Ok(value as u8),
+            _ => self.error(format!("{} does not fit in a nibble", value)),
+        }
+    }
+
+    fn address(&mut self) -> Result<Address, AssembleError> {
+        let token = self.next()?;
+
+        if let Some(&addr) = self.labels.get(&token) {
+            return Ok(Address::Known(addr));
+        }
+
+        match self.lookup_value(&token) {
+            Some(value) if value >= 0.0 => Ok(Address::Known(value as usize)),
+            Some(_) => self.error("addresses can't be negative"),
+            None if self.lookup_register(&token).is_some() => self.error(format!("expected an address, found register '{}'", token)),
+            None => Ok(Address::Forward(token)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AssembleError> {
+        if let Some(reg) = self.peek().and_then(|token| self.lookup_register(token)) {
+            self.pos += 1;
+            return Ok(Operand::Register(reg));
+        }
+
+        self.byte().map(Operand::Byte)
+    }
+
+    /* Statements */
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+
+                if self.labels.insert(name.clone(), self.here).is_some() {
+                    return self.error(format!("label '{}' is defined twice", name));
+                }
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            },
+            ":const" => {
+                let name = self.next()?;
+                let token = self.next()?;
+
+                let value = match self.lookup_value(&token).or_else(|| self.labels.get(&token).map(|&addr| addr as f64)) {
+                    Some(value) => value,
+                    None => return self.error(format!("expected a value, found '{}'", token)),
+                };
+
+                self.consts.insert(name, value);
+            },
+            ":calc" => {
+                let name = self.next()?;
+                let value = self.braced_expression()?;
+                self.consts.insert(name, value);
+            },
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.braced_expression()?.floor() as i64
+                } else {
+                    self.number()?
+                };
+
+                let byte = self.to_byte(value)?;
+                self.emit_byte(byte);
+            },
+            ":org" => {
+                let addr = self.number()?;
+
+                if addr < self.origin as i64 {
+                    return self.error(format!(":org 0x{:X} is before the start of the program", addr));
+                }
+
+                self.here = addr as usize;
+            },
+            ":call" => {
+                let addr = self.address()?;
+                self.emit_address(0x2000, addr)?;
+            },
+            ":unpack" => {
+                let nibble = self.nibble()?;
+
+                match self.address()? {
+                    Address::Known(addr) => {
+                        self.emit(0x6000 | (nibble as u16) << 4 | ((addr >> 8) & 0xF) as u16);
+                        self.emit(0x6100 | (addr & 0xFF) as u16);
+                    },
+                    Address::Forward(label) => {
+                        self.fixup(self.here + 1, label.clone(), FixupKind::High(nibble));
+                        self.emit(0x6000);
+                        self.fixup(self.here + 1, label, FixupKind::Low);
+                        self.emit(0x6100);
+                    },
+                }
+            },
+            // Debugger hints, which have no effect on the program
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n as u16);
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n as u16);
+            },
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | (n as u16) << 8);
+            },
+            "jump" => {
+                let addr = self.address()?;
+                self.emit_address(0x1000, addr)?;
+            },
+            "jump0" => {
+                let addr = self.address()?;
+                self.emit_address(0xB000, addr)?;
+            },
+            "native" => {
+                let addr = self.address()?;
+                self.emit_address(0x0000, addr)?;
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16);
+            },
+            "bcd" => self.register_op(0xF033)?,
+            "saveflags" => self.register_op(0xF075)?,
+            "loadflags" => self.register_op(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()?;
+
+                // XO-CHIP ranges, such as `save v2 - v5`
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    let op = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(op | (x as u16) << 8 | (y as u16) << 4);
+                } else {
+                    let op = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(op | (x as u16) << 8);
+                }
+            },
+            "delay" => {
+                self.expect(":=")?;
+                self.register_op(0xF015)?;
+            },
+            "buzzer" => {
+                self.expect(":=")?;
+                self.register_op(0xF018)?;
+            },
+            "pitch" => {
+                self.expect(":=")?;
+                self.register_op(0xF03A)?;
+            },
+            "i" => self.index_assignment()?,
+            "if" => self.conditional()?,
+            "else" => {
+                let Some(Flow { kind: FlowKind::If(skip), line }) = self.flow.pop() else {
+                    return self.error("else without a matching if ... begin");
+                };
+
+                let jump = self.here;
+                self.emit(0x1000);
+                self.patch(skip, 0x1000 | self.here as u16);
+                self.flow.push(Flow { kind: FlowKind::Else(jump), line });
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow { kind: FlowKind::If(jump) | FlowKind::Else(jump), .. }) => self.patch(jump, 0x1000 | self.here as u16),
+                _ => return self.error("end without a matching if ... begin"),
+            },
+            "loop" => {
+                let line = self.line();
+                self.flow.push(Flow { kind: FlowKind::Loop(self.here, Vec::new()), line });
+            },
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(&condition, true);
+
+                let jump = self.here;
+                self.emit(0x1000);
+
+                match self.flow.iter_mut().rev().find(|flow| matches!(flow.kind, FlowKind::Loop(..))) {
+                    Some(Flow { kind: FlowKind::Loop(_, exits), .. }) => exits.push(jump),
+                    _ => return self.error("while outside of a loop"),
+                }
+            },
+            "again" => {
+                let Some(Flow { kind: FlowKind::Loop(start, exits), .. }) = self.flow.pop() else {
+                    return self.error("again without a matching loop");
+                };
+
+                self.emit(0x1000 | start as u16);
+
+                for exit in exits {
+                    self.patch(exit, 0x1000 | self.here as u16);
+                }
+            },
+            _ if token.starts_with(':') => return self.error(format!("unsupported directive '{}'", token)),
+            _ => {
+                if let Some(reg) = self.lookup_register(&token) {
+                    return self.register_assignment(reg);
+                }
+
+                // Bare numbers are data, and bare names are calls
+                if let Some(value) = self.lookup_value(&token) {
+                    let byte = self.to_byte(value.floor() as i64)?;
+                    self.emit_byte(byte);
+                } else {
+                    self.pos -= 1;
+                    let addr = self.address()?;
+                    self.emit_address(0x2000, addr)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    // Emit an FX.. instruction on the next register.
+    fn register_op(&mut self, opcode: u16) -> Result<(), AssembleError> {
+        let reg = self.register()?;
+        self.emit(opcode | (reg as u16) << 8);
+        Ok(())
+    }
+
+    fn index_assignment(&mut self) -> Result<(), AssembleError> {
+        match self.next()?.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.pos += 1;
+                    self.register_op(0xF029)
+                },
+                Some("bighex") => {
+                    self.pos += 1;
+                    self.register_op(0xF030)
+                },
+                Some("long") => {
+                    self.pos += 1;
+                    self.emit(0xF000);
+
+                    match self.address()? {
+                        Address::Known(addr) => self.emit(addr as u16),
+                        Address::Forward(label) => {
+                            self.fixup(self.here, label, FixupKind::Word);
+                            self.emit(0x0000);
+                        },
+                    }
+
+                    Ok(())
+                },
+                _ => {
+                    let addr = self.address()?;
+                    self.emit_address(0xA000, addr)
+                },
+            },
+            "+=" => self.register_op(0xF01E),
+            op => self.error(format!("unknown operator 'i {}'", op)),
+        }
+    }
+
+    fn register_assignment(&mut self, x: usize) -> Result<(), AssembleError> {
+        let op = self.next()?;
+        let x_bits = (x as u16) << 8;
+
+        if op == ":=" {
+            match self.peek() {
+                Some("delay") => {
+                    self.pos += 1;
+                    self.emit(0xF007 | x_bits);
+                    return Ok(());
+                },
+                Some("key") => {
+                    self.pos += 1;
+                    self.emit(0xF00A | x_bits);
+                    return Ok(());
+                },
+                Some("random") => {
+                    self.pos += 1;
+                    let mask = self.byte()?;
+                    self.emit(0xC000 | x_bits | mask as u16);
+                    return Ok(());
+                },
+                _ => (),
+            }
+        }
+
+        let operand = self.operand()?;
+
+        let opcode = match (op.as_str(), operand) {
+            (":=", Operand::Byte(value)) => 0x6000 | value as u16,
+            ("+=", Operand::Byte(value)) => 0x7000 | value as u16,
+            ("-=", Operand::Byte(value)) => 0x7000 | value.wrapping_neg() as u16,
+            (_, Operand::Byte(_)) => return self.error(format!("'{}' needs a register on the right", op)),
+            (op, Operand::Register(y)) => {
+                let n = match op {
+                    ":=" => 0x0,
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "+=" => 0x4,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return self.error(format!("unknown operator '{}'", op)),
+                };
+
+                0x8000 | (y as u16) << 4 | n
+            },
+        };
+
+        self.emit(opcode | x_bits);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let reg = self.register()?;
+
+        let comparison = match self.next()?.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            op => return self.error(format!("unknown comparison '{}'", op)),
+        };
+
+        let operand = match comparison {
+            Comparison::Key | Comparison::NotKey => None,
+            _ => Some(self.operand()?),
+        };
+
+        Ok(Condition { reg, comparison, operand })
+    }
+
+    // Emit instructions skipping the next one unless the condition holds, or when
+    // negated, if it does.
+    fn skip_unless(&mut self, condition: &Condition, negated: bool) {
+        let comparison = if negated {
+            condition.comparison.negated()
+        } else {
+            condition.comparison
+        };
+
+        let x = (condition.reg as u16) << 8;
+
+        // Less and greater compare by subtracting into vF and testing the flag
+        let mut subtract = |a: Operand, b: Operand| {
+            match (a, b) {
+                (Operand::Register(a), Operand::Register(b)) => {
+                    self.emit(0x8F00 | (a as u16) << 4);
+                    self.emit(0x8F05 | (b as u16) << 4);
+                },
+                (Operand::Register(a), Operand::Byte(b)) => {
+                    self.emit(0x6F00 | b as u16);
+                    self.emit(0x8F07 | (a as u16) << 4);
+                },
+                (Operand::Byte(a), Operand::Register(b)) => {
+                    self.emit(0x6F00 | a as u16);
+                    self.emit(0x8F05 | (b as u16) << 4);
+                },
+                (Operand::Byte(_), Operand::Byte(_)) => unreachable!("one side is always a register"),
+            }
+        };
+
+        let reg = Operand::Register(condition.reg);
+
+        match (comparison, condition.operand) {
+            (Comparison::Key, _) => self.emit(0xE0A1 | x),
+            (Comparison::NotKey, _) => self.emit(0xE09E | x),
+            (Comparison::Equal, Some(Operand::Byte(value))) => self.emit(0x4000 | x | value as u16),
+            (Comparison::Equal, Some(Operand::Register(y))) => self.emit(0x9000 | x | (y as u16) << 4),
+            (Comparison::NotEqual, Some(Operand::Byte(value))) => self.emit(0x3000 | x | value as u16),
+            (Comparison::NotEqual, Some(Operand::Register(y))) => self.emit(0x5000 | x | (y as u16) << 4),
+            // vF is 1 when no borrow occurred
+            (Comparison::Less, Some(operand)) => {
+                subtract(reg, operand);
+                self.emit(0x3F01);
+            },
+            (Comparison::Greater, Some(operand)) => {
+                subtract(operand, reg);
+                self.emit(0x3F01);
+            },
+            (Comparison::LessOrEqual, Some(operand)) => {
+                subtract(operand, reg);
+                self.emit(0x3F00);
+            },
+            (Comparison::GreaterOrEqual, Some(operand)) => {
+                subtract(reg, operand);
+                self.emit(0x3F00);
+            },
+            (_, None) => unreachable!("comparisons other than key always have an operand"),
+        }
+    }
+
+    fn conditional(&mut self) -> Result<(), AssembleError> {
+        let condition = self.condition()?;
+
+        match self.next()?.as_str() {
+            "then" => {
+                self.skip_unless(&condition, false);
+                self.statement()
+            },
+            "begin" => {
+                // Jump over the block unless the condition holds
+                let line = self.line();
+                self.skip_unless(&condition, true);
+                let jump = self.here;
+                self.emit(0x1000);
+                self.flow.push(Flow { kind: FlowKind::If(jump), line });
+                Ok(())
+            },
+            token => self.error(format!("expected 'then' or 'begin', found '{}'", token)),
+        }
+    }
+
+    /* Expressions */
+
+    fn braced_expression(&mut self) -> Result<f64, AssembleError> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    // As in Octo, binary operators have no precedence and apply right to left, so
+    // `2 * 3 + 4` is 14.
+    fn expression(&mut self) -> Result<f64, AssembleError> {
+        let left = self.term()?;
+
+        let op = match self.peek() {
+            Some(op @ ("+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "min" | "max" | "<" | ">" | "<=" | ">=" | "==" | "!=")) => op.to_string(),
+            _ => return Ok(left),
+        };
+
+        self.pos += 1;
+        let right = self.expression()?;
+
+        let (a, b) = (left as i64, right as i64);
+
+        Ok(match op.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" | "%" if right == 0.0 => return self.error("division by zero"),
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => (a << b) as f64,
+            ">>" => (a >> b) as f64,
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            _ => (left != right) as i64 as f64,
+        })
+    }
+
+    fn term(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next()?;
+
+        match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            "-" => Ok(-self.term()?),
+            "~" => Ok(!(self.term()? as i64) as f64),
+            "!" => Ok((self.term()? == 0.0) as i64 as f64),
+            "abs" => Ok(self.term()?.abs()),
+            "floor" => Ok(self.term()?.floor()),
+            "HERE" => Ok(self.here as f64),
+            _ => match self.lookup_value(&token).or_else(|| self.labels.get(&token).map(|&addr| addr as f64)) {
+                Some(value) => Ok(value),
+                None => self.error(format!("unknown name '{}' in expression", token)),
+            },
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use crate::memory::{Memory, MemoryError};
+
+#[derive(Debug)]
+pub enum PatchError {
+    Io(io::Error),
+    // Line that isn't an address and a byte
+    Parse(usize),
+    // IPS file cut off partway through a record
+    Truncated,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Io(error) => write!(f, "{}", error),
+            PatchError::Parse(line) => write!(f, "line {}: expected an address and a byte, such as 2A4:FF", line),
+            PatchError::Truncated => write!(f, "IPS file ends partway through a record"),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+// Bytes to write over a ROM once it is loaded, for bug fixes and cheats. Patch files
+// are either IPS files, with offsets from the start of the ROM, or text with one
+// `2A4:FF` memory address and byte in hex per line, with `#` comments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patch {
+    writes: Vec<(usize, Vec<u8>)>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Read a patch for a ROM loaded at program_start.
+    pub fn read(path: &Path, program_start: usize) -> Result<Self, PatchError> {
+        let bytes = fs::read(path).map_err(PatchError::Io)?;
+
+        match bytes.strip_prefix(b"PATCH") {
+            Some(records) => Self::parse_ips(records, program_start),
+            None => Self::parse(&String::from_utf8_lossy(&bytes)),
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, PatchError> {
+        let mut patch = Self::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("").trim();
+
+            if code.is_empty() {
+                continue;
+            }
+
+            let parsed = code.split_once(':').and_then(|(addr, byte)| {
+                Some((parse_hex(addr)?, u8::try_from(parse_hex(byte)?).ok()?))
+            });
+
+            match parsed {
+                Some((addr, byte)) => patch.insert(addr, &[byte]),
+                None => return Err(PatchError::Parse(index + 1)),
+            }
+        }
+
+        Ok(patch)
+    }
+
+    // IPS records following the PATCH header: a 24-bit offset and a 16-bit length
+    // followed by that many bytes, or a zero length then a 16-bit run length and the
+    // byte to repeat. An EOF marker ends the file.
+    fn parse_ips(mut records: &[u8], program_start: usize) -> Result<Self, PatchError> {
+        let mut patch = Self::new();
+
+        while !records.starts_with(b"EOF") {
+            let [o0, o1, o2, l0, l1, rest @ ..] = records else {
+                return Err(PatchError::Truncated);
+            };
+
+            let offset = u32::from_be_bytes([0, *o0, *o1, *o2]) as usize;
+            let len = u16::from_be_bytes([*l0, *l1]) as usize;
+
+            let (bytes, rest) = if len > 0 {
+                let bytes = rest.get(..len).ok_or(PatchError::Truncated)?;
+                (bytes.to_vec(), &rest[len..])
+            } else {
+                let [r0, r1, byte, rest @ ..] = rest else {
+                    return Err(PatchError::Truncated);
+                };
+
+                (vec![*byte; u16::from_be_bytes([*r0, *r1]) as usize], rest)
+            };
+
+            patch.insert(program_start + offset, &bytes);
+            records = rest;
+        }
+
+        Ok(patch)
+    }
+
+    // Write bytes at addr once applied, over any earlier writes.
+    pub fn insert(&mut self, addr: usize, bytes: &[u8]) {
+        self.writes.push((addr, bytes.to_vec()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    // Write the patch into memory, ignoring protection as the loader does.
+    pub fn apply(&self, memory: &mut Memory) -> Result<(), MemoryError> {
+        for (addr, bytes) in &self.writes {
+            memory.load_buf(*addr, bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+
+    usize::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,277 @@
+// Effects applied to frames on their way to the display, such as a phosphor glow or
+// scanlines. Each frame is coloured with the palette and passed through the stages
+// in order, and the display is given the result as a colour frame, so effects work
+// the same on any display that draws colour.
+
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::{config::DisplayConfig, devices::Display};
+
+// How much of a pixel's brightness is left a frame later, by default
+const DEFAULT_DECAY: f32 = 0.6;
+
+// How much darker every other line is, by default
+const DEFAULT_STRENGTH: f32 = 0.5;
+
+// A frame of ARGB pixels, in row order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub pixels: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+}
+
+// A stage of post-processing, which changes each frame before it's shown and may
+// keep state from one frame to the next. Stages may change the resolution too, as
+// long as they keep the frame's shape.
+pub trait PostProcess {
+    fn process(&mut self, image: &mut Image);
+}
+
+// Lit pixels fade out over the following frames rather than going dark at once, as
+// on the slow phosphor of old screens, which also smooths out flicker.
+pub struct Phosphor {
+    decay: f32,
+    previous: Option<Image>,
+}
+
+impl Phosphor {
+    pub fn new(decay: f32) -> Self {
+        Self {
+            decay: decay.clamp(0.0, 1.0),
+            previous: None,
+        }
+    }
+}
+
+impl PostProcess for Phosphor {
+    fn process(&mut self, image: &mut Image) {
+        // The glow starts over when the resolution changes
+        if let Some(previous) = &self.previous {
+            if (previous.width, previous.height) == (image.width, image.height) {
+                for (pixel, &before) in image.pixels.iter_mut().zip(&previous.pixels) {
+                    *pixel = combine(*pixel, before, |now, before| now.max((before as f32 * self.decay) as u8));
+                }
+            }
+        }
+
+        self.previous = Some(image.clone());
+    }
+}
+
+// Every other line is darkened, as between the lines an electron beam traces out.
+// Pixels are doubled in each direction to leave room for the lines between them.
+pub struct Scanlines {
+    strength: f32,
+}
+
+impl Scanlines {
+    pub fn new(strength: f32) -> Self {
+        Self {
+            strength: strength.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl PostProcess for Scanlines {
+    fn process(&mut self, image: &mut Image) {
+        let width = image.width * 2;
+        let mut pixels = Vec::with_capacity(image.pixels.len() * 4);
+
+        for row in image.pixels.chunks(image.width.max(1)) {
+            let line: Vec<u32> = row.iter().flat_map(|&pixel| [pixel, pixel]).collect();
+            let dark = line.iter().map(|&pixel| map_rgb(pixel, |channel| (channel as f32 * (1.0 - self.strength)) as u8));
+
+            pixels.extend_from_slice(&line);
+            pixels.extend(dark);
+        }
+
+        *image = Image {
+            pixels,
+            width,
+            height: image.height * 2,
+        };
+    }
+}
+
+// Brightness passed through a gamma curve, brightening midtones above 1 and
+// darkening them below.
+pub struct Gamma {
+    curve: [u8; 256],
+}
+
+impl Gamma {
+    pub fn new(gamma: f32) -> Self {
+        let exponent = 1.0 / gamma.max(f32::EPSILON);
+
+        Self {
+            curve: std::array::from_fn(|value| ((value as f32 / 255.0).powf(exponent) * 255.0).round() as u8),
+        }
+    }
+}
+
+impl PostProcess for Gamma {
+    fn process(&mut self, image: &mut Image) {
+        for pixel in &mut image.pixels {
+            *pixel = map_rgb(*pixel, |channel| self.curve[channel as usize]);
+        }
+    }
+}
+
+// Apply a function to each colour channel of a pixel, keeping its alpha.
+fn map_rgb(pixel: u32, f: impl Fn(u8) -> u8) -> u32 {
+    let [a, r, g, b] = pixel.to_be_bytes();
+    u32::from_be_bytes([a, f(r), f(g), f(b)])
+}
+
+// Combine each colour channel of two pixels, keeping the first's alpha.
+fn combine(pixel: u32, other: u32, f: impl Fn(u8, u8) -> u8) -> u32 {
+    let [a, r, g, b] = pixel.to_be_bytes();
+    let [_, other_r, other_g, other_b] = other.to_be_bytes();
+
+    u32::from_be_bytes([a, f(r, other_r), f(g, other_g), f(b, other_b)])
+}
+
+// A built-in stage as configured, by name with an optional parameter after a colon,
+// such as `phosphor:0.8` or `gamma:1.8`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stage {
+    Phosphor(f32),
+    Scanlines(f32),
+    Gamma(f32),
+}
+
+impl Stage {
+    pub fn build(self) -> Box<dyn PostProcess> {
+        match self {
+            Stage::Phosphor(decay) => Box::new(Phosphor::new(decay)),
+            Stage::Scanlines(strength) => Box::new(Scanlines::new(strength)),
+            Stage::Gamma(gamma) => Box::new(Gamma::new(gamma)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StageError {
+    Unknown(String),
+    // Stage, and the parameter it couldn't make sense of
+    Parameter(&'static str, String),
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::Unknown(name) => write!(f, "unknown stage {}, expected phosphor, scanlines or gamma", name),
+            StageError::Parameter(stage, value) => write!(f, "invalid {} parameter: {}", stage, value),
+        }
+    }
+}
+
+impl Error for StageError {}
+
+impl FromStr for Stage {
+    type Err = StageError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match value.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (value, None),
+        };
+
+        let number = |stage: &'static str, default: Option<f32>| {
+            match parameter {
+                Some(parameter) => parameter.parse::<f32>().ok()
+                    .filter(|number| number.is_finite() && *number > 0.0)
+                    .ok_or_else(|| StageError::Parameter(stage, parameter.to_string())),
+                None => default.ok_or_else(|| StageError::Parameter(stage, "missing".to_string())),
+            }
+        };
+
+        match name {
+            "phosphor" => number("phosphor", Some(DEFAULT_DECAY)).map(Stage::Phosphor),
+            "scanlines" => number("scanlines", Some(DEFAULT_STRENGTH)).map(Stage::Scanlines),
+            "gamma" => number("gamma", None).map(Stage::Gamma),
+            _ => Err(StageError::Unknown(name.to_string())),
+        }
+    }
+}
+
+// Wrap a display so its frames go through the stages first, in order. Displays are
+// left as they are when there are no stages. Frames are processed the way round
+// they're shown, so lines run across the screen however it's rotated.
+pub fn post_process(display: Box<dyn Display>, stages: Vec<Box<dyn PostProcess>>, config: &DisplayConfig) -> Box<dyn Display> {
+    if stages.is_empty() {
+        return display;
+    }
+
+    let (width, height) = config.oriented_size(config.width, config.height);
+
+    Box::new(PostProcessed {
+        display,
+        stages,
+        colors: config.colors,
+        width,
+        height,
+    })
+}
+
+// Display colouring each frame and passing it through the stages, then drawing the
+// result on the display it wraps as a colour frame.
+struct PostProcessed {
+    display: Box<dyn Display>,
+    stages: Vec<Box<dyn PostProcess>>,
+    colors: [(u8, u8, u8); 2],
+
+    // Resolution of the frames being drawn
+    width: usize,
+    height: usize,
+}
+
+impl PostProcessed {
+    fn show(&mut self, mut image: Image) {
+        for stage in &mut self.stages {
+            stage.process(&mut image);
+        }
+
+        self.display.draw_color(&image.pixels, image.width, image.height);
+    }
+}
+
+impl Display for PostProcessed {
+    fn draw(&mut self, frame: &[bool], _frame_number: u64) {
+        let palette = self.colors.map(|(r, g, b)| u32::from_be_bytes([0xFF, r, g, b]));
+
+        self.show(Image {
+            pixels: frame.iter().map(|&lit| palette[lit as usize]).collect(),
+            width: self.width,
+            height: self.height,
+        });
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.display.set_resolution(width, height);
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        self.show(Image {
+            pixels: frame.to_vec(),
+            width,
+            height,
+        });
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.display.set_title(title);
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.colors = colors;
+        self.display.set_colors(colors);
+    }
+
+    fn set_scale(&mut self, scale_factor: usize) {
+        self.display.set_scale(scale_factor);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{error::Error, fmt};
+
+use crate::devices::Key;
+
+const FRAME: u8 = 0x01;
+const KEY: u8 = 0x02;
+const ROWS: u8 = 0x03;
+
+#[derive(Debug, PartialEq)]
+pub enum ProtocolError {
+    Empty,
+    UnknownType(u8),
+    Truncated(usize),
+    InvalidKey(u8),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Empty => write!(f, "empty message"),
+            ProtocolError::UnknownType(kind) => write!(f, "unknown message type 0x{:02X}", kind),
+            ProtocolError::Truncated(len) => write!(f, "message truncated at {} bytes", len),
+            ProtocolError::InvalidKey(key) => write!(f, "invalid keycode {}", key),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+// Binary messages exchanged with remote frontends, each starting with a type byte.
+//
+// Frames are sent to clients as the type byte, the big endian 16-bit width and
+// height, then the pixels packed 8 to a byte (most significant bit first) in
+// row-major order. When only a few rows of a frame changed, they are sent instead as
+// the type byte and the big endian 16-bit width, then for each row its big endian
+// 16-bit index and its pixels packed the same way, padded to a whole byte. Clients
+// send key events as the type byte, the keycode, then 1 if the key was pressed or 0
+// if it was released.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Frame {
+        width: u16,
+        height: u16,
+        pixels: Vec<bool>,
+    },
+    Rows {
+        width: u16,
+        // Index and pixels of each changed row
+        rows: Vec<(u16, Vec<bool>)>,
+    },
+    Key {
+        key: Key,
+        pressed: bool,
+    },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Frame { width, height, pixels } => {
+                let mut bytes = vec![FRAME];
+                bytes.extend_from_slice(&width.to_be_bytes());
+                bytes.extend_from_slice(&height.to_be_bytes());
+                bytes.extend(pack(pixels));
+
+                bytes
+            },
+            Message::Rows { width, rows } => {
+                let mut bytes = vec![ROWS];
+                bytes.extend_from_slice(&width.to_be_bytes());
+
+                for (index, pixels) in rows {
+                    bytes.extend_from_slice(&index.to_be_bytes());
+                    bytes.extend(pack(pixels));
+                }
+
+                bytes
+            },
+            Message::Key { key, pressed } => vec![KEY, *key as u8, *pressed as u8],
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let truncated = ProtocolError::Truncated(bytes.len());
+
+        match bytes.first() {
+            None => Err(ProtocolError::Empty),
+            Some(&FRAME) => {
+                let [_, w0, w1, h0, h1, packed @ ..] = bytes else {
+                    return Err(truncated);
+                };
+
+                let width = u16::from_be_bytes([*w0, *w1]);
+                let height = u16::from_be_bytes([*h0, *h1]);
+                let len = width as usize * height as usize;
+
+                if packed.len() < len.div_ceil(8) {
+                    return Err(truncated);
+                }
+
+                Ok(Message::Frame { width, height, pixels: unpack(packed, len) })
+            },
+            Some(&ROWS) => {
+                let [_, w0, w1, packed @ ..] = bytes else {
+                    return Err(truncated);
+                };
+
+                let width = u16::from_be_bytes([*w0, *w1]);
+                let stride = 2 + (width as usize).div_ceil(8);
+
+                if packed.len() % stride != 0 {
+                    return Err(truncated);
+                }
+
+                let rows = packed.chunks(stride)
+                    .map(|row| (u16::from_be_bytes([row[0], row[1]]), unpack(&row[2..], width as usize)))
+                    .collect();
+
+                Ok(Message::Rows { width, rows })
+            },
+            Some(&KEY) => {
+                let [_, key, pressed, ..] = bytes else {
+                    return Err(truncated);
+                };
+
+                Ok(Message::Key {
+                    key: Key::try_from(*key).map_err(|_| ProtocolError::InvalidKey(*key))?,
+                    pressed: *pressed != 0,
+                })
+            },
+            Some(&kind) => Err(ProtocolError::UnknownType(kind)),
+        }
+    }
+}
+
+// Pixels packed 8 to a byte, most significant bit first.
+fn pack(pixels: &[bool]) -> impl Iterator<Item = u8> + '_ {
+    pixels.chunks(8).map(|chunk| {
+        chunk.iter()
+            .enumerate()
+            .fold(0, |byte, (bit, &on)| byte | ((on as u8) << (7 - bit)))
+    })
+}
+
+fn unpack(packed: &[u8], len: usize) -> Vec<bool> {
+    (0..len)
+        .map(|index| (packed[index / 8] >> (7 - index % 8)) & 1 == 1)
+        .collect()
+}
@@ -0,0 +1,38 @@
+// Renders monochrome pixels as text, for terminals and logs. Each line covers two
+// rows of pixels using half block characters, so pixels come out roughly square.
+
+// Pixels of an 8 pixel wide sprite, one byte per row, most significant bit leftmost.
+pub fn sprite_pixels(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 != 0))
+        .collect()
+}
+
+// Pixels in row order, width to a row, as lines of text. A trailing odd row is
+// padded with unlit pixels.
+pub fn render(pixels: &[bool], width: usize) -> String {
+    if width == 0 {
+        return String::new();
+    }
+
+    let rows: Vec<&[bool]> = pixels.chunks(width).collect();
+
+    rows.chunks(2)
+        .map(|pair| {
+            (0..width)
+                .map(|x| {
+                    let top = pair[0].get(x).copied().unwrap_or(false);
+                    let bottom = pair.get(1).and_then(|row| row.get(x)).copied().unwrap_or(false);
+
+                    match (top, bottom) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,254 @@
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    io,
+    iter::Peekable,
+    path::Path,
+    slice,
+    thread,
+    time::{Duration, Instant},
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::{
+    devices::{Key, KeyEvent},
+    storage,
+    system::ChipEight,
+};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    // Line that isn't a header or an event
+    Parse(usize),
+    RomMismatch,
+    // Cycles per frame of the recording, and of the machine playing it
+    SpeedMismatch(u64, u64),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Parse(line) => write!(f, "line {}: expected a header or a frame and a key, such as 120 +5", line),
+            ReplayError::RomMismatch => write!(f, "the replay was recorded with a different ROM"),
+            ReplayError::SpeedMismatch(recorded, ours) => write!(
+                f,
+                "the replay was recorded at {} cycles a frame, rather than {}",
+                recorded,
+                ours,
+            ),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+// The key presses and releases of a run and the frames they happened on, to play
+// the run back exactly. Like netplay, runs are driven a frame at a time from a
+// random seed, with the timers counting down on vblank.
+//
+// Saved as text, with a header and then an event per line, `+` for a press and `-`
+// for a release of a key in hex:
+//
+// rom 0a1b...
+// seed 1234
+// cycles-per-frame 10
+// frames 3600
+// 120 +5
+// 126 -5
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    rom_id: String,
+    seed: u64,
+    cycles_per_frame: u64,
+    frames: u64,
+    events: Vec<(u64, Key, bool)>,
+}
+
+impl Replay {
+    pub fn read(path: &Path) -> Result<Self, ReplayError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError> {
+        let mut replay = Self::default();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed = line.split_once(' ').and_then(|(name, value)| {
+                match name {
+                    "rom" => replay.rom_id = value.to_string(),
+                    "seed" => replay.seed = value.parse().ok()?,
+                    "cycles-per-frame" => replay.cycles_per_frame = value.parse().ok()?,
+                    "frames" => replay.frames = value.parse().ok()?,
+                    frame => {
+                        let pressed = match value.as_bytes().first()? {
+                            b'+' => true,
+                            b'-' => false,
+                            _ => return None,
+                        };
+                        let key = Key::try_from(u8::from_str_radix(&value[1..], 16).ok()?).ok()?;
+
+                        replay.events.push((frame.parse().ok()?, key, pressed));
+                    },
+                }
+
+                Some(())
+            });
+
+            if parsed.is_none() {
+                return Err(ReplayError::Parse(index + 1));
+            }
+        }
+
+        Ok(replay)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        storage::write_atomic(path, self.to_string())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Whether the replay was recorded with the given ROM.
+    pub fn matches(&self, rom: &[u8]) -> bool {
+        format!("{:x}", Sha1::digest(rom)) == self.rom_id
+    }
+
+    // Load a ROM and run it until stopped or the program exits, recording the key
+    // events from the machine's input device.
+    pub fn record(chip_eight: &mut ChipEight, rom: &[u8]) -> Self {
+        let mut input = chip_eight.take_input();
+
+        let mut replay = Self {
+            rom_id: format!("{:x}", Sha1::digest(rom)),
+            seed: rand::random(),
+            cycles_per_frame: chip_eight.cycles_per_frame(),
+            frames: 0,
+            events: vec![],
+        };
+
+        chip_eight.set_seed(replay.seed);
+        chip_eight.load(rom);
+
+        run_frames(chip_eight, None, |frame| {
+            let events = input.poll_events();
+            replay.events.extend(events.iter().map(|event| (frame, event.key, event.pressed)));
+            replay.frames = frame + 1;
+            events
+        });
+
+        replay
+    }
+
+    // Load the ROM the replay was recorded with and play it back, until the last
+    // recorded frame or stopped. Key events from the machine's input device are
+    // ignored, but its other controls, such as closing the window, still work.
+    pub fn play(&self, chip_eight: &mut ChipEight, rom: &[u8]) -> Result<(), ReplayError> {
+        let mut playback = self.start(chip_eight, rom)?;
+        let mut input = chip_eight.take_input();
+
+        run_frames(chip_eight, Some(self.frames), |frame| {
+            input.poll_events();
+
+            playback.events(frame)
+        });
+
+        Ok(())
+    }
+
+    // Load the ROM the replay was recorded with, ready for the caller to run the
+    // frames itself with the key events from the returned playback.
+    pub fn start(&self, chip_eight: &mut ChipEight, rom: &[u8]) -> Result<Playback<'_>, ReplayError> {
+        if !self.matches(rom) {
+            return Err(ReplayError::RomMismatch);
+        }
+
+        if chip_eight.cycles_per_frame() != self.cycles_per_frame {
+            return Err(ReplayError::SpeedMismatch(self.cycles_per_frame, chip_eight.cycles_per_frame()));
+        }
+
+        chip_eight.set_seed(self.seed);
+        chip_eight.load(rom);
+
+        Ok(Playback {
+            events: self.events.iter().peekable(),
+        })
+    }
+}
+
+// Key events of a replay being played back, handed out a frame at a time.
+pub struct Playback<'a> {
+    events: Peekable<slice::Iter<'a, (u64, Key, bool)>>,
+}
+
+impl Playback<'_> {
+    // Key events due by the given frame that haven't been handed out yet.
+    pub fn events(&mut self, frame: u64) -> Vec<KeyEvent> {
+        let mut due = vec![];
+
+        while let Some(&&(_, key, pressed)) = self.events.peek().filter(|(at, _, _)| *at <= frame) {
+            due.push(KeyEvent::new(key, pressed));
+            self.events.next();
+        }
+
+        due
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rom {}", self.rom_id)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "cycles-per-frame {}", self.cycles_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+
+        for (frame, key, pressed) in &self.events {
+            writeln!(f, "{} {}{:X}", frame, if *pressed { '+' } else { '-' }, *key as u8)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Run a frame at a time, applying the key events for each frame from events,
+// until stopped, the program exits or there have been a number of frames.
+fn run_frames(chip_eight: &mut ChipEight, frames: Option<u64>, mut events: impl FnMut(u64) -> Vec<KeyEvent>) {
+    let stop = chip_eight.stop_handle();
+    let mut deadline = Instant::now();
+
+    for frame in 0..frames.unwrap_or(u64::MAX) {
+        if stop.is_stopped() || chip_eight.exited() {
+            break;
+        }
+
+        for event in events(frame) {
+            chip_eight.key_event(event);
+        }
+
+        chip_eight.run_frames(1);
+
+        if !chip_eight.config().unthrottled() {
+            deadline += FRAME_DURATION;
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// The machine's sound drawn over the display: a speaker in the bottom right corner
+// while the tone plays and, for XO-CHIP audio patterns, the pattern beside it. Kept
+// up to date from the machine's events, so embedders can subscribe one of their own
+// and draw it into whatever they show frames on.
+
+use crate::bus::CoreEvent;
+
+// Speaker with a sound wave coming off it, 5 pixels square
+const SPEAKER: [u8; 5] = [0b00101, 0b11010, 0b11010, 0b11010, 0b00101];
+const SPEAKER_SIZE: usize = 5;
+
+// Samples of the 128 sample pattern shown by each bar
+const SAMPLES_PER_BAR: usize = 4;
+
+#[derive(Default)]
+pub struct SoundScope {
+    playing: bool,
+    pattern: Option<[u8; 16]>,
+}
+
+impl SoundScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Follow an event, ignoring those that aren't about sound.
+    pub fn handle(&mut self, event: CoreEvent) {
+        match event {
+            CoreEvent::SoundStarted => self.playing = true,
+            CoreEvent::SoundStopped => self.playing = false,
+            CoreEvent::PatternChanged(pattern, _) => self.pattern = pattern,
+            _ => (),
+        }
+    }
+
+    // Whether there's anything to draw, which is only while the tone plays.
+    pub fn is_visible(&self) -> bool {
+        self.playing
+    }
+
+    // Draw into the bottom right corner of a frame: the speaker, and to its left a bar
+    // for each few samples of any pattern, as tall as how many of them are set.
+    pub fn draw(&self, frame: &mut [bool], width: usize) {
+        let height = frame.len().checked_div(width).unwrap_or(0);
+
+        if !self.playing || width <= SPEAKER_SIZE + 1 || height <= SPEAKER_SIZE {
+            return;
+        }
+
+        let (right, bottom) = (width - 1, height - 1);
+        let mut set = |x: usize, y: usize| frame[y * width + x] = true;
+
+        for (row, bits) in SPEAKER.iter().enumerate() {
+            for column in 0..SPEAKER_SIZE {
+                if (bits >> (SPEAKER_SIZE - 1 - column)) & 1 == 1 {
+                    set(right - SPEAKER_SIZE + column, bottom - SPEAKER_SIZE + row);
+                }
+            }
+        }
+
+        let Some(pattern) = &self.pattern else {
+            return;
+        };
+
+        let samples: Vec<bool> = pattern.iter().flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1)).collect();
+
+        // Bars end a pixel short of the speaker, with the last left off in narrow frames
+        let end = right - SPEAKER_SIZE - 1;
+        let start = end.saturating_sub(samples.len() / SAMPLES_PER_BAR);
+
+        for (x, samples) in (start..end).zip(samples.chunks(SAMPLES_PER_BAR)) {
+            // A bar is at least a pixel high, so silence still shows
+            let rows = 1 + samples.iter().filter(|&&sample| sample).count();
+
+            for row in 0..rows {
+                set(x, bottom - 1 - row);
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    fs,
+    io,
+    path::Path,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use mlua::{Function, Lua, RegistryKey, Table};
+
+use crate::{
+    devices::{Display, Key},
+    menu,
+    system::ChipEight,
+};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Lua(mlua::Error),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(error) => write!(f, "{}", error),
+            ScriptError::Lua(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+impl From<mlua::Error> for ScriptError {
+    fn from(error: mlua::Error) -> Self {
+        ScriptError::Lua(error)
+    }
+}
+
+// Functions the script registered with chip8.on_frame and chip8.on_instruction.
+#[derive(Default)]
+struct Callbacks {
+    frame: Vec<RegistryKey>,
+    instruction: Vec<RegistryKey>,
+}
+
+// Text drawn over the frame with chip8.text.
+struct Text {
+    x: usize,
+    y: usize,
+    text: String,
+}
+
+// A Lua script driving the machine, for cheats, auto-splitters and bots. Scripts
+// register callbacks with the global `chip8` table, which only gives access to the
+// machine while a callback is running:
+//
+// chip8.on_frame(function(frame) ... end)
+// chip8.on_instruction(function(pc, opcode) ... end)
+// chip8.pc(), chip8.i(), chip8.reg(x), chip8.set_reg(x, value)
+// chip8.peek(addr), chip8.poke(addr, byte)
+// chip8.press(key), chip8.release(key)
+// chip8.text(x, y, text), shown over the current frame only
+pub struct Script {
+    lua: Lua,
+    callbacks: Rc<RefCell<Callbacks>>,
+    overlay: Rc<RefCell<Vec<Text>>>,
+}
+
+impl Script {
+    // Run the script at path, letting it register its callbacks.
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let source = fs::read_to_string(path).map_err(ScriptError::Io)?;
+        let lua = Lua::new();
+        let callbacks = Rc::new(RefCell::new(Callbacks::default()));
+
+        let chip8 = lua.create_table()?;
+
+        chip8.set("on_frame", lua.create_function({
+            let callbacks = callbacks.clone();
+            move |lua, callback: Function| {
+                callbacks.borrow_mut().frame.push(lua.create_registry_value(callback)?);
+                Ok(())
+            }
+        })?)?;
+
+        chip8.set("on_instruction", lua.create_function({
+            let callbacks = callbacks.clone();
+            move |lua, callback: Function| {
+                callbacks.borrow_mut().instruction.push(lua.create_registry_value(callback)?);
+                Ok(())
+            }
+        })?)?;
+
+        lua.globals().set("chip8", chip8)?;
+        lua.load(source).set_name(path.display().to_string()).exec()?;
+
+        Ok(Self {
+            lua,
+            callbacks,
+            overlay: Rc::new(RefCell::new(Vec::new())),
+        })
+    }
+
+    // Wrap a display so it shows the script's text over each frame, given the
+    // width of the frames it starts out drawing.
+    pub fn overlay(&self, display: Box<dyn Display>, width: usize) -> Box<dyn Display> {
+        Box::new(Overlay {
+            display,
+            texts: self.overlay.clone(),
+            width,
+        })
+    }
+
+    // Run the loaded program a frame at a time, calling the script's callbacks,
+    // until stopped or the program exits.
+    pub fn play(&self, chip_eight: &mut ChipEight) -> Result<(), ScriptError> {
+        let stop = chip_eight.stop_handle();
+        let mut deadline = Instant::now();
+
+        for frame in 0u64.. {
+            if stop.is_stopped() || chip_eight.exited() {
+                break;
+            }
+
+            self.run_frame(chip_eight, frame)?;
+            chip_eight.vblank();
+
+            if !chip_eight.config().unthrottled() {
+                deadline += FRAME_DURATION;
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        }
+
+        chip_eight.shutdown();
+        Ok(())
+    }
+
+    // Run a frame's worth of cycles, with the machine functions in scope for the
+    // callbacks.
+    fn run_frame(&self, chip_eight: &mut ChipEight, frame: u64) -> Result<(), ScriptError> {
+        let machine = RefCell::new(chip_eight);
+        let chip8: Table = self.lua.globals().get("chip8")?;
+
+        self.lua.scope(|scope| {
+            chip8.set("pc", scope.create_function(|_, ()| Ok(machine.borrow().pc()))?)?;
+            chip8.set("i", scope.create_function(|_, ()| Ok(machine.borrow().index()))?)?;
+
+            chip8.set("reg", scope.create_function(|_, reg: usize| {
+                Ok(machine.borrow().registers().get(reg).copied())
+            })?)?;
+
+            chip8.set("set_reg", scope.create_function(|_, (reg, value): (usize, u8)| {
+                if reg >= 16 {
+                    return Err(mlua::Error::runtime(format!("no register v{}", reg)));
+                }
+
+                machine.borrow_mut().set_register(reg as u8, value);
+                Ok(())
+            })?)?;
+
+            chip8.set("peek", scope.create_function(|_, addr: usize| {
+                machine.borrow().memory().read_byte(addr).map_err(mlua::Error::runtime)
+            })?)?;
+
+            chip8.set("poke", scope.create_function(|_, (addr, value): (usize, u8)| {
+                machine.borrow_mut().memory_mut().write_byte(addr, value).map_err(mlua::Error::runtime)
+            })?)?;
+
+            chip8.set("press", scope.create_function(|_, key: u8| {
+                let key = Key::try_from(key).map_err(mlua::Error::runtime)?;
+                machine.borrow_mut().press_key(key);
+                Ok(())
+            })?)?;
+
+            chip8.set("release", scope.create_function(|_, key: u8| {
+                let key = Key::try_from(key).map_err(mlua::Error::runtime)?;
+                machine.borrow_mut().release_key(key);
+                Ok(())
+            })?)?;
+
+            chip8.set("tap", scope.create_function(|_, (key, frames): (u8, Option<u64>)| {
+                let key = Key::try_from(key).map_err(mlua::Error::runtime)?;
+                machine.borrow_mut().tap_key_for(key, frames.unwrap_or(1));
+                Ok(())
+            })?)?;
+
+            chip8.set("quirk", scope.create_function(|_, name: String| {
+                Ok(machine.borrow().quirks().get(&name))
+            })?)?;
+
+            chip8.set("set_quirk", scope.create_function(|_, (name, value): (String, String)| {
+                let mut machine = machine.borrow_mut();
+                let mut quirks = machine.quirks();
+
+                quirks.set(&name, &value).map_err(mlua::Error::runtime)?;
+                machine.set_quirks(quirks);
+                Ok(())
+            })?)?;
+
+            chip8.set("text", scope.create_function(|_, (x, y, text): (usize, usize, String)| {
+                self.overlay.borrow_mut().push(Text { x, y, text });
+                Ok(())
+            })?)?;
+
+            let cycles = machine.borrow().cycles_per_frame();
+            let on_instruction = self.functions(|callbacks| &callbacks.instruction)?;
+
+            if on_instruction.is_empty() {
+                machine.borrow_mut().run_cycles(cycles);
+            } else {
+                for _ in 0..cycles {
+                    let (pc, opcode) = {
+                        let machine = machine.borrow();
+
+                        // Cycles spent waiting on the display or keypad don't execute anything
+                        let executing = !machine.waiting_for_vblank() && machine.key_wait().is_none();
+                        let opcode = machine.memory().read_buf(machine.pc(), 2).ok()
+                            .filter(|_| executing)
+                            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+
+                        (machine.pc(), opcode)
+                    };
+
+                    if let Some(opcode) = opcode {
+                        for callback in &on_instruction {
+                            callback.call::<_, ()>((pc, opcode))?;
+                        }
+                    }
+
+                    machine.borrow_mut().cycle();
+
+                    if machine.borrow().exited() {
+                        break;
+                    }
+                }
+            }
+
+            self.overlay.borrow_mut().clear();
+
+            for callback in self.functions(|callbacks| &callbacks.frame)? {
+                callback.call::<_, ()>(frame)?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    // Callbacks of one kind, taken out of the registry so they can register more.
+    fn functions(&self, kind: impl Fn(&Callbacks) -> &Vec<RegistryKey>) -> mlua::Result<Vec<Function<'_>>> {
+        kind(&self.callbacks.borrow())
+            .iter()
+            .map(|key| self.lua.registry_value(key))
+            .collect()
+    }
+}
+
+// Display drawing the script's text over each frame before passing it on.
+struct Overlay {
+    display: Box<dyn Display>,
+    texts: Rc<RefCell<Vec<Text>>>,
+    width: usize,
+}
+
+impl Display for Overlay {
+    fn draw(&mut self, frame: &[bool], frame_number: u64) {
+        let texts = self.texts.borrow();
+
+        if texts.is_empty() {
+            self.display.draw(frame, frame_number);
+            return;
+        }
+
+        let mut frame = frame.to_vec();
+
+        for text in texts.iter() {
+            menu::draw_text(&mut frame, self.width, text.x, text.y, &text.text, true);
+        }
+
+        self.display.draw(&frame, frame_number);
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.display.set_resolution(width, height);
+    }
+
+    fn draw_color(&mut self, frame: &[u32], width: usize, height: usize) {
+        self.display.draw_color(frame, width, height);
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.display.set_title(title);
+    }
+
+    fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.display.set_colors(colors);
+    }
+
+    fn set_scale(&mut self, scale_factor: usize) {
+        self.display.set_scale(scale_factor);
+    }
+}
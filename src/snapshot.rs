@@ -0,0 +1,507 @@
+use std::{error::Error, fmt, io};
+
+use crate::{
+    config::Profile,
+    cpu::{MachineCalls, QuirksConfig, RandomModel, VblankModel},
+    devices::Key,
+    system::{CallFrame, KeyWait},
+};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 3;
+
+// Oldest version this one can read. Versions before the chunks came in are fields
+// one after the other, and the first lacks the VIP random seed.
+const MIN_VERSION: u8 = 1;
+const CHUNKED_VERSION: u8 = 3;
+
+// Tags of the chunks this version writes
+const INFO: &[u8; 4] = b"INFO";
+const REGISTERS: &[u8; 4] = b"REGS";
+const TIMERS: &[u8; 4] = b"TIME";
+const MEMORY: &[u8; 4] = b"MEM ";
+const FRAME: &[u8; 4] = b"FRAM";
+const SOUND: &[u8; 4] = b"SND ";
+const RANDOM: &[u8; 4] = b"RAND";
+const QUIRKS: &[u8; 4] = b"QURK";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // Data that isn't a snapshot this version can read
+    Format(&'static str),
+    // Machines running this profile can't be snapshotted yet
+    Unsupported(Profile),
+    // Memory size of the snapshot, and of the machine restoring it
+    MemorySize(usize, usize),
+    // Snapshots are kept per ROM, so need the storage and a loaded ROM
+    NoStorage,
+    EmptySlot(u8),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::Format(message) => write!(f, "invalid snapshot: {}", message),
+            SnapshotError::Unsupported(profile) => write!(f, "snapshots aren't supported for the {:?} profile", profile),
+            SnapshotError::MemorySize(theirs, ours) => write!(
+                f,
+                "snapshot has {} bytes of memory, but the machine has {}",
+                theirs,
+                ours,
+            ),
+            SnapshotError::NoStorage => write!(f, "no data directory to keep snapshots in"),
+            SnapshotError::EmptySlot(slot) => write!(f, "nothing saved in slot {}", slot),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+// Everything needed to carry on a run from where it was taken, as
+// ChipEight::snapshot captures it.
+//
+// Encoded as the magic `C8ST` and a version byte, then a chunk for each part of the
+// machine: a 4 byte tag, the 32-bit length of its contents, then the contents, with
+// little endian integers, vectors prefixed with their 32-bit length, and the frame
+// buffer packed 8 pixels to a byte. Later versions only add chunks, and fields to
+// the end of chunks, so readers skip the chunks they don't know and the bytes past
+// the fields they do, and read states from versions after their own. A change that
+// can't be made that way takes a new tag.
+//
+// INFO  time taken, as u64 seconds since the Unix epoch
+// REGS  pc, v0 to vF, i, the stack, the calls, the key wait and the flags
+// TIME  delay and sound timers
+// MEM   memory
+// FRAM  width, height and pixels
+// SND   audio pattern and pitch
+// RAND  seeds of the random number generator and of the VIP's routine
+// QURK  quirks the machine ran with, which not every state has
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    // Seconds since the Unix epoch when it was taken
+    pub saved_at: u64,
+    pub pc: usize,
+    pub v: [u8; 16],
+    pub i: usize,
+    pub timers: (u8, u8),
+    pub stack: Vec<usize>,
+    pub calls: Vec<CallFrame>,
+    pub memory: Vec<u8>,
+    pub resolution: (usize, usize),
+    pub frame_buffer: Vec<bool>,
+    pub key_wait: Option<KeyWait>,
+    pub waiting_for_vblank: bool,
+    pub exited: bool,
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    // The random number generator carries on from this seed
+    pub rng_seed: u64,
+    // Seed of the VIP's random number routine
+    pub random_seed: u16,
+    // Quirks in effect when it was taken, kept for reference, as states from before
+    // version 3 don't have them
+    pub quirks: Option<QuirksConfig>,
+}
+
+// What a snapshot file holds, without decoding it.
+#[derive(Debug, PartialEq)]
+pub struct SnapshotInfo {
+    pub version: u8,
+    // Tag and length of each chunk, in order, with none before version 3
+    pub chunks: Vec<([u8; 4], usize)>,
+}
+
+impl Snapshot {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        put_chunk(&mut bytes, INFO, &self.saved_at.to_le_bytes());
+
+        let mut registers = vec![];
+        put_u32(&mut registers, self.pc);
+        registers.extend_from_slice(&self.v);
+        put_u32(&mut registers, self.i);
+
+        put_u32(&mut registers, self.stack.len());
+        for &addr in &self.stack {
+            put_u32(&mut registers, addr);
+        }
+
+        put_u32(&mut registers, self.calls.len());
+        for call in &self.calls {
+            put_u32(&mut registers, call.call_site);
+            put_u32(&mut registers, call.routine);
+        }
+
+        registers.extend_from_slice(&encode_key_wait(self.key_wait));
+        registers.push(self.flags());
+        put_chunk(&mut bytes, REGISTERS, &registers);
+
+        put_chunk(&mut bytes, TIMERS, &[self.timers.0, self.timers.1]);
+        put_chunk(&mut bytes, MEMORY, &self.memory);
+
+        let mut frame = vec![];
+        put_u32(&mut frame, self.resolution.0);
+        put_u32(&mut frame, self.resolution.1);
+        frame.extend(pack(&self.frame_buffer));
+        put_chunk(&mut bytes, FRAME, &frame);
+
+        let mut sound = encode_pattern(&self.audio_pattern);
+        sound.push(self.pitch);
+        put_chunk(&mut bytes, SOUND, &sound);
+
+        let mut random = self.rng_seed.to_le_bytes().to_vec();
+        random.extend_from_slice(&self.random_seed.to_le_bytes());
+        put_chunk(&mut bytes, RANDOM, &random);
+
+        if let Some(quirks) = &self.quirks {
+            put_chunk(&mut bytes, QUIRKS, &encode_quirks(quirks));
+        }
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let (version, mut reader) = header(bytes)?;
+
+        if version < CHUNKED_VERSION {
+            return decode_fields(&mut reader, version);
+        }
+
+        let chunks = read_chunks(&mut reader)?;
+        let chunk = |tag: &'static [u8; 4]| {
+            chunks.iter()
+                .find(|(found, _)| found == tag)
+                .map(|&(_, contents)| Reader { bytes: contents })
+        };
+        let required = |tag: &'static [u8; 4], missing: &'static str| chunk(tag).ok_or(SnapshotError::Format(missing));
+
+        let saved_at = required(INFO, "missing info")?.u64()?;
+
+        let mut registers = required(REGISTERS, "missing registers")?;
+        let pc = registers.u32()?;
+        let v = registers.take(16)?.try_into().unwrap();
+        let i = registers.u32()?;
+        let stack = read_stack(&mut registers)?;
+        let calls = read_calls(&mut registers)?;
+        let key_wait = read_key_wait(&mut registers)?;
+        let flags = registers.u8()?;
+
+        let mut timers = required(TIMERS, "missing timers")?;
+        let timers = (timers.u8()?, timers.u8()?);
+
+        let memory = required(MEMORY, "missing memory")?.bytes.to_vec();
+
+        let mut frame = required(FRAME, "missing frame")?;
+        let resolution = (frame.u32()?, frame.u32()?);
+        let frame_buffer = unpack(&mut frame, resolution.0 * resolution.1)?;
+
+        let mut sound = required(SOUND, "missing sound")?;
+        let audio_pattern = read_pattern(&mut sound)?;
+        let pitch = sound.u8()?;
+
+        let mut random = required(RANDOM, "missing random seeds")?;
+        let rng_seed = random.u64()?;
+        let random_seed = random.u16()?;
+
+        let quirks = chunk(QUIRKS).map(|mut quirks| read_quirks(&mut quirks)).transpose()?;
+
+        Ok(Self {
+            saved_at,
+            pc,
+            v,
+            i,
+            timers,
+            stack,
+            calls,
+            memory,
+            resolution,
+            frame_buffer,
+            key_wait,
+            waiting_for_vblank: flags & 1 != 0,
+            exited: flags & 2 != 0,
+            audio_pattern,
+            pitch,
+            rng_seed,
+            random_seed,
+            quirks,
+        })
+    }
+
+    // The version and chunks of an encoded snapshot.
+    pub fn info(bytes: &[u8]) -> Result<SnapshotInfo, SnapshotError> {
+        let (version, mut reader) = header(bytes)?;
+
+        let chunks = match version < CHUNKED_VERSION {
+            true => vec![],
+            false => read_chunks(&mut reader)?.into_iter().map(|(tag, contents)| (tag, contents.len())).collect(),
+        };
+
+        Ok(SnapshotInfo { version, chunks })
+    }
+
+    fn flags(&self) -> u8 {
+        self.waiting_for_vblank as u8 | (self.exited as u8) << 1
+    }
+}
+
+// Check the magic and version, returning the version and a reader of what follows.
+fn header(bytes: &[u8]) -> Result<(u8, Reader<'_>), SnapshotError> {
+    let mut reader = Reader { bytes };
+
+    if reader.take(4)? != MAGIC {
+        return Err(SnapshotError::Format("not a snapshot"));
+    }
+
+    let version = reader.u8()?;
+
+    if version < MIN_VERSION {
+        return Err(SnapshotError::Format("unsupported version"));
+    }
+
+    Ok((version, reader))
+}
+
+// Tag and contents of a chunk
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+// Each chunk through to the end.
+fn read_chunks<'a>(reader: &mut Reader<'a>) -> Result<Vec<Chunk<'a>>, SnapshotError> {
+    let mut chunks = vec![];
+
+    while !reader.bytes.is_empty() {
+        let tag = reader.take(4)?.try_into().unwrap();
+        let len = reader.u32()?;
+
+        chunks.push((tag, reader.take(len)?));
+    }
+
+    Ok(chunks)
+}
+
+// Versions before chunks, each field in order with nothing to mark where it is.
+fn decode_fields(reader: &mut Reader, version: u8) -> Result<Snapshot, SnapshotError> {
+    let saved_at = reader.u64()?;
+    let pc = reader.u32()?;
+    let v = reader.take(16)?.try_into().unwrap();
+    let i = reader.u32()?;
+    let timers = (reader.u8()?, reader.u8()?);
+    let stack = read_stack(reader)?;
+    let calls = read_calls(reader)?;
+
+    let memory_len = reader.u32()?;
+    let memory = reader.take(memory_len)?.to_vec();
+
+    let resolution = (reader.u32()?, reader.u32()?);
+    let pixels = reader.u32()?;
+    let frame_buffer = unpack(reader, pixels)?;
+
+    let key_wait = read_key_wait(reader)?;
+    let flags = reader.u8()?;
+    let audio_pattern = read_pattern(reader)?;
+    let pitch = reader.u8()?;
+    let rng_seed = reader.u64()?;
+    let random_seed = if version >= 2 { reader.u16()? } else { 0 };
+
+    Ok(Snapshot {
+        saved_at,
+        pc,
+        v,
+        i,
+        timers,
+        stack,
+        calls,
+        memory,
+        resolution,
+        frame_buffer,
+        key_wait,
+        waiting_for_vblank: flags & 1 != 0,
+        exited: flags & 2 != 0,
+        audio_pattern,
+        pitch,
+        rng_seed,
+        random_seed,
+        quirks: None,
+    })
+}
+
+fn read_stack(reader: &mut Reader) -> Result<Vec<usize>, SnapshotError> {
+    (0..reader.u32()?).map(|_| reader.u32()).collect()
+}
+
+fn read_calls(reader: &mut Reader) -> Result<Vec<CallFrame>, SnapshotError> {
+    (0..reader.u32()?)
+        .map(|_| Ok(CallFrame { call_site: reader.u32()?, routine: reader.u32()? }))
+        .collect()
+}
+
+fn encode_key_wait(key_wait: Option<KeyWait>) -> [u8; 3] {
+    match key_wait {
+        None => [0, 0, 0],
+        Some(KeyWait::Press(reg)) => [1, reg as u8, 0],
+        Some(KeyWait::Release(reg, key)) => [2, reg as u8, key as u8],
+    }
+}
+
+fn read_key_wait(reader: &mut Reader) -> Result<Option<KeyWait>, SnapshotError> {
+    match [reader.u8()?, reader.u8()?, reader.u8()?] {
+        [0, _, _] => Ok(None),
+        [1, reg, _] => Ok(Some(KeyWait::Press(reg as usize))),
+        [2, reg, key] => Ok(Some(KeyWait::Release(
+            reg as usize,
+            Key::try_from(key).map_err(|_| SnapshotError::Format("invalid key"))?,
+        ))),
+        _ => Err(SnapshotError::Format("invalid key wait")),
+    }
+}
+
+fn encode_pattern(pattern: &Option<[u8; 16]>) -> Vec<u8> {
+    match pattern {
+        Some(pattern) => [&[1], pattern.as_slice()].concat(),
+        None => vec![0],
+    }
+}
+
+fn read_pattern(reader: &mut Reader) -> Result<Option<[u8; 16]>, SnapshotError> {
+    match reader.u8()? {
+        0 => Ok(None),
+        _ => Ok(Some(reader.take(16)?.try_into().unwrap())),
+    }
+}
+
+// Pixels packed 8 to a byte, the first in the top bit.
+fn pack(pixels: &[bool]) -> Vec<u8> {
+    pixels.chunks(8)
+        .map(|chunk| {
+            chunk.iter()
+                .enumerate()
+                .fold(0, |byte, (bit, &on)| byte | ((on as u8) << (7 - bit)))
+        })
+        .collect()
+}
+
+fn unpack(reader: &mut Reader, pixels: usize) -> Result<Vec<bool>, SnapshotError> {
+    Ok(reader.take(pixels.div_ceil(8))?
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 != 0))
+        .take(pixels)
+        .collect())
+}
+
+// The on or off quirks as bits of a 32-bit word, then a byte for each of the others.
+fn encode_quirks(quirks: &QuirksConfig) -> Vec<u8> {
+    let bits = [
+        quirks.skip_reset_vf,
+        quirks.preserve_index,
+        quirks.wrap_x,
+        quirks.wrap_y,
+        quirks.skip_shift_set,
+        quirks.jump_with_vx,
+        quirks.index_overflow_flag,
+        quirks.collision_count,
+    ];
+
+    let mut bytes = vec![];
+    put_u32(&mut bytes, bits.iter().enumerate().fold(0, |word, (bit, &on)| word | (on as usize) << bit));
+    bytes.push(match quirks.vblank_model {
+        VblankModel::Vip => 0,
+        VblankModel::Schip => 1,
+        VblankModel::None => 2,
+    });
+    bytes.push(match quirks.machine_calls {
+        MachineCalls::Ignore => 0,
+        MachineCalls::Trap => 1,
+        MachineCalls::Error => 2,
+    });
+    bytes.push(match quirks.rng {
+        RandomModel::Modern => 0,
+        RandomModel::Vip => 1,
+    });
+
+    bytes
+}
+
+fn read_quirks(reader: &mut Reader) -> Result<QuirksConfig, SnapshotError> {
+    let bits = reader.u32()?;
+    let bit = |bit: usize| bits >> bit & 1 != 0;
+    let invalid = SnapshotError::Format("invalid quirks");
+
+    Ok(QuirksConfig {
+        skip_reset_vf: bit(0),
+        preserve_index: bit(1),
+        wrap_x: bit(2),
+        wrap_y: bit(3),
+        skip_shift_set: bit(4),
+        jump_with_vx: bit(5),
+        index_overflow_flag: bit(6),
+        collision_count: bit(7),
+        vblank_model: match reader.u8()? {
+            0 => VblankModel::Vip,
+            1 => VblankModel::Schip,
+            2 => VblankModel::None,
+            _ => return Err(invalid),
+        },
+        machine_calls: match reader.u8()? {
+            0 => MachineCalls::Ignore,
+            1 => MachineCalls::Trap,
+            2 => MachineCalls::Error,
+            _ => return Err(invalid),
+        },
+        rng: match reader.u8()? {
+            0 => RandomModel::Modern,
+            1 => RandomModel::Vip,
+            _ => return Err(invalid),
+        },
+    })
+}
+
+fn put_chunk(bytes: &mut Vec<u8>, tag: &[u8; 4], contents: &[u8]) {
+    bytes.extend_from_slice(tag);
+    put_u32(bytes, contents.len());
+    bytes.extend_from_slice(contents);
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::Format("truncated"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<usize, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
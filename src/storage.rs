@@ -0,0 +1,256 @@
+use std::{
+    env,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::snapshot::{Snapshot, SnapshotError};
+
+const APP_DIR: &str = "chip-eight";
+
+// Number of save state slots, selected with Ctrl and a number key.
+pub const SLOTS: u8 = 9;
+
+// Where settings and saved data live on disk, following the XDG base directory
+// spec unless a single directory is given for everything:
+//
+// ~/.config/chip-eight/config.toml             settings for every ROM
+// ~/.config/chip-eight/roms/<sha1>.args        per-ROM command line flags, such as quirks
+// ~/.local/share/chip-eight/roms/<sha1>/rpl    SUPER-CHIP RPL user flags
+// ~/.local/share/chip-eight/roms/<sha1>/states save states
+// ~/.local/share/chip-eight/roms/<sha1>/replays
+// ~/.local/share/chip-eight/crashes             reports of faults the machine hit
+// ~/.local/share/chip-eight/archive             programs downloaded from the archive
+//
+// ROMs are identified by the SHA-1 of their file, so renaming or moving one keeps
+// its settings and saves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Storage {
+    config_dir: PathBuf,
+    data_dir: PathBuf,
+}
+
+impl Storage {
+    // The XDG directories, or None when neither they nor a home directory are set.
+    pub fn xdg() -> Option<Self> {
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let xdg_dir = |var: &str, fallback: &str| {
+            env::var_os(var)
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .or_else(|| home.as_ref().map(|home| home.join(fallback)))
+                .map(|dir| dir.join(APP_DIR))
+        };
+
+        Some(Self {
+            config_dir: xdg_dir("XDG_CONFIG_HOME", ".config")?,
+            data_dir: xdg_dir("XDG_DATA_HOME", ".local/share")?,
+        })
+    }
+
+    // Keep settings and data together in one directory, such as a portable install.
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            config_dir: dir.to_path_buf(),
+            data_dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    // TOML file of flags for every run, ahead of per-ROM settings.
+    pub fn config_path(&self) -> PathBuf {
+        self.config_dir.join("config.toml")
+    }
+
+    pub fn crash_dir(&self) -> PathBuf {
+        self.data_dir.join("crashes")
+    }
+
+    // Cached catalogue and ROMs of the program archive.
+    pub fn archive_dir(&self) -> PathBuf {
+        self.data_dir.join("archive")
+    }
+
+    // Locations for a ROM, given the contents of its file.
+    pub fn rom(&self, rom: &[u8]) -> RomStorage {
+        let id = format!("{:x}", Sha1::digest(rom));
+
+        RomStorage {
+            settings: self.config_dir.join("roms").join(format!("{}.args", id)),
+            dir: self.data_dir.join("roms").join(&id),
+            id,
+        }
+    }
+}
+
+// Where one ROM's settings and saved data live.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomStorage {
+    id: String,
+    settings: PathBuf,
+    dir: PathBuf,
+}
+
+impl RomStorage {
+    // SHA-1 of the ROM in hex.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    // File of command line flags to run the ROM with, ahead of those given on the
+    // command line, with `#` comments.
+    pub fn settings_path(&self) -> &Path {
+        &self.settings
+    }
+
+    // Flags from the settings file, or none if there isn't one.
+    pub fn settings(&self) -> io::Result<Vec<String>> {
+        let source = match fs::read_to_string(&self.settings) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error),
+        };
+
+        Ok(source.lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+            .map(str::to_string)
+            .collect())
+    }
+
+    // Set a flag in the settings file, in place of any line setting it already, so
+    // that the ROM runs with it from then on. Other lines and comments are kept.
+    pub fn save_setting(&self, flag: &str, value: &str) -> io::Result<()> {
+        let source = match fs::read_to_string(&self.settings) {
+            Ok(source) => source,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+
+        let sets_flag = |line: &str| {
+            line.split_whitespace().next()
+                .is_some_and(|first| first == flag || first.strip_prefix(flag).is_some_and(|rest| rest.starts_with('=')))
+        };
+
+        let mut lines: Vec<&str> = source.lines().filter(|line| !sets_flag(line)).collect();
+        let setting = format!("{} {}", flag, value);
+        lines.push(&setting);
+
+        if let Some(parent) = self.settings.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        write_atomic(&self.settings, lines.join("\n") + "\n")
+    }
+
+    pub fn rpl_flags_path(&self) -> PathBuf {
+        self.dir.join("rpl")
+    }
+
+    pub fn save_state_path(&self, slot: u8) -> PathBuf {
+        self.dir.join("states").join(format!("{}.state", slot))
+    }
+
+    // Kept apart from the numbered slots, so resuming never overwrites a save.
+    pub fn auto_save_path(&self) -> PathBuf {
+        self.dir.join("states").join("auto.state")
+    }
+
+    // Save a snapshot to a slot, replacing whatever was there, returning the path
+    // it was written to.
+    pub fn write_state(&self, slot: u8, snapshot: &Snapshot) -> Result<PathBuf, SnapshotError> {
+        write_snapshot(self.save_state_path(slot), snapshot)
+    }
+
+    pub fn read_state(&self, slot: u8) -> Result<Snapshot, SnapshotError> {
+        match fs::read(self.save_state_path(slot)) {
+            Ok(bytes) => Snapshot::decode(&bytes),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(SnapshotError::EmptySlot(slot)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn delete_state(&self, slot: u8) -> Result<(), SnapshotError> {
+        match fs::remove_file(self.save_state_path(slot)) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(SnapshotError::EmptySlot(slot)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn write_auto_save(&self, snapshot: &Snapshot) -> Result<PathBuf, SnapshotError> {
+        write_snapshot(self.auto_save_path(), snapshot)
+    }
+
+    // The snapshot written when the ROM last shut down, if there is one.
+    pub fn read_auto_save(&self) -> Result<Option<Snapshot>, SnapshotError> {
+        match fs::read(self.auto_save_path()) {
+            Ok(bytes) => Snapshot::decode(&bytes).map(Some),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn delete_auto_save(&self) -> io::Result<()> {
+        match fs::remove_file(self.auto_save_path()) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    // Slots from 1 to SLOTS holding a save state, and when each was saved, in
+    // seconds since the Unix epoch. Unreadable states are left out.
+    pub fn states(&self) -> Vec<(u8, u64)> {
+        (1..=SLOTS)
+            .filter_map(|slot| Some((slot, self.read_state(slot).ok()?.saved_at)))
+            .collect()
+    }
+
+    pub fn replay_dir(&self) -> PathBuf {
+        self.dir.join("replays")
+    }
+}
+
+fn write_snapshot(path: PathBuf, snapshot: &Snapshot) -> Result<PathBuf, SnapshotError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    write_atomic(&path, snapshot.encode())?;
+    Ok(path)
+}
+
+// Write a file whole or not at all, by writing it alongside and renaming it into
+// place, so being stopped partway never leaves a file cut short.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)
+}
+
+// How long ago a time in seconds since the Unix epoch was, such as `5m ago`.
+pub fn age(saved_at: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let seconds = now.saturating_sub(saved_at);
+
+    match seconds {
+        0..60 => format!("{}s ago", seconds),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::BTreeMap, error::Error, fmt, fs, io, iter, path::Path};
+
+use crate::system::CallFrame;
+
+#[derive(Debug)]
+pub enum SymbolsError {
+    Io(io::Error),
+    // Line that isn't an address and a label
+    Parse(usize),
+}
+
+impl fmt::Display for SymbolsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolsError::Io(error) => write!(f, "{}", error),
+            SymbolsError::Parse(line) => write!(f, "line {}: expected an address and a label", line),
+        }
+    }
+}
+
+impl Error for SymbolsError {}
+
+// Labels for addresses in a program, so that addresses can be shown the way they
+// are written in its source. Symbol files hold one `0x2A4 draw_player` pair per
+// line, in either order, with `#` comments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    labels: BTreeMap<usize, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(path: &Path) -> Result<Self, SymbolsError> {
+        Self::parse(&fs::read_to_string(path).map_err(SymbolsError::Io)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, SymbolsError> {
+        let mut symbols = Self::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            let parts: Vec<&str> = code.split_whitespace().collect();
+
+            match parts[..] {
+                [] => continue,
+                [first, second] => match (parse_addr(first), parse_addr(second)) {
+                    (Some(addr), _) => symbols.insert(addr, second),
+                    (None, Some(addr)) => symbols.insert(addr, first),
+                    (None, None) => return Err(SymbolsError::Parse(index + 1)),
+                },
+                _ => return Err(SymbolsError::Parse(index + 1)),
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    // Label an address, replacing any label it already had.
+    pub fn insert(&mut self, addr: usize, label: &str) {
+        self.labels.insert(addr, label.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn label(&self, addr: usize) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    // Address of a label, for looking up breakpoints and the like by name.
+    pub fn addr(&self, label: &str) -> Option<usize> {
+        self.labels.iter().find(|(_, name)| *name == label).map(|(&addr, _)| addr)
+    }
+
+    // An address relative to the closest label at or before it, such as
+    // `draw_player+0x4`, or in hex if there is none.
+    pub fn describe(&self, addr: usize) -> String {
+        match self.labels.range(..=addr).next_back() {
+            Some((&start, label)) if start == addr => label.clone(),
+            Some((&start, label)) => format!("{}+0x{:X}", label, addr - start),
+            None => format!("0x{:03X}", addr),
+        }
+    }
+
+    // Where execution is, followed by the call sites it is nested in, such as
+    // `draw_score+0x4 ← game_loop+0x12 ← main+0x6`.
+    pub fn backtrace(&self, pc: usize, calls: &[CallFrame]) -> String {
+        let call_sites = calls.iter().rev().map(|call| call.call_site);
+
+        iter::once(pc)
+            .chain(call_sites)
+            .map(|addr| self.describe(addr))
+            .collect::<Vec<_>>()
+            .join(" ← ")
+    }
+}
+
+// Written in the same format it is read from.
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, label) in &self.labels {
+            writeln!(f, "0x{:03X} {}", addr, label)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_addr(text: &str) -> Option<usize> {
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))?;
+    usize::from_str_radix(hex, 16).ok()
+}
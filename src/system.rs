@@ -1,37 +1,187 @@
 use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
     sync::{
         atomic,
-        mpmc,
+        mpsc::Receiver,
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
-use ctrlc;
-use rand::{self, Rng};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    config::Config, devices::{
-        create_audio_device, create_display_device, create_input_device, Audio, DeviceEvent, Display, Input, Key
-    }, instructions::Instruction, memory::Memory, timer::Timer
+    bus::{AudioEvent, ControlEvent, CoreEvent, DisplayEvent, EventBus}, config::{Colors, Config, ExitAfter, Profile, VblankModel}, cpu::{Cpu, Effect, Peripherals, Quirks, QuirksConfig}, devices::{
+        create_audio_device, create_display_device, create_input_device, null::{NullAudio, NullInput}, orient_display, Audio, Display, Input, Key, KeyEvent, MergedInput
+    }, cache::InstructionCache, chip8x::Chip8X, crash::{self, CrashReport}, frame::FrameBuffer, history::{Delta, History}, instructions::Instruction, loader::{self, RomError}, megachip::{self, MegaChip}, memory::{Access, Memory, MemoryError}, menu::{self, Demo, Menu}, patch::Patch, postprocess::{post_process, PostProcess}, scope::SoundScope, snapshot::{Snapshot, SnapshotError}, storage::{self, RomStorage, Storage}, timer::Timer, timing::TimingStats, watch::RomWatcher, watches::{Watch, WatchExpr, Watches}
 };
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+
+pub use crate::cpu::{CallFrame, ExecutionError};
+
+// Resolution and entry point of programs for the hi-res interpreter.
+const HIRES_WIDTH: usize = 64;
+const HIRES_HEIGHT: usize = 64;
+const HIRES_ENTRY_POINT: usize = 0x2C0;
+
+// XO-CHIP pitch at which audio patterns play at 4000 samples per second.
+const DEFAULT_PITCH: u8 = 64;
+
+// Range of multiples of the clock speed a machine can be run at.
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 8.0;
+
+// Range of device pixels per virtual pixel the display can be scaled to at runtime.
+pub const MIN_SCALE: usize = 1;
+pub const MAX_SCALE: usize = 40;
+
+// Frames a message stays on screen for.
+const MESSAGE_FRAMES: u32 = 120;
+
+// How long the ROM menu sits idle before playing demos, and how long each demo
+// plays for before the next.
+const ATTRACT_DELAY: Duration = Duration::from_secs(30);
+const DEMO_LENGTH: Duration = Duration::from_secs(30);
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Lag behind real time the run loop makes up by running cycles without sleeping,
+// beyond which it gives up on catching up and carries on from the present.
+const MAX_DEFICIT: Duration = Duration::from_millis(100);
+
+// Longest a run stopped from a signal handler has to shut down cleanly, writing its
+// recordings and auto-save, before the process exits regardless
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Exit status of a process ended by Ctrl-C, as shells report it
+const INTERRUPTED_STATUS: i32 = 130;
+
+// Shared flag used to ask a running machine to stop.
+#[derive(Clone, Default)]
+pub struct StopToken(Arc<atomic::AtomicBool>);
+
+// Progress of FX0A, which completes once a key is pressed and then released.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyWait {
+    // Waiting for a key to be pressed, to store in the register
+    Press(usize),
+    // Waiting for the key pressed to be released
+    Release(usize, Key),
+}
+
+// What the machine is doing, as shown in the window title.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Running,
+    Paused,
+    // Running faster or slower than the clock speed
+    FastForward,
+    SlowMotion,
+    // Stepped back through the history, and not yet run forwards again
+    Rewinding,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Running => write!(f, "running"),
+            Mode::Paused => write!(f, "paused"),
+            Mode::FastForward => write!(f, "fast-forward"),
+            Mode::SlowMotion => write!(f, "slow motion"),
+            Mode::Rewinding => write!(f, "rewinding"),
+        }
+    }
+}
+
+impl StopToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Ask to stop from a signal handler, such as on Ctrl-C. The run then has the
+    // timeout given to shut down before the process exits regardless, and asking
+    // again exits straight away. Files are written whole or not at all, so exiting
+    // partway through shutting down leaves the last ones written intact.
+    pub fn stop_or_exit(&self, timeout: Duration) {
+        if self.is_stopped() {
+            warn!("Exiting without shutting down");
+            process::exit(INTERRUPTED_STATUS);
+        }
+
+        self.stop();
+
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            error!("Shutting down took longer than {:?}, exiting", timeout);
+            process::exit(INTERRUPTED_STATUS);
+        });
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(atomic::Ordering::SeqCst)
+    }
+
+    // Withdraw the request to stop, for tokens that interrupt more than one run.
+    pub fn clear(&self) {
+        self.0.store(false, atomic::Ordering::SeqCst);
+    }
+}
+
+type InstructionHook = Box<dyn FnMut(usize, &Instruction)>;
+type MemoryAccessHook = Box<dyn FnMut(Range<usize>, Access)>;
+type ShutdownHook = Box<dyn FnOnce(&ChipEight)>;
+type QuirksHook = Box<dyn FnMut(usize, &Instruction, &mut QuirksConfig)>;
+type FrameHook = Box<dyn FnMut(&[bool])>;
+type SoundChangeHook = Box<dyn FnMut(bool)>;
+
+// Makes a fresh post-processing stage for each display, as stages keep state from
+// one frame to the next.
+type StageFactory = Box<dyn Fn() -> Box<dyn PostProcess>>;
+
+// Callbacks registered by embedders, invoked from the run loop. Walking an empty
+// list is just a length check, so unused hooks cost nothing.
+#[derive(Default)]
+struct Hooks {
+    instruction: Vec<InstructionHook>,
+    memory_access: Vec<MemoryAccessHook>,
+    quirks: Vec<QuirksHook>,
+    frame: Vec<FrameHook>,
+    sound_change: Vec<SoundChangeHook>,
+    shutdown: Vec<ShutdownHook>,
+}
 
 pub struct ChipEight {
     // General configuration
     config: Config,
 
-    // Stack containing addressess used to call/return from functions and subroutines.
-    stack: Vec<usize>,
+    // Registers, stack, memory and frame buffer, and the executor of the instructions
+    // the extensions share.
+    cpu: Cpu,
 
-    // Program counter which points to the current instruction in memory.
-    pc: usize,
-    
-    // 16 8-bit general purpose variable registers.
-    v: [u8; 16],
+    // Last completed frame, copied from the CPU's frame buffer at each display
+    // interrupt so displays never see a sprite half drawn, and how many frames have
+    // been completed.
+    front: FrameBuffer,
+    frame_count: u64,
 
-    // Index register to point at locations in memory.
-    i: usize,
+    // Hash of the last completed frame, and how many frames in a row have matched
+    // the one before, counted from the first frame with anything drawn on it.
+    frame_hash: u64,
+    stable_frames: Option<u64>,
 
     // Delay timer which is decremented at a rate of 60 Hz until it reaches 0. Can
     // be set and read.
@@ -41,383 +191,2405 @@ pub struct ChipEight {
     // sound when the value is not 0.
     sound: Timer,
 
-    // Memory model
-    memory: Memory,
+    // Colour mode state, when running the MegaChip profile.
+    megachip: Option<MegaChip>,
 
-    // Frame data used to determine what to draw to each pixel, as
-    // well as whether drawing a pixel resulted in a collision.
-    frame_buffer: Vec<bool>,
+    // Colour attributes, when running the CHIP-8X profile.
+    chip8x: Option<Chip8X>,
 
-    // MPSC receiver for device events
-    device_channel: (mpmc::Sender<DeviceEvent>, mpmc::Receiver<DeviceEvent>),
+    // Queues of events to and from the devices
+    bus: EventBus,
 
-    // Devices
-    display: Option<Box<dyn Display>>,
-    audio: Option<Box<dyn Audio>>,
-    input: Option<Box<dyn Input>>,
+    // Devices, with frames drawn on every display
+    displays: Vec<Box<dyn Display>>,
+    audio: Box<dyn Audio>,
+    input: Box<dyn Input>,
+
+    // Keys held down, going by the key events so far.
+    keys_down: Vec<Key>,
+
+    // Keys held with tap_key_for, and the display interrupts until each is released
+    taps: Vec<(Key, u64)>,
+
+    // Progress of FX0A, which holds execution until it completes.
+    key_wait: Option<KeyWait>,
+
+    // Whether the program reached the exit opcode.
+    exited: bool,
+
+    // Address of the machine call execution is held on, under --machine-calls trap.
+    trap: Option<usize>,
+
+    // Name of the ROM loaded, and the window title last shown for it.
+    rom_name: Option<String>,
+    title: String,
+
+    // Whether step_back has undone instructions since the last one executed.
+    rewinding: bool,
+
+    // Whether the sound timer is currently producing a tone.
+    sound_playing: bool,
+
+    // XO-CHIP audio pattern replacing the tone, once loaded, and its playback pitch.
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+
+    // Observer callbacks
+    hooks: Hooks,
+
+    // Decoded instructions, invalidated by writes to memory.
+    cache: InstructionCache,
+
+    // Undo information for the latest instructions, when enabled.
+    history: Option<History>,
+
+    // Written over each ROM once loaded.
+    patch: Patch,
+
+    // Memory held at a value, rewritten after every cycle.
+    freezes: BTreeMap<usize, u8>,
+
+    // Where save states go, and the place for those of the loaded ROM.
+    storage: Option<Storage>,
+    rom_storage: Option<RomStorage>,
+
+    // Whether loading a ROM resumes its auto-save, and shutting down writes it.
+    resume: bool,
+    // Whether the machine has shut down, which it only does once
+    shut_down: bool,
+
+    // Latest instructions executed, for crash reports when enabled.
+    trace: Option<VecDeque<(usize, Instruction)>>,
+
+    // Save state slot the hotkeys save to and load from.
+    slot: u8,
+
+    // Text shown over the frame, and for how many more frames.
+    message: Option<(String, u32)>,
+
+    // Values shown over the frame, with their history.
+    watches: Watches,
+
+    // Sound shown over the frame when enabled, following the machine's events.
+    sound_scope: Option<(SoundScope, Receiver<CoreEvent>)>,
+
+    // Compiled blocks of instructions, when enabled.
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
+
+    // Source of CXNN's random numbers, reseeded on reset when a seed was given.
+    rng: StdRng,
+    seed: Option<u64>,
+
+    // Ends the run loop once stopped.
+    stop: StopToken,
+
+    // Multiple of the clock speed the run loop paces instructions at.
+    speed: f64,
+
+    // Master volume as a percentage, and whether it's muted.
+    volume: u8,
+    muted: bool,
+
+    // Whether the run loop is holding the machine still, timers included.
+    paused: bool,
+
+    // Colours and scale factor the displays are using, which the hotkeys change.
+    colors: [(u8, u8, u8); 2],
+    scale_factor: usize,
+
+    // Post-processing stages of the embedder's own, for each display added.
+    post_process: Vec<StageFactory>,
+
+    // How closely the run loop is keeping to real time.
+    timing: TimingStats,
 }
 
 impl From<Config> for ChipEight {
     fn from(config: Config) -> Self {
-        let (device_tx, device_rx) = mpmc::channel();
+        ChipEightBuilder::new(config).build()
+    }
+}
+
+// Assembles a ChipEight, using any user-provided devices in place of the engines
+// selected in the config.
+pub struct ChipEightBuilder {
+    config: Config,
+    displays: Vec<Box<dyn Display>>,
+    audio: Option<Box<dyn Audio>>,
+    inputs: Vec<Box<dyn Input>>,
+    seed: Option<u64>,
+    history: Option<usize>,
+    patch: Patch,
+    storage: Option<Storage>,
+    resume: bool,
+    crash_reports: bool,
+    post_process: Vec<StageFactory>,
+    #[cfg(feature = "jit")]
+    jit: bool,
+}
 
+impl ChipEightBuilder {
+    pub fn new(config: Config) -> Self {
         Self {
-            stack: Vec::new(),
-            pc: config.memory.program_start, 
-            v: [0; 16],
-            i: 0,
+            config,
+            displays: vec![],
+            audio: None,
+            inputs: vec![],
+            seed: None,
+            history: None,
+            patch: Patch::new(),
+            storage: None,
+            resume: false,
+            crash_reports: false,
+            post_process: vec![],
+            #[cfg(feature = "jit")]
+            jit: false,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // Draw frames on a display, alongside any others added. The display engine in
+    // the config is only opened when none are.
+    pub fn with_display(mut self, display: Box<dyn Display>) -> Self {
+        self.displays.push(display);
+        self
+    }
+
+    pub fn with_audio(mut self, audio: Box<dyn Audio>) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    // Take key presses from an input device, alongside any others added. The input
+    // engine in the config is only opened when none are.
+    pub fn with_input(mut self, input: Box<dyn Input>) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    // Seed the random number generator, so the same inputs always produce the same run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Remember the changes made by up to capacity of the latest instructions, so that
+    // step_back can undo them one at a time.
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = Some(capacity);
+        self
+    }
+
+    // Write a patch over every ROM once it is loaded.
+    pub fn with_patch(mut self, patch: Patch) -> Self {
+        self.patch = patch;
+        self
+    }
+
+    // Keep save states for each ROM loaded under the storage's data directory.
+    pub fn with_storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    // Carry on from where each ROM was left when it is loaded, if it was auto-saved
+    // when the machine last shut down, and auto-save it again on shutdown. Needs the
+    // storage to keep the auto-saves in.
+    pub fn with_resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    // Write a crash report under the storage's data directory when the machine
+    // hits a fault it can't carry on from, keeping a trace of the latest
+    // instructions for it.
+    pub fn with_crash_reports(mut self) -> Self {
+        self.crash_reports = true;
+        self
+    }
+
+    // Pass frames through a post-processing stage of its own before they're shown,
+    // after any stages from the config, in the order they're added. Each display is
+    // given a stage of its own from the factory.
+    pub fn with_post_process(mut self, factory: impl Fn() -> Box<dyn PostProcess> + 'static) -> Self {
+        self.post_process.push(Box::new(factory));
+        self
+    }
+
+    // Compile runs of register instructions to native code, for run_cycles to
+    // execute as a block. Falls back to the interpreter where cranelift doesn't
+    // support the host.
+    #[cfg(feature = "jit")]
+    pub fn with_jit(mut self) -> Self {
+        self.jit = true;
+        self
+    }
+
+    pub fn build(self) -> ChipEight {
+        let config = self.config;
+        let mut bus = EventBus::new();
+
+        // The scope hears about sound the way subscribers do
+        let sound_scope = config.sound_overlay.then(|| (SoundScope::new(), bus.subscribe()));
+
+        debug!("Building {:?} machine at {}Hz with {:?}", config.profile, config.clock_speed, config.quirks);
+
+        let mut cpu = Cpu::new(
+            config.quirks,
+            config.memory.clone(),
+            config.profile,
+            config.display.width,
+            config.display.height,
+        );
+        cpu.frame_buffer.set_palette(config.display.colors);
+        let cache = InstructionCache::new();
+        cpu.memory.on_write({
+            let cache = cache.clone();
+            move |range| cache.invalidate(range)
+        });
+
+        #[cfg(feature = "jit")]
+        let jit = self.jit.then(Jit::new).flatten();
+        #[cfg(feature = "jit")]
+        match &jit {
+            Some(jit) => cpu.memory.on_write(jit.invalidator()),
+            None if self.jit => warn!("JIT not supported on this host, interpreting instead"),
+            None => (),
+        }
+
+        let front = cpu.frame_buffer.clone();
+
+        let mut displays = self.displays;
+
+        if displays.is_empty() {
+            displays.push(create_display_device(config.display.clone()));
+        }
+
+        let mut inputs = self.inputs;
+
+        if inputs.is_empty() {
+            inputs.push(create_input_device(config.input.clone(), bus.control_sender()));
+        }
+
+        let mut chip_eight = ChipEight {
+            cpu,
+            front,
+            frame_count: 0,
+            frame_hash: 0,
+            stable_frames: None,
             delay: Timer::new(None),
-            sound: Timer::new(Some(device_tx.clone())),
-            memory: Memory::new(config.memory.clone()),
-            frame_buffer: vec![false; config.display.width * config.display.height],
-            device_channel: (device_tx, device_rx),
-            display: create_display_device(config.display.clone()),
-            audio: create_audio_device(config.audio.clone()),
-            input: create_input_device(config.input.clone()),
+            sound: Timer::new(Some(bus.audio_sender())),
+            megachip: (config.profile == Profile::MegaChip).then(MegaChip::new),
+            chip8x: (config.profile == Profile::Chip8X).then(|| Chip8X::new(config.display.width, config.display.height)),
+            displays: vec![],
+            audio: self.audio.unwrap_or_else(|| create_audio_device(config.audio.clone())),
+            input: match inputs.len() {
+                1 => inputs.remove(0),
+                _ => Box::new(MergedInput::new(inputs)),
+            },
+            bus,
+            keys_down: vec![],
+            taps: vec![],
+            key_wait: None,
+            exited: false,
+            trap: None,
+            rom_name: None,
+            title: String::new(),
+            rewinding: false,
+            sound_playing: false,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            hooks: Hooks::default(),
+            cache,
+            history: self.history.map(History::new),
+            patch: self.patch,
+            freezes: BTreeMap::new(),
+            storage: self.storage,
+            rom_storage: None,
+            resume: self.resume,
+            shut_down: false,
+            trace: self.crash_reports.then(|| VecDeque::with_capacity(crash::TRACE_LEN)),
+            slot: 1,
+            message: None,
+            watches: Watches::new(&config.watches),
+            sound_scope,
+            #[cfg(feature = "jit")]
+            jit,
+            rng: seeded_rng(self.seed),
+            seed: self.seed,
+            stop: StopToken::new(),
+            speed: 1.0,
+            volume: config.audio.volume,
+            muted: config.audio.muted,
+            paused: false,
+            colors: config.display.colors,
+            scale_factor: config.display.scale_factor,
+            post_process: self.post_process,
+            timing: TimingStats::new(config.clock_speed),
             config,
+        };
+
+        for display in displays {
+            chip_eight.add_display(display);
         }
+
+        chip_eight.apply_volume();
+        chip_eight
     }
 }
 
 impl ChipEight {
-    pub fn play(&mut self, rom: &[u8]) {
-        let running = Arc::new(atomic::AtomicBool::new(true));
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        let running_clone = running.clone();
-        ctrlc::set_handler(move || {
-            println!("\nShutting down...");
-            running_clone.store(false, atomic::Ordering::SeqCst);
-        }).expect("Failed to set Ctrl-C handler");
+    // Quirks the next instruction executes with, which start out as those configured
+    // but can be changed while the machine runs.
+    pub fn quirks(&self) -> QuirksConfig {
+        self.cpu.quirks()
+    }
 
-        // Store default font
-        self.memory.write_buf(self.config.memory.font_start, &self.config.memory.default_font).unwrap_or_else(|error| {
-            panic!("Failed to load default font: {}", error);
-        });
+    // Change the quirks from the next instruction on.
+    pub fn set_quirks(&mut self, quirks: QuirksConfig) {
+        self.cpu.quirks_handle().set(quirks);
+    }
 
-        // Store ROM
-        self.memory.write_buf(self.config.memory.program_start, rom).unwrap_or_else(|error| {
-            panic!("Failed to load rom: {}", error);
-        });
+    // Handle to the quirks, for changing them from elsewhere, such as a script or UI.
+    pub fn quirks_handle(&self) -> Quirks {
+        self.cpu.quirks_handle()
+    }
 
-        let (device_tx, device_rx) = &self.device_channel;
-        let should_draw = Arc::new(atomic::AtomicBool::new(false));
+    // General purpose registers v0 to vF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.cpu.v
+    }
 
-        let running_clone = running.clone();
-        let device_tx_clone = device_tx.clone();
-        let should_draw_clone = should_draw.clone();
-        thread::spawn(move || {
-            let tick_duration = Duration::from_millis(1000 / 60); // 60hz
-            
-            while running_clone.load(atomic::Ordering::SeqCst) {
-                device_tx_clone.send(DeviceEvent::Draw)
-                    .expect("Failed to send draw event");
+    // Set one of v0 to vF. Only the low nibble of reg is used, as in an opcode, so
+    // any register number is safe to pass.
+    pub fn set_register(&mut self, reg: u8, value: u8) {
+        self.cpu.v[(reg & 0xF) as usize] = value;
+    }
 
-                let _ = should_draw_clone.compare_exchange(
-                    false,
-                    true,
-                    atomic::Ordering::Acquire,
-                    atomic::Ordering::SeqCst,
-                );
+    pub fn pc(&self) -> usize {
+        self.cpu.pc
+    }
 
-                thread::sleep(tick_duration);
-            }
-        });
+    // Whether the program stopped the machine by reaching the exit opcode.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
 
-        while running.load(atomic::Ordering::SeqCst) {
-            // Handle device events
-            if let Ok(event) = device_rx.try_recv() {
-                match event {
-                    DeviceEvent::Draw => if let Some(display) = &mut self.display {
-                        display.draw(&self.frame_buffer);
-                    },
-                    DeviceEvent::PlayTone => if let Some(audio) = &self.audio {
-                        audio.play_tone();
-                    },
-                    DeviceEvent::StopTone => if let Some(audio) = &self.audio {
-                        audio.stop_tone();
-                    },
-                }
-            }
+    // Keys held down, going by the key events so far.
+    pub fn keys_down(&self) -> &[Key] {
+        &self.keys_down
+    }
 
-            let keys_down = if let Some(input) = &mut self.input {
-                input.get_keys_down()
-            } else {
-                vec![]
-            };
+    // Progress of an FX0A waiting on the keypad.
+    pub fn key_wait(&self) -> Option<KeyWait> {
+        self.key_wait
+    }
 
-            // Fetch and decode current instruction
-            let parts = self.memory.read_buf(self.pc, 2).unwrap_or_else(|error| {
-                panic!("Failed to fetch instruction: {}", error);
-            });
-            let opcode = ((parts[0] as u16) << 8) | parts[1] as u16;
-            let instruction: Instruction = opcode
-                .try_into()
-                .unwrap_or_else(|error| {
-                    panic!("Failed to parse instruction: {}", error);
-                });
+    // Address of the machine call execution is held on, when trapping them. The
+    // program counter stays on the call until resume_trap.
+    pub fn trapped(&self) -> Option<usize> {
+        self.trap
+    }
 
-            // Increment PC to point to next instruction
-            self.pc += 2;
+    // Carry on past the machine call trapped on, as if it were ignored.
+    pub fn resume_trap(&mut self) {
+        if self.trap.take().is_some() {
+            self.cpu.pc += 2;
+        }
+    }
 
-            // Execute instruction
-            match instruction {
-                Instruction::Clear => {
-                    self.frame_buffer.fill(false);
+    // Whether a sprite draw is holding execution until the next vblank.
+    pub fn waiting_for_vblank(&self) -> bool {
+        self.cpu.waiting_for_vblank
+    }
 
-                    device_tx.send(DeviceEvent::Draw)
-                        .expect("Failed to send draw event");
-                },
-                Instruction::Return => {
-                    self.pc = self.stack.pop()
-                        .expect("Failed to return from subroutine: stack is empty");
-                },
-                Instruction::Jump(addr) => self.pc = addr,
-                Instruction::Call(addr) => {
-                    self.stack.push(self.pc);
-                    self.pc = addr;
-                }
-                Instruction::IfVxEq(reg, val) => {
-                    if self.v[reg] == val {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::IfVxNotEq(reg, val) => {
-                    if self.v[reg] != val {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::IfVxEqVy(reg_x, reg_y) => {
-                    if self.v[reg_x] == self.v[reg_y] {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::SetVx(reg, val) => self.v[reg] = val,
-                Instruction::AddToVx(reg, val) => self.v[reg] = self.v[reg].wrapping_add(val),
-                Instruction::SetVxToVy(reg_x, reg_y) => self.v[reg_x] = self.v[reg_y],
-                Instruction::SetVxOrVy(reg_x, reg_y) => {
-                    self.v[reg_x] |= self.v[reg_y];
-
-                    if !self.config.quirks.skip_reset_vf {
-                        self.v[0xF] = 0;
-                    }
-                },
-                Instruction::SetVxAndVy(reg_x, reg_y) => {
-                    self.v[reg_x] &= self.v[reg_y];
+    pub fn index(&self) -> usize {
+        self.cpu.i
+    }
 
-                    if !self.config.quirks.skip_reset_vf {
-                        self.v[0xF] = 0;
-                    }
-                },
-                Instruction::SetVxXorVy(reg_x, reg_y) => {
-                    self.v[reg_x] ^= self.v[reg_y];
+    pub fn memory(&self) -> &Memory {
+        &self.cpu.memory
+    }
 
-                    if !self.config.quirks.skip_reset_vf {
-                        self.v[0xF] = 0;
-                    }
-                },
-                Instruction::AddVyToVx(reg_x, reg_y) => {
-                    let (result, overflowed) = self.v[reg_x].overflowing_add(self.v[reg_y]);
-                    self.v[reg_x] = result;
-                    self.v[0xF] = overflowed.into();
-                },
-                Instruction::SubVyFromVx(reg_x, reg_y) => {
-                    let (result, overflowed) = self.v[reg_x].overflowing_sub(self.v[reg_y]);
-                    self.v[reg_x] = result;
-                    self.v[0xF] = (!overflowed).into();
-                },
-                Instruction::RightShiftVx(reg_x, reg_y) => {
-                    let reg = if self.config.quirks.skip_shift_set {
-                        reg_x
-                    } else {
-                        reg_y
-                    };
-
-                    let bit = self.v[reg] & 1;
-                    self.v[reg_x] = self.v[reg] >> 1;
-                    self.v[0xF] = bit;
-                },
-                Instruction::SubVxFromVy(reg_x, reg_y) => {
-                    let (result, overflowed) = self.v[reg_y].overflowing_sub(self.v[reg_x]);
-                    self.v[reg_x] = result;
-                    self.v[0xF] = (!overflowed).into();
-                },
-                Instruction::LeftShiftVx(reg_x, reg_y) => {
-                    let reg = if self.config.quirks.skip_shift_set {
-                        reg_x
-                    } else {
-                        reg_y
-                    };
-
-                    let bit = (self.v[reg] >> 7) & 1;
-                    self.v[reg_x] = self.v[reg] << 1;
-                    self.v[0xF] = bit;
-                },
-                Instruction::IfVxNotEqVy(reg_x, reg_y) => {
-                    if self.v[reg_x] != self.v[reg_y] {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::SetI(addr) => self.i = addr,
-                Instruction::JumpWithOffset(addr) => {
-                    let offset = if self.config.quirks.jump_with_vx {
-                        self.v[(addr >> 8) & 0xF]
-                    } else {
-                        self.v[0]
-                    };
-
-                    self.pc = addr + offset as usize;
-                },
-                Instruction::SetVxRand(reg, val) => self.v[reg] = rand::rng().random::<u8>() & val,
-                Instruction::Draw(reg_x, reg_y, sprite_height) => {
-                    let config = &self.config.display;
-
-                    self.v[0xF] = 0;
-
-                    let x = self.v[reg_x] as usize % config.width;
-                    let y = self.v[reg_y] as usize % config.height;
-
-                    let sprite = self.memory
-                        .read_buf(self.i, sprite_height.into())
-                        .unwrap_or_else(|error| {
-                            panic!("Failed to fetch sprite: {}", error);
-                        });
-
-                    for (layer, byte) in sprite.iter().enumerate() {
-                        let mut current_y = y + layer;
-                        
-                        if !self.config.quirks.wrap_sprites {
-                            if current_y >= config.height {
-                                break;
-                            }
-                        } else {
-                            current_y = current_y % config.height;
-                        }
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.cpu.memory
+    }
 
+    // Return addresses, innermost call last.
+    pub fn stack(&self) -> &[usize] {
+        &self.cpu.stack
+    }
 
-                        for position in 0..8 {
-                            let mut current_x = x + position;
+    // Where each call on the stack was made from and to, innermost call last.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.cpu.calls
+    }
 
-                            if !self.config.quirks.wrap_sprites {
-                                if current_x >= config.width {
-                                    break;
-                                }
-                            } else {
-                                current_x = current_x % config.width;
-                            }
+    // Current values of the delay and sound timers, in that order.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay.get(), self.sound.get())
+    }
 
-                            let bit = (byte.reverse_bits() >> position) & 1;
+    // Frame buffer the program draws to, which can be part way through a frame.
+    // Displays are shown the last completed frame instead.
+    pub fn frame_buffer(&self) -> &[bool] {
+        &self.cpu.frame_buffer
+    }
 
-                            if bit != 0 {
-                                if let Some(pixel) = self.frame_buffer.get_mut(current_y * config.width + current_x) {
-                                    if *pixel {
-                                        self.v[0xF] = 1;
-                                    }
+    // Last frame completed at a display interrupt, as presented to the display.
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.front
+    }
 
-                                    *pixel = !*pixel;
-                                }
-                            }
-                        }
-                    }
+    // Number of frames completed so far.
+    pub fn frame_number(&self) -> u64 {
+        self.frame_count
+    }
 
-                    if !self.config.quirks.skip_draw_wait {
-                        loop {
-                            if running.load(atomic::Ordering::SeqCst) {
-                                if let Ok(true) = should_draw.compare_exchange(
-                                    true,
-                                    false,
-                                    atomic::Ordering::Acquire,
-                                    atomic::Ordering::Relaxed,
-                                ) {
-                                    break;
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                },
-                Instruction::IfKeyPressed(reg) => {
-                    let key = self.v[reg] & 0xF;
-
-                    if keys_down.contains(
-                        &Key::try_from(key)
-                            .expect("Attempted to check an invalid keycode")
-                    ) {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::IfKeyNotPressed(reg) => {
-                    let key = self.v[reg] & 0xF;
-
-                    if !keys_down.contains(
-                        &Key::try_from(key)
-                            .expect("Attempted to check an invalid keycode")
-                    ) {
-                        self.pc += 2;
-                    }
-                },
-                Instruction::SetVxToDelay(reg) => self.v[reg] = self.delay.get(),
-                Instruction::SetVxToKey(reg) => {
-                    if let Some(input) = &mut self.input {
-                        if let [key, ..] = keys_down.as_slice() {
-                            while running.load(atomic::Ordering::SeqCst) {
-                                if !input.get_keys_down().contains(key) {
-                                    break;
-                                }
-                            }
+    // Last completed frame as a packed bitfield, with rows padded to whole bytes and
+    // the leftmost pixel of each byte in its most significant bit.
+    pub fn frame_bits(&self) -> &[u8] {
+        self.front.bits()
+    }
 
-                            self.v[reg] = *key as u8;
-                        } else {
-                            self.pc -= 2;
-                        }
-                    } else {
-                        panic!("Attempt to wait for key press failed: no available input peripheral");
-                    }
-                },
-                Instruction::SetDelayToVx(reg) => self.delay.set(self.v[reg]),
-                Instruction::SetSoundToVx(reg) => self.sound.set(self.v[reg]),
-                Instruction::AddVxToI(reg) => self.i = self.i.wrapping_add(self.v[reg] as usize),
-                Instruction::SetIToCharInVx(reg) => self.i = self.config.memory.font_start + ((self.v[reg] & 0xF) * 5) as usize,
-                Instruction::StoreVxBCDAtI(reg) => {
-                    let mut value = self.v[reg];
-                    for index in (0..3).rev() {
-                        self.memory.write_byte(self.i + index, value % 10)
-                            .unwrap_or_else(|error| {
-                                panic!("Failed to store BCD digit to memory: {}", error);
-                            });
-                        
-                        value /= 10;
-                    }
-                },
-                Instruction::VDump(reg) => {
-                    for index in 0..=reg {
-                        self.memory.write_byte(self.i + index, self.v[index])
-                            .unwrap_or_else(|error| {
-                                panic!("Failed to store value in register to memory: {}", error);
-                            });
-                    }
+    // Last completed frame as RGBA8888 bytes in the display's colours.
+    pub fn frame_rgba(&self) -> &[u8] {
+        self.front.rgba()
+    }
 
-                    if !self.config.quirks.preserve_index {
-                        self.i += reg + 1;
-                    }
-                },
-                Instruction::VLoad(reg) => {
-                    for index in 0..=reg {
-                        let byte = self.memory.read_byte(self.i + index)
-                            .unwrap_or_else(|error| {
-                                panic!("Failed to load value from memory to register: {}", error);
-                            });
-                        self.v[index] = byte;
-                    }
+    // Width and height of the frame buffer, in that order.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.cpu.width, self.cpu.height)
+    }
 
-                    if !self.config.quirks.preserve_index {
-                        self.i += reg + 1;
-                    }
-                },
-            }
+    // Multiple of the clock speed the machine runs at.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
 
-            // Sleep to ensure roughly correct clock speed
-            thread::sleep(Duration::from_millis(1000 / self.config.clock_speed));
+    // Run at a multiple of the configured clock speed, between MIN_SPEED and MAX_SPEED.
+    // Display interrupts stay at 60Hz, with more or fewer instructions in between.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    // Master volume as a percentage, kept while muted.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    // Set the master volume as a percentage, up to 100.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+        self.apply_volume();
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&self) {
+        self.audio.set_volume(if self.muted { 0.0 } else { self.volume as f32 / 100.0 });
+    }
+
+    // Show the volume, or that it's muted, over the frame.
+    fn show_volume(&mut self) {
+        if self.muted {
+            self.show_message("MUTED".to_string());
+        } else {
+            self.show_message(format!("VOLUME {}", self.volume));
+        }
+    }
+
+    // Colours of unlit and lit pixels.
+    pub fn colors(&self) -> [(u8, u8, u8); 2] {
+        self.colors
+    }
+
+    // Change the colours of unlit and lit pixels from the next frame, keeping them
+    // in the ROM's settings.
+    pub fn set_colors(&mut self, colors: [(u8, u8, u8); 2]) {
+        self.colors = colors;
+        self.cpu.frame_buffer.set_palette(colors);
+        self.front.set_palette(colors);
+        self.front.mark_all_changed();
+
+        for display in &mut self.displays {
+            display.set_colors(colors);
+        }
+
+        let value = match Colors(colors).theme() {
+            Some(theme) => theme.to_string(),
+            None => colors.map(|(r, g, b)| format!("{:02X}{:02X}{:02X}", r, g, b)).join(","),
+        };
+        self.save_setting("--colors", &value);
+    }
+
+    // Switch to the colour theme after the current one, returning its name.
+    pub fn next_theme(&mut self) -> &'static str {
+        let (name, colors) = Colors(self.colors).next_theme();
+        self.set_colors(colors.0);
+
+        name
+    }
+
+    // Device pixels per virtual pixel.
+    pub fn scale_factor(&self) -> usize {
+        self.scale_factor
+    }
+
+    // Resize the display to a scale factor between MIN_SCALE and MAX_SCALE, keeping
+    // it in the ROM's settings.
+    pub fn set_scale_factor(&mut self, scale_factor: usize) {
+        self.scale_factor = scale_factor.clamp(MIN_SCALE, MAX_SCALE);
+
+        for display in &mut self.displays {
+            display.set_scale(self.scale_factor);
+        }
+
+        self.front.mark_all_changed();
+
+        self.save_setting("--scale-factor", &self.scale_factor.to_string());
+    }
+
+    // Keep a flag in the loaded ROM's settings, for display settings changed while
+    // it runs.
+    fn save_setting(&self, flag: &str, value: &str) {
+        let Some(rom_storage) = &self.rom_storage else {
+            return;
+        };
+
+        match rom_storage.save_setting(flag, value) {
+            Ok(()) => debug!("Saved {} {} to {}", flag, value, rom_storage.settings_path().display()),
+            Err(error) => error!("Failed to save settings to {}: {}", rom_storage.settings_path().display(), error),
+        }
+    }
+
+    // How closely the latest run kept to real time, for runs of play and run_async.
+    pub fn timing(&self) -> &TimingStats {
+        &self.timing
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Hold the machine still, or let it carry on: the run loops stop executing
+    // instructions, the timers stop counting down, and the tone is silenced.
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+
+        self.paused = paused;
+        self.update_title();
+
+        if self.sound_playing {
+            if paused {
+                self.audio.stop_tone();
+            } else {
+                self.audio.play_tone();
+            }
         }
     }
+
+    pub fn mode(&self) -> Mode {
+        if self.paused {
+            Mode::Paused
+        } else if self.rewinding {
+            Mode::Rewinding
+        } else if self.speed > 1.0 {
+            Mode::FastForward
+        } else if self.speed < 1.0 {
+            Mode::SlowMotion
+        } else {
+            Mode::Running
+        }
+    }
+
+    // Name the ROM in the window title, such as after its file.
+    pub fn set_rom_name(&mut self, name: &str) {
+        self.rom_name = Some(name.to_string());
+        self.update_title();
+    }
+
+    // Title for the machine's window, such as "chip-eight — Pong (chip8, 600 IPS)",
+    // with the mode added when not simply running.
+    pub fn title(&self) -> String {
+        let profile = self.config.profile.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+
+        let rate = if self.config.unthrottled() {
+            "unlimited".to_string()
+        } else {
+            format!("{} IPS", (self.config.clock_speed as f64 * self.speed).round())
+        };
+
+        let mode = match self.mode() {
+            Mode::Running => String::new(),
+            mode => format!(", {}", mode),
+        };
+
+        match &self.rom_name {
+            Some(name) => format!("chip-eight \u{2014} {} ({}, {}{})", name, profile, rate, mode),
+            None => format!("chip-eight ({}, {}{})", profile, rate, mode),
+        }
+    }
+
+    // Show the title on the display, when it has changed.
+    fn update_title(&mut self) {
+        if self.displays.is_empty() {
+            return;
+        }
+
+        let title = self.title();
+
+        if title != self.title {
+            for display in &mut self.displays {
+                display.set_title(&title);
+            }
+
+            self.title = title;
+        }
+    }
+
+    // Number of cycles between display interrupts at the current speed
+    pub fn cycles_per_frame(&self) -> u64 {
+        ((self.config.cycles_per_frame() as f64 * self.speed).round() as u64).max(1)
+    }
+
+    // Time a cycle should take at the current speed
+    fn cycle_duration(&self) -> Duration {
+        if self.config.unthrottled() {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(1.0 / (self.config.clock_speed as f64 * self.speed))
+        }
+    }
+
+    // Reseed the random number generator, as with_seed does, for hosts that only
+    // learn the seed once the machine is built.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = seeded_rng(self.seed);
+    }
+
+    // Handle for stopping this machine's run loop from another thread or a signal
+    // handler. Once stopped, run loops return immediately.
+    pub fn stop_handle(&self) -> StopToken {
+        self.stop.clone()
+    }
+
+    // Reset and reload the ROM at path whenever its file changes, for as long as the
+    // returned watcher is kept.
+    pub fn watch_rom(&self, path: &Path) -> notify::Result<RomWatcher> {
+        RomWatcher::new(path, self.bus.control_sender())
+    }
+
+    // Register a callback invoked with the address and decoded form of every
+    // instruction, just before it is executed.
+    pub fn on_instruction(&mut self, callback: impl FnMut(usize, &Instruction) + 'static) {
+        self.hooks.instruction.push(Box::new(callback));
+    }
+
+    // Register a callback invoked with each range of memory an instruction fetches,
+    // reads or writes, just before it is executed. Accesses made other than by
+    // instructions, such as loading a ROM or saving a state, aren't reported.
+    pub fn on_memory_access(&mut self, callback: impl FnMut(Range<usize>, Access) + 'static) {
+        self.hooks.memory_access.push(Box::new(callback));
+    }
+
+    // Register a callback invoked with the address and decoded form of every
+    // instruction, just before it is executed, and the quirks it is about to execute
+    // with, to change for that instruction alone. Callbacks see the changes of those
+    // registered before them.
+    pub fn on_quirks(&mut self, callback: impl FnMut(usize, &Instruction, &mut QuirksConfig) + 'static) {
+        self.hooks.quirks.push(Box::new(callback));
+    }
+
+    // Register a callback invoked with the frame buffer each time a frame is presented.
+    pub fn on_frame(&mut self, callback: impl FnMut(&[bool]) + 'static) {
+        self.hooks.frame.push(Box::new(callback));
+    }
+
+    // Register a callback invoked with the machine when it shuts down, before the
+    // auto-save is written and the devices are dropped, for recorders to write out
+    // what they hold. Callbacks are invoked in the order registered.
+    pub fn on_shutdown(&mut self, callback: impl FnOnce(&ChipEight) + 'static) {
+        self.hooks.shutdown.push(Box::new(callback));
+    }
+
+    // Register a callback invoked with true when the sound timer becomes nonzero and the
+    // tone starts, and with false when it reaches zero and the tone stops.
+    pub fn on_sound_change(&mut self, callback: impl FnMut(bool) + 'static) {
+        self.hooks.sound_change.push(Box::new(callback));
+    }
+
+    // Draw frames on another display as well, from the next frame on, such as a
+    // recorder attached partway through a run. Displays are post-processed and
+    // oriented as configured, and caught up with the resolution, colours, scale and
+    // title in use.
+    pub fn add_display(&mut self, display: Box<dyn Display>) {
+        let config = &self.config.display;
+
+        let mut stages: Vec<Box<dyn PostProcess>> = config.post_process.iter().map(|stage| stage.build()).collect();
+        stages.extend(self.post_process.iter().map(|factory| factory()));
+
+        let mut display = orient_display(post_process(display, stages, config), config.clone());
+
+        if (self.cpu.width, self.cpu.height) != (config.width, config.height) {
+            display.set_resolution(self.cpu.width, self.cpu.height);
+        }
+
+        if self.colors != config.colors {
+            display.set_colors(self.colors);
+        }
+
+        if self.scale_factor != config.scale_factor {
+            display.set_scale(self.scale_factor);
+        }
+
+        if !self.title.is_empty() {
+            display.set_title(&self.title);
+        }
+
+        self.displays.push(display);
+        self.front.mark_all_changed();
+    }
+
+    // Subscribe to the machine's events: the tone starting and stopping, audio pattern
+    // changes, frames being completed, resolution changes and halts. Events are
+    // queued until received, from any thread, and the queue is closed when the
+    // machine is dropped.
+    pub fn events(&mut self) -> Receiver<CoreEvent> {
+        self.bus.subscribe()
+    }
+
+    fn set_sound_playing(&mut self, playing: bool) {
+        if self.sound_playing != playing {
+            self.sound_playing = playing;
+            self.bus.publish(if playing { CoreEvent::SoundStarted } else { CoreEvent::SoundStopped });
+
+            for hook in &mut self.hooks.sound_change {
+                hook(playing);
+            }
+        }
+    }
+
+    // Return the machine to its power-on state, discarding the loaded program.
+    pub fn reset(&mut self) {
+        debug!("Resetting machine");
+
+        self.cpu.reset();
+
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+
+        self.delay.set(0);
+        self.sound.set(0);
+
+        if let Some(megachip) = &mut self.megachip {
+            *megachip = MegaChip::new();
+            self.audio.stop_samples();
+        }
+
+        if let Some(chip8x) = &mut self.chip8x {
+            *chip8x = Chip8X::new(self.cpu.width, self.cpu.height);
+        }
+
+        if self.audio_pattern.take().is_some() {
+            self.pitch = DEFAULT_PITCH;
+            self.update_pattern();
+        }
+
+        self.key_wait = None;
+        self.exited = false;
+        self.trap = None;
+        self.stable_frames = None;
+        self.rng = seeded_rng(self.seed);
+    }
+
+    // Store the fonts and the given ROM in memory. Panics if the ROM doesn't fit,
+    // which try_load reports instead.
+    pub fn load(&mut self, rom: &[u8]) {
+        self.try_load(rom).unwrap_or_else(|error| {
+            panic!("Failed to load rom: {}", error);
+        });
+    }
+
+    // Same as load, failing when the ROM doesn't fit in memory.
+    pub fn try_load(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        loader::check_fits(rom, &self.config.memory)?;
+        self.cpu.load(rom).map_err(RomError::Memory)?;
+
+        debug!("Loaded {} byte ROM at 0x{:03X}", rom.len(), self.config.memory.program_start);
+
+        self.rom_storage = self.storage.as_ref().map(|storage| storage.rom(rom));
+        self.stable_frames = None;
+
+        if !self.patch.is_empty() {
+            match self.patch.apply(&mut self.cpu.memory) {
+                Ok(()) => debug!("Applied patch"),
+                Err(error) => error!("Failed to apply patch: {}", error),
+            }
+        }
+
+        // Programs for the 64x64 hi-res interpreter start by jumping over the code that
+        // patched it into the original, and really begin at 0x2C0
+        if self.config.profile == Profile::Chip8 && rom.starts_with(&[0x12, 0x60]) {
+            debug!("Detected hi-res program, entering at 0x{:03X}", HIRES_ENTRY_POINT);
+            self.set_resolution(HIRES_WIDTH, HIRES_HEIGHT);
+            self.cpu.pc = HIRES_ENTRY_POINT;
+        } else {
+            self.set_resolution(self.config.display.width, self.config.display.height);
+        }
+
+        if self.resume {
+            self.resume_auto_save();
+        }
+
+        Ok(())
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        if (width, height) == (self.cpu.width, self.cpu.height) {
+            return;
+        }
+
+        self.cpu.set_resolution(width, height);
+
+        if let Some(chip8x) = &mut self.chip8x {
+            *chip8x = Chip8X::new(width, height);
+        }
+
+        self.bus.send_display(DisplayEvent::ModeChange(width, height));
+        self.bus.publish(CoreEvent::ModeChanged(width, height));
+    }
+
+    // Let the user choose a ROM from the menu, using the display and input devices
+    // directly rather than through an interpreted program. Dropping a file onto the
+    // window picks it instead. Left idle, the menu plays its demos until a key is
+    // pressed. None when the menu is closed, which stops the machine.
+    pub fn pick_rom(&mut self, mut menu: Menu) -> Option<PathBuf> {
+        let mut idle_since = Instant::now();
+
+        loop {
+            if let Some(picked) = self.menu_controls() {
+                return picked;
+            }
+
+            let mut frame = vec![false; self.front.len()];
+            menu.render(&mut frame, self.config.display.width, self.config.display.height);
+            self.front.copy_from(&frame);
+            self.frame_count += 1;
+
+            for display in &mut self.displays {
+                display.draw(&self.front, self.frame_count);
+            }
+
+            let events = self.input.poll_events();
+
+            for event in events.into_iter().filter(|event| event.pressed) {
+                idle_since = Instant::now();
+
+                match event.key {
+                    Key::_2 => menu.up(),
+                    Key::_8 => menu.down(),
+                    Key::_5 | Key::_6 => {
+                        self.front.fill(false);
+                        return Some(menu.selected().to_path_buf());
+                    },
+                    _ => (),
+                }
+            }
+
+            if idle_since.elapsed() >= ATTRACT_DELAY && !menu.demos().is_empty() {
+                if let Some(picked) = self.attract(menu.demos()) {
+                    return picked;
+                }
+
+                idle_since = Instant::now();
+            }
+
+            thread::sleep(Duration::from_millis(1000 / 60));
+        }
+    }
+
+    // Handle the control events raised while the menu is up, returning what the menu
+    // comes to when one ends it: the ROM dropped onto the window, or None when shut
+    // down.
+    fn menu_controls(&mut self) -> Option<Option<PathBuf>> {
+        while let Some(event) = self.bus.next_control() {
+            match event {
+                ControlEvent::LoadRom(path) => {
+                    self.front.fill(false);
+                    return Some(Some(path));
+                },
+                ControlEvent::Shutdown => {
+                    self.stop.stop();
+                    return Some(None);
+                },
+                ControlEvent::SetSpeed(speed) => self.speed = speed.clamp(MIN_SPEED, MAX_SPEED),
+                // Nothing is running to save or load yet
+                ControlEvent::SelectSlot(_) | ControlEvent::SaveState | ControlEvent::LoadState => (),
+                ControlEvent::ToggleMute => self.set_muted(!self.muted),
+                ControlEvent::ChangeVolume(change) => {
+                    self.muted = false;
+                    self.set_volume(self.volume.saturating_add_signed(change));
+                },
+                // Nothing is running to pause
+                ControlEvent::FocusLost | ControlEvent::FocusGained => (),
+                ControlEvent::NextTheme => {
+                    self.next_theme();
+                },
+                ControlEvent::ChangeScale(change) => {
+                    self.set_scale_factor(self.scale_factor.saturating_add_signed(change.into()));
+                },
+            }
+        }
+
+        None
+    }
+
+    // Play the demos in turn, each for up to DEMO_LENGTH, until a key is pressed,
+    // then return to the menu. Returns what the menu comes to instead if a control
+    // event ends it. Demos recorded at another speed are passed over.
+    fn attract(&mut self, demos: &[Demo]) -> Option<Option<PathBuf>> {
+        info!("Playing demos until a key is pressed");
+
+        let mut deadline = Instant::now();
+
+        let picked = 'demos: loop {
+            let mut played = false;
+
+            for demo in demos {
+                self.reset();
+
+                let mut playback = match demo.replay.start(self, &demo.rom) {
+                    Ok(playback) => playback,
+                    Err(error) => {
+                        debug!("Passing over demo: {}", error);
+                        continue;
+                    },
+                };
+
+                played = true;
+
+                let started = Instant::now();
+
+                for frame in 0..demo.replay.frames() {
+                    if started.elapsed() >= DEMO_LENGTH || self.exited {
+                        break;
+                    }
+
+                    if let Some(picked) = self.menu_controls() {
+                        break 'demos Some(picked);
+                    }
+
+                    let events = self.input.poll_events();
+
+                    if events.iter().any(|event| event.pressed) {
+                        break 'demos None;
+                    }
+
+                    for event in playback.events(frame) {
+                        self.key_event(event);
+                    }
+
+                    self.run_frames(1);
+
+                    deadline += FRAME_DURATION;
+                    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                }
+            }
+
+            if !played {
+                break None;
+            }
+        };
+
+        // Leave nothing of the demo running, sounding or held down
+        self.reset();
+        self.keys_down.clear();
+        self.taps.clear();
+        self.front.fill(false);
+        self.audio.stop_tone();
+        self.set_sound_playing(false);
+
+        picked
+    }
+
+    // Complete the frame the program has drawn, and present it to the display and any
+    // frame hooks.
+    fn present(&mut self) {
+        // Resolution changes reach the display before the first frame at the new size
+        while let Some(DisplayEvent::ModeChange(width, height)) = self.bus.next_display() {
+            for display in &mut self.displays {
+                display.set_resolution(width, height);
+            }
+        }
+
+        // MegaChip's colour mode is presented by 00E0 instead
+        if self.megachip.as_ref().is_some_and(|megachip| megachip.enabled) {
+            return;
+        }
+
+        if (self.front.width(), self.front.height()) != (self.cpu.width, self.cpu.height) {
+            self.front.resize(self.cpu.width, self.cpu.height);
+        }
+
+        self.front.copy_from(&self.cpu.frame_buffer);
+        self.frame_count += 1;
+        self.track_settling();
+
+        if let Some((scope, events)) = &mut self.sound_scope {
+            let was_visible = scope.is_visible();
+
+            for event in events.try_iter() {
+                scope.handle(event);
+            }
+
+            // Rub the scope out once the sound stops
+            if was_visible && !scope.is_visible() {
+                self.front.mark_all_changed();
+            }
+        }
+
+        let changed_rows = self.front.take_changed_rows();
+
+        // Messages take the place of the timing statistics while they're up
+        let text = match &mut self.message {
+            Some((text, frames)) => {
+                *frames -= 1;
+                Some(text.clone())
+            },
+            None => self.config.timing_stats.then(|| self.timing.overlay_text()),
+        };
+
+        let values: Vec<Option<usize>> = self.watches.exprs().map(|expr| expr.evaluate(self)).collect();
+        self.watches.record(values);
+
+        if !self.displays.is_empty() {
+            let scope = self.sound_scope.as_ref().map(|(scope, _)| scope).filter(|scope| scope.is_visible());
+            let overlay = text.is_some() || !self.watches.is_empty() || scope.is_some();
+
+            match (&self.chip8x, overlay) {
+                (Some(chip8x), _) => {
+                    let frame = chip8x.colorize(&self.front);
+
+                    for display in &mut self.displays {
+                        display.draw_color(&frame, self.cpu.width, self.cpu.height);
+                    }
+                },
+                (None, true) => {
+                    let mut frame = self.front.to_vec();
+                    let mut y = 1;
+
+                    if let Some(text) = text {
+                        menu::draw_text(&mut frame, self.cpu.width, 1, y, &text, true);
+                        y += menu::LINE_HEIGHT;
+                    }
+
+                    self.watches.draw(&mut frame, self.cpu.width, y);
+
+                    if let Some(scope) = scope {
+                        scope.draw(&mut frame, self.cpu.width);
+                    }
+
+                    for display in &mut self.displays {
+                        display.draw(&frame, self.frame_count);
+                    }
+                },
+                (None, false) => {
+                    for display in &mut self.displays {
+                        display.draw_changes(&self.front, self.frame_count, &changed_rows);
+                    }
+                },
+            }
+        }
+
+        if self.message.as_ref().is_some_and(|(_, frames)| *frames == 0) {
+            self.message = None;
+
+            // Rub the message out
+            self.front.mark_all_changed();
+        }
+
+        for hook in &mut self.hooks.frame {
+            hook(&self.front);
+        }
+
+        self.bus.publish(CoreEvent::FrameCompleted(self.frame_count));
+    }
+
+    // Count the frames in a row the completed frame has stayed the same for.
+    fn track_settling(&mut self) {
+        let mut hasher = DefaultHasher::new();
+        (self.front.width(), self.front.bits()).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        self.stable_frames = match self.stable_frames {
+            Some(frames) if hash == self.frame_hash => Some(frames + 1),
+            Some(_) => Some(0),
+            // A blank screen isn't a result
+            None => self.front.bits().iter().any(|&byte| byte != 0).then_some(0),
+        };
+        self.frame_hash = hash;
+    }
+
+    // Frames in a row the completed frame has stayed the same for, or None when
+    // nothing has been drawn since the program was loaded.
+    pub fn stable_frames(&self) -> Option<u64> {
+        self.stable_frames
+    }
+
+    // Whether the frame has stayed the same for as many frames as the config asks
+    // before stopping.
+    pub fn settled(&self) -> bool {
+        self.config.exit_when_settled.zip(self.stable_frames).is_some_and(|(limit, frames)| frames >= limit)
+    }
+
+    // Mark a 60Hz frame boundary (the display interrupt): count the timers down,
+    // handle the device events raised during the frame, present it, and release any
+    // sprite draw waiting on it. The timers only count down here, so they keep in
+    // step with the program at any speed.
+    pub fn vblank(&mut self) {
+        self.delay.tick();
+        self.sound.tick();
+        self.cpu.tick_random_seed();
+        self.release_taps();
+
+        self.handle_device_events();
+        self.present();
+        self.update_title();
+        self.cpu.waiting_for_vblank = false;
+    }
+
+    // Handle every pending audio and control event.
+    fn handle_device_events(&mut self) {
+        while let Some(event) = self.bus.next_audio() {
+            let playing = match event {
+                AudioEvent::PlayTone => true,
+                AudioEvent::StopTone => false,
+            };
+
+            if playing {
+                self.audio.play_tone();
+            } else {
+                self.audio.stop_tone();
+            }
+
+            self.set_sound_playing(playing);
+        }
+
+        while let Some(event) = self.bus.next_control() {
+            match event {
+                ControlEvent::LoadRom(path) => {
+                    match loader::read(&path, &self.config.memory, None) {
+                        Ok((rom, _)) => {
+                            info!("Loading {}", path.display());
+                            self.auto_save();
+                            self.reset();
+                            self.load(&rom);
+
+                            if let Some(name) = path.file_stem() {
+                                self.set_rom_name(&name.to_string_lossy());
+                            }
+                        },
+                        Err(error) => error!("Failed to read {}: {}", path.display(), error),
+                    }
+                },
+                ControlEvent::Shutdown => {
+                    info!("Shutdown requested by input device");
+                    self.stop.stop();
+                },
+                ControlEvent::SetSpeed(speed) => {
+                    self.set_speed(speed);
+                    debug!("Speed set to {}x", self.speed);
+                },
+                ControlEvent::SelectSlot(slot) => {
+                    self.slot = slot;
+                    self.show_message(format!("SLOT {}", slot));
+                },
+                ControlEvent::SaveState => match self.save_state(self.slot) {
+                    Ok(path) => {
+                        info!("Saved state to {}", path.display());
+                        self.show_message(format!("SAVED {}", self.slot));
+                    },
+                    Err(error) => error!("Failed to save state: {}", error),
+                },
+                ControlEvent::LoadState => match self.load_state(self.slot) {
+                    Ok(()) => {
+                        info!("Loaded state from slot {}", self.slot);
+                        self.show_message(format!("LOADED {}", self.slot));
+                    },
+                    Err(error) => error!("Failed to load state: {}", error),
+                },
+                ControlEvent::ToggleMute => {
+                    self.set_muted(!self.muted);
+                    self.show_volume();
+                },
+                // Turning the volume up or down unmutes it
+                ControlEvent::ChangeVolume(change) => {
+                    self.muted = false;
+                    self.set_volume(self.volume.saturating_add_signed(change));
+                    self.show_volume();
+                },
+                ControlEvent::FocusLost if self.config.input.pause_on_focus_loss => {
+                    info!("Paused while the window is out of focus");
+                    self.set_paused(true);
+                },
+                ControlEvent::FocusGained if self.paused => {
+                    info!("Resumed");
+                    self.set_paused(false);
+                },
+                ControlEvent::FocusLost | ControlEvent::FocusGained => (),
+                ControlEvent::NextTheme => {
+                    let theme = self.next_theme();
+                    self.show_message(format!("THEME {}", theme));
+                },
+                ControlEvent::ChangeScale(change) => {
+                    self.set_scale_factor(self.scale_factor.saturating_add_signed(change.into()));
+                    self.show_message(format!("SCALE {}", self.scale_factor));
+                },
+            }
+        }
+    }
+
+    // Handle the device events of a frame spent paused, which may resume the machine.
+    fn paused_frame(&mut self) {
+        self.poll_input();
+        self.handle_device_events();
+    }
+
+    // Apply the presses and releases on the input device since the last poll.
+    fn poll_input(&mut self) {
+        for event in self.input.poll_events() {
+            self.key_event(event);
+        }
+    }
+
+    // Take key presses from another input device as well, from the next poll on.
+    pub fn add_input(&mut self, input: Box<dyn Input>) {
+        let existing = mem::replace(&mut self.input, Box::new(NullInput));
+        self.input = Box::new(MergedInput::new(vec![existing, input]));
+    }
+
+    // Remove the input device, for hosts that poll it themselves and pass its
+    // events on with key_event, leaving the null input in its place.
+    pub fn take_input(&mut self) -> Box<dyn Input> {
+        mem::replace(&mut self.input, Box::new(NullInput))
+    }
+
+    // Press or release a key, as if the input device had reported it. Tracks the
+    // keys held down, and advances FX0A, which stores a key once it has been
+    // pressed and released.
+    pub fn key_event(&mut self, event: KeyEvent) {
+        if event.pressed && !self.keys_down.contains(&event.key) {
+            self.keys_down.push(event.key);
+        } else if !event.pressed {
+            self.keys_down.retain(|&key| key != event.key);
+        }
+
+        self.key_wait = match (self.key_wait, event.pressed) {
+            (Some(KeyWait::Press(reg)), true) => Some(KeyWait::Release(reg, event.key)),
+            (Some(KeyWait::Release(reg, key)), false) if key == event.key => {
+                self.cpu.v[reg] = key as u8;
+                None
+            },
+            (key_wait, _) => key_wait,
+        };
+    }
+
+    // Press a key, as if the input device had, until released.
+    pub fn press_key(&mut self, key: Key) {
+        self.key_event(KeyEvent::new(key, true));
+    }
+
+    // Release a key, whether pressed by the input device or from here.
+    pub fn release_key(&mut self, key: Key) {
+        self.taps.retain(|&(tapped, _)| tapped != key);
+        self.key_event(KeyEvent::new(key, false));
+    }
+
+    // Press a key and hold it for a number of frames, releasing it at the display
+    // interrupt that ends the last of them. Programs polling with EX9E see it held
+    // throughout, and at least for the rest of this frame however few are given.
+    // Tapping a key already tapped holds it for the new number of frames instead.
+    pub fn tap_key_for(&mut self, key: Key, frames: u64) {
+        self.taps.retain(|&(tapped, _)| tapped != key);
+        self.taps.push((key, frames.max(1)));
+        self.press_key(key);
+    }
+
+    // Count down the frames tapped keys are held for, releasing those that are done.
+    fn release_taps(&mut self) {
+        for (_, frames) in &mut self.taps {
+            *frames -= 1;
+        }
+
+        let released: Vec<Key> = self.taps.iter().filter(|&&(_, frames)| frames == 0).map(|&(key, _)| key).collect();
+
+        for key in released {
+            self.release_key(key);
+        }
+    }
+
+    // One clock cycle of the machine: poll input, then step a single instruction.
+    pub fn cycle(&mut self) {
+        self.poll_input();
+        self.step();
+        self.apply_freezes();
+    }
+
+    // Same as cycle, but returns the fault instead of panicking.
+    pub fn try_cycle(&mut self) -> Result<(), ExecutionError> {
+        self.poll_input();
+        let result = self.try_step();
+        self.apply_freezes();
+        result
+    }
+
+    // Hold the byte at addr at value, whatever the program writes there, such as to
+    // keep a lives counter from running down. Replaces any freeze already there.
+    pub fn freeze(&mut self, addr: usize, value: u8) -> Result<(), MemoryError> {
+        self.cpu.memory.read_byte(addr)?;
+        self.freezes.insert(addr, value);
+        self.apply_freezes();
+        Ok(())
+    }
+
+    // Let the program write the byte at addr again. False if it wasn't frozen.
+    pub fn unfreeze(&mut self, addr: usize) -> bool {
+        self.freezes.remove(&addr).is_some()
+    }
+
+    // Frozen addresses and the values they are held at.
+    pub fn freezes(&self) -> &BTreeMap<usize, u8> {
+        &self.freezes
+    }
+
+    // Show text over the frame for a couple of seconds, such as to confirm a hotkey.
+    pub fn show_message(&mut self, text: String) {
+        self.message = Some((text, MESSAGE_FRAMES));
+    }
+
+    // Show a value over the frame from now on, in place of any watch of the same
+    // expression.
+    pub fn add_watch(&mut self, watch: Watch) {
+        self.watches.add(watch);
+    }
+
+    // Stop showing a value. False if it wasn't shown.
+    pub fn remove_watch(&mut self, expr: &WatchExpr) -> bool {
+        let removed = self.watches.remove(expr);
+
+        if removed {
+            self.front.mark_all_changed();
+        }
+
+        removed
+    }
+
+    pub fn watches(&self) -> &Watches {
+        &self.watches
+    }
+
+    // Capture the machine's state, to carry on from later with restore. The random
+    // number generator is reseeded from itself, so that both carry on alike.
+    pub fn snapshot(&mut self) -> Result<Snapshot, SnapshotError> {
+        if matches!(self.config.profile, Profile::MegaChip | Profile::Chip8X) {
+            return Err(SnapshotError::Unsupported(self.config.profile));
+        }
+
+        let rng_seed = self.rng.random();
+        self.rng = StdRng::seed_from_u64(rng_seed);
+
+        let memory = self.cpu.memory.read_buf(0, self.cpu.memory.len())
+            .map_err(|_| SnapshotError::Format("memory unreadable"))?
+            .into_owned();
+
+        Ok(Snapshot {
+            saved_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            pc: self.cpu.pc,
+            v: self.cpu.v,
+            i: self.cpu.i,
+            timers: self.timers(),
+            stack: self.cpu.stack.clone(),
+            calls: self.cpu.calls.clone(),
+            memory,
+            resolution: (self.cpu.width, self.cpu.height),
+            frame_buffer: self.cpu.frame_buffer.to_vec(),
+            key_wait: self.key_wait,
+            waiting_for_vblank: self.cpu.waiting_for_vblank,
+            exited: self.exited,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng_seed,
+            random_seed: self.cpu.random_seed,
+            quirks: Some(self.cpu.quirks()),
+        })
+    }
+
+    // Carry on from a snapshot, discarding the history. The machine keeps its own
+    // quirks.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if matches!(self.config.profile, Profile::MegaChip | Profile::Chip8X) {
+            return Err(SnapshotError::Unsupported(self.config.profile));
+        }
+
+        if snapshot.memory.len() != self.cpu.memory.len() {
+            return Err(SnapshotError::MemorySize(snapshot.memory.len(), self.cpu.memory.len()));
+        }
+
+        let (width, height) = snapshot.resolution;
+
+        if snapshot.frame_buffer.len() != width * height {
+            return Err(SnapshotError::Format("frame buffer doesn't match the resolution"));
+        }
+
+        self.cpu.memory.load_buf(0, &snapshot.memory)
+            .map_err(|_| SnapshotError::Format("memory unwritable"))?;
+
+        self.cpu.pc = snapshot.pc;
+        self.cpu.v = snapshot.v;
+        self.cpu.i = snapshot.i;
+        self.delay.set(snapshot.timers.0);
+        self.sound.set(snapshot.timers.1);
+        self.cpu.stack = snapshot.stack.clone();
+        self.cpu.calls = snapshot.calls.clone();
+        self.set_resolution(width, height);
+        self.cpu.frame_buffer.copy_from(&snapshot.frame_buffer);
+        self.key_wait = snapshot.key_wait;
+        self.cpu.waiting_for_vblank = snapshot.waiting_for_vblank;
+        self.exited = snapshot.exited;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.update_pattern();
+        self.rng = StdRng::seed_from_u64(snapshot.rng_seed);
+        self.cpu.random_seed = snapshot.random_seed;
+
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+
+        Ok(())
+    }
+
+    // Where the loaded ROM's save states and other data go, if there is storage.
+    pub fn rom_storage(&self) -> Option<&RomStorage> {
+        self.rom_storage.as_ref()
+    }
+
+    // Save state slot the hotkeys use.
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    // Snapshot the machine into a save state slot for the loaded ROM, returning
+    // where it was written.
+    pub fn save_state(&mut self, slot: u8) -> Result<PathBuf, SnapshotError> {
+        let snapshot = self.snapshot()?;
+        let rom_storage = self.rom_storage.as_ref().ok_or(SnapshotError::NoStorage)?;
+
+        rom_storage.write_state(slot, &snapshot)
+    }
+
+    // Restore the save state in a slot for the loaded ROM.
+    pub fn load_state(&mut self, slot: u8) -> Result<(), SnapshotError> {
+        let rom_storage = self.rom_storage.as_ref().ok_or(SnapshotError::NoStorage)?;
+        let snapshot = rom_storage.read_state(slot)?;
+
+        self.restore(&snapshot)
+    }
+
+    // Restore the loaded ROM's auto-save, booting it afresh if there isn't one.
+    fn resume_auto_save(&mut self) {
+        let Some(rom_storage) = &self.rom_storage else {
+            return;
+        };
+
+        match rom_storage.read_auto_save() {
+            Ok(Some(snapshot)) => match self.restore(&snapshot) {
+                Ok(()) => info!("Resumed where it was left {}", storage::age(snapshot.saved_at)),
+                Err(error) => warn!("Failed to resume, starting afresh: {}", error),
+            },
+            Ok(None) => debug!("No auto-save to resume"),
+            Err(error) => warn!("Failed to read auto-save, starting afresh: {}", error),
+        }
+    }
+
+    // Auto-save the loaded ROM for the next run to resume, when resuming is on.
+    // Programs that have exited start afresh next time instead.
+    pub fn auto_save(&mut self) {
+        if !self.resume || self.rom_storage.is_none() {
+            return;
+        }
+
+        if self.exited {
+            if let Some(Err(error)) = self.rom_storage.as_ref().map(RomStorage::delete_auto_save) {
+                warn!("Failed to remove auto-save: {}", error);
+            }
+
+            return;
+        }
+
+        let result = self.snapshot().and_then(|snapshot| {
+            self.rom_storage.as_ref().ok_or(SnapshotError::NoStorage)?.write_auto_save(&snapshot)
+        });
+
+        match result {
+            Ok(path) => debug!("Auto-saved to {}", path.display()),
+            Err(error) => warn!("Failed to auto-save: {}", error),
+        }
+    }
+
+    // Shut down once the run is over, however it was stopped: stop the machine, so
+    // nothing more runs, let the shutdown callbacks write out what they hold, write
+    // the auto-save, then drop the devices, silencing the audio and closing the
+    // window. The machine can still be inspected afterwards. Only the first call
+    // does anything.
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+
+        self.shut_down = true;
+        self.stop.stop();
+
+        for hook in mem::take(&mut self.hooks.shutdown) {
+            hook(self);
+        }
+
+        self.auto_save();
+        self.audio.stop_tone();
+
+        self.displays.clear();
+        self.audio = Box::new(NullAudio);
+        self.input = Box::new(NullInput);
+
+        info!("Shut down");
+    }
+
+    // Rewrite any frozen bytes the program changed.
+    fn apply_freezes(&mut self) {
+        for (&addr, &value) in &self.freezes {
+            // Writes invalidate decoded instructions, so leave bytes that still hold
+            if self.cpu.memory.read_byte(addr) != Ok(value) {
+                let _ = self.cpu.memory.load_buf(addr, &[value]);
+            }
+        }
+    }
+
+    // A number of clock cycles in a row, cut short if the program exits. With the
+    // JIT enabled, runs of register instructions execute as one compiled block,
+    // still taking a cycle each.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let mut remaining = cycles;
+
+        while remaining > 0 && !self.exited {
+            if let Some(skipped) = self.skip_delay_wait(remaining) {
+                remaining -= skipped;
+                continue;
+            }
+
+            // Blocks never read input, so only interpreted cycles poll it
+            #[cfg(feature = "jit")]
+            if let Some(executed) = self.run_block(remaining) {
+                remaining -= executed;
+                continue;
+            }
+
+            self.cycle();
+            remaining -= 1;
+        }
+    }
+
+    // A number of frames in a row, each a frame's worth of cycles at the current speed
+    // followed by the display interrupt, which releases a draw waiting on it. Cut
+    // short if the program exits. Returns the frame completed at the last interrupt,
+    // for video tests and recordings that advance a frame at a time.
+    pub fn run_frames(&mut self, frames: u64) -> &[bool] {
+        for _ in 0..frames {
+            if self.exited {
+                break;
+            }
+
+            self.run_cycles(self.cycles_per_frame());
+            self.vblank();
+        }
+
+        &self.front
+    }
+
+    // Whether cycles have to run one at a time rather than be skipped or run as a
+    // compiled block: when execution is held, or when instruction hooks or the
+    // history expect to see every instruction.
+    fn observes_every_cycle(&self) -> bool {
+        let hooked = !self.hooks.instruction.is_empty() || !self.hooks.memory_access.is_empty() || !self.hooks.quirks.is_empty();
+
+        self.cpu.waiting_for_vblank || self.key_wait.is_some() || self.trap.is_some() || hooked || self.history.is_some()
+    }
+
+    // Skip as many times round a loop waiting on the delay timer as fit in up to max
+    // cycles, returning how many cycles that was. The loop reads the timer with
+    // FX07, skips on vX, and jumps back to the FX07, and until the timer next counts
+    // down leaves the machine as it found it each time round. Only whole times round
+    // are skipped, so the program counter ends up where running them would have.
+    fn skip_delay_wait(&mut self, max: u64) -> Option<u64> {
+        if !self.config.skip_delay_waits || self.observes_every_cycle() {
+            return None;
+        }
+
+        let pc = self.cpu.pc;
+        let delay = self.delay.get();
+
+        let [Some(read), Some(test), Some(Instruction::Jump(target))] = [pc, pc + 2, pc + 4].map(|addr| self.instruction_at(addr)) else {
+            return None;
+        };
+
+        let Instruction::SetVxToDelay(reg) = read else {
+            return None;
+        };
+
+        // Going round again rather than skipping the jump
+        let looping = target == pc && match test {
+            Instruction::IfVxEq(x, value) => x == reg && delay != value,
+            Instruction::IfVxNotEq(x, value) => x == reg && delay == value,
+            _ => false,
+        };
+
+        let skipped = max - max % 3;
+
+        if !looping || skipped == 0 {
+            return None;
+        }
+
+        self.cpu.v[reg] = delay;
+
+        // Crash reports end with the instructions skipped, as if they had run
+        if let Some(trace) = &mut self.trace {
+            let loop_trace = [(pc, read), (pc + 2, test), (pc + 4, Instruction::Jump(target))];
+            let len = skipped.min(crash::TRACE_LEN as u64) as usize;
+
+            // Starting part way round, so the trace ends on the jump
+            for &entry in loop_trace.iter().cycle().skip((3 - len % 3) % 3).take(len) {
+                if trace.len() == crash::TRACE_LEN {
+                    trace.pop_front();
+                }
+
+                trace.push_back(entry);
+            }
+        }
+
+        Some(skipped)
+    }
+
+    // Instruction at an address, as execute_next would decode it, unless it's the
+    // exit opcode, mapped, or can't be decoded.
+    fn instruction_at(&self, addr: usize) -> Option<Instruction> {
+        if let Some(instruction) = self.cache.get(addr) {
+            return Some(instruction);
+        }
+
+        if self.cpu.memory.is_mapped(addr..addr + 2) {
+            return None;
+        }
+
+        let bytes = self.cpu.memory.read_buf(addr, 2).ok()?;
+        let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+
+        if Some(opcode) == self.config.exit_opcode {
+            return None;
+        }
+
+        Instruction::decode(opcode, self.config.profile).ok()
+    }
+
+    // Execute the compiled block at the program counter, if there is one that fits
+    // in the cycles left, returning how many instructions it executed.
+    #[cfg(feature = "jit")]
+    fn run_block(&mut self, max: u64) -> Option<u64> {
+        if self.observes_every_cycle() {
+            return None;
+        }
+
+        let block = self.jit.as_mut()?.block(self.cpu.pc, &self.cpu.memory, &self.config, self.cpu.quirks())?;
+
+        if block.len > max {
+            return None;
+        }
+
+        // Blocks only access the registers and index register passed in
+        unsafe { (block.func)(self.cpu.v.as_mut_ptr(), &mut self.cpu.i) };
+        self.cpu.pc = block.next;
+
+        Some(block.len)
+    }
+
+    // Fetch, decode and execute a single instruction. Does nothing while a sprite
+    // draw is waiting for the display interrupt. Panics if the program faults.
+    pub fn step(&mut self) {
+        if let Err(error) = self.try_step() {
+            self.report_crash(&error);
+            panic!("{}", error);
+        }
+    }
+
+    // Write a crash report for a fault, when enabled.
+    fn report_crash(&self, error: &ExecutionError) {
+        let (Some(trace), Some(storage)) = (&self.trace, &self.storage) else {
+            return;
+        };
+
+        let report = CrashReport {
+            error: error.to_string(),
+            rom_id: self.rom_storage.as_ref().map(|rom_storage| rom_storage.id().to_string()),
+            config: vec![
+                format!("Profile: {:?}", self.config.profile),
+                format!("Clock speed: {}Hz", self.config.clock_speed),
+                format!("Quirks: {:?}", self.cpu.quirks()),
+                format!("Memory: {} bytes, program at 0x{:03X}", self.cpu.memory.len(), self.config.memory.program_start),
+                format!("Exit opcode: {:04X?}", self.config.exit_opcode),
+            ],
+            trace: trace.iter().copied().collect(),
+            pc: self.cpu.pc,
+            v: self.cpu.v,
+            i: self.cpu.i,
+            timers: self.timers(),
+            calls: self.cpu.calls.clone(),
+            resolution: self.resolution(),
+            frame_buffer: self.cpu.frame_buffer.to_vec(),
+            colors: self.colors,
+        };
+
+        match report.write(&storage.crash_dir()) {
+            Ok(path) => error!("Wrote a crash report to {}, please include it when reporting the problem", path.display()),
+            Err(error) => error!("Failed to write crash report: {}", error),
+        }
+    }
+
+    // Same as step, but returns the fault instead of panicking, for hosts that run
+    // untrusted programs.
+    pub fn try_step(&mut self) -> Result<(), ExecutionError> {
+        let result = self.execute_next();
+
+        if result.is_err() {
+            self.bus.publish(CoreEvent::Halted);
+        }
+
+        result
+    }
+
+    fn execute_next(&mut self) -> Result<(), ExecutionError> {
+        if self.cpu.waiting_for_vblank || self.key_wait.is_some() || self.trap.is_some() {
+            return Ok(());
+        }
+
+        self.rewinding = false;
+
+        // Fetch and decode current instruction, unless it was decoded before
+        let instruction = match self.cache.get(self.cpu.pc) {
+            Some(instruction) => instruction,
+            None => {
+                let parts = self.cpu.memory.read_buf(self.cpu.pc, 2)
+                    .map_err(|error| ExecutionError::Memory("fetch instruction", error))?;
+                let opcode = ((parts[0] as u16) << 8) | parts[1] as u16;
+
+                // Halt on the exit opcode, leaving the machine as it was for inspection.
+                // It is never cached, so it is always caught here.
+                if Some(opcode) == self.config.exit_opcode {
+                    debug!("Reached exit opcode at 0x{:03X}", self.cpu.pc);
+                    self.exited = true;
+                    self.stop.stop();
+                    self.bus.publish(CoreEvent::Halted);
+                    return Ok(());
+                }
+
+                let instruction = Instruction::decode(opcode, self.config.profile)
+                    .map_err(ExecutionError::InvalidOpcode)?;
+
+                // Mapped addresses may read differently every time
+                if !self.cpu.memory.is_mapped(self.cpu.pc..self.cpu.pc + 2) {
+                    self.cache.insert(self.cpu.pc, instruction);
+                }
+
+                instruction
+            },
+        };
+
+        for hook in &mut self.hooks.instruction {
+            hook(self.cpu.pc, &instruction);
+        }
+
+        if !self.hooks.quirks.is_empty() {
+            let mut quirks = self.cpu.quirks();
+
+            for hook in &mut self.hooks.quirks {
+                hook(self.cpu.pc, &instruction, &mut quirks);
+            }
+
+            self.cpu.override_quirks(quirks);
+        }
+
+        if !self.hooks.memory_access.is_empty() {
+            for (range, access) in self.accesses(&instruction) {
+                for hook in &mut self.hooks.memory_access {
+                    hook(range.clone(), access);
+                }
+            }
+        }
+
+        if let Some(trace) = &mut self.trace {
+            if trace.len() == crash::TRACE_LEN {
+                trace.pop_front();
+            }
+
+            trace.push_back((self.cpu.pc, instruction));
+        }
+
+        if self.history.is_some() {
+            let delta = self.delta(&instruction);
+
+            if let Some(history) = &mut self.history {
+                history.push(delta);
+            }
+        }
+
+        // Increment PC to point to next instruction
+        self.cpu.pc += 2;
+
+        // MegaChip's colour mode clears and draws a frame of its own
+        let effect = match instruction {
+            Instruction::Clear | Instruction::Draw(..) if self.megachip.as_ref().is_some_and(|megachip| megachip.enabled) => {
+                self.execute_megachip(instruction)?;
+                None
+            },
+            _ => {
+                let mut peripherals = MachinePeripherals {
+                    delay: &mut self.delay,
+                    sound: &mut self.sound,
+                    keys_down: &self.keys_down,
+                    rng: &mut self.rng,
+                };
+
+                self.cpu.execute(instruction, &mut peripherals)?
+            },
+        };
+
+        match effect {
+            None => (),
+            // Shown at the next display interrupt, like any other change to the frame
+            Some(Effect::Cleared) => (),
+            Some(Effect::WaitForKey(reg)) => {
+                // Execution holds until poll_input, or key_event, sees a key pressed
+                // and released
+                self.key_wait = Some(KeyWait::Press(reg));
+            },
+            // Held on the call for the debugger to break on, or play to stop at
+            Some(Effect::Trapped) => {
+                debug!("Trapped on machine call at 0x{:03X}", self.cpu.pc);
+                self.trap = Some(self.cpu.pc);
+                self.bus.publish(CoreEvent::Halted);
+            },
+            Some(Effect::Extension(instruction)) => self.execute_extension(instruction)?,
+        }
+
+        Ok(())
+    }
+
+    // Execute a clear or sprite draw in MegaChip's colour mode.
+    fn execute_megachip(&mut self, instruction: Instruction) -> Result<(), ExecutionError> {
+        let Some(megachip) = &mut self.megachip else {
+            return Ok(());
+        };
+
+        match instruction {
+            Instruction::Clear => {
+                if !self.displays.is_empty() {
+                    let frame = megachip.frame();
+
+                    for display in &mut self.displays {
+                        display.draw_color(&frame, megachip::WIDTH, megachip::HEIGHT);
+                    }
+                }
+
+                megachip.clear();
+
+                if self.cpu.quirks().vblank_model != VblankModel::None {
+                    self.cpu.waiting_for_vblank = true;
+                }
+            },
+            Instruction::Draw(reg_x, reg_y, _) => {
+                let sprite = self.cpu.memory
+                    .read_buf(self.cpu.i, megachip.sprite_width * megachip.sprite_height)
+                    .map_err(|error| ExecutionError::Memory("fetch sprite", error))?;
+
+                self.cpu.v[0xF] = megachip.draw(self.cpu.v[reg_x] as usize, self.cpu.v[reg_y] as usize, &sprite).into();
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    // Execute an instruction of the MegaChip, CHIP-8X or XO-CHIP extensions, which
+    // need more than the CPU has.
+    fn execute_extension(&mut self, instruction: Instruction) -> Result<(), ExecutionError> {
+        match instruction {
+            Instruction::LoadAudioPattern => {
+                let pattern = self.cpu.memory.read_buf(self.cpu.i, 16)
+                    .map_err(|error| ExecutionError::Memory("fetch audio pattern", error))?;
+
+                self.audio_pattern = (*pattern).try_into().ok();
+                self.update_pattern();
+            },
+            Instruction::SetPitch(reg) => {
+                self.pitch = self.cpu.v[reg];
+
+                if self.audio_pattern.is_some() {
+                    self.update_pattern();
+                }
+            },
+            Instruction::DisableMegaChip => {
+                self.megachip()?.enabled = false;
+
+                // The display was last drawn in colour
+                self.front.mark_all_changed();
+            },
+            Instruction::EnableMegaChip => self.megachip()?.enabled = true,
+            Instruction::LoadPalette(count) => {
+                let colors = self.cpu.memory.read_buf(self.cpu.i, count as usize * 4)
+                    .map_err(|error| ExecutionError::Memory("fetch palette", error))?
+                    .to_vec();
+
+                self.megachip()?.load_palette(&colors);
+            },
+            // Sizes of 0 stand for 256
+            Instruction::SetSpriteWidth(width) => self.megachip()?.sprite_width = if width == 0 { 256 } else { width as usize },
+            Instruction::SetSpriteHeight(height) => self.megachip()?.sprite_height = if height == 0 { 256 } else { height as usize },
+            Instruction::SetScreenAlpha(alpha) => self.megachip()?.alpha = alpha,
+            Instruction::PlaySample(looping) => {
+                self.megachip()?;
+
+                // Header of a 16-bit sample rate and 24-bit length, then a reserved byte
+                let header = self.cpu.memory.read_buf(self.cpu.i, 6)
+                    .map_err(|error| ExecutionError::Memory("fetch sample header", error))?;
+                let rate = u16::from_be_bytes([header[0], header[1]]) as u32;
+                let len = ((header[2] as usize) << 16) | ((header[3] as usize) << 8) | header[4] as usize;
+
+                let samples = self.cpu.memory.read_buf(self.cpu.i + 6, len)
+                    .map_err(|error| ExecutionError::Memory("fetch samples", error))?;
+
+                self.audio.play_samples(&samples, rate, looping);
+            },
+            Instruction::StopSample => {
+                self.megachip()?;
+                self.audio.stop_samples();
+            },
+            Instruction::SetBlendMode(mode) => self.megachip()?.blend = mode.into(),
+            Instruction::SetCollisionColor(index) => self.megachip()?.collision_color = index,
+            Instruction::StepBackgroundColor => self.chip8x()?.step_background(),
+            Instruction::SetZoneColor(reg_x, reg_y) => {
+                let (horizontal, vertical, color) = (self.cpu.v[reg_x], self.cpu.v[(reg_x + 1) & 0xF], self.cpu.v[reg_y]);
+                self.chip8x()?.set_zones(horizontal, vertical, color);
+            },
+            Instruction::SetRowColor(reg_x, reg_y, height) => {
+                let (x, y, color) = (self.cpu.v[reg_x], self.cpu.v[(reg_x + 1) & 0xF], self.cpu.v[reg_y]);
+                self.chip8x()?.set_rows(x as usize, y as usize, height as usize, color);
+            },
+            // The CPU executes everything else
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    // Memory the instruction about to execute fetches, reads and writes, within the
+    // bounds of memory.
+    fn accesses(&self, instruction: &Instruction) -> Vec<(Range<usize>, Access)> {
+        let (pc, i) = (self.cpu.pc, self.cpu.i);
+
+        let len = match *instruction {
+            Instruction::SetIWide | Instruction::SetILong(_) => 4,
+            _ => 2,
+        };
+        let mut accesses = vec![(pc..pc + len, Access::Execute)];
+
+        let data = match *instruction {
+            Instruction::Draw(..) if self.megachip.as_ref().is_some_and(|megachip| megachip.enabled) => {
+                self.megachip.as_ref().map(|megachip| (megachip.sprite_width * megachip.sprite_height, Access::Read))
+            },
+            Instruction::Draw(_, _, sprite_height) => {
+                let (rows, row_bytes) = self.cpu.sprite_size(sprite_height);
+                Some((rows * row_bytes, Access::Read))
+            },
+            Instruction::VLoad(reg) => Some((reg + 1, Access::Read)),
+            Instruction::LoadAudioPattern => Some((16, Access::Read)),
+            Instruction::LoadPalette(count) => Some((count as usize * 4, Access::Read)),
+            // The sample's header gives its length
+            Instruction::PlaySample(_) => {
+                let len = self.cpu.memory.read_buf(i, 6).map_or(0, |header| {
+                    ((header[2] as usize) << 16) | ((header[3] as usize) << 8) | header[4] as usize
+                });
+
+                Some((6 + len, Access::Read))
+            },
+            Instruction::VDump(reg) => Some((reg + 1, Access::Write)),
+            Instruction::StoreVxBCDAtI(_) => Some((3, Access::Write)),
+            _ => None,
+        };
+
+        accesses.extend(data.map(|(len, access)| (i..i + len, access)));
+
+        let end = self.cpu.memory.len();
+        accesses.into_iter()
+            .map(|(range, access)| (range.start.min(end)..range.end.min(end), access))
+            .filter(|(range, _)| !range.is_empty())
+            .collect()
+    }
+
+    // State the instruction about to execute could change, for undoing it.
+    fn delta(&self, instruction: &Instruction) -> Delta {
+        let written = match *instruction {
+            Instruction::StoreVxBCDAtI(_) => Some(3),
+            Instruction::VDump(reg) => Some(reg + 1),
+            _ => None,
+        };
+
+        // Writes to mapped regions go to their handlers, which can't be undone
+        let memory = written
+            .filter(|&len| !self.cpu.memory.is_mapped(self.cpu.i..self.cpu.i + len))
+            .and_then(|len| self.cpu.memory.read_buf(self.cpu.i, len).ok())
+            .map(|bytes| (self.cpu.i, bytes.into_owned()));
+
+        let frame = match instruction {
+            Instruction::Clear | Instruction::Draw(..) => Some(self.cpu.frame_buffer.to_vec()),
+            _ => None,
+        };
+
+        let returned_from = match instruction {
+            Instruction::Return => self.cpu.stack.last().copied().zip(self.cpu.calls.last().copied()),
+            _ => None,
+        };
+
+        Delta {
+            pc: self.cpu.pc,
+            v: self.cpu.v,
+            i: self.cpu.i,
+            timers: self.timers(),
+            key_wait: self.key_wait,
+            waiting_for_vblank: self.cpu.waiting_for_vblank,
+            stack_len: self.cpu.stack.len(),
+            returned_from,
+            memory,
+            frame,
+            rng: matches!(instruction, Instruction::SetVxRand(..)).then(|| (self.rng.clone(), self.cpu.random_seed)),
+            audio: matches!(instruction, Instruction::LoadAudioPattern | Instruction::SetPitch(_))
+                .then_some((self.audio_pattern, self.pitch)),
+        }
+    }
+
+    // Undo the latest instruction recorded in the history, returning false when
+    // there is none. MegaChip and CHIP-8X state, and the effects of writes to mapped
+    // regions, aren't restored.
+    pub fn step_back(&mut self) -> bool {
+        let Some(delta) = self.history.as_mut().and_then(History::pop) else {
+            return false;
+        };
+
+        self.cpu.pc = delta.pc;
+        self.cpu.v = delta.v;
+        self.cpu.i = delta.i;
+        self.delay.set(delta.timers.0);
+        self.sound.set(delta.timers.1);
+        self.key_wait = delta.key_wait;
+        self.cpu.waiting_for_vblank = delta.waiting_for_vblank;
+        self.exited = false;
+        self.trap = None;
+
+        self.cpu.stack.truncate(delta.stack_len);
+        self.cpu.calls.truncate(delta.stack_len);
+
+        if let Some((return_addr, call)) = delta.returned_from {
+            self.cpu.stack.push(return_addr);
+            self.cpu.calls.push(call);
+        }
+
+        if let Some((addr, bytes)) = delta.memory {
+            // Read from the same range before it was written
+            let _ = self.cpu.memory.load_buf(addr, &bytes);
+        }
+
+        if let Some(frame) = delta.frame {
+            self.cpu.frame_buffer.copy_from(&frame);
+        }
+
+        if let Some((rng, random_seed)) = delta.rng {
+            self.rng = rng;
+            self.cpu.random_seed = random_seed;
+        }
+
+        if let Some((pattern, pitch)) = delta.audio {
+            self.audio_pattern = pattern;
+            self.pitch = pitch;
+            self.update_pattern();
+        }
+
+        self.rewinding = true;
+        self.update_title();
+        true
+    }
+
+    // Number of instructions step_back can undo.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    // Hand the current audio pattern, at the current pitch, to the audio device, and
+    // let subscribers know.
+    fn update_pattern(&mut self) {
+        self.bus.publish(CoreEvent::PatternChanged(self.audio_pattern, self.pitch));
+
+        // Each step of pitch is 1/48 of an octave away from 4000Hz at 64
+        let rate = 4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0);
+
+        self.audio.set_pattern(self.audio_pattern.as_ref(), rate);
+    }
+
+    // CHIP-8X colour state, for executing its instructions.
+    fn chip8x(&mut self) -> Result<&mut Chip8X, ExecutionError> {
+        self.chip8x.as_mut().ok_or(ExecutionError::Profile("CHIP-8X", "chip8x"))
+    }
+
+    // MegaChip state, for executing its instructions.
+    fn megachip(&mut self) -> Result<&mut MegaChip, ExecutionError> {
+        self.megachip.as_mut().ok_or(ExecutionError::Profile("MegaChip", "megachip"))
+    }
+
+    // Cycles the run can go on for before reaching the configured limit on them.
+    fn cycles_left(&self, cycles: u64) -> u64 {
+        match self.config.exit_after {
+            Some(ExitAfter::Cycles(limit)) => limit.saturating_sub(cycles),
+            _ => u64::MAX,
+        }
+    }
+
+    // Whether the run has gone on for as long as the config allows.
+    fn run_limit_reached(&self, cycles: u64, started: Instant) -> bool {
+        if self.settled() {
+            return true;
+        }
+
+        match self.config.exit_after {
+            Some(ExitAfter::Cycles(limit)) => cycles >= limit,
+            Some(ExitAfter::Seconds(limit)) => started.elapsed().as_secs_f64() >= limit,
+            None => false,
+        }
+    }
+
+    // Run the ROM until stopped, or until the configured run limit is reached, then
+    // shut down.
+    pub fn play(&mut self, rom: &[u8]) {
+        self.load(rom);
+
+        debug!("Running until stopped");
+
+        // The display interrupt fires every cycles_per_frame cycles, so frame
+        // boundaries fall at the same point in the program on every run
+        let mut cycles = 0;
+
+        let started = Instant::now();
+        let mut total_cycles = 0;
+
+        // When the next cycle is due. Cycles that run late leave the deficit for the
+        // ones after to make up by not sleeping, rather than drifting behind.
+        let mut due = Instant::now();
+        self.timing = TimingStats::new(self.config.clock_speed);
+
+        while !self.stop.is_stopped() && self.trap.is_none() && !self.run_limit_reached(total_cycles, started) {
+            if self.paused {
+                self.paused_frame();
+                thread::sleep(FRAME_DURATION);
+                due = Instant::now();
+                continue;
+            }
+
+            // Both depend on the speed, which can change between cycles
+            let cycle_duration = self.cycle_duration();
+            let cycles_per_frame = self.cycles_per_frame();
+
+            let ran = if self.cpu.waiting_for_vblank {
+                // Nothing can run until the interrupt, so idle out the rest of the frame
+                let idle = cycles_per_frame.saturating_sub(cycles);
+                cycles = cycles_per_frame;
+                idle
+            } else if let Some(skipped) = self.skip_delay_wait(cycles_per_frame.saturating_sub(cycles).min(self.cycles_left(total_cycles))) {
+                // Slept through like the cycles they stand in for
+                cycles += skipped;
+                skipped
+            } else {
+                self.cycle();
+                cycles += 1;
+                1
+            };
+
+            total_cycles += ran;
+            self.timing.add_cycles(ran);
+
+            // Sleep to ensure roughly correct clock speed
+            due += cycle_duration * ran as u32;
+            let now = Instant::now();
+
+            if cycle_duration.is_zero() {
+                // Unthrottled, with no time to keep
+                due = now;
+            } else if due > now {
+                thread::sleep(due - now);
+            } else if now - due > MAX_DEFICIT {
+                // Too far behind to catch up on, such as after the host was suspended
+                due = now;
+                self.timing.resync();
+            }
+
+            if cycles >= cycles_per_frame {
+                self.vblank();
+                self.timing.frame(Instant::now());
+                cycles = 0;
+            }
+        }
+
+        // With no debugger to break into, a trapped machine call ends the run
+        if let Some(addr) = self.trap {
+            warn!("Stopped on the machine call at 0x{:03X}; run with --debug to break into the debugger there", addr);
+        }
+
+        self.shutdown();
+    }
+
+    // Same as play, but paced by tokio timers rather than sleeping threads, and also
+    // ending once the stop token given is stopped, such as by another task.
+    #[cfg(feature = "async")]
+    pub async fn run_async(&mut self, rom: &[u8], stop: StopToken) {
+        use tokio::time;
+
+        self.load(rom);
+
+        let started = Instant::now();
+        let mut total_cycles = 0;
+        self.timing = TimingStats::new(self.config.clock_speed);
+
+        if self.config.unthrottled() {
+            // Run a frame at a time, yielding to other tasks in between
+            while !stop.is_stopped() && !self.stop.is_stopped() && self.trap.is_none() && !self.run_limit_reached(total_cycles, started) {
+                if self.paused {
+                    self.paused_frame();
+                    time::sleep(FRAME_DURATION).await;
+                    continue;
+                }
+
+                for _ in 0..self.cycles_per_frame() {
+                    self.cycle();
+                }
+
+                total_cycles += self.cycles_per_frame();
+                self.timing.add_cycles(self.cycles_per_frame());
+                self.vblank();
+                self.timing.frame(Instant::now());
+                tokio::task::yield_now().await;
+            }
+
+            self.shutdown();
+            return;
+        }
+
+        let mut speed = self.speed;
+        let mut clock = time::interval(self.cycle_duration());
+
+        // As in play, the display interrupt fires every cycles_per_frame cycles,
+        // paced by the one clock
+        let mut cycles = 0;
+
+        while !stop.is_stopped() && !self.stop.is_stopped() && self.trap.is_none() && !self.run_limit_reached(total_cycles, started) {
+            if self.paused {
+                self.paused_frame();
+                time::sleep(FRAME_DURATION).await;
+                clock.reset();
+                continue;
+            }
+
+            clock.tick().await;
+
+            self.cycle();
+            cycles += 1;
+            total_cycles += 1;
+            self.timing.add_cycles(1);
+
+            if cycles >= self.cycles_per_frame() {
+                self.vblank();
+                self.timing.frame(Instant::now());
+                cycles = 0;
+            }
+
+            if self.speed != speed {
+                speed = self.speed;
+                clock = time::interval(self.cycle_duration());
+            }
+        }
+
+        self.shutdown();
+    }
+}
+
+// The timers, keypad and random number generator, lent to the CPU for an
+// instruction.
+struct MachinePeripherals<'a> {
+    delay: &'a mut Timer,
+    sound: &'a mut Timer,
+    keys_down: &'a [Key],
+    rng: &'a mut StdRng,
+}
+
+impl Peripherals for MachinePeripherals<'_> {
+    fn delay(&self) -> u8 {
+        self.delay.get()
+    }
+
+    fn set_delay(&mut self, value: u8) {
+        self.delay.set(value);
+    }
+
+    fn set_sound(&mut self, value: u8) {
+        self.sound.set(value);
+    }
+
+    fn key_down(&self, key: u8) -> bool {
+        Key::try_from(key).is_ok_and(|key| self.keys_down.contains(&key))
+    }
+
+    fn random(&mut self) -> u8 {
+        self.rng.random()
+    }
+}
+
+fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    }
 }
@@ -1,74 +1,53 @@
-use std::thread;
-use std::time::Duration;
-use std::sync::{
-    Arc,
-    mpmc::Sender,
-    atomic::{
-        AtomicU8,
-        AtomicBool,
-        Ordering,
-    },
-};
+use std::sync::mpmc::Sender;
 
-use crate::devices::DeviceEvent;
+use crate::bus::AudioEvent;
 
+// Delay or sound timer, counted down by the machine at each display interrupt so
+// that it keeps in step with the program and is saved and restored exactly.
 pub struct Timer {
-    value: Arc<AtomicU8>,
-    running: Arc<AtomicBool>,
-    handle: Option<thread::JoinHandle<()>>,
+    value: u8,
+    event_channel: Option<Sender<AudioEvent>>,
 }
 
 impl Timer {
-    pub fn new(event_channel: Option<Sender<DeviceEvent>>) -> Self {
-        let value = Arc::new(AtomicU8::new(0));
-        let running = Arc::new(AtomicBool::new(true));
-
-        let value_clone = Arc::clone(&value);
-        let running_clone = Arc::clone(&running);
-
-        let handle = thread::spawn(move || {
-            let tick_duration = Duration::from_millis(1000 / 60); // 60hz
-            
-            while running_clone.load(Ordering::Relaxed) {
-                thread::sleep(tick_duration);
-
-                let current = value_clone.load(Ordering::Acquire);
-
-                if current > 0 {
-                    value_clone.store(current - 1, Ordering::Release);
-                    if let Some(sender) = &event_channel {
-                        let _ = sender.send(DeviceEvent::PlayTone);
-                    };
-                } else {
-                    if let Some(sender) = &event_channel {
-                        let _ = sender.send(DeviceEvent::StopTone);
-                    };
-                }
-            }
-        });
-
+    pub fn new(event_channel: Option<Sender<AudioEvent>>) -> Self {
         Self {
-            value,
-            running,
-            handle: Some(handle),
+            value: 0,
+            event_channel,
         }
     }
 
     pub fn get(&self) -> u8 {
-        self.value.load(Ordering::Acquire)
+        self.value
     }
 
-    pub fn set(&self, new_value: u8) {
-        self.value.store(new_value, Ordering::Release)
+    pub fn set(&mut self, new_value: u8) {
+        let previous = std::mem::replace(&mut self.value, new_value);
+
+        match (previous > 0, new_value > 0) {
+            (false, true) => self.send(AudioEvent::PlayTone),
+            (true, false) => self.send(AudioEvent::StopTone),
+            _ => (),
+        }
     }
-}
 
-impl Drop for Timer {
-    fn drop(&mut self) {
-        self.running.store(false, Ordering::Relaxed);
+    // Count down once, stopping the tone as the timer reaches 0. Events are only sent
+    // on these edges, and on set, rather than every tick.
+    pub fn tick(&mut self) {
+        if self.value == 0 {
+            return;
+        }
+
+        self.value -= 1;
+
+        if self.value == 0 {
+            self.send(AudioEvent::StopTone);
+        }
+    }
 
-        if let Some(handle) = self.handle.take() {
-            handle.join().unwrap();
+    fn send(&self, event: AudioEvent) {
+        if let Some(sender) = &self.event_channel {
+            let _ = sender.send(event);
         }
     }
 }
@@ -0,0 +1,134 @@
+// How closely a run keeps to real time: the instructions per second achieved
+// against those asked for, and how far the 60Hz display interrupts stray from
+// falling a 60th of a second apart.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+// Interval the display interrupt is meant to fire at
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Clone, Debug)]
+pub struct TimingStats {
+    // Instructions per second asked for, or 0 when unthrottled
+    target_ips: u64,
+    started: Instant,
+    cycles: u64,
+    frames: u64,
+    last_frame: Option<Instant>,
+
+    // Total and largest difference between a frame's length and FRAME_DURATION
+    total_jitter: Duration,
+    max_jitter: Duration,
+
+    // Times the run loop fell so far behind that it gave up catching up
+    resyncs: u64,
+
+    // Start and cycle count of the second being measured, and the rate over the
+    // last whole one
+    window: (Instant, u64),
+    recent_ips: u64,
+}
+
+impl TimingStats {
+    pub fn new(target_ips: u64) -> Self {
+        let now = Instant::now();
+
+        Self {
+            target_ips,
+            started: now,
+            cycles: 0,
+            frames: 0,
+            last_frame: None,
+            total_jitter: Duration::ZERO,
+            max_jitter: Duration::ZERO,
+            resyncs: 0,
+            window: (now, 0),
+            recent_ips: 0,
+        }
+    }
+
+    pub fn add_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    // Note a display interrupt that fired at now.
+    pub fn frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            let jitter = now.duration_since(last).abs_diff(FRAME_DURATION);
+            self.total_jitter += jitter;
+            self.max_jitter = self.max_jitter.max(jitter);
+        }
+
+        self.frames += 1;
+        self.last_frame = Some(now);
+
+        let (start, cycles) = self.window;
+
+        if now.duration_since(start) >= Duration::from_secs(1) {
+            self.recent_ips = ((self.cycles - cycles) as f64 / now.duration_since(start).as_secs_f64()).round() as u64;
+            self.window = (now, self.cycles);
+        }
+    }
+
+    // Note the run loop dropping a deficit too large to catch up on.
+    pub fn resync(&mut self) {
+        self.resyncs += 1;
+    }
+
+    // Instructions per second over the whole run.
+    pub fn ips(&self) -> f64 {
+        self.cycles as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    // Instructions per second over the last whole second.
+    pub fn recent_ips(&self) -> u64 {
+        self.recent_ips
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.frames as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn mean_jitter(&self) -> Duration {
+        self.total_jitter / self.frames.saturating_sub(1).max(1) as u32
+    }
+
+    pub fn max_jitter(&self) -> Duration {
+        self.max_jitter
+    }
+
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    // Line to show over the display.
+    pub fn overlay_text(&self) -> String {
+        format!("IPS {}", self.recent_ips)
+    }
+}
+
+impl fmt::Display for TimingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Timing over {:.1}s:", self.started.elapsed().as_secs_f64())?;
+
+        // Unthrottled runs have no pace to keep
+        if self.target_ips == 0 {
+            writeln!(f, "  instructions per second: {:.0} (unthrottled)", self.ips())?;
+            return write!(f, "  frames per second: {:.1}", self.fps());
+        }
+
+        let percent = self.ips() / self.target_ips as f64 * 100.0;
+        writeln!(f, "  instructions per second: {:.0} of {} ({:.1}%)", self.ips(), self.target_ips, percent)?;
+        writeln!(f, "  frames per second: {:.1}", self.fps())?;
+        writeln!(
+            f,
+            "  frame jitter: {:.2}ms mean, {:.2}ms max",
+            self.mean_jitter().as_secs_f64() * 1000.0,
+            self.max_jitter.as_secs_f64() * 1000.0,
+        )?;
+        write!(f, "  resyncs: {}", self.resyncs)
+    }
+}
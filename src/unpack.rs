@@ -0,0 +1,215 @@
+// ROMs inside the zip and gzip files ROM collections tend to ship as. Zip members
+// may be stored or deflated, and are checked against their CRC-32, as is gzip data.
+
+use std::{error::Error, fmt};
+
+use crate::inflate::{self, InflateError};
+
+// Extensions of the files in a zip taken to be ROMs, or sources of one
+pub const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "8o"];
+
+const ZIP_END_SIGNATURE: u32 = 0x0605_4B50;
+const ZIP_ENTRY_SIGNATURE: u32 = 0x0201_4B50;
+const ZIP_LOCAL_SIGNATURE: u32 = 0x0403_4B50;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+// gzip header flags
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+#[derive(Debug, PartialEq)]
+pub enum UnpackError {
+    // Not a zip or gzip file, or cut short
+    Malformed(&'static str),
+    // Compressed with something other than DEFLATE
+    Method(u16),
+    Inflate(InflateError),
+    Checksum,
+    // No member with the name asked for, and the names there are
+    NoMember(String, Vec<String>),
+    // No member with a ROM's extension, and the names there are
+    NoRom(Vec<String>),
+}
+
+impl fmt::Display for UnpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnpackError::Malformed(what) => write!(f, "malformed archive: {}", what),
+            UnpackError::Method(method) => write!(f, "unsupported compression method {}", method),
+            UnpackError::Inflate(error) => write!(f, "{}", error),
+            UnpackError::Checksum => write!(f, "checksum mismatch, the file is corrupt"),
+            UnpackError::NoMember(name, names) => write!(f, "no member named {} (has {})", name, names.join(", ")),
+            UnpackError::NoRom(names) => {
+                write!(f, "no .{} file among {}", ROM_EXTENSIONS.join("/."), names.join(", "))
+            },
+        }
+    }
+}
+
+impl Error for UnpackError {}
+
+impl From<InflateError> for UnpackError {
+    fn from(error: InflateError) -> Self {
+        UnpackError::Inflate(error)
+    }
+}
+
+// A file in a zip, as listed in its central directory.
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    local_header: usize,
+}
+
+// The member of a zip with the name given, matching its path or just its file
+// name and ignoring case, or else the first with a ROM's extension. Returns the
+// member's name along with its contents.
+pub fn unzip(data: &[u8], member: Option<&str>) -> Result<(String, Vec<u8>), UnpackError> {
+    let entries = zip_entries(data)?;
+    let names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+    let files = entries.iter().filter(|entry| !entry.name.ends_with('/'));
+
+    let entry = match member {
+        Some(member) => files.clone()
+            .find(|entry| entry.name.eq_ignore_ascii_case(member) || file_name(&entry.name).eq_ignore_ascii_case(member))
+            .ok_or_else(|| UnpackError::NoMember(member.to_string(), names.clone()))?,
+        None => files.clone()
+            .find(|entry| is_rom_name(&entry.name))
+            .ok_or_else(|| UnpackError::NoRom(names.clone()))?,
+    };
+
+    let local = entry.local_header;
+
+    if read_u32(data, local)? != ZIP_LOCAL_SIGNATURE {
+        return Err(UnpackError::Malformed("local header"));
+    }
+
+    let start = local + 30 + read_u16(data, local + 26)? as usize + read_u16(data, local + 28)? as usize;
+    let compressed = data.get(start..start + entry.compressed_size).ok_or(UnpackError::Malformed("member data"))?;
+
+    let contents = match entry.method {
+        0 => compressed.to_vec(),
+        8 => inflate::inflate(compressed)?.0,
+        method => return Err(UnpackError::Method(method)),
+    };
+
+    if crc32(&contents) != entry.crc {
+        return Err(UnpackError::Checksum);
+    }
+
+    Ok((entry.name.clone(), contents))
+}
+
+// Decompress gzip data, of which only the first member is read.
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, UnpackError> {
+    if data.get(..2) != Some(&GZIP_MAGIC) {
+        return Err(UnpackError::Malformed("not gzip"));
+    }
+
+    let method = *data.get(2).ok_or(UnpackError::Malformed("header"))?;
+
+    if method != 8 {
+        return Err(UnpackError::Method(method.into()));
+    }
+
+    let flags = *data.get(3).ok_or(UnpackError::Malformed("header"))?;
+    let mut at = 10;
+
+    if flags & FEXTRA != 0 {
+        at += 2 + read_u16(data, at)? as usize;
+    }
+
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data.get(at..).and_then(|rest| rest.iter().position(|&byte| byte == 0));
+            at += end.ok_or(UnpackError::Malformed("header"))? + 1;
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        at += 2;
+    }
+
+    let (contents, length) = inflate::inflate(data.get(at..).ok_or(UnpackError::Malformed("header"))?)?;
+
+    if read_u32(data, at + length)? != crc32(&contents) {
+        return Err(UnpackError::Checksum);
+    }
+
+    Ok(contents)
+}
+
+// Whether a file's name has one of the extensions of ROMs.
+pub fn is_rom_name(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, extension)| {
+        ROM_EXTENSIONS.iter().any(|rom| rom.eq_ignore_ascii_case(extension))
+    })
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn zip_entries(data: &[u8]) -> Result<Vec<ZipEntry>, UnpackError> {
+    // The end record sits at the end, after a comment of up to 64K
+    let end = (0..data.len().saturating_sub(21)).rev()
+        .take(0x10000 + 22)
+        .find(|&at| read_u32(data, at) == Ok(ZIP_END_SIGNATURE))
+        .ok_or(UnpackError::Malformed("not a zip"))?;
+
+    let count = read_u16(data, end + 10)? as usize;
+    let mut at = read_u32(data, end + 16)? as usize;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        if read_u32(data, at)? != ZIP_ENTRY_SIGNATURE {
+            return Err(UnpackError::Malformed("central directory"));
+        }
+
+        let name_length = read_u16(data, at + 28)? as usize;
+        let name = data.get(at + 46..at + 46 + name_length).ok_or(UnpackError::Malformed("central directory"))?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: read_u16(data, at + 10)?,
+            crc: read_u32(data, at + 16)?,
+            compressed_size: read_u32(data, at + 20)? as usize,
+            local_header: read_u32(data, at + 42)? as usize,
+        });
+
+        at += 46 + name_length + read_u16(data, at + 30)? as usize + read_u16(data, at + 32)? as usize;
+    }
+
+    Ok(entries)
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, UnpackError> {
+    data.get(at..at + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(UnpackError::Malformed("ends early"))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, UnpackError> {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(UnpackError::Malformed("ends early"))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
@@ -0,0 +1,70 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpmc::Sender, mpsc},
+    thread,
+    time::Duration,
+};
+
+use log::{info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::bus::ControlEvent;
+
+// How long to let a write settle before reloading, so the burst of events an
+// editor or assembler causes while saving turns into a single reload.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+// Reloads a ROM into the machine whenever its file is rewritten, until dropped.
+pub struct RomWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl RomWatcher {
+    pub fn new(path: &Path, control: Sender<ControlEvent>) -> notify::Result<Self> {
+        let path = path.canonicalize()?;
+
+        // Editors often save by replacing the file rather than writing to it, which a
+        // watch on the file itself wouldn't survive, so watch its directory instead
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("/"));
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_tx)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        thread::spawn(move || {
+            while let Ok(event) = event_rx.recv() {
+                if !rewrites(&event, &path) {
+                    continue;
+                }
+
+                thread::sleep(SETTLE_TIME);
+                while event_rx.try_recv().is_ok() {}
+
+                info!("{} changed, reloading", path.display());
+
+                // The machine has gone away
+                if control.send(ControlEvent::LoadRom(path.clone())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            _watcher: watcher,
+        })
+    }
+}
+
+// Whether the event leaves new contents at path.
+fn rewrites(event: &notify::Result<Event>, path: &Path) -> bool {
+    match event {
+        Ok(event) => {
+            (event.kind.is_create() || event.kind.is_modify())
+                && event.paths.iter().any(|changed| changed == path)
+        },
+        Err(error) => {
+            warn!("Failed to watch ROM: {}", error);
+            false
+        },
+    }
+}
@@ -0,0 +1,228 @@
+// Values of the machine's state shown over the display every frame, such as a
+// register or a byte of memory holding the score, so they can be followed while
+// playing without pausing.
+
+use std::{collections::VecDeque, error::Error, fmt, str::FromStr};
+
+use crate::{menu, ChipEight};
+
+// Frames of history a plot shows, a column of pixels each
+pub const PLOT_LEN: usize = 16;
+
+// Height of a plot, the same as a line of text
+const PLOT_HEIGHT: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchExpr {
+    Register(usize),
+    Index,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    // Byte of memory at an address
+    Memory(usize),
+}
+
+impl WatchExpr {
+    // Current value, or None for memory out of bounds.
+    pub fn evaluate(&self, chip_eight: &ChipEight) -> Option<usize> {
+        match *self {
+            WatchExpr::Register(reg) => Some(chip_eight.registers()[reg] as usize),
+            WatchExpr::Index => Some(chip_eight.index()),
+            WatchExpr::Pc => Some(chip_eight.pc()),
+            WatchExpr::DelayTimer => Some(chip_eight.timers().0 as usize),
+            WatchExpr::SoundTimer => Some(chip_eight.timers().1 as usize),
+            WatchExpr::Memory(addr) => chip_eight.memory().read_byte(addr).ok().map(usize::from),
+        }
+    }
+
+    // Short name to show over the display, where there's room for few characters.
+    fn label(&self) -> String {
+        match self {
+            WatchExpr::Register(reg) => format!("V{:X}", reg),
+            WatchExpr::Index => "I".to_string(),
+            WatchExpr::Pc => "PC".to_string(),
+            WatchExpr::DelayTimer => "DT".to_string(),
+            WatchExpr::SoundTimer => "ST".to_string(),
+            WatchExpr::Memory(addr) => format!("M{:03X}", addr),
+        }
+    }
+
+    // Hex digits the value is shown with, enough for any value it can take.
+    fn digits(&self) -> usize {
+        match self {
+            WatchExpr::Index | WatchExpr::Pc => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for WatchExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchExpr::Register(reg) => write!(f, "v{:X}", reg),
+            WatchExpr::Index => write!(f, "i"),
+            WatchExpr::Pc => write!(f, "pc"),
+            WatchExpr::DelayTimer => write!(f, "dt"),
+            WatchExpr::SoundTimer => write!(f, "st"),
+            WatchExpr::Memory(addr) => write!(f, "mem[0x{:03X}]", addr),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WatchExprError(String);
+
+impl fmt::Display for WatchExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown expression {}, expected v0 to vF, i, pc, dt, st or mem[ADDR]", self.0)
+    }
+}
+
+impl Error for WatchExprError {}
+
+impl FromStr for WatchExpr {
+    type Err = WatchExprError;
+
+    // Registers as v0 to vF, i, pc, dt and st, and memory as mem[0x3A0] or mem[928].
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || WatchExprError(value.to_string());
+        let lower = value.trim().to_ascii_lowercase();
+
+        let expr = match lower.as_str() {
+            "i" => WatchExpr::Index,
+            "pc" => WatchExpr::Pc,
+            "dt" => WatchExpr::DelayTimer,
+            "st" => WatchExpr::SoundTimer,
+            _ => {
+                if let Some(addr) = lower.strip_prefix("mem[").and_then(|rest| rest.strip_suffix(']')) {
+                    let addr = match addr.strip_prefix("0x") {
+                        Some(hex) => usize::from_str_radix(hex, 16),
+                        None => addr.parse(),
+                    };
+
+                    WatchExpr::Memory(addr.map_err(|_| error())?)
+                } else {
+                    let digit = lower.strip_prefix('v').filter(|digit| digit.len() == 1).ok_or_else(error)?;
+                    WatchExpr::Register(usize::from_str_radix(digit, 16).map_err(|_| error())?)
+                }
+            },
+        };
+
+        Ok(expr)
+    }
+}
+
+// An expression to show, and whether to plot its recent history beside it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watch {
+    pub expr: WatchExpr,
+    pub plot: bool,
+}
+
+impl FromStr for Watch {
+    type Err = WatchExprError;
+
+    // An expression, followed by :plot to plot it.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_suffix(":plot") {
+            Some(expr) => Ok(Self { expr: expr.parse()?, plot: true }),
+            None => Ok(Self { expr: value.parse()?, plot: false }),
+        }
+    }
+}
+
+// The watches shown, each with its values over the latest frames.
+#[derive(Default)]
+pub struct Watches {
+    entries: Vec<(Watch, VecDeque<Option<usize>>)>,
+}
+
+impl Watches {
+    pub fn new(watches: &[Watch]) -> Self {
+        let mut entries = Self::default();
+
+        for &watch in watches {
+            entries.add(watch);
+        }
+
+        entries
+    }
+
+    // Show a watch, in place of any of the same expression.
+    pub fn add(&mut self, watch: Watch) {
+        self.remove(&watch.expr);
+        self.entries.push((watch, VecDeque::with_capacity(PLOT_LEN)));
+    }
+
+    // Stop showing an expression, returning false if it wasn't shown.
+    pub fn remove(&mut self, expr: &WatchExpr) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(watch, _)| watch.expr != *expr);
+
+        self.entries.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watch> {
+        self.entries.iter().map(|(watch, _)| watch)
+    }
+
+    pub fn exprs(&self) -> impl Iterator<Item = WatchExpr> + '_ {
+        self.entries.iter().map(|(watch, _)| watch.expr)
+    }
+
+    // Note the value of each watch in a new frame, in the order of exprs.
+    pub fn record(&mut self, values: impl IntoIterator<Item = Option<usize>>) {
+        for ((_, history), value) in self.entries.iter_mut().zip(values) {
+            if history.len() == PLOT_LEN {
+                history.pop_front();
+            }
+
+            history.push_back(value);
+        }
+    }
+
+    // Draw a line for each watch over the frame from row y down, its label and value
+    // on the left and any plot against the right edge, scaled to the values shown.
+    pub fn draw(&self, frame: &mut [bool], width: usize, y: usize) {
+        for (line, (watch, history)) in self.entries.iter().enumerate() {
+            let top = y + line * menu::LINE_HEIGHT;
+
+            let value = match history.back().copied().flatten() {
+                Some(value) => format!("{:0digits$X}", value, digits = watch.expr.digits()),
+                None => "--".to_string(),
+            };
+            menu::draw_text(frame, width, 1, top, &format!("{} {}", watch.expr.label(), value), true);
+
+            if !watch.plot || width < PLOT_LEN + 1 {
+                continue;
+            }
+
+            let values: Vec<usize> = history.iter().flatten().copied().collect();
+            let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+                continue;
+            };
+
+            let left = width - PLOT_LEN - 1;
+
+            for (column, value) in history.iter().enumerate() {
+                let Some(value) = value else {
+                    continue;
+                };
+
+                // Rows lit from the bottom, at least one for the smallest value
+                let rows = 1 + (value - min) * (PLOT_HEIGHT - 1) / (max - min).max(1);
+
+                for row in PLOT_HEIGHT - rows..PLOT_HEIGHT {
+                    if let Some(pixel) = frame.get_mut((top + row) * width + left + column) {
+                        *pixel = true;
+                    }
+                }
+            }
+        }
+    }
+}
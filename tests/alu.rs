@@ -0,0 +1,76 @@
+// Property tests for the arithmetic, shift and BCD instructions, which have the
+// most room for carry, borrow and flag ordering mistakes. Each case runs a single
+// instruction through the public step API, with vX in v1 and vY in v2.
+
+mod common;
+
+use proptest::prelude::*;
+
+const X: usize = 1;
+const Y: usize = 2;
+
+// Execute 8XYN with the given operands, returning vX and vF.
+fn alu(n: u16, vx: u8, vy: u8) -> (u8, u8) {
+    let mut chip_eight = common::headless_machine(&[], &[0x8000 | (X as u16) << 8 | (Y as u16) << 4 | n]);
+    chip_eight.set_register(X as u8, vx);
+    chip_eight.set_register(Y as u8, vy);
+
+    chip_eight.step();
+
+    (chip_eight.registers()[X], chip_eight.registers()[0xF])
+}
+
+proptest! {
+    #[test]
+    fn add_sets_carry(vx: u8, vy: u8) {
+        let sum = vx as u16 + vy as u16;
+
+        prop_assert_eq!(alu(0x4, vx, vy), (sum as u8, (sum > 0xFF) as u8));
+    }
+
+    #[test]
+    fn sub_sets_not_borrow(vx: u8, vy: u8) {
+        prop_assert_eq!(alu(0x5, vx, vy), (vx.wrapping_sub(vy), (vx >= vy) as u8));
+    }
+
+    #[test]
+    fn reverse_sub_sets_not_borrow(vx: u8, vy: u8) {
+        prop_assert_eq!(alu(0x7, vx, vy), (vy.wrapping_sub(vx), (vy >= vx) as u8));
+    }
+
+    #[test]
+    fn right_shift_moves_low_bit_to_flag(vx: u8, vy: u8) {
+        prop_assert_eq!(alu(0x6, vx, vy), (vy >> 1, vy & 1));
+    }
+
+    #[test]
+    fn left_shift_moves_high_bit_to_flag(vx: u8, vy: u8) {
+        prop_assert_eq!(alu(0xE, vx, vy), (vy << 1, vy >> 7));
+    }
+
+    // The flag is written last, so it wins when vF is also the destination
+    #[test]
+    fn flag_overrides_result_in_vf(vf: u8, vy: u8) {
+        let mut chip_eight = common::headless_machine(&[], &[0x8F04 | (Y as u16) << 4]);
+        chip_eight.set_register(0xF, vf);
+        chip_eight.set_register(Y as u8, vy);
+
+        chip_eight.step();
+
+        prop_assert_eq!(chip_eight.registers()[0xF], (vf as u16 + vy as u16 > 0xFF) as u8);
+    }
+
+    #[test]
+    fn bcd_stores_decimal_digits(vx: u8) {
+        // Point I past the program, then store the digits of vX there
+        let mut chip_eight = common::headless_machine(&[], &[0xA300, 0xF033 | (X as u16) << 8]);
+        chip_eight.set_register(X as u8, vx);
+
+        chip_eight.step();
+        chip_eight.step();
+
+        let digits = chip_eight.memory().read_buf(0x300, 3).unwrap();
+        prop_assert_eq!(&*digits, &[vx / 100, vx / 10 % 10, vx % 10]);
+        prop_assert_eq!(chip_eight.index(), 0x300);
+    }
+}
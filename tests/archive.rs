@@ -0,0 +1,46 @@
+use std::env;
+
+use chip_eight::archive::{Archive, ArchiveError, Program};
+
+fn program(name: &str, platform: &str, quirks: &[(&str, bool)]) -> Program {
+    Program {
+        name: name.to_string(),
+        title: String::new(),
+        authors: vec![],
+        event: String::new(),
+        release: String::new(),
+        description: String::new(),
+        platform: platform.to_string(),
+        tickrate: None,
+        colors: None,
+        rotation: None,
+        quirks: quirks.iter().map(|&(quirk, set)| (quirk.to_string(), set)).collect(),
+    }
+}
+
+#[test]
+fn rejects_names_outside_the_cache() {
+    let archive = Archive::new("file:///nonexistent", &env::temp_dir().join("chip-eight-archive-test"));
+
+    for name in ["../escape", "roms/nested", "back\\slash", ".."] {
+        let result = archive.rom(&program(name, "chip8", &[]));
+        assert!(matches!(result, Err(ArchiveError::InvalidName(_))), "{} was accepted", name);
+    }
+}
+
+#[test]
+fn schip_programs_get_super_chip_quirks() {
+    let flags = program("game", "schip", &[]).flags();
+
+    for flag in ["--skip-shift-set", "--preserve-index", "--jump-with-vx", "--skip-reset-vf", "--vblank-model=none"] {
+        assert!(flags.iter().any(|given| given == flag), "{} missing from {:?}", flag, flags);
+    }
+}
+
+#[test]
+fn schip_quirks_given_by_the_archive_win() {
+    let flags = program("game", "schip", &[("shiftQuirks", false), ("loadStoreQuirk", false)]).flags();
+
+    assert!(!flags.iter().any(|flag| flag == "--skip-shift-set" || flag == "--preserve-index"), "{:?}", flags);
+    assert!(flags.iter().any(|flag| flag == "--jump-with-vx"));
+}
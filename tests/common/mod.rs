@@ -0,0 +1,32 @@
+// Machines shared by the integration tests: headless, with no display, audio or
+// input device, and a program of a few opcodes loaded.
+
+// Each test file uses only some of these
+#![allow(dead_code)]
+
+use clap::Parser;
+
+use chip_eight::{
+    config::{Args, Config},
+    ChipEight,
+    ChipEightBuilder,
+};
+
+// Config of a headless machine, with flags such as quirks given on top.
+pub fn headless_config(flags: &[&str]) -> Config {
+    let args = ["chip-eight", "--display-engine", "none", "--audio-engine", "none", "--input-engine", "none"];
+
+    Config::from(Args::parse_from(args.iter().chain(flags)))
+}
+
+pub fn bytes(program: &[u16]) -> Vec<u8> {
+    program.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+// Headless machine with the flags given, and the program loaded at the start.
+pub fn headless_machine(flags: &[&str], program: &[u16]) -> ChipEight {
+    let mut chip_eight = ChipEightBuilder::new(headless_config(flags)).build();
+    chip_eight.load(&bytes(program));
+
+    chip_eight
+}
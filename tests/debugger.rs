@@ -0,0 +1,43 @@
+// Tests driving the debugger through the binary, with commands on stdin.
+
+mod common;
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// Output of a headless run of the program under --debug, given the commands and the
+// flags.
+fn debug(program: &[u16], flags: &[&str], commands: &str) -> String {
+    let rom = std::env::temp_dir().join(format!("chip-eight-debugger-{}.ch8", std::process::id()));
+    std::fs::write(&rom, common::bytes(program)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip-eight"))
+        .args(["--display-engine", "none", "--audio-engine", "none", "--input-engine", "none", "--debug"])
+        .args(flags)
+        .arg(&rom)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&rom).unwrap();
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn trapped_machine_calls_break_into_the_prompt() {
+    // v0 = 1, call 0x123, v1 = 2, then loop
+    let output = debug(&[0x6001, 0x0123, 0x6102, 0x1206], &["--machine-calls", "trap"], "c\nregs\ns\nregs\nq\n");
+
+    assert!(output.contains("Trapped on machine call at 0x202"), "{}", output);
+    assert!(output.contains("pc 0x202"), "{}", output);
+
+    // Stepping carries on past the call, without the debugger having quit
+    assert!(output.contains("pc 0x204"), "{}", output);
+}
@@ -0,0 +1,60 @@
+// Tests that skipping loops waiting on the delay timer leaves programs running
+// exactly as they would have cycle by cycle.
+
+mod common;
+
+use chip_eight::ChipEight;
+
+use common::headless_machine;
+
+// Waits out the delay timer set from v0 in v1, counting the waits in v2, forever
+const WAIT: [u16; 7] = [0x6005, 0xF015, 0xF107, 0x3100, 0x1204, 0x7201, 0x1200];
+
+// Sets the delay timer from vA, waits in v3 until it reaches 4, then while it's
+// still 4, counting the waits in v2, forever
+const WAIT_FOR: [u16; 10] = [0x6A08, 0xFA15, 0xF307, 0x3304, 0x1204, 0xF307, 0x4304, 0x120A, 0x7201, 0x1200];
+
+fn state(chip_eight: &ChipEight) -> (usize, [u8; 16], (u8, u8)) {
+    (chip_eight.pc(), *chip_eight.registers(), chip_eight.timers())
+}
+
+fn runs_alike(program: &[u16]) {
+    // Frames of 10 and 11 cycles, which the 3 instruction loops don't divide
+    for clock_speed in ["600", "660"] {
+        let mut skipping = headless_machine(&["--clock-speed", clock_speed], program);
+        let mut running = headless_machine(&["--clock-speed", clock_speed, "--no-skip-delay-waits"], program);
+
+        for _ in 0..40 {
+            skipping.run_frames(1);
+            running.run_frames(1);
+
+            assert_eq!(state(&skipping), state(&running));
+        }
+
+        assert!(skipping.registers()[2] > 0);
+    }
+}
+
+#[test]
+fn skipped_waits_run_alike() {
+    runs_alike(&WAIT);
+}
+
+#[test]
+fn skipped_waits_on_other_registers_and_values_run_alike() {
+    runs_alike(&WAIT_FOR);
+}
+
+#[test]
+fn hooks_see_every_cycle() {
+    let mut chip_eight = headless_machine(&[], &WAIT);
+    let executed = std::rc::Rc::new(std::cell::Cell::new(0));
+
+    chip_eight.on_instruction({
+        let executed = executed.clone();
+        move |_, _| executed.set(executed.get() + 1)
+    });
+
+    chip_eight.run_frames(2);
+    assert_eq!(executed.get(), 2 * chip_eight.cycles_per_frame());
+}
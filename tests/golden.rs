@@ -0,0 +1,103 @@
+// Golden trace regression tests. Each test ROM is run headless for a fixed number
+// of cycles under a few quirk profiles, recording the program counter, opcode and
+// registers after every executed instruction, and the trace is compared against the
+// one committed under `tests/golden`.
+//
+// After an intentional change to instruction semantics, regenerate the traces with
+// `GOLDEN_BLESS=1 cargo test --test golden` and review the diff.
+
+use std::{cell::Cell, env, fmt::Write, fs, path::PathBuf, rc::Rc};
+
+mod common;
+
+use chip_eight::ChipEightBuilder;
+
+const CYCLES: usize = 2000;
+const CYCLES_PER_FRAME: usize = 10;
+
+const ROMS: [&str; 3] = ["IBM", "CORAX", "FLAGS"];
+
+// Quirk flags of each profile, as passed on the command line
+const PROFILES: [(&str, &[&str]); 3] = [
+    ("vip", &[]),
+    ("schip", &["--skip-reset-vf", "--preserve-index", "--vblank-model", "schip", "--skip-shift-set", "--jump-with-vx"]),
+    ("xochip", &["--skip-reset-vf", "--vblank-model", "none", "--wrap-sprites"]),
+];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+// Run a ROM until it settles into a jump to itself, or for at most CYCLES cycles,
+// tracing every executed instruction.
+fn trace(rom: &[u8], quirks: &[&str]) -> String {
+    let mut chip_eight = ChipEightBuilder::new(common::headless_config(quirks))
+        .with_seed(0)
+        .build();
+
+    let executed = Rc::new(Cell::new(false));
+    chip_eight.on_instruction({
+        let executed = executed.clone();
+        move |_, _| executed.set(true)
+    });
+
+    chip_eight.load(rom);
+
+    let mut trace = String::new();
+
+    for cycle in 1..=CYCLES {
+        let pc = chip_eight.pc();
+        let memory = chip_eight.memory();
+        let opcode = u16::from_be_bytes([memory.read_byte(pc).unwrap(), memory.read_byte(pc + 1).unwrap()]);
+
+        executed.set(false);
+        chip_eight.cycle();
+
+        if executed.get() {
+            let registers: String = chip_eight.registers().iter().map(|v| format!("{:02X}", v)).collect();
+            writeln!(trace, "{:03X} {:04X} {} {:03X}", pc, opcode, registers, chip_eight.index()).unwrap();
+
+            if chip_eight.pc() == pc {
+                break;
+            }
+        }
+
+        if cycle % CYCLES_PER_FRAME == 0 {
+            chip_eight.vblank();
+        }
+    }
+
+    trace
+}
+
+fn check(rom_name: &str) {
+    let rom = fs::read(root().join("roms/tests").join(rom_name)).unwrap();
+
+    for (profile, quirks) in PROFILES {
+        let path = root().join("tests/golden").join(format!("{}.{}.trace", rom_name, profile));
+        let actual = trace(&rom, quirks);
+
+        if env::var_os("GOLDEN_BLESS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("Failed to read {}: {}", path.display(), error));
+
+        // Report the first diverging instruction rather than the whole trace
+        if let Some((line, (expected, actual))) = expected.lines().zip(actual.lines()).enumerate().find(|(_, (e, a))| e != a) {
+            panic!("{} ({}) diverges at instruction {}:\nexpected {}\n  actual {}", rom_name, profile, line, expected, actual);
+        }
+
+        assert_eq!(expected.lines().count(), actual.lines().count(), "{} ({}) trace length changed", rom_name, profile);
+    }
+}
+
+#[test]
+fn golden_traces() {
+    for rom in ROMS {
+        check(rom);
+    }
+}
@@ -0,0 +1,46 @@
+// Tests for pressing keys through the machine rather than an input device, as
+// scripts, bindings and tests do.
+
+mod common;
+
+use chip_eight::devices::Key;
+
+#[test]
+fn pressed_keys_complete_key_waits() {
+    // v3 = key
+    let mut chip_eight = common::headless_machine(&[], &[0xF30A]);
+
+    chip_eight.step();
+    chip_eight.press_key(Key::B);
+    chip_eight.release_key(Key::B);
+
+    assert_eq!(chip_eight.registers()[3], 0xB);
+    assert!(chip_eight.key_wait().is_none());
+}
+
+#[test]
+fn tapped_keys_are_held_for_frames() {
+    let mut chip_eight = common::headless_machine(&[], &[]);
+    chip_eight.tap_key_for(Key::_5, 2);
+
+    chip_eight.vblank();
+    assert_eq!(chip_eight.keys_down(), [Key::_5]);
+
+    chip_eight.vblank();
+    assert!(chip_eight.keys_down().is_empty());
+}
+
+#[test]
+fn tapping_again_holds_for_longer() {
+    let mut chip_eight = common::headless_machine(&[], &[]);
+    chip_eight.tap_key_for(Key::A, 1);
+    chip_eight.tap_key_for(Key::A, 3);
+
+    chip_eight.vblank();
+    chip_eight.vblank();
+    assert_eq!(chip_eight.keys_down(), [Key::A]);
+
+    chip_eight.release_key(Key::A);
+    chip_eight.vblank();
+    assert!(chip_eight.keys_down().is_empty());
+}
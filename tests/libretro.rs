@@ -0,0 +1,45 @@
+// Tests driving the libretro core through its C entry points, as a frontend does.
+
+#![cfg(feature = "libretro")]
+
+use std::{ffi::c_void, ptr};
+
+use chip_eight::libretro::{
+    retro_get_memory_data, retro_get_memory_size, retro_load_game, retro_run, retro_unload_game, RetroGameInfo,
+};
+
+const RETRO_MEMORY_SYSTEM_RAM: u32 = 2;
+
+// Layout of retro_game_info, whose fields the core keeps to itself
+#[repr(C)]
+struct GameInfo {
+    path: *const i8,
+    data: *const c_void,
+    size: usize,
+    meta: *const i8,
+}
+
+#[test]
+fn frontend_memory_writes_reach_running_code() {
+    // v0 = 1, I = 0x300, store v0, loop
+    let rom: [u8; 8] = [0x60, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+    let game = GameInfo { path: ptr::null(), data: rom.as_ptr() as *const c_void, size: rom.len(), meta: ptr::null() };
+
+    unsafe {
+        assert!(retro_load_game(&game as *const GameInfo as *const RetroGameInfo));
+
+        let memory = retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM) as *mut u8;
+        assert!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM) > 0x300);
+
+        retro_run();
+        assert_eq!(*memory.add(0x300), 1);
+
+        // Patch the program to store 2, as a cheat would
+        *memory.add(0x201) = 2;
+
+        retro_run();
+        assert_eq!(*memory.add(0x300), 2);
+    }
+
+    retro_unload_game();
+}
@@ -0,0 +1,24 @@
+// Tests for MegaChip's 16MB of memory, which is only allocated as it's written.
+
+mod common;
+
+use common::headless_machine;
+
+#[test]
+fn megachip_memory_reads_back_past_the_rom() {
+    // v0 = 0x42, I = 0x800000, store v0, v0 = 0, I = 0x800000, load v0
+    let mut chip_eight = headless_machine(
+        &["--profile", "megachip"],
+        &[0x6042, 0x0180, 0x0000, 0xF055, 0x6000, 0x0180, 0x0000, 0xF065],
+    );
+
+    for _ in 0..6 {
+        chip_eight.step();
+    }
+
+    assert_eq!(chip_eight.memory().len(), 0x1000000);
+    assert_eq!(chip_eight.registers()[0], 0x42);
+    assert_eq!(chip_eight.memory().read_byte(0x800000), Ok(0x42));
+    assert_eq!(chip_eight.memory().read_byte(0xFFFFFF), Ok(0));
+    assert_eq!(chip_eight.memory().read_buf(0x7FFFFF, 3).unwrap().as_ref(), &[0, 0x42, 0]);
+}
@@ -0,0 +1,385 @@
+// Tests for each quirk flag, with and without it. Each test runs a ROM of a few
+// instructions through the public step API and checks the registers or frame
+// buffer, so quirk behaviour is pinned down without downloading test ROMs.
+
+mod common;
+
+use chip_eight::{cpu::ExecutionError, ChipEight, ChipEightBuilder};
+
+use common::{bytes, headless_config, headless_machine as machine};
+
+// Offset of the entry point of 64x64 hi-res programs from the program start
+const HIRES_OFFSET: usize = 0xC0;
+
+// Machine running the program at 0x2C0 in 64x64 hi-res, behind the 1260 jump that
+// marks those programs.
+fn hires_machine(quirks: &[&str], program: &[u16]) -> ChipEight {
+    let mut rom = vec![0x12, 0x60];
+    rom.resize(HIRES_OFFSET, 0);
+    rom.extend(bytes(program));
+
+    let mut chip_eight = ChipEightBuilder::new(headless_config(quirks)).build();
+    chip_eight.load(&rom);
+
+    assert_eq!(chip_eight.resolution(), (64, 64));
+    chip_eight
+}
+
+fn steps(chip_eight: &mut ChipEight, count: usize) {
+    for _ in 0..count {
+        chip_eight.step();
+    }
+}
+
+fn pixel(chip_eight: &ChipEight, x: usize, y: usize) -> bool {
+    chip_eight.frame_buffer()[y * chip_eight.resolution().0 + x]
+}
+
+// v1 |= v2, with vF set beforehand
+fn logic_vf(quirks: &[&str]) -> u8 {
+    let mut chip_eight = machine(quirks, &[0x8121]);
+    chip_eight.set_register(0xF, 5);
+
+    chip_eight.step();
+    chip_eight.registers()[0xF]
+}
+
+#[test]
+fn logic_resets_vf() {
+    assert_eq!(logic_vf(&[]), 0);
+}
+
+#[test]
+fn skip_reset_vf_keeps_vf() {
+    assert_eq!(logic_vf(&["--skip-reset-vf"]), 5);
+}
+
+// Index register after storing then loading v0 to v2 from 0x300
+fn index_after_dump_and_load(quirks: &[&str]) -> (usize, usize) {
+    let mut chip_eight = machine(quirks, &[0xA300, 0xF255, 0xA300, 0xF265]);
+
+    steps(&mut chip_eight, 2);
+    let after_dump = chip_eight.index();
+
+    steps(&mut chip_eight, 2);
+    (after_dump, chip_eight.index())
+}
+
+#[test]
+fn dump_and_load_increment_index() {
+    assert_eq!(index_after_dump_and_load(&[]), (0x303, 0x303));
+}
+
+#[test]
+fn preserve_index_leaves_index() {
+    assert_eq!(index_after_dump_and_load(&["--preserve-index"]), (0x300, 0x300));
+}
+
+// Whether a draw at the start of the program waits for the display interrupt
+fn draw_waits(chip_eight: &mut ChipEight) -> bool {
+    chip_eight.step();
+    let waiting = chip_eight.waiting_for_vblank();
+
+    // Nothing runs until the interrupt
+    let pc = chip_eight.pc();
+    chip_eight.step();
+    assert_eq!(chip_eight.pc() == pc, waiting);
+
+    waiting
+}
+
+#[test]
+fn vip_draws_wait_for_vblank() {
+    assert!(draw_waits(&mut machine(&[], &[0xD011, 0x6000])));
+}
+
+#[test]
+fn vblank_model_none_draws_immediately() {
+    assert!(!draw_waits(&mut machine(&["--vblank-model", "none"], &[0xD011, 0x6000])));
+    assert!(!draw_waits(&mut machine(&["--skip-draw-wait"], &[0xD011, 0x6000])));
+}
+
+#[test]
+fn schip_draws_wait_only_in_lores() {
+    assert!(draw_waits(&mut machine(&["--vblank-model", "schip"], &[0xD011, 0x6000])));
+    assert!(!draw_waits(&mut hires_machine(&["--vblank-model", "schip"], &[0xD011, 0x6000])));
+}
+
+// Draw a row of 8 pixels from x 60, at the right edge
+fn draw_over_right_edge(quirks: &[&str]) -> ChipEight {
+    let mut chip_eight = machine(quirks, &[0x603C, 0x6100, 0xA208, 0xD011, 0xFF00]);
+    steps(&mut chip_eight, 4);
+
+    assert!(pixel(&chip_eight, 63, 0));
+    chip_eight
+}
+
+#[test]
+fn sprites_clip_at_right_edge() {
+    let chip_eight = draw_over_right_edge(&[]);
+    assert!(!pixel(&chip_eight, 0, 0));
+}
+
+#[test]
+fn wrap_x_wraps_at_right_edge() {
+    let chip_eight = draw_over_right_edge(&["--wrap-x"]);
+    assert!(pixel(&chip_eight, 0, 0));
+    assert!(pixel(&chip_eight, 3, 0));
+    assert!(!pixel(&chip_eight, 4, 0));
+}
+
+// Draw a column of 2 pixels from y 31, at the bottom edge
+fn draw_over_bottom_edge(quirks: &[&str]) -> ChipEight {
+    let mut chip_eight = machine(quirks, &[0x6000, 0x611F, 0xA208, 0xD012, 0x8080]);
+    steps(&mut chip_eight, 4);
+
+    assert!(pixel(&chip_eight, 0, 31));
+    chip_eight
+}
+
+#[test]
+fn sprites_clip_at_bottom_edge() {
+    let chip_eight = draw_over_bottom_edge(&[]);
+    assert!(!pixel(&chip_eight, 0, 0));
+}
+
+#[test]
+fn wrap_y_wraps_at_bottom_edge() {
+    let chip_eight = draw_over_bottom_edge(&["--wrap-y"]);
+    assert!(pixel(&chip_eight, 0, 0));
+}
+
+#[test]
+fn wrap_sprites_wraps_both_edges() {
+    assert!(pixel(&draw_over_right_edge(&["--wrap-sprites"]), 0, 0));
+    assert!(pixel(&draw_over_bottom_edge(&["--wrap-sprites"]), 0, 0));
+}
+
+// v1 shifted right, with v1 = 0b1100 and v2 = 0b0011
+fn shift(quirks: &[&str]) -> (u8, u8) {
+    let mut chip_eight = machine(quirks, &[0x8126]);
+    chip_eight.set_register(1, 0b1100);
+    chip_eight.set_register(2, 0b0011);
+
+    chip_eight.step();
+    (chip_eight.registers()[1], chip_eight.registers()[0xF])
+}
+
+#[test]
+fn shifts_set_vx_to_vy() {
+    assert_eq!(shift(&[]), (0b0001, 1));
+}
+
+#[test]
+fn skip_shift_set_shifts_vx() {
+    assert_eq!(shift(&["--skip-shift-set"]), (0b0110, 0));
+}
+
+// Program counter after B210, with v0 = 4 and v2 = 8
+fn jump(quirks: &[&str]) -> usize {
+    let mut chip_eight = machine(quirks, &[0xB210]);
+    chip_eight.set_register(0, 4);
+    chip_eight.set_register(2, 8);
+
+    chip_eight.step();
+    chip_eight.pc()
+}
+
+#[test]
+fn jump_offsets_by_v0() {
+    assert_eq!(jump(&[]), 0x214);
+}
+
+#[test]
+fn jump_with_vx_offsets_by_vx() {
+    assert_eq!(jump(&["--jump-with-vx"]), 0x218);
+}
+
+// vF after adding v1 to an index register of 0xFF8, with vF = 7 beforehand
+fn add_to_index_vf(quirks: &[&str], v1: u8) -> u8 {
+    let mut chip_eight = machine(quirks, &[0xAFF8, 0xF11E]);
+    chip_eight.set_register(1, v1);
+    chip_eight.set_register(0xF, 7);
+
+    steps(&mut chip_eight, 2);
+    assert_eq!(chip_eight.index(), 0xFF8 + v1 as usize);
+
+    chip_eight.registers()[0xF]
+}
+
+#[test]
+fn add_to_index_leaves_vf() {
+    assert_eq!(add_to_index_vf(&[], 0x10), 7);
+}
+
+#[test]
+fn index_overflow_flag_sets_vf_past_memory() {
+    assert_eq!(add_to_index_vf(&["--index-overflow-flag"], 0x10), 1);
+    assert_eq!(add_to_index_vf(&["--index-overflow-flag"], 0x07), 0);
+}
+
+// vF after each of two draws of the same sprite at y 60, 4 rows from the bottom of
+// the 64x64 hi-res display. The sprite is 32 bytes of 0xFF, drawn 8 rows high, or
+// 16x16 for DXY0.
+fn hires_collisions(quirks: &[&str], draw: u16) -> (u8, u8) {
+    let mut program = vec![0x6000, 0x613C, 0xA2CC, draw, draw, 0x12CA];
+    program.extend([0xFFFF; 16]);
+
+    let mut chip_eight = hires_machine(&[&["--vblank-model", "none"], quirks].concat(), &program);
+
+    steps(&mut chip_eight, 4);
+    let first = chip_eight.registers()[0xF];
+
+    chip_eight.step();
+    (first, chip_eight.registers()[0xF])
+}
+
+#[test]
+fn collisions_set_vf_to_1() {
+    assert_eq!(hires_collisions(&[], 0xD018), (0, 1));
+}
+
+#[test]
+fn collision_count_counts_collided_and_clipped_rows() {
+    assert_eq!(hires_collisions(&["--collision-count"], 0xD018), (4, 8));
+}
+
+#[test]
+fn collision_count_draws_16x16_sprites() {
+    assert_eq!(hires_collisions(&["--collision-count"], 0xD010), (12, 16));
+
+    let mut program = vec![0x6000, 0x6100, 0xA2C8, 0xD010];
+    program.extend([0xFFFF; 16]);
+
+    let mut chip_eight = hires_machine(&["--vblank-model", "none", "--collision-count"], &program);
+    steps(&mut chip_eight, 4);
+    assert!(pixel(&chip_eight, 15, 15));
+    assert!(!pixel(&chip_eight, 16, 0));
+}
+
+#[test]
+fn hires_draws_16x16_sprites_without_collision_count() {
+    assert_eq!(hires_collisions(&[], 0xD010), (0, 1));
+
+    let mut program = vec![0x6000, 0x6100, 0xA2C8, 0xD010];
+    program.extend([0xFFFF; 16]);
+
+    let mut chip_eight = hires_machine(&["--vblank-model", "none"], &program);
+    steps(&mut chip_eight, 4);
+    assert!(pixel(&chip_eight, 15, 15));
+    assert!(!pixel(&chip_eight, 16, 0));
+    assert_eq!(chip_eight.registers()[0xF], 0);
+}
+
+#[test]
+fn collision_count_only_applies_in_hires() {
+    let mut chip_eight = machine(
+        &["--vblank-model", "none", "--collision-count"],
+        &[0x6000, 0x611C, 0xA20C, 0xD018, 0xD018, 0x120A, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF],
+    );
+
+    steps(&mut chip_eight, 4);
+    assert_eq!(chip_eight.registers()[0xF], 0);
+
+    chip_eight.step();
+    assert_eq!(chip_eight.registers()[0xF], 1);
+}
+
+// v1 from CXFF after storing 0x42 at 0x101 and 0x17 at 0x102, with a display
+// interrupt before it when asked
+fn random(quirks: &[&str], interrupt: bool) -> u8 {
+    let mut chip_eight = machine(quirks, &[0x6042, 0x6117, 0xA101, 0xF155, 0xC1FF]);
+    steps(&mut chip_eight, 4);
+
+    if interrupt {
+        chip_eight.vblank();
+    }
+
+    chip_eight.step();
+    chip_eight.registers()[1]
+}
+
+#[test]
+fn modern_rng_ignores_memory() {
+    let numbers: Vec<u8> = (0..8).map(|_| random(&[], false)).collect();
+    assert!(numbers.iter().any(|&number| number != 0x42));
+}
+
+#[test]
+fn vip_rng_steps_through_memory() {
+    assert_eq!(random(&["--rng", "vip"], false), 0x42);
+    assert_eq!(random(&["--rng", "vip"], true), 0x17);
+}
+
+#[test]
+fn machine_calls_are_ignored() {
+    let mut chip_eight = machine(&[], &[0x0123]);
+
+    chip_eight.step();
+    assert_eq!(chip_eight.pc(), 0x202);
+}
+
+#[test]
+fn machine_calls_trap_holds_on_call() {
+    let mut chip_eight = machine(&["--machine-calls", "trap"], &[0x0123]);
+
+    steps(&mut chip_eight, 2);
+    assert_eq!(chip_eight.pc(), 0x200);
+    assert_eq!(chip_eight.trapped(), Some(0x200));
+    assert!(!chip_eight.stop_handle().is_stopped());
+
+    chip_eight.resume_trap();
+    assert_eq!(chip_eight.pc(), 0x202);
+    assert_eq!(chip_eight.trapped(), None);
+}
+
+#[test]
+fn machine_calls_error_fails() {
+    let mut chip_eight = machine(&["--machine-calls", "error"], &[0x0123]);
+
+    assert_eq!(chip_eight.try_step(), Err(ExecutionError::MachineCall(0x123)));
+}
+
+#[test]
+fn quirks_change_between_instructions() {
+    let mut chip_eight = machine(&[], &[0x8126, 0x8126]);
+    chip_eight.set_register(2, 0b0011);
+
+    chip_eight.step();
+    assert_eq!(chip_eight.registers()[1], 0b0001);
+
+    let mut quirks = chip_eight.quirks();
+    quirks.set("skip-shift-set", "on").unwrap();
+    chip_eight.set_quirks(quirks);
+
+    chip_eight.set_register(1, 0b1100);
+    chip_eight.step();
+    assert_eq!(chip_eight.registers()[1], 0b0110);
+    assert_eq!(chip_eight.quirks().get("skip_shift_set").as_deref(), Some("on"));
+}
+
+#[test]
+fn quirks_are_overridden_for_one_instruction() {
+    let mut chip_eight = machine(&[], &[0x8126, 0x8126]);
+    chip_eight.set_register(2, 0b0011);
+
+    chip_eight.on_quirks(|pc, _, quirks| quirks.skip_shift_set = pc == 0x200);
+
+    chip_eight.set_register(1, 0b1100);
+    chip_eight.step();
+    assert_eq!(chip_eight.registers()[1], 0b0110);
+
+    chip_eight.step();
+    assert_eq!(chip_eight.registers()[1], 0b0001);
+    assert!(!chip_eight.quirks().skip_shift_set);
+}
+
+#[test]
+fn quirks_are_named() {
+    let mut quirks = headless_config(&[]).quirks;
+
+    assert!(quirks.set("rng", "vip").is_ok());
+    assert_eq!(quirks.get("rng").as_deref(), Some("vip"));
+    assert!(quirks.set("wrap_x", "maybe").is_err());
+    assert!(quirks.set("shift_vx", "on").is_err());
+}
@@ -0,0 +1,86 @@
+// Tests for the save state format: states round trip, states from before chunks
+// still load, and readers get past what later versions add.
+
+mod common;
+
+use chip_eight::snapshot::Snapshot;
+
+// State of a machine a few instructions into a program that draws and calls.
+fn snapshot() -> Snapshot {
+    let mut chip_eight = common::headless_machine(&["--wrap-x"], &[0x602A, 0xA20A, 0x2208, 0x1206, 0xD001, 0xF000]);
+
+    for _ in 0..4 {
+        chip_eight.step();
+    }
+
+    chip_eight.snapshot().unwrap()
+}
+
+// A snapshot as version 2 wrote it, each field in order.
+fn version_2(snapshot: &Snapshot) -> Vec<u8> {
+    let u32 = |value: usize| (value as u32).to_le_bytes();
+
+    let mut bytes = b"C8ST\x02".to_vec();
+    bytes.extend(snapshot.saved_at.to_le_bytes());
+    bytes.extend(u32(snapshot.pc));
+    bytes.extend(snapshot.v);
+    bytes.extend(u32(snapshot.i));
+    bytes.extend([snapshot.timers.0, snapshot.timers.1]);
+    bytes.extend(u32(snapshot.stack.len()));
+    bytes.extend(snapshot.stack.iter().flat_map(|&addr| u32(addr)));
+    bytes.extend(u32(snapshot.calls.len()));
+    bytes.extend(snapshot.calls.iter().flat_map(|call| [u32(call.call_site), u32(call.routine)].concat()));
+    bytes.extend(u32(snapshot.memory.len()));
+    bytes.extend(&snapshot.memory);
+    bytes.extend(u32(snapshot.resolution.0));
+    bytes.extend(u32(snapshot.resolution.1));
+    bytes.extend(u32(snapshot.frame_buffer.len()));
+    bytes.extend(snapshot.frame_buffer.chunks(8).map(|chunk| {
+        chunk.iter().enumerate().fold(0, |byte, (bit, &on)| byte | (on as u8) << (7 - bit))
+    }));
+    // No key wait or audio pattern, around the flags
+    bytes.extend([0, 0, 0, snapshot.waiting_for_vblank as u8 | (snapshot.exited as u8) << 1, 0]);
+    bytes.push(snapshot.pitch);
+    bytes.extend(snapshot.rng_seed.to_le_bytes());
+    bytes.extend(snapshot.random_seed.to_le_bytes());
+
+    bytes
+}
+
+#[test]
+fn states_round_trip() {
+    let snapshot = snapshot();
+    assert_eq!(snapshot.stack, vec![0x206]);
+    assert!(snapshot.quirks.as_ref().is_some_and(|quirks| quirks.wrap_x));
+
+    assert_eq!(Snapshot::decode(&snapshot.encode()).unwrap(), snapshot);
+}
+
+#[test]
+fn states_from_before_chunks_load() {
+    let snapshot = snapshot();
+    let info = Snapshot::info(&version_2(&snapshot)).unwrap();
+    assert_eq!((info.version, info.chunks.len()), (2, 0));
+
+    let decoded = Snapshot::decode(&version_2(&snapshot)).unwrap();
+    assert_eq!(decoded, Snapshot { quirks: None, ..snapshot });
+}
+
+#[test]
+fn later_chunks_and_fields_are_skipped() {
+    let snapshot = snapshot();
+    let mut bytes = snapshot.encode();
+
+    // A later version, with a chunk of its own and a field added to the timers
+    bytes[4] = 9;
+    bytes.extend(b"NEW!\x02\x00\x00\x00\xAB\xCD");
+
+    let timers = bytes.windows(4).position(|window| window == b"TIME").unwrap();
+    bytes[timers + 4] += 1;
+    bytes.insert(timers + 10, 0xEF);
+
+    let info = Snapshot::info(&bytes).unwrap();
+    assert_eq!(info.chunks.last(), Some(&(*b"NEW!", 2)));
+
+    assert_eq!(Snapshot::decode(&bytes).unwrap(), snapshot);
+}